@@ -12,26 +12,45 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use arrow::compute::kernels::sort::SortOptions;
+use arrow::datatypes::{DataType, DateUnit, TimeUnit};
+
 use crate::physical::aggregate;
 use crate::physical::trigger;
+use crate::physical::cast::Cast;
+use crate::physical::conditional::Case;
 use crate::physical::csv::CSVSource;
+use crate::physical::distinct::Distinct;
 use crate::physical::expression;
 use crate::physical::expression::WildcardExpression;
 use crate::physical::filter::Filter;
 use crate::physical::functions::BUILTIN_FUNCTIONS;
 use crate::physical::group_by::GroupBy;
 use crate::physical::json::JSONSource;
+use crate::physical::lateral_join::LateralJoin;
+use crate::physical::limit::Limit;
 use crate::physical::map;
+use crate::physical::nested::{FieldAccess, Subscript, Unnest};
 use crate::physical::physical;
 use crate::physical::physical::Identifier;
 use crate::physical::requalifier::Requalifier;
-use crate::physical::stream_join::StreamJoin;
+use crate::physical::set_operation::{SetOperation, SetOperator};
+use crate::physical::shared::Shared;
+use crate::physical::sort::Sort;
+use crate::physical::stream_join::{JoinType, StreamJoin};
+use crate::physical::table_functions::{TABLE_FUNCTIONS, TableFunctionArgs};
+use crate::physical::top_n::TopN;
+use crate::physical::window::{Window, WindowFrame, WindowFunction};
 
 #[derive(Debug)]
 pub enum Error {
     Unexpected(String),
+    // The query doesn't fit the data, like an unknown column or mismatching types.
+    BadInput(String),
 }
 
 #[derive(Debug)]
@@ -63,11 +82,64 @@ pub enum Node {
         source_key: Vec<Box<Expression>>,
         joined: Box<Node>,
         joined_key: Vec<Box<Expression>>,
+        join_type: JoinType,
     },
     Requalifier {
         source: Box<Node>,
         alias: String,
     },
+    Unnest {
+        source: Box<Node>,
+        expr: Box<Expression>,
+        name: Identifier,
+    },
+    Sort {
+        source: Box<Node>,
+        keys: Vec<Box<Expression>>,
+        options: Vec<SortOptions>,
+    },
+    Limit {
+        source: Box<Node>,
+        limit: Option<u64>,
+        offset: u64,
+    },
+    TopN {
+        source: Box<Node>,
+        keys: Vec<Box<Expression>>,
+        options: Vec<SortOptions>,
+        limit: u64,
+        offset: u64,
+    },
+    // Without keys, the whole record is the key.
+    Distinct {
+        source: Box<Node>,
+        keys: Vec<Box<Expression>>,
+    },
+    SetOperation {
+        sources: Vec<Box<Node>>,
+        operator: SetOperator,
+        all: bool,
+    },
+    // A reference to a common table expression, whose plan is shared by all references to it.
+    CommonTableExpression {
+        source: Arc<Node>,
+        materialized: bool,
+    },
+    // Adds a column for each window expression.
+    Window {
+        source: Box<Node>,
+        windows: Vec<(Box<Expression>, Identifier)>,
+    },
+    // Arguments have to be constants, named ones have their name.
+    TableFunction {
+        name: Identifier,
+        args: Vec<(Option<String>, Box<Expression>)>,
+    },
+    // The lateral query runs for each record of the source, with access to its fields.
+    LateralJoin {
+        source: Box<Node>,
+        lateral: Box<Node>,
+    },
 }
 
 #[derive(Debug)]
@@ -77,27 +149,113 @@ pub enum Expression {
     Function(Identifier, Vec<Box<Expression>>),
     Wildcard(Option<String>),
     Subquery(Box<Node>),
+    Case {
+        operand: Option<Box<Expression>>,
+        conditions: Vec<Box<Expression>>,
+        results: Vec<Box<Expression>>,
+        else_result: Option<Box<Expression>>,
+    },
+    Cast(Box<Expression>, Type),
+    TryCast(Box<Expression>, Type),
+    FieldAccess(Box<Expression>, String),
+    Subscript(Box<Expression>, Box<Expression>),
+    // Only valid as an expression of a Window node.
+    Window {
+        function: Identifier,
+        args: Vec<Box<Expression>>,
+        partition_by: Vec<Box<Expression>>,
+        order_by: Vec<(Box<Expression>, SortOptions)>,
+        frame: WindowFrame,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Type {
+    Boolean,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    String,
+    Date,
+    Timestamp,
+}
+
+impl Type {
+    pub fn arrow_type(&self) -> DataType {
+        match self {
+            Type::Boolean => DataType::Boolean,
+            Type::Int8 => DataType::Int8,
+            Type::Int16 => DataType::Int16,
+            Type::Int32 => DataType::Int32,
+            Type::Int64 => DataType::Int64,
+            Type::Float32 => DataType::Float32,
+            Type::Float64 => DataType::Float64,
+            Type::String => DataType::Utf8,
+            Type::Date => DataType::Date32(DateUnit::Day),
+            Type::Timestamp => DataType::Timestamp(TimeUnit::Nanosecond, None),
+        }
+    }
+
+    /// The type with the given arrow type, if there's one.
+    pub fn from_arrow(data_type: &DataType) -> Option<Type> {
+        match data_type {
+            DataType::Boolean => Some(Type::Boolean),
+            DataType::Int8 => Some(Type::Int8),
+            DataType::Int16 => Some(Type::Int16),
+            DataType::Int32 => Some(Type::Int32),
+            DataType::Int64 => Some(Type::Int64),
+            DataType::Float32 => Some(Type::Float32),
+            DataType::Float64 => Some(Type::Float64),
+            DataType::Utf8 => Some(Type::String),
+            DataType::Date32(DateUnit::Day) => Some(Type::Date),
+            DataType::Timestamp(TimeUnit::Nanosecond, None) => Some(Type::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Aggregate {
     KeyPart,
     Count,
     Sum,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Trigger {
     Counting(u64),
 }
 
-pub struct MaterializationContext {}
+#[derive(Default)]
+pub struct MaterializationContext {
+    // The physical nodes of materialized common table expressions, by the address of their logical plan.
+    shared: RefCell<HashMap<usize, Arc<dyn physical::Node>>>,
+    // Physical nodes used in place of the ones which would be created out of logical nodes, by their address.
+    substitutes: HashMap<usize, Arc<dyn physical::Node>>,
+}
+
+impl MaterializationContext {
+    /// Makes the physical node stand in for the logical one, which has to stay where it is until it's forgotten.
+    pub fn substitute(&mut self, node: &Node, physical: Arc<dyn physical::Node>) {
+        self.substitutes.insert(node as *const Node as usize, physical);
+    }
+
+    pub fn forget(&mut self, node: &Node) {
+        self.substitutes.remove(&(node as *const Node as usize));
+    }
+}
 
 impl Node {
     pub fn physical(
         &self,
         mat_ctx: &MaterializationContext,
     ) -> Result<Arc<dyn physical::Node>, Error> {
+        if let Some(substitute) = mat_ctx.substitutes.get(&(self as *const Node as usize)) {
+            return Ok(substitute.clone());
+        }
         match self {
             Node::Source { name, alias: _ } => {
                 let path = name.to_string();
@@ -106,7 +264,7 @@ impl Node {
                 } else if path.contains(".csv") {
                     Ok(Arc::new(CSVSource::new(path)))
                 } else {
-                    unimplemented!()
+                    Err(Error::BadInput(format!("unknown source `{}`, only .csv and .json files can be queried", path)))
                 }
             }
             Node::Filter {
@@ -218,6 +376,7 @@ impl Node {
                 source_key,
                 joined,
                 joined_key,
+                join_type,
             } => {
                 let source_key_exprs = source_key
                     .into_iter()
@@ -234,11 +393,179 @@ impl Node {
                     source_key_exprs,
                     joined.physical(mat_ctx)?,
                     joined_key_exprs,
+                    *join_type,
                 )))
             }
             Node::Requalifier { source, alias } => {
                 Ok(Arc::new(Requalifier::new(alias.clone(), source.physical(mat_ctx)?)))
             }
+            Node::Unnest { source, expr, name } => {
+                Ok(Arc::new(Unnest::new(source.physical(mat_ctx)?, expr.physical(mat_ctx)?, name.clone())))
+            }
+            Node::Sort { source, keys, options } => {
+                let keys_physical = keys
+                    .iter()
+                    .map(|expr| expr.physical(mat_ctx))
+                    .collect::<Result<_, _>>()?;
+                Ok(Arc::new(Sort::new(source.physical(mat_ctx)?, keys_physical, options.clone())))
+            }
+            Node::Limit { source, limit, offset } => {
+                Ok(Arc::new(Limit::new(source.physical(mat_ctx)?, limit.map(|limit| limit as usize), *offset as usize)))
+            }
+            Node::TopN { source, keys, options, limit, offset } => {
+                let keys_physical = keys
+                    .iter()
+                    .map(|expr| expr.physical(mat_ctx))
+                    .collect::<Result<_, _>>()?;
+                Ok(Arc::new(TopN::new(source.physical(mat_ctx)?, keys_physical, options.clone(), *limit as usize, *offset as usize)))
+            }
+            Node::Distinct { source, keys } => {
+                let keys_physical = keys
+                    .iter()
+                    .map(|expr| expr.physical(mat_ctx))
+                    .collect::<Result<_, _>>()?;
+                Ok(Arc::new(Distinct::new(source.physical(mat_ctx)?, keys_physical)))
+            }
+            Node::SetOperation { sources, operator, all } => {
+                let sources_physical = sources
+                    .iter()
+                    .map(|source| source.physical(mat_ctx))
+                    .collect::<Result<_, _>>()?;
+                Ok(Arc::new(SetOperation::new(sources_physical, *operator, *all)))
+            }
+            Node::CommonTableExpression { source, materialized, .. } => {
+                if !materialized {
+                    return source.physical(mat_ctx);
+                }
+                let key = Arc::as_ptr(source) as usize;
+                if let Some(shared) = mat_ctx.shared.borrow().get(&key) {
+                    return Ok(shared.clone());
+                }
+                let shared: Arc<dyn physical::Node> = Arc::new(Shared::new(source.physical(mat_ctx)?));
+                mat_ctx.shared.borrow_mut().insert(key, shared.clone());
+                Ok(shared)
+            }
+            Node::Window { source, windows } => {
+                let functions = windows
+                    .iter()
+                    .map(|(expr, _)| expr.window_function(mat_ctx))
+                    .collect::<Result<_, _>>()?;
+                let names = windows.iter().map(|(_, name)| name.clone()).collect();
+                Ok(Arc::new(Window::new(source.physical(mat_ctx)?, functions, names)))
+            }
+            Node::TableFunction { name, args } => {
+                let name = name.to_string().to_lowercase();
+                let mut positional = vec![];
+                let mut named = HashMap::new();
+                for (arg_name, arg) in args {
+                    let value = arg.physical(mat_ctx)?.constant_value()
+                        .ok_or_else(|| Error::Unexpected(format!("arguments of table function {} have to be constants", name)))?;
+                    match arg_name {
+                        None => positional.push(value),
+                        Some(arg_name) => {
+                            named.insert(arg_name.to_lowercase(), value);
+                        }
+                    }
+                }
+                let constructor = TABLE_FUNCTIONS.get(name.as_str())
+                    .ok_or_else(|| Error::Unexpected(format!("unknown table function: {}", name)))?;
+                constructor(&TableFunctionArgs::new(name.as_str(), positional, named))
+                    .map_err(|err| Error::Unexpected(format!("{:?}", err)))
+            }
+            Node::LateralJoin { source, lateral } => {
+                let mut variables = vec![];
+                lateral.collect_variables(&mut variables);
+                Ok(Arc::new(LateralJoin::new(source.physical(mat_ctx)?, lateral.physical(mat_ctx)?, variables, source.produces_retractions())))
+            }
+        }
+    }
+
+    /// Whether the records of the node may contain retractions, which only happens after aggregating.
+    pub fn produces_retractions(&self) -> bool {
+        match self {
+            Node::Source { .. } | Node::TableFunction { .. } => false,
+            Node::GroupBy { .. } | Node::TopN { .. } => true,
+            Node::CommonTableExpression { source, .. } => source.produces_retractions(),
+            // The sort emits its result once, with the retractions applied.
+            Node::Sort { .. } => false,
+            // Like the sort, the window emits its result once.
+            Node::Window { .. } => false,
+            // A newer record for a key retracts the previous one.
+            Node::Distinct { source, keys } => !keys.is_empty() || source.produces_retractions(),
+            Node::Join { source, joined, .. } => source.produces_retractions() || joined.produces_retractions(),
+            Node::LateralJoin { source, lateral } => source.produces_retractions() || lateral.produces_retractions(),
+            // A record of a later source of an EXCEPT retracts the same record of the first one.
+            Node::SetOperation { sources, operator, .. } => {
+                *operator == SetOperator::Except || sources.iter().any(|source| source.produces_retractions())
+            }
+            Node::Filter { source, .. }
+            | Node::Map { source, .. }
+            | Node::Requalifier { source, .. }
+            | Node::Unnest { source, .. }
+            | Node::Limit { source, .. } => source.produces_retractions(),
+        }
+    }
+
+    /// Collects the variables referenced anywhere in the plan, including nested subqueries.
+    pub fn collect_variables(&self, variables: &mut Vec<Identifier>) {
+        match self {
+            Node::Source { .. } => {}
+            Node::Filter { source, filter_expr } => {
+                source.collect_variables(variables);
+                filter_expr.collect_variables(variables);
+            }
+            Node::Map { source, expressions, .. } => {
+                source.collect_variables(variables);
+                for (expr, _) in expressions {
+                    expr.collect_variables(variables);
+                }
+            }
+            Node::GroupBy { source, key_exprs, aggregated_exprs, .. } => {
+                source.collect_variables(variables);
+                for expr in key_exprs.iter().chain(aggregated_exprs.iter()) {
+                    expr.collect_variables(variables);
+                }
+            }
+            Node::Join { source, source_key, joined, joined_key, .. } => {
+                source.collect_variables(variables);
+                joined.collect_variables(variables);
+                for expr in source_key.iter().chain(joined_key.iter()) {
+                    expr.collect_variables(variables);
+                }
+            }
+            Node::Requalifier { source, .. } => source.collect_variables(variables),
+            Node::Unnest { source, expr, .. } => {
+                source.collect_variables(variables);
+                expr.collect_variables(variables);
+            }
+            Node::Sort { source, keys, .. } | Node::TopN { source, keys, .. } | Node::Distinct { source, keys } => {
+                source.collect_variables(variables);
+                for expr in keys {
+                    expr.collect_variables(variables);
+                }
+            }
+            Node::Limit { source, .. } => source.collect_variables(variables),
+            Node::SetOperation { sources, .. } => {
+                for source in sources {
+                    source.collect_variables(variables);
+                }
+            }
+            Node::CommonTableExpression { source, .. } => source.collect_variables(variables),
+            Node::Window { source, windows } => {
+                source.collect_variables(variables);
+                for (expr, _) in windows {
+                    expr.collect_variables(variables);
+                }
+            }
+            Node::TableFunction { args, .. } => {
+                for (_, expr) in args {
+                    expr.collect_variables(variables);
+                }
+            }
+            Node::LateralJoin { source, lateral } => {
+                source.collect_variables(variables);
+                lateral.collect_variables(variables);
+            }
         }
     }
 }
@@ -261,14 +588,105 @@ impl Expression {
                     Identifier::SimpleIdentifier(ident) => {
                         match BUILTIN_FUNCTIONS.get(ident.to_lowercase().as_str()) {
                             None => { Err(Error::Unexpected(format!("unknown function: {}", ident.as_str()))) }
-                            Some(fn_constructor) => Ok(fn_constructor(args_physical)),
+                            Some(function) => Ok(function.call(args_physical)),
                         }
                     }
                     _ => unimplemented!(),
                 }
             }
             Expression::Wildcard(qualifier) => Ok(Arc::new(WildcardExpression::new(qualifier.as_ref().map(|s| s.as_str())))),
-            Expression::Subquery(query) => Ok(Arc::new(expression::Subquery::new(query.physical(mat_ctx)?))),
+            Expression::Subquery(query) => {
+                let mut variables = vec![];
+                query.collect_variables(&mut variables);
+                Ok(Arc::new(expression::Subquery::new(query.physical(mat_ctx)?, variables)))
+            }
+            Expression::Case { operand, conditions, results, else_result } => {
+                let operand_physical = match operand {
+                    None => None,
+                    Some(expr) => Some(expr.physical(mat_ctx)?),
+                };
+                let conditions_physical = conditions
+                    .iter()
+                    .map(|expr| expr.physical(mat_ctx))
+                    .collect::<Result<_, _>>()?;
+                let results_physical = results
+                    .iter()
+                    .map(|expr| expr.physical(mat_ctx))
+                    .collect::<Result<_, _>>()?;
+                let else_result_physical = match else_result {
+                    None => None,
+                    Some(expr) => Some(expr.physical(mat_ctx)?),
+                };
+
+                Ok(Arc::new(Case::new(operand_physical, conditions_physical, results_physical, else_result_physical)))
+            }
+            Expression::Cast(expr, data_type) => {
+                Ok(Arc::new(Cast::new(expr.physical(mat_ctx)?, data_type.arrow_type(), false)))
+            }
+            Expression::TryCast(expr, data_type) => {
+                Ok(Arc::new(Cast::new(expr.physical(mat_ctx)?, data_type.arrow_type(), true)))
+            }
+            Expression::FieldAccess(expr, name) => {
+                Ok(Arc::new(FieldAccess::new(expr.physical(mat_ctx)?, name.clone())))
+            }
+            Expression::Subscript(expr, index) => {
+                Ok(Arc::new(Subscript::new(expr.physical(mat_ctx)?, index.physical(mat_ctx)?)))
+            }
+            Expression::Window { function, .. } => {
+                Err(Error::Unexpected(format!("window function {} is only allowed in the select list", function.to_string())))
+            }
+        }
+    }
+
+    fn window_function(
+        &self,
+        mat_ctx: &MaterializationContext,
+    ) -> Result<WindowFunction, Error> {
+        match self {
+            Expression::Window { function, args, partition_by, order_by, frame } => {
+                let physical_vec = |exprs: &mut dyn Iterator<Item=&Box<Expression>>| exprs
+                    .map(|expr| expr.physical(mat_ctx))
+                    .collect::<Result<Vec<_>, _>>();
+                WindowFunction::new(
+                    function.to_string().as_str(),
+                    physical_vec(&mut args.iter())?,
+                    physical_vec(&mut partition_by.iter())?,
+                    physical_vec(&mut order_by.iter().map(|(expr, _)| expr))?,
+                    order_by.iter().map(|(_, options)| *options).collect(),
+                    *frame,
+                ).map_err(|err| Error::Unexpected(format!("{:?}", err)))
+            }
+            _ => Err(Error::Unexpected("not a window function".to_string())),
+        }
+    }
+
+    pub fn collect_variables(&self, variables: &mut Vec<Identifier>) {
+        match self {
+            Expression::Variable(name) => variables.push(name.clone()),
+            Expression::Constant(_) | Expression::Wildcard(_) => {}
+            Expression::Function(_, args) => {
+                for arg in args {
+                    arg.collect_variables(variables);
+                }
+            }
+            Expression::Subquery(query) => query.collect_variables(variables),
+            Expression::Case { operand, conditions, results, else_result } => {
+                for expr in operand.iter().chain(conditions.iter()).chain(results.iter()).chain(else_result.iter()) {
+                    expr.collect_variables(variables);
+                }
+            }
+            Expression::Cast(expr, _) | Expression::TryCast(expr, _) | Expression::FieldAccess(expr, _) => {
+                expr.collect_variables(variables);
+            }
+            Expression::Subscript(expr, index) => {
+                expr.collect_variables(variables);
+                index.collect_variables(variables);
+            }
+            Expression::Window { args, partition_by, order_by, .. } => {
+                for expr in args.iter().chain(partition_by.iter()).chain(order_by.iter().map(|(expr, _)| expr)) {
+                    expr.collect_variables(variables);
+                }
+            }
         }
     }
 }
@@ -14,3 +14,4 @@
 
 pub mod logical;
 pub mod sql;
+pub mod validation;
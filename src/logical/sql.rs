@@ -12,20 +12,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
 
-use crate::logical::logical::{Aggregate, Expression, Node, Trigger};
+use arrow::compute::kernels::sort::SortOptions;
+
+use crate::logical::logical::{Aggregate, Expression, Node, Trigger, Type};
 use crate::parser;
-use crate::parser::{Operator, SelectExpression, Value};
-use crate::physical::physical::{Identifier, ScalarValue};
+use crate::parser::{Operator, SelectExpression, UnaryOperator, Value};
+use crate::physical::datetime::interval_day_time;
+use crate::physical::physical::{Identifier, INTERNAL_FIELD_PREFIX, ScalarValue};
+use crate::physical::set_operation::SetOperator;
+use crate::physical::stream_join::JoinType;
+use crate::physical::window::{FrameBound, FrameUnits, WindowFrame};
+
+/// The common table expressions in scope, by name, with whether they're materialized.
+#[derive(Clone, Default)]
+pub struct Scope {
+    ctes: HashMap<String, (Arc<Node>, bool)>,
+}
 
-pub fn query_to_logical_plan(query: &parser::Query) -> Box<Node> {
+pub fn query_to_logical_plan(scope: &Scope, query: &parser::Query) -> Box<Node> {
     match query {
-        parser::Query::Select { expressions, filter, from, order_by: _, group_by, trigger } => {
-            if group_by.is_empty() {
-                let mut plan = source_to_logical_plan(from.as_ref());
+        parser::Query::Select { expressions, distinct, distinct_on, filter, from, order_by, group_by, trigger, limit, offset } => {
+            let (source, filter) = from_to_logical_plan(scope, from.as_ref(), filter.as_ref().map(Box::as_ref));
+            let mut plan = if group_by.is_empty() {
+                let mut plan = source;
 
                 let mut variables: BTreeMap<Identifier, Box<Expression>> = BTreeMap::new();
+                // Expressions containing window functions can only be computed after filtering.
+                let mut windows = vec![];
+                let mut window_map_expressions = vec![];
 
                 let mut topmost_map_fields = Vec::with_capacity(expressions.len());
                 let mut topmost_map_wildcards = vec![];
@@ -40,7 +58,32 @@ pub fn query_to_logical_plan(query: &parser::Query) -> Box<Node> {
                                     parser::Identifier::SimpleIdentifier(format!("column_{}", i))
                                 });
                             let ident = identifier_to_logical_plan(&name);
-                            variables.insert(ident.clone(), expression_to_logical_plan(expr.as_ref()));
+                            let decorrelated = match expr.as_ref() {
+                                parser::Expression::Subquery(subquery) => decorrelate_subquery(scope, subquery.as_ref(), format!("{}subquery_{}", INTERNAL_FIELD_PREFIX, i).as_str()),
+                                _ => None,
+                            };
+                            match decorrelated {
+                                Some(subquery) => {
+                                    plan = Box::new(Node::Join {
+                                        source: plan,
+                                        source_key: subquery.outer_key,
+                                        joined: subquery.plan,
+                                        joined_key: subquery.key,
+                                        join_type: JoinType::LeftOuter,
+                                    });
+                                    variables.insert(ident.clone(), subquery.value);
+                                }
+                                None => {
+                                    let mut expr = expression_to_logical_plan(scope, expr.as_ref());
+                                    let window_count = windows.len();
+                                    extract_windows(&mut expr, &mut windows);
+                                    if windows.len() == window_count {
+                                        variables.insert(ident.clone(), expr);
+                                    } else {
+                                        window_map_expressions.push((expr, ident.clone()));
+                                    }
+                                }
+                            }
 
                             topmost_map_fields.push(ident);
                         }
@@ -62,8 +105,32 @@ pub fn query_to_logical_plan(query: &parser::Query) -> Box<Node> {
                     keep_source_fields: true,
                 });
 
-                if let Some(expr) = filter {
-                    plan = Box::new(Node::Filter { source: plan, filter_expr: expression_to_logical_plan(expr.as_ref()) });
+                if let Some(filter_expr) = filter {
+                    plan = Box::new(Node::Filter { source: plan, filter_expr });
+                }
+
+                if !windows.is_empty() {
+                    plan = Box::new(Node::Window { source: plan, windows });
+                    plan = Box::new(Node::Map {
+                        source: plan,
+                        expressions: window_map_expressions,
+                        wildcards: vec![],
+                        keep_source_fields: true,
+                    });
+                }
+
+                if !distinct_on.is_empty() {
+                    plan = Box::new(Node::Distinct {
+                        source: plan,
+                        keys: distinct_on.iter().map(Box::as_ref).map(|expr| expression_to_logical_plan(scope, expr)).collect(),
+                    });
+                }
+
+                // Sorting happens before the topmost map, so that it can also use columns which aren't selected,
+                // unless the selected records have to be distinct first.
+                let distinct_records = *distinct && distinct_on.is_empty();
+                if !order_by.is_empty() && !distinct_records {
+                    plan = order_by_to_logical_plan(scope, plan, order_by, &output_names(expressions), *limit, *offset);
                 }
 
                 let topmost_map_expressions = topmost_map_fields.into_iter()
@@ -77,17 +144,24 @@ pub fn query_to_logical_plan(query: &parser::Query) -> Box<Node> {
                     keep_source_fields: false,
                 });
 
+                if distinct_records {
+                    plan = Box::new(Node::Distinct { source: plan, keys: vec![] });
+                    if !order_by.is_empty() {
+                        plan = order_by_to_logical_plan(scope, plan, order_by, &output_names(expressions), *limit, *offset);
+                    }
+                }
+
                 plan
             } else {
-                let mut plan = source_to_logical_plan(from.as_ref());
+                let mut plan = source;
 
-                if let Some(expr) = filter {
-                    plan = Box::new(Node::Filter { source: plan, filter_expr: expression_to_logical_plan(expr.as_ref()) });
+                if let Some(filter_expr) = filter {
+                    plan = Box::new(Node::Filter { source: plan, filter_expr });
                 }
 
                 let key_exprs = group_by.iter()
                     .map(Box::as_ref)
-                    .map(expression_to_logical_plan)
+                    .map(|expr| expression_to_logical_plan(scope, expr))
                     .collect();
 
                 let (aggregate_exprs, output_fields): (Vec<_>, Vec<_>) = expressions.iter()
@@ -99,7 +173,7 @@ pub fn query_to_logical_plan(query: &parser::Query) -> Box<Node> {
                             unimplemented!()
                         }
                     })
-                    .map(|(expr, ident)| (aggregate_expression_to_logical_plan(expr), ident.as_ref().map(identifier_to_logical_plan)))
+                    .map(|(expr, ident)| (aggregate_expression_to_logical_plan(scope, expr), ident.as_ref().map(identifier_to_logical_plan)))
                     .enumerate()
                     .map(|(i, (expr, ident))| (expr, ident.unwrap_or(Identifier::SimpleIdentifier(format!("column_{}", i)))))
                     .unzip();
@@ -119,14 +193,411 @@ pub fn query_to_logical_plan(query: &parser::Query) -> Box<Node> {
                     trigger: trigger_logical,
                 });
 
+                if *distinct {
+                    plan = Box::new(Node::Distinct {
+                        source: plan,
+                        keys: distinct_on.iter().map(Box::as_ref).map(|expr| expression_to_logical_plan(scope, expr)).collect(),
+                    });
+                }
+
+                if !order_by.is_empty() {
+                    plan = order_by_to_logical_plan(scope, plan, order_by, &output_names(expressions), *limit, *offset);
+                }
+
                 plan
+            };
+
+            let top_n = !order_by.is_empty() && limit.is_some() && plan.produces_retractions();
+            if (limit.is_some() || offset.is_some()) && !top_n {
+                // Without an order, the final result of an aggregation still has to be known before limiting it.
+                if order_by.is_empty() && plan.produces_retractions() {
+                    plan = Box::new(Node::Sort { source: plan, keys: vec![], options: vec![] });
+                }
+                plan = Box::new(Node::Limit { source: plan, limit: *limit, offset: offset.unwrap_or(0) });
+            }
+
+            plan
+        }
+        parser::Query::With { ctes, query } => {
+            let mut scope = scope.clone();
+            for cte in ctes {
+                let plan = query_to_logical_plan(&scope, cte.query.as_ref());
+                scope.ctes.insert(cte.name.clone(), (Arc::from(plan), cte.materialized));
+            }
+            query_to_logical_plan(&scope, query.as_ref())
+        }
+        parser::Query::SetOperation { operator, all, .. } => {
+            let mut sources = vec![];
+            set_operation_sources_to_logical_plan(scope, query, *operator, *all, &mut sources);
+            Box::new(Node::SetOperation {
+                sources,
+                operator: set_operator_to_logical_plan(operator),
+                all: *all,
+            })
+        }
+    }
+}
+
+// Chains of the same set operation are flattened into a single node with all of their sources.
+// Only the left side of an EXCEPT can be flattened, as it isn't associative.
+fn set_operation_sources_to_logical_plan(scope: &Scope, query: &parser::Query, operator: parser::SetOperator, all: bool, sources: &mut Vec<Box<Node>>) {
+    match query {
+        parser::Query::SetOperation { operator: query_operator, all: query_all, left, right } if *query_operator == operator && *query_all == all => {
+            set_operation_sources_to_logical_plan(scope, left.as_ref(), operator, all, sources);
+            if operator == parser::SetOperator::Except {
+                sources.push(query_to_logical_plan(scope, right.as_ref()));
+            } else {
+                set_operation_sources_to_logical_plan(scope, right.as_ref(), operator, all, sources);
+            }
+        }
+        _ => sources.push(query_to_logical_plan(scope, query)),
+    }
+}
+
+fn set_operator_to_logical_plan(operator: &parser::SetOperator) -> SetOperator {
+    match operator {
+        parser::SetOperator::Union => SetOperator::Union,
+        parser::SetOperator::Intersect => SetOperator::Intersect,
+        parser::SetOperator::Except => SetOperator::Except,
+    }
+}
+
+// The names of the columns of a query, which ORDER BY can refer to by position.
+fn output_names(expressions: &[SelectExpression]) -> Vec<Option<Identifier>> {
+    expressions.iter()
+        .enumerate()
+        .map(|(i, select_expr)| match select_expr {
+            SelectExpression::Expression(expr, alias) => Some(alias.as_ref()
+                .map(identifier_to_logical_plan)
+                .unwrap_or_else(|| if let parser::Expression::Variable(ident) = expr.as_ref() {
+                    identifier_to_logical_plan(ident)
+                } else {
+                    Identifier::SimpleIdentifier(format!("column_{}", i))
+                })),
+            SelectExpression::Wildcard(_) => None,
+        })
+        .collect()
+}
+
+// With a limit over records which may be retracted, the top is maintained continuously instead of sorting once at the end.
+fn order_by_to_logical_plan(scope: &Scope, source: Box<Node>, order_by: &[parser::OrderByExpression], select_names: &[Option<Identifier>], limit: Option<u64>, offset: Option<u64>) -> Box<Node> {
+    let keys = order_by.iter()
+        .map(|order_by| match order_by.expr.as_ref() {
+            parser::Expression::Constant(Value::Integer(position)) => {
+                let name = select_names.get((*position as usize).wrapping_sub(1))
+                    .cloned()
+                    .flatten()
+                    .expect("ORDER BY positions are checked by the parser");
+                Box::new(Expression::Variable(name))
+            }
+            expr => expression_to_logical_plan(scope, expr),
+        })
+        .collect();
+    let options = order_by.iter()
+        .map(|order_by| SortOptions { descending: order_by.descending, nulls_first: order_by.nulls_first })
+        .collect();
+    match limit {
+        Some(limit) if source.produces_retractions() => Box::new(Node::TopN { source, keys, options, limit, offset: offset.unwrap_or(0) }),
+        _ => Box::new(Node::Sort { source, keys, options }),
+    }
+}
+
+/// A correlated scalar subquery rewritten into a grouped plan, which gets left outer joined with the outer query.
+struct DecorrelatedSubquery {
+    plan: Box<Node>,
+    key: Vec<Box<Expression>>,
+    outer_key: Vec<Box<Expression>>,
+    value: Box<Expression>,
+}
+
+/// Rewrites `(SELECT agg(x) FROM t alias WHERE alias.a = outer.b AND ...)`, where the aggregate is count or sum,
+/// the correlated conditions are equalities between a column of the subquery and the outer query
+/// and the other conditions only reference the subquery, into a GROUP BY over the subquery's keys.
+/// This way the subquery runs once, instead of once for each row of the outer query.
+/// Returns None for all other subqueries, which are then evaluated for each row.
+fn decorrelate_subquery(scope: &Scope, query: &parser::Query, alias: &str) -> Option<DecorrelatedSubquery> {
+    let (expressions, filter, from, group_by, trigger) = match query {
+        parser::Query::Select { expressions, filter, from, group_by, trigger, limit: None, offset: None, .. } => (expressions, filter, from, group_by, trigger),
+        _ => return None,
+    };
+    let inner_qualifier = match from.as_ref() {
+        parser::Source::Table(_, Some(parser::Identifier::SimpleIdentifier(qualifier))) => qualifier.clone(),
+        _ => return None,
+    };
+    if !group_by.is_empty() || !trigger.is_empty() || expressions.len() != 1 {
+        return None;
+    }
+    let (aggregate, aggregated_expr) = match &expressions[0] {
+        SelectExpression::Expression(expr, _) => match expr.as_ref() {
+            parser::Expression::Function(parser::Identifier::SimpleIdentifier(name), args) if args.len() == 1 => {
+                match name.to_lowercase().as_str() {
+                    "count" => (Aggregate::Count, expression_to_logical_plan(scope, args[0].as_ref())),
+                    "sum" => (Aggregate::Sum, expression_to_logical_plan(scope, args[0].as_ref())),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        },
+        SelectExpression::Wildcard(_) => return None,
+    };
+
+    // Whether all variables of the expression belong to the subquery (Some(true)) or to the outer query (Some(false)).
+    let side = |expr: &Expression| -> Option<bool> {
+        let mut variables = vec![];
+        expr.collect_variables(&mut variables);
+        let mut sides = variables.iter().map(|variable| match variable {
+            Identifier::NamespacedIdentifier(qualifier, _) => Some(qualifier == &inner_qualifier),
+            Identifier::SimpleIdentifier(_) => None,
+        });
+        let first = sides.next()??;
+        if sides.all(|side| side == Some(first)) { Some(first) } else { None }
+    };
+    let is_inner = |expr: &Expression| {
+        let mut variables = vec![];
+        expr.collect_variables(&mut variables);
+        variables.is_empty() || side(expr) == Some(true)
+    };
+    if !is_inner(aggregated_expr.as_ref()) {
+        return None;
+    }
+
+    let mut conjuncts = vec![];
+    if let Some(filter) = filter {
+        split_conjunction(expression_to_logical_plan(scope, filter.as_ref()), &mut conjuncts);
+    }
+    let mut key = vec![];
+    let mut outer_key = vec![];
+    let mut inner_conditions = vec![];
+    for conjunct in conjuncts {
+        if is_inner(conjunct.as_ref()) {
+            inner_conditions.push(conjunct);
+            continue;
+        }
+        match *conjunct {
+            Expression::Function(Identifier::SimpleIdentifier(name), mut args) if name == "=" => {
+                let right = args.pop().unwrap();
+                let left = args.pop().unwrap();
+                let (inner, outer) = match (side(left.as_ref())?, side(right.as_ref())?) {
+                    (true, false) => (left, right),
+                    (false, true) => (right, left),
+                    _ => return None,
+                };
+                // Only columns can be grouped by and then output as the key.
+                match *inner {
+                    Expression::Variable(Identifier::NamespacedIdentifier(_, column)) => {
+                        key.push((Identifier::NamespacedIdentifier(inner_qualifier.clone(), column.clone()), column));
+                    }
+                    _ => return None,
+                }
+                outer_key.push(outer);
             }
+            _ => return None,
         }
     }
+    if key.is_empty() {
+        return None;
+    }
+
+    let mut plan = source_to_logical_plan(scope, from.as_ref());
+    if let Some(condition) = inner_conditions.into_iter().fold(None, |acc: Option<Box<Expression>>, condition| Some(match acc {
+        None => condition,
+        Some(acc) => Box::new(Expression::Function(operator_to_logical_plan(&Operator::AND), vec![acc, condition])),
+    })) {
+        plan = Box::new(Node::Filter { source: plan, filter_expr: condition });
+    }
+
+    let is_count = match aggregate {
+        Aggregate::Count => true,
+        _ => false,
+    };
+    let mut aggregates = vec![];
+    let mut aggregated_exprs = vec![];
+    let mut output_fields = vec![];
+    for (variable, column) in &key {
+        aggregates.push(Aggregate::KeyPart);
+        aggregated_exprs.push(Box::new(Expression::Variable(variable.clone())));
+        output_fields.push(Identifier::SimpleIdentifier(column.clone()));
+    }
+    aggregates.push(aggregate);
+    aggregated_exprs.push(aggregated_expr);
+    output_fields.push(Identifier::SimpleIdentifier("value".to_string()));
+    plan = Box::new(Node::GroupBy {
+        source: plan,
+        key_exprs: key.iter().map(|(variable, _)| Box::new(Expression::Variable(variable.clone()))).collect(),
+        aggregates,
+        aggregated_exprs,
+        output_fields,
+        trigger: vec![],
+    });
+    plan = Box::new(Node::Requalifier { source: plan, alias: alias.to_string() });
+
+    // Without any matching row, the count is 0 instead of null.
+    let mut value = Box::new(Expression::Variable(Identifier::NamespacedIdentifier(alias.to_string(), "value".to_string())));
+    if is_count {
+        value = Box::new(Expression::Function(Identifier::SimpleIdentifier("coalesce".to_string()), vec![value, Box::new(Expression::Constant(ScalarValue::Int64(0)))]));
+    }
+
+    Some(DecorrelatedSubquery {
+        plan,
+        key: key.into_iter()
+            .map(|(_, column)| Box::new(Expression::Variable(Identifier::NamespacedIdentifier(alias.to_string(), column))))
+            .collect(),
+        outer_key,
+        value,
+    })
 }
 
-pub fn source_to_logical_plan(expr: &parser::Source) -> Box<Node> {
+fn split_conjunction(expr: Box<Expression>, conjuncts: &mut Vec<Box<Expression>>) {
+    match *expr {
+        Expression::Function(Identifier::SimpleIdentifier(name), args) if name == "AND" => {
+            for arg in args {
+                split_conjunction(arg, conjuncts);
+            }
+        }
+        other => conjuncts.push(Box::new(other)),
+    }
+}
+
+/// Plans the FROM clause together with the WHERE clause, as equalities between joined sources become join keys.
+/// Returns the remaining filter.
+fn from_to_logical_plan(scope: &Scope, from: &parser::Source, filter: Option<&parser::Expression>) -> (Box<Node>, Option<Box<Expression>>) {
+    let mut conjuncts = vec![];
+    if let Some(filter) = filter {
+        split_conjunction(expression_to_logical_plan(scope, filter), &mut conjuncts);
+    }
+    let plan = match from {
+        parser::Source::Join(left, right) => join_to_logical_plan(scope, left.as_ref(), right.as_ref(), &mut conjuncts).0,
+        _ => source_to_logical_plan(scope, from),
+    };
+    let filter = conjuncts.into_iter().fold(None, |acc: Option<Box<Expression>>, condition| Some(match acc {
+        None => condition,
+        Some(acc) => Box::new(Expression::Function(operator_to_logical_plan(&Operator::AND), vec![acc, condition])),
+    }));
+    (plan, filter)
+}
+
+// Each equality of the conjuncts between an expression over the left sources and one over the right source
+// is removed and becomes part of the join key. Without any, the join has an empty key, so all records
+// end up with the same one and every pair of records is joined, which is the cross product.
+// A lateral right source is run for each record of the left one instead, so it has no key.
+// Returns the plan with the qualifiers of all joined sources.
+fn join_to_logical_plan(scope: &Scope, left: &parser::Source, right: &parser::Source, conjuncts: &mut Vec<Box<Expression>>) -> (Box<Node>, Vec<String>) {
+    let plan_side = |source: &parser::Source, conjuncts: &mut Vec<Box<Expression>>| match source {
+        parser::Source::Join(left, right) => join_to_logical_plan(scope, left.as_ref(), right.as_ref(), conjuncts),
+        _ => match implicit_qualifier(scope, source) {
+            Some(qualifier) => {
+                let plan = Box::new(Node::Requalifier { source: source_to_logical_plan(scope, source), alias: qualifier.clone() });
+                (plan, vec![qualifier])
+            }
+            None => (source_to_logical_plan(scope, source), source_qualifiers(scope, source)),
+        },
+    };
+    let (source, mut qualifiers) = plan_side(left, conjuncts);
+    if let parser::Source::Lateral(subquery, alias) = right {
+        let mut lateral = query_to_logical_plan(scope, subquery.as_ref());
+        if let Some(parser::Identifier::SimpleIdentifier(alias)) = alias {
+            lateral = Box::new(Node::Requalifier { source: lateral, alias: alias.clone() });
+        }
+        qualifiers.extend(source_qualifiers(scope, right));
+        return (Box::new(Node::LateralJoin { source, lateral }), qualifiers);
+    }
+    let (joined, joined_qualifiers) = plan_side(right, conjuncts);
+
+    // Whether all variables of the expression are qualified with one of the qualifiers.
+    let belongs_to = |expr: &Expression, qualifiers: &[String]| {
+        let mut variables = vec![];
+        expr.collect_variables(&mut variables);
+        !variables.is_empty() && variables.iter().all(|variable| match variable {
+            Identifier::NamespacedIdentifier(qualifier, _) => qualifiers.contains(qualifier),
+            Identifier::SimpleIdentifier(_) => false,
+        })
+    };
+
+    let mut source_key = vec![];
+    let mut joined_key = vec![];
+    let mut remaining = vec![];
+    for conjunct in conjuncts.drain(..) {
+        match *conjunct {
+            Expression::Function(Identifier::SimpleIdentifier(name), mut args) if name == "=" => {
+                let right = args.pop().unwrap();
+                let left = args.pop().unwrap();
+                if belongs_to(left.as_ref(), &qualifiers) && belongs_to(right.as_ref(), &joined_qualifiers) {
+                    source_key.push(left);
+                    joined_key.push(right);
+                } else if belongs_to(right.as_ref(), &qualifiers) && belongs_to(left.as_ref(), &joined_qualifiers) {
+                    source_key.push(right);
+                    joined_key.push(left);
+                } else {
+                    remaining.push(Box::new(Expression::Function(Identifier::SimpleIdentifier(name), vec![left, right])));
+                }
+            }
+            other => remaining.push(Box::new(other)),
+        }
+    }
+    *conjuncts = remaining;
+
+    qualifiers.extend(joined_qualifiers);
+    let plan = Box::new(Node::Join {
+        source,
+        source_key,
+        joined,
+        joined_key,
+        join_type: JoinType::Inner,
+    });
+    (plan, qualifiers)
+}
+
+// Files without an alias are qualified with their name without the extension when joined,
+// so that join conditions can tell their columns apart, like a.x = b.y in FROM a.csv, b.csv.
+fn implicit_qualifier(scope: &Scope, source: &parser::Source) -> Option<String> {
+    match source {
+        parser::Source::Table(parser::Identifier::SimpleIdentifier(name), None) if scope.ctes.contains_key(name) => None,
+        parser::Source::Table(name, None) => {
+            let path = identifier_to_logical_plan(name).to_string();
+            Path::new(path.as_str()).file_stem().map(|stem| stem.to_string_lossy().to_string())
+        }
+        _ => None,
+    }
+}
+
+// The qualifiers of the columns of the source, sources without an alias have unqualified columns.
+fn source_qualifiers(scope: &Scope, source: &parser::Source) -> Vec<String> {
+    match source {
+        parser::Source::Table(parser::Identifier::SimpleIdentifier(name), None) if scope.ctes.contains_key(name) => vec![name.clone()],
+        parser::Source::Table(_, Some(parser::Identifier::SimpleIdentifier(alias)))
+        | parser::Source::Subquery(_, Some(parser::Identifier::SimpleIdentifier(alias)))
+        | parser::Source::Lateral(_, Some(parser::Identifier::SimpleIdentifier(alias)))
+        | parser::Source::TableFunction(_, _, Some(parser::Identifier::SimpleIdentifier(alias))) => vec![alias.clone()],
+        parser::Source::Table(..) | parser::Source::Subquery(..) | parser::Source::Lateral(..) | parser::Source::TableFunction(..) => vec![],
+        parser::Source::Unnest(source, _, name) => {
+            let mut qualifiers = source_qualifiers(scope, source.as_ref());
+            if let parser::Identifier::NamespacedIdentifier(qualifier, _) = name {
+                qualifiers.push(qualifier.clone());
+            }
+            qualifiers
+        }
+        parser::Source::Join(left, right) => {
+            let mut qualifiers = source_qualifiers(scope, left.as_ref());
+            qualifiers.extend(source_qualifiers(scope, right.as_ref()));
+            qualifiers
+        }
+    }
+}
+
+pub fn source_to_logical_plan(scope: &Scope, expr: &parser::Source) -> Box<Node> {
     match expr {
+        parser::Source::Table(parser::Identifier::SimpleIdentifier(name), alias) if scope.ctes.contains_key(name) => {
+            let (source, materialized) = scope.ctes[name].clone();
+            // Without an alias, the columns are qualified with the name of the common table expression.
+            let qualifier = match alias {
+                Some(parser::Identifier::SimpleIdentifier(alias)) => alias.clone(),
+                _ => name.clone(),
+            };
+            Box::new(Node::Requalifier {
+                source: Box::new(Node::CommonTableExpression { source, materialized }),
+                alias: qualifier,
+            })
+        }
         parser::Source::Table(ident, alias) => {
             let mut plan = Box::new(Node::Source { name: identifier_to_logical_plan(&ident), alias: alias.clone().map(|ident| identifier_to_logical_plan(&ident)) });
             if let Some(parser::Identifier::SimpleIdentifier(ident)) = alias {
@@ -134,17 +605,36 @@ pub fn source_to_logical_plan(expr: &parser::Source) -> Box<Node> {
             }
             plan
         }
-        parser::Source::Subquery(subquery, alias) => {
-            let mut plan = query_to_logical_plan(&subquery);
+        // Without preceding sources, a lateral subquery is just a subquery.
+        parser::Source::Subquery(subquery, alias) | parser::Source::Lateral(subquery, alias) => {
+            let mut plan = query_to_logical_plan(scope, &subquery);
             if let Some(parser::Identifier::SimpleIdentifier(ident)) = alias {
                 plan = Box::new(Node::Requalifier { source: plan, alias: ident.clone() })
             }
             plan
         }
+        parser::Source::Unnest(source, expr, name) => {
+            Box::new(Node::Unnest {
+                source: source_to_logical_plan(scope, source.as_ref()),
+                expr: expression_to_logical_plan(scope, expr.as_ref()),
+                name: identifier_to_logical_plan(name),
+            })
+        }
+        parser::Source::Join(left, right) => join_to_logical_plan(scope, left.as_ref(), right.as_ref(), &mut vec![]).0,
+        parser::Source::TableFunction(name, args, alias) => {
+            let mut plan = Box::new(Node::TableFunction {
+                name: identifier_to_logical_plan(name),
+                args: args.iter().map(|(name, arg)| (name.clone(), expression_to_logical_plan(scope, arg.as_ref()))).collect(),
+            });
+            if let Some(parser::Identifier::SimpleIdentifier(alias)) = alias {
+                plan = Box::new(Node::Requalifier { source: plan, alias: alias.clone() })
+            }
+            plan
+        }
     }
 }
 
-pub fn expression_to_logical_plan(expr: &parser::Expression) -> Box<Expression> {
+pub fn expression_to_logical_plan(scope: &Scope, expr: &parser::Expression) -> Box<Expression> {
     match expr {
         parser::Expression::Variable(ident) => {
             Box::new(Expression::Variable(identifier_to_logical_plan(&ident)))
@@ -153,26 +643,146 @@ pub fn expression_to_logical_plan(expr: &parser::Expression) -> Box<Expression>
             Box::new(Expression::Constant(value_to_logical_plan(&value)))
         }
         parser::Expression::Function(name, args) => {
-            Box::new(Expression::Function(identifier_to_logical_plan(name), args.iter().map(Box::as_ref).map(expression_to_logical_plan).collect()))
+            Box::new(Expression::Function(identifier_to_logical_plan(name), args.iter().map(Box::as_ref).map(|expr| expression_to_logical_plan(scope, expr)).collect()))
         }
         parser::Expression::Operator(left, op, right) => {
-            Box::new(Expression::Function(operator_to_logical_plan(op), vec![expression_to_logical_plan(left.as_ref()), expression_to_logical_plan(right.as_ref())]))
+            Box::new(Expression::Function(operator_to_logical_plan(op), vec![expression_to_logical_plan(scope, left.as_ref()), expression_to_logical_plan(scope, right.as_ref())]))
+        }
+        parser::Expression::UnaryOperator(op, expr) => {
+            Box::new(Expression::Function(unary_operator_to_logical_plan(op), vec![expression_to_logical_plan(scope, expr.as_ref())]))
         }
         parser::Expression::Wildcard(qualifier) => {
             Box::new(Expression::Wildcard(qualifier.clone()))
         }
         parser::Expression::Subquery(query) => {
-            Box::new(Expression::Subquery(query_to_logical_plan(query.as_ref())))
+            Box::new(Expression::Subquery(query_to_logical_plan(scope, query.as_ref())))
+        }
+        parser::Expression::Case { operand, conditions, results, else_result } => {
+            Box::new(Expression::Case {
+                operand: operand.as_ref().map(|expr| expression_to_logical_plan(scope, expr.as_ref())),
+                conditions: conditions.iter().map(Box::as_ref).map(|expr| expression_to_logical_plan(scope, expr)).collect(),
+                results: results.iter().map(Box::as_ref).map(|expr| expression_to_logical_plan(scope, expr)).collect(),
+                else_result: else_result.as_ref().map(|expr| expression_to_logical_plan(scope, expr.as_ref())),
+            })
+        }
+        parser::Expression::InList { expr, list, negated } => {
+            let mut args = vec![expression_to_logical_plan(scope, expr.as_ref())];
+            args.extend(list.iter().map(Box::as_ref).map(|expr| expression_to_logical_plan(scope, expr)));
+            negate_if(*negated, Box::new(Expression::Function(Identifier::SimpleIdentifier("IN".to_string()), args)))
+        }
+        parser::Expression::Between { expr, negated, low, high } => {
+            let args = vec![expression_to_logical_plan(scope, expr.as_ref()), expression_to_logical_plan(scope, low.as_ref()), expression_to_logical_plan(scope, high.as_ref())];
+            negate_if(*negated, Box::new(Expression::Function(Identifier::SimpleIdentifier("BETWEEN".to_string()), args)))
+        }
+        parser::Expression::Cast(expr, data_type) => {
+            Box::new(Expression::Cast(expression_to_logical_plan(scope, expr.as_ref()), type_to_logical_plan(data_type)))
+        }
+        parser::Expression::TryCast(expr, data_type) => {
+            Box::new(Expression::TryCast(expression_to_logical_plan(scope, expr.as_ref()), type_to_logical_plan(data_type)))
+        }
+        parser::Expression::FieldAccess(expr, name) => {
+            Box::new(Expression::FieldAccess(expression_to_logical_plan(scope, expr.as_ref()), name.clone()))
+        }
+        parser::Expression::Subscript(expr, index) => {
+            Box::new(Expression::Subscript(expression_to_logical_plan(scope, expr.as_ref()), expression_to_logical_plan(scope, index.as_ref())))
+        }
+        parser::Expression::Parameter(name) => {
+            Box::new(Expression::Variable(parameter_identifier(name)))
+        }
+        parser::Expression::Window { function, args, partition_by, order_by, frame } => {
+            Box::new(Expression::Window {
+                function: identifier_to_logical_plan(function),
+                // count(*) counts all rows of the frame.
+                args: args.iter()
+                    .filter(|arg| !matches!(arg.as_ref(), parser::Expression::Wildcard(None)))
+                    .map(|arg| expression_to_logical_plan(scope, arg.as_ref()))
+                    .collect(),
+                partition_by: partition_by.iter().map(Box::as_ref).map(|expr| expression_to_logical_plan(scope, expr)).collect(),
+                order_by: order_by.iter()
+                    .map(|order_by| (
+                        expression_to_logical_plan(scope, order_by.expr.as_ref()),
+                        SortOptions { descending: order_by.descending, nulls_first: order_by.nulls_first },
+                    ))
+                    .collect(),
+                frame: frame.as_ref()
+                    .map(window_frame_to_logical_plan)
+                    .unwrap_or_else(|| WindowFrame::default_for(!order_by.is_empty())),
+            })
         }
     }
 }
 
+/// Parameters are variables of the root variable context, with a prefix so that they never clash with columns.
+pub fn parameter_identifier(name: &str) -> Identifier {
+    Identifier::SimpleIdentifier(format!("{}parameter_{}", INTERNAL_FIELD_PREFIX, name))
+}
+
+/// The name of the parameter, if the identifier is one.
+pub fn parameter_name(ident: &Identifier) -> Option<&str> {
+    match ident {
+        Identifier::SimpleIdentifier(name) => name.strip_prefix(INTERNAL_FIELD_PREFIX)?.strip_prefix("parameter_"),
+        Identifier::NamespacedIdentifier(..) => None,
+    }
+}
+
+// Replaces the window functions in the expression with variables referencing their results, which a Window node computes.
+// Subqueries have their own select lists, so they aren't searched.
+fn extract_windows(expr: &mut Box<Expression>, windows: &mut Vec<(Box<Expression>, Identifier)>) {
+    match expr.as_mut() {
+        Expression::Window { .. } => {
+            let name = Identifier::SimpleIdentifier(format!("{}window_{}", INTERNAL_FIELD_PREFIX, windows.len()));
+            let window = std::mem::replace(expr, Box::new(Expression::Variable(name.clone())));
+            windows.push((window, name));
+        }
+        Expression::Variable(_) | Expression::Constant(_) | Expression::Wildcard(_) | Expression::Subquery(_) => {}
+        Expression::Function(_, args) => {
+            for arg in args {
+                extract_windows(arg, windows);
+            }
+        }
+        Expression::Case { operand, conditions, results, else_result } => {
+            for expr in operand.iter_mut().chain(conditions.iter_mut()).chain(results.iter_mut()).chain(else_result.iter_mut()) {
+                extract_windows(expr, windows);
+            }
+        }
+        Expression::Cast(expr, _) | Expression::TryCast(expr, _) | Expression::FieldAccess(expr, _) => extract_windows(expr, windows),
+        Expression::Subscript(expr, index) => {
+            extract_windows(expr, windows);
+            extract_windows(index, windows);
+        }
+    }
+}
+
+pub fn window_frame_to_logical_plan(frame: &parser::WindowFrame) -> WindowFrame {
+    let bound = |bound: &parser::WindowFrameBound| match bound {
+        parser::WindowFrameBound::Preceding(n) => FrameBound::Preceding(*n),
+        parser::WindowFrameBound::CurrentRow => FrameBound::CurrentRow,
+        parser::WindowFrameBound::Following(n) => FrameBound::Following(*n),
+    };
+    WindowFrame {
+        units: match frame.units {
+            parser::WindowFrameUnits::Rows => FrameUnits::Rows,
+            parser::WindowFrameUnits::Range => FrameUnits::Range,
+        },
+        start: bound(&frame.start),
+        end: bound(&frame.end),
+    }
+}
+
+fn negate_if(negated: bool, expr: Box<Expression>) -> Box<Expression> {
+    if negated {
+        Box::new(Expression::Function(unary_operator_to_logical_plan(&UnaryOperator::NOT), vec![expr]))
+    } else {
+        expr
+    }
+}
+
 // TODO: Maybe it should be Aggregate(Expr), this way the aggregate receives the record batch and calculates everything itself.
 // Would be easier for stars and stuff I suppose.
 // Think about it.
 // The Cons is that each aggregate will have to define evaluating the underlying expression, which might be meh.
 // Especially since star and star distinct can operate on some kind of tuple... maybe?
-pub fn aggregate_expression_to_logical_plan(expr: &parser::Expression) -> (Aggregate, Box<Expression>) {
+pub fn aggregate_expression_to_logical_plan(scope: &Scope, expr: &parser::Expression) -> (Aggregate, Box<Expression>) {
     match expr {
         parser::Expression::Variable(ident) => {
             (Aggregate::KeyPart, Box::new(Expression::Variable(identifier_to_logical_plan(&ident))))
@@ -182,10 +792,10 @@ pub fn aggregate_expression_to_logical_plan(expr: &parser::Expression) -> (Aggre
                 parser::Identifier::SimpleIdentifier(name) => {
                     match name.to_lowercase().as_str() {
                         "count" => {
-                            (Aggregate::Count, expression_to_logical_plan(args[0].as_ref()))
+                            (Aggregate::Count, expression_to_logical_plan(scope, args[0].as_ref()))
                         }
                         "sum" => {
-                            (Aggregate::Sum, expression_to_logical_plan(args[0].as_ref()))
+                            (Aggregate::Sum, expression_to_logical_plan(scope, args[0].as_ref()))
                         }
                         _ => unimplemented!(),
                     }
@@ -193,10 +803,8 @@ pub fn aggregate_expression_to_logical_plan(expr: &parser::Expression) -> (Aggre
                 _ => unimplemented!(),
             }
         }
-        _ => {
-            dbg!(expr);
-            panic!("invalid aggregate expression")
-        }
+        // Anything else has to be a key, which validation checks.
+        _ => (Aggregate::KeyPart, expression_to_logical_plan(scope, expr)),
     }
 }
 
@@ -222,7 +830,28 @@ pub fn value_to_logical_plan(val: &parser::Value) -> ScalarValue {
         parser::Value::Integer(v) => {
             ScalarValue::Int64(v.clone())
         }
+        Value::Float(v) => {ScalarValue::Float64(v.parse().unwrap())}
         Value::String(v) => {ScalarValue::Utf8(v.clone())}
+        Value::Boolean(v) => {ScalarValue::Boolean(*v)}
+        Value::IntervalYearMonth(v) => {ScalarValue::IntervalYearMonth(*v)}
+        // The parser only accepts intervals whose days fit, so packing them can't fail.
+        Value::IntervalDayTime(v) => {ScalarValue::IntervalDayTime(interval_day_time(*v).expect("interval out of range"))}
+        Value::Null => {ScalarValue::Null}
+    }
+}
+
+pub fn type_to_logical_plan(data_type: &parser::Type) -> Type {
+    match data_type {
+        parser::Type::Boolean => Type::Boolean,
+        parser::Type::Int8 => Type::Int8,
+        parser::Type::Int16 => Type::Int16,
+        parser::Type::Int32 => Type::Int32,
+        parser::Type::Int64 => Type::Int64,
+        parser::Type::Float32 => Type::Float32,
+        parser::Type::Float64 => Type::Float64,
+        parser::Type::String => Type::String,
+        parser::Type::Date => Type::Date,
+        parser::Type::Timestamp => Type::Timestamp,
     }
 }
 
@@ -231,11 +860,79 @@ pub fn operator_to_logical_plan(op: &parser::Operator) -> Identifier {
         Operator::Lt => "<".to_string(),
         Operator::LtEq => "<=".to_string(),
         Operator::Eq => "=".to_string(),
+        Operator::NotEq => "<>".to_string(),
         Operator::GtEq => ">=".to_string(),
         Operator::Gt => ">".to_string(),
         Operator::Plus => "+".to_string(),
         Operator::Minus => "-".to_string(),
+        Operator::Multiply => "*".to_string(),
+        Operator::Divide => "/".to_string(),
+        Operator::Modulo => "%".to_string(),
         Operator::AND => "AND".to_string(),
         Operator::OR => "OR".to_string(),
+        Operator::Like => "LIKE".to_string(),
+        Operator::NotLike => "NOT LIKE".to_string(),
+        Operator::ILike => "ILIKE".to_string(),
+        Operator::NotILike => "NOT ILIKE".to_string(),
+        Operator::RegexpMatches => "REGEXP_MATCHES".to_string(),
+        Operator::Concat => "||".to_string(),
+        Operator::AtTimeZone => "AT TIME ZONE".to_string(),
+    })
+}
+
+pub fn unary_operator_to_logical_plan(op: &parser::UnaryOperator) -> Identifier {
+    Identifier::SimpleIdentifier(match op {
+        UnaryOperator::NOT => "NOT".to_string(),
+        UnaryOperator::IsNull => "IS NULL".to_string(),
+        UnaryOperator::IsNotNull => "IS NOT NULL".to_string(),
+        UnaryOperator::Minus => "NEGATE".to_string(),
     })
 }
+
+#[test]
+fn test_decorrelate_subquery() {
+    use crate::parser::parser::parse_sql;
+    use crate::physical::testing::{net, query};
+
+    let uses_join = |sql: &str| {
+        let plan = query_to_logical_plan(&Scope::default(), parse_sql(sql).unwrap().as_ref());
+        format!("{:?}", plan).contains("LeftOuter")
+    };
+    assert!(uses_join("SELECT t.id, (SELECT count(*) FROM goals.csv g WHERE g.team = t.id AND g.minute > 10) AS goals FROM teams.csv t"));
+    assert!(uses_join("SELECT t.id, (SELECT sum(g.points) FROM goals.csv g WHERE t.id = g.team) AS points FROM teams.csv t"));
+    // Not an aggregate, or not an equality, so it's evaluated for each row.
+    assert!(!uses_join("SELECT t.id, (SELECT g.minute FROM goals.csv g WHERE g.team = t.id) AS minute FROM teams.csv t"));
+    assert!(!uses_join("SELECT t.id, (SELECT count(*) FROM goals.csv g WHERE g.team > t.id) AS goals FROM teams.csv t"));
+
+    // A team without matching goals counts none, while its sum stays NULL.
+    let text = |value: &str| ScalarValue::Utf8(value.to_string());
+    let rows = query("SELECT t.country, (SELECT count(*) FROM goals.csv g WHERE g.team = t.id AND g.team < 1) AS goals FROM teams.csv t WHERE t.id < 2 ORDER BY 1").unwrap();
+    assert_eq!(net(rows), vec![vec![text("Andshall"), ScalarValue::Int64(7586)], vec![text("Thernbel"), ScalarValue::Int64(0)]]);
+    let rows = query("SELECT t.country, (SELECT sum(g.team) FROM goals.csv g WHERE t.id = g.team AND g.team > 0) AS points FROM teams.csv t WHERE t.id < 2 ORDER BY 1").unwrap();
+    assert_eq!(net(rows), vec![vec![text("Andshall"), ScalarValue::Null], vec![text("Thernbel"), ScalarValue::Int64(7444)]]);
+}
+
+#[test]
+fn test_join_keys() {
+    use crate::parser::parser::parse_sql;
+    use crate::physical::testing::{net, query};
+
+    let has_filter = |sql: &str| {
+        let plan = query_to_logical_plan(&Scope::default(), parse_sql(sql).unwrap().as_ref());
+        format!("{:?}", plan).contains("Filter")
+    };
+    assert!(!has_filter("SELECT c.name FROM cats.csv c, goals.csv g WHERE c.id = g.team"));
+    assert!(!has_filter("SELECT cats.name FROM cats.csv, goals.csv WHERE goals.team = cats.id"));
+    assert!(!has_filter("SELECT c.name FROM cats.csv c CROSS JOIN goals.csv"));
+    // Conditions which aren't equalities between the sources are left in the filter.
+    assert!(has_filter("SELECT cats.name FROM cats.csv, goals.csv WHERE cats.id < goals.team"));
+    assert!(has_filter("SELECT cats.name FROM cats.csv, goals.csv WHERE cats.id = cats.age"));
+
+    // Both aliased and unaliased sources are joined on their keys.
+    let text = |value: &str| ScalarValue::Utf8(value.to_string());
+    let expected = vec![vec![text("Andshall"), ScalarValue::Int64(7586)], vec![text("Thernbel"), ScalarValue::Int64(7444)]];
+    let rows = query("SELECT t.country, count(g.team) AS goals FROM teams.csv t, goals.csv g WHERE g.team = t.id AND t.id < 2 GROUP BY t.country ORDER BY 1").unwrap();
+    assert_eq!(net(rows), expected);
+    let rows = query("SELECT teams.country, count(goals.team) AS goals FROM teams.csv, goals.csv WHERE goals.team = teams.id AND teams.id < 2 GROUP BY teams.country ORDER BY 1").unwrap();
+    assert_eq!(net(rows), expected);
+}
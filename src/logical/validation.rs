@@ -0,0 +1,609 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Schema};
+
+use crate::logical::logical::{Aggregate, Error, Expression, MaterializationContext, Node, Type};
+use crate::physical::arrow::{common_supertype, get_scalar_value, is_numeric, scalar_to_array};
+use crate::physical::cast::{can_cast, cast_array};
+use crate::physical::functions::BUILTIN_FUNCTIONS;
+use crate::physical::physical;
+use crate::physical::physical::{ExecutionContext, Identifier, INTERNAL_FIELD_PREFIX, MetaSendFn, ProduceFn, RETRACTIONS_FIELD, ScalarValue, SchemaContext, SchemaContextWithSchema, VariableContext};
+use crate::physical::table_functions::TABLE_FUNCTIONS;
+
+/// Checks a logical plan against the schemas of its sources before it gets turned into a physical one.
+/// Every variable has to resolve to a field of the node's source or of an enclosing query, where unqualified names
+/// get qualified if that's unambiguous, and operands have to have compatible types.
+/// Returns the plan with the implicit conversions of comparisons and join keys made explicit.
+pub fn validate(plan: &Node, variable_context: Arc<VariableContext>) -> Result<Box<Node>, Error> {
+    let mut schemas = vec![];
+    let mut context = Some(variable_context.clone());
+    while let Some(current) = context {
+        schemas.insert(0, current.schema.clone());
+        context = current.previous.clone();
+    }
+    let scope = Scope {
+        context: variable_context,
+        schemas,
+    };
+
+    let mut validator = Validator { ctes: HashMap::new(), known_schemas: MaterializationContext::default() };
+    Ok(validator.node(plan, &scope)?.0)
+}
+
+// The schemas of the enclosing queries, innermost last, whose fields can be referenced as variables.
+struct Scope {
+    context: Arc<dyn SchemaContext>,
+    schemas: Vec<Arc<Schema>>,
+}
+
+impl Scope {
+    fn with(&self, schema: &Arc<Schema>) -> Scope {
+        let mut schemas = self.schemas.clone();
+        schemas.push(schema.clone());
+        Scope {
+            context: Arc::new(SchemaContextWithSchema {
+                previous: self.context.clone(),
+                schema: schema.clone(),
+            }),
+            schemas,
+        }
+    }
+}
+
+struct Validator {
+    // Validated common table expressions, by the address of their logical plan, so that all references keep sharing one.
+    ctes: HashMap<usize, Arc<Node>>,
+    // Stand-ins for the validated nodes, which report their schemas.
+    known_schemas: MaterializationContext,
+}
+
+// A physical node which only knows the schema of a validated logical one, it's never run.
+struct KnownSchema(Arc<Schema>);
+
+impl physical::Node for KnownSchema {
+    fn schema(&self, _schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, physical::Error> {
+        Ok(self.0.clone())
+    }
+
+    fn run(&self, _ctx: &ExecutionContext, _produce: ProduceFn, _meta_send: MetaSendFn) -> Result<(), physical::Error> {
+        Err(physical::Error::Unexpected)
+    }
+}
+
+impl Validator {
+    // Returns the validated node with its schema.
+    fn node(&mut self, node: &Node, scope: &Scope) -> Result<(Box<Node>, Arc<Schema>), Error> {
+        let node = match node {
+            Node::Source { name, alias } => Node::Source { name: name.clone(), alias: alias.clone() },
+            Node::Filter { source, filter_expr } => {
+                let (source, schema) = self.node(source, scope)?;
+                let (filter_expr, data_type) = self.expression(filter_expr, &schema, scope)?;
+                expect_boolean(&data_type, || format!("the condition {} has to be a boolean", describe(&filter_expr)))?;
+                Node::Filter { source, filter_expr }
+            }
+            Node::Map { source, expressions, wildcards, keep_source_fields } => {
+                let (source, schema) = self.node(source, scope)?;
+                let expressions = expressions.iter()
+                    .map(|(expr, name)| Ok((self.expression(expr, &schema, scope)?.0, name.clone())))
+                    .collect::<Result<_, Error>>()?;
+                for qualifier in wildcards.iter().flatten() {
+                    check_qualifier(qualifier, &schema)?;
+                }
+                Node::Map { source, expressions, wildcards: wildcards.clone(), keep_source_fields: *keep_source_fields }
+            }
+            Node::GroupBy { source, key_exprs, aggregates, aggregated_exprs, output_fields, trigger } => {
+                let (source, schema) = self.node(source, scope)?;
+                // Selected expressions which aren't aggregates have to be keys, windows can't be computed over groups.
+                for (aggregate, expr) in aggregates.iter().zip(aggregated_exprs.iter()) {
+                    match (aggregate, expr.as_ref()) {
+                        (_, Expression::Window { function, .. }) => {
+                            return Err(Error::BadInput(format!("window function {} can't be used in a query with GROUP BY", function.to_string())));
+                        }
+                        (Aggregate::KeyPart, Expression::Variable(_)) => {}
+                        (Aggregate::KeyPart, expr) => {
+                            return Err(Error::BadInput(format!("{} has to be a GROUP BY key or an aggregate", describe(expr))));
+                        }
+                        _ => {}
+                    }
+                }
+                Node::GroupBy {
+                    source,
+                    key_exprs: self.expressions(key_exprs, &schema, scope)?,
+                    aggregates: aggregates.clone(),
+                    aggregated_exprs: self.expressions(aggregated_exprs, &schema, scope)?,
+                    output_fields: output_fields.clone(),
+                    trigger: trigger.clone(),
+                }
+            }
+            Node::Join { source, source_key, joined, joined_key, join_type } => {
+                let (source, source_schema) = self.node(source, scope)?;
+                let (joined, joined_schema) = self.node(joined, scope)?;
+
+                // Keys are compared by value, so both sides have to have the same type.
+                let mut keys = (vec![], vec![]);
+                for (source_expr, joined_expr) in source_key.iter().zip(joined_key.iter()) {
+                    let source_expr = self.expression(source_expr, &source_schema, scope)?;
+                    let joined_expr = self.expression(joined_expr, &joined_schema, scope)?;
+                    let mut unified = unify(vec![source_expr, joined_expr], "join on")?;
+                    keys.1.push(unified.pop().unwrap());
+                    keys.0.push(unified.pop().unwrap());
+                }
+                Node::Join { source, source_key: keys.0, joined, joined_key: keys.1, join_type: *join_type }
+            }
+            Node::Requalifier { source, alias } => {
+                Node::Requalifier { source: self.node(source, scope)?.0, alias: alias.clone() }
+            }
+            Node::Unnest { source, expr, name } => {
+                let (source, schema) = self.node(source, scope)?;
+                let (expr, data_type) = self.expression(expr, &schema, scope)?;
+                if !matches!(data_type, DataType::List(_) | DataType::Null) {
+                    return Err(Error::BadInput(format!("only lists can be unnested, {} is {:?}", describe(&expr), data_type)));
+                }
+                Node::Unnest { source, expr, name: name.clone() }
+            }
+            Node::Sort { source, keys, options } => {
+                let (source, schema) = self.node(source, scope)?;
+                Node::Sort { source, keys: self.expressions(keys, &schema, scope)?, options: options.clone() }
+            }
+            Node::Limit { source, limit, offset } => {
+                Node::Limit { source: self.node(source, scope)?.0, limit: *limit, offset: *offset }
+            }
+            Node::TopN { source, keys, options, limit, offset } => {
+                let (source, schema) = self.node(source, scope)?;
+                Node::TopN { source, keys: self.expressions(keys, &schema, scope)?, options: options.clone(), limit: *limit, offset: *offset }
+            }
+            Node::Distinct { source, keys } => {
+                let (source, schema) = self.node(source, scope)?;
+                Node::Distinct { source, keys: self.expressions(keys, &schema, scope)? }
+            }
+            Node::SetOperation { sources, operator, all } => {
+                let sources = sources.iter()
+                    .map(|source| Ok(self.node(source, scope)?.0))
+                    .collect::<Result<_, Error>>()?;
+                Node::SetOperation { sources, operator: *operator, all: *all }
+            }
+            Node::CommonTableExpression { source, materialized } => {
+                let key = Arc::as_ptr(source) as usize;
+                let source = match self.ctes.get(&key) {
+                    Some(validated) => validated.clone(),
+                    None => {
+                        let (validated, schema) = self.node(source, scope)?;
+                        // The node moves out of its box, so its substitute goes with it.
+                        self.known_schemas.forget(&validated);
+                        let validated = Arc::new(*validated);
+                        self.known_schemas.substitute(&validated, Arc::new(KnownSchema(schema)));
+                        self.ctes.insert(key, validated.clone());
+                        validated
+                    }
+                };
+                Node::CommonTableExpression { source, materialized: *materialized }
+            }
+            Node::Window { source, windows } => {
+                let (source, schema) = self.node(source, scope)?;
+                // Windows are computed once all records are known, which isn't the case for results which keep changing.
+                if source.produces_retractions() {
+                    return Err(Error::BadInput("window functions over records which may be retracted, like the results of GROUP BY, aren't supported yet".to_string()));
+                }
+                let windows = windows.iter()
+                    .map(|(window, name)| Ok((self.window(window, &schema, scope)?, name.clone())))
+                    .collect::<Result<_, Error>>()?;
+                Node::Window { source, windows }
+            }
+            Node::TableFunction { name, args } => {
+                let function_name = name.to_string().to_lowercase();
+                if !TABLE_FUNCTIONS.contains_key(function_name.as_str()) {
+                    return Err(Error::BadInput(with_suggestion(
+                        format!("unknown table function `{}`", function_name),
+                        closest(function_name.as_str(), TABLE_FUNCTIONS.keys().cloned()),
+                    )));
+                }
+                let empty = Arc::new(Schema::empty());
+                let args = args.iter()
+                    .map(|(arg_name, arg)| Ok((arg_name.clone(), self.expression(arg, &empty, scope)?.0)))
+                    .collect::<Result<_, Error>>()?;
+                Node::TableFunction { name: name.clone(), args }
+            }
+            Node::LateralJoin { source, lateral } => {
+                let (source, schema) = self.node(source, scope)?;
+                let lateral = self.node(lateral, &scope.with(&schema))?.0;
+                Node::LateralJoin { source, lateral }
+            }
+        };
+
+        // The inputs stand in with their known schemas, so only this node's schema is computed here.
+        // Mismatches between nodes, like set operation sources with different columns, show up in it.
+        let node = Box::new(node);
+        let schema = node.physical(&self.known_schemas)?
+            .schema(scope.context.clone())
+            .map_err(physical_error)?;
+        self.known_schemas.substitute(&node, Arc::new(KnownSchema(schema.clone())));
+        Ok((node, schema))
+    }
+
+    fn expressions(&mut self, exprs: &[Box<Expression>], schema: &Arc<Schema>, scope: &Scope) -> Result<Vec<Box<Expression>>, Error> {
+        exprs.iter()
+            .map(|expr| Ok(self.expression(expr, schema, scope)?.0))
+            .collect()
+    }
+
+    // Returns the validated expression with its type.
+    fn expression(&mut self, expr: &Expression, schema: &Arc<Schema>, scope: &Scope) -> Result<(Box<Expression>, DataType), Error> {
+        let expr = match expr {
+            Expression::Variable(name) => Expression::Variable(resolve(name, schema, scope)?),
+            Expression::Constant(value) => Expression::Constant(value.clone()),
+            Expression::Function(name, args) => {
+                let args = args.iter()
+                    .map(|arg| self.expression(arg, schema, scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                function(name, args)?
+            }
+            Expression::Wildcard(qualifier) => {
+                if let Some(qualifier) = qualifier {
+                    check_qualifier(qualifier, schema)?;
+                }
+                Expression::Wildcard(qualifier.clone())
+            }
+            Expression::Subquery(query) => {
+                let subquery_scope = scope.with(schema);
+                let (query, schema) = self.node(query, &subquery_scope)?;
+                let columns = schema.fields().len() - 1;
+                if columns != 1 {
+                    return Err(Error::BadInput(format!("a subquery used as a value has to return a single column, not {}", columns)));
+                }
+                Expression::Subquery(query)
+            }
+            Expression::Case { operand, conditions, results, else_result } => {
+                let operand = operand.as_ref().map(|expr| self.expression(expr, schema, scope)).transpose()?;
+                let conditions = conditions.iter()
+                    .map(|condition| self.expression(condition, schema, scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if operand.is_none() {
+                    for (condition, data_type) in &conditions {
+                        expect_boolean(data_type, || format!("the condition {} of CASE has to be a boolean", describe(condition)))?;
+                    }
+                }
+                Expression::Case {
+                    operand: operand.map(|(expr, _)| expr),
+                    conditions: conditions.into_iter().map(|(expr, _)| expr).collect(),
+                    results: self.expressions(results, schema, scope)?,
+                    else_result: else_result.as_ref().map(|expr| Ok(self.expression(expr, schema, scope)?.0)).transpose()?,
+                }
+            }
+            Expression::Cast(expr, data_type) => Expression::Cast(self.cast(expr, data_type, schema, scope)?, data_type.clone()),
+            Expression::TryCast(expr, data_type) => Expression::TryCast(self.cast(expr, data_type, schema, scope)?, data_type.clone()),
+            Expression::FieldAccess(expr, name) => {
+                Expression::FieldAccess(self.expression(expr, schema, scope)?.0, name.clone())
+            }
+            Expression::Subscript(expr, index) => {
+                Expression::Subscript(self.expression(expr, schema, scope)?.0, self.expression(index, schema, scope)?.0)
+            }
+            Expression::Window { function, .. } => {
+                return Err(Error::BadInput(format!("window function {} is only allowed in the select list", function.to_string())));
+            }
+        };
+
+        let data_type = expr.physical(&MaterializationContext::default())?
+            .field_meta(scope.context.clone(), schema)
+            .map_err(physical_error)?
+            .data_type()
+            .clone();
+        Ok((Box::new(expr), data_type))
+    }
+
+    fn cast(&mut self, expr: &Expression, data_type: &Type, schema: &Arc<Schema>, scope: &Scope) -> Result<Box<Expression>, Error> {
+        let (expr, expr_type) = self.expression(expr, schema, scope)?;
+        if !can_cast(&expr_type, &data_type.arrow_type()) {
+            return Err(Error::BadInput(format!("cannot cast {} of type {:?} to {:?}", describe(&expr), expr_type, data_type.arrow_type())));
+        }
+        Ok(expr)
+    }
+
+    fn window(&mut self, window: &Expression, schema: &Arc<Schema>, scope: &Scope) -> Result<Box<Expression>, Error> {
+        match window {
+            Expression::Window { function, args, partition_by, order_by, frame } => {
+                Ok(Box::new(Expression::Window {
+                    function: function.clone(),
+                    args: self.expressions(args, schema, scope)?,
+                    partition_by: self.expressions(partition_by, schema, scope)?,
+                    order_by: order_by.iter()
+                        .map(|(expr, options)| Ok((self.expression(expr, schema, scope)?.0, *options)))
+                        .collect::<Result<_, Error>>()?,
+                    frame: *frame,
+                }))
+            }
+            _ => Err(Error::Unexpected("not a window function".to_string())),
+        }
+    }
+}
+
+fn function(name: &Identifier, args: Vec<(Box<Expression>, DataType)>) -> Result<Expression, Error> {
+    let function_name = match name {
+        Identifier::SimpleIdentifier(function_name) => function_name.to_lowercase(),
+        Identifier::NamespacedIdentifier(_, _) => return Err(Error::BadInput(format!("unknown function `{}`", name.to_string()))),
+    };
+    let function = match BUILTIN_FUNCTIONS.get(function_name.as_str()) {
+        Some(function) => function,
+        None => {
+            let names = BUILTIN_FUNCTIONS.keys().cloned()
+                .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+            return Err(Error::BadInput(with_suggestion(
+                format!("unknown function `{}`", function_name),
+                closest(function_name.as_str(), names),
+            )));
+        }
+    };
+    if !function.arity.contains(&args.len()) {
+        return Err(Error::BadInput(format!("wrong number of arguments for {}: {}", function_name, args.len())));
+    }
+
+    let args = match function_name.as_str() {
+        "=" | "<>" | "<" | "<=" | ">" | ">=" | "between" | "in" => unify(args, "compare")?,
+        "and" | "or" | "not" => {
+            for (arg, data_type) in &args {
+                expect_boolean(data_type, || format!("the operand {} of {} has to be a boolean", describe(arg), function_name.to_uppercase()))?;
+            }
+            args.into_iter().map(|(arg, _)| arg).collect()
+        }
+        _ => args.into_iter().map(|(arg, _)| arg).collect(),
+    };
+    Ok(Expression::Function(name.clone(), args))
+}
+
+// Converts the operands of a comparison to their common type. String literals compared with numbers are
+// parsed right away, so that i.e. age = '3' works and age = 'three' fails before running the query.
+fn unify(operands: Vec<(Box<Expression>, DataType)>, action: &str) -> Result<Vec<Box<Expression>>, Error> {
+    let is_string_literal = |expr: &Expression| matches!(expr, Expression::Constant(ScalarValue::Utf8(_)));
+
+    let mut common: Option<(&Expression, DataType)> = None;
+    for (expr, data_type) in operands.iter().filter(|(expr, _)| !is_string_literal(expr)) {
+        common = match common {
+            None => Some((expr.as_ref(), data_type.clone())),
+            Some((common_expr, common_type)) => match common_supertype(&common_type, data_type) {
+                Some(supertype) => Some((common_expr, supertype)),
+                None => return Err(Error::BadInput(format!(
+                    "can't {} {} of type {:?} with {} of type {:?}", action, describe(common_expr), common_type, describe(expr), data_type,
+                ))),
+            },
+        };
+    }
+    let (common_expr, target) = match common {
+        Some((common_expr, target)) => (describe(common_expr), target),
+        None => return Ok(operands.into_iter().map(|(expr, _)| expr).collect()),
+    };
+
+    operands.into_iter()
+        .map(|(expr, data_type)| {
+            if data_type == target || data_type == DataType::Null || target == DataType::Null {
+                return Ok(expr);
+            }
+            match (expr.as_ref(), Type::from_arrow(&target)) {
+                // Other literals, like dates, are parsed by the comparison itself.
+                (Expression::Constant(value @ ScalarValue::Utf8(text)), _) if is_numeric(&target) || target == DataType::Boolean => {
+                    let converted = get_scalar_value(&cast_array(&scalar_to_array(value, 1).map_err(physical_error)?, &target).map_err(physical_error)?, 0)
+                        .map_err(physical_error)?;
+                    if converted == ScalarValue::Null {
+                        return Err(Error::BadInput(format!("can't {} {} of type {:?} with '{}'", action, common_expr, target, text)));
+                    }
+                    Ok(Box::new(Expression::Constant(converted)))
+                }
+                (Expression::Constant(ScalarValue::Utf8(_)), _) | (_, None) => Ok(expr),
+                (_, Some(target)) => Ok(Box::new(Expression::Cast(expr, target))),
+            }
+        })
+        .collect()
+}
+
+fn resolve(name: &Identifier, schema: &Arc<Schema>, scope: &Scope) -> Result<Identifier, Error> {
+    let full_name = name.to_string();
+    // The record's own fields shadow the ones of enclosing queries.
+    let schemas = std::iter::once(schema).chain(scope.schemas.iter().rev()).collect::<Vec<_>>();
+    if schemas.iter().any(|schema| schema.field_with_name(full_name.as_str()).is_ok()) {
+        return Ok(name.clone());
+    }
+
+    match name {
+        Identifier::SimpleIdentifier(column) => {
+            for schema in &schemas {
+                let matching = schema.fields().iter()
+                    .filter_map(|field| split_name(field.name()))
+                    .filter(|(_, field_column)| field_column == column)
+                    .collect::<Vec<_>>();
+                match matching.as_slice() {
+                    [] => continue,
+                    [(qualifier, _)] => return Ok(Identifier::NamespacedIdentifier(qualifier.to_string(), column.clone())),
+                    _ => {
+                        let names = matching.iter().map(|(qualifier, _)| format!("`{}.{}`", qualifier, column)).collect::<Vec<_>>();
+                        return Err(Error::BadInput(format!("column `{}` is ambiguous, it could be {}", column, names.join(" or "))));
+                    }
+                }
+            }
+            let names = schemas.iter()
+                .flat_map(|schema| schema.fields().iter())
+                .map(|field| field.name().as_str())
+                .filter(|field_name| is_visible(field_name));
+            Err(Error::BadInput(with_suggestion(
+                format!("column `{}` not found", column),
+                closest_by(column.as_str(), names, |field_name| split_name(field_name).map(|(_, column)| column).unwrap_or(field_name)),
+            )))
+        }
+        Identifier::NamespacedIdentifier(qualifier, column) => {
+            // A field of a struct column.
+            if let Ok(field) = schema.field_with_name(qualifier) {
+                return match field.data_type() {
+                    DataType::Struct(fields) => Err(Error::BadInput(with_suggestion(
+                        format!("field `{}` not found in `{}`", column, qualifier),
+                        closest(column.as_str(), fields.iter().map(|field| field.name().as_str())),
+                    ))),
+                    data_type => Err(Error::BadInput(format!("`{}` is of type {:?}, not a struct with field `{}`", qualifier, data_type, column))),
+                };
+            }
+            let fields = schemas.iter()
+                .flat_map(|schema| schema.fields().iter())
+                .filter_map(|field| split_name(field.name()))
+                .filter(|(field_qualifier, _)| field_qualifier == qualifier)
+                .map(|(_, column)| column)
+                .collect::<Vec<_>>();
+            if fields.is_empty() {
+                return Err(unknown_qualifier(qualifier, schemas.iter().cloned()));
+            }
+            Err(Error::BadInput(with_suggestion(
+                format!("column `{}` not found in `{}`", column, qualifier),
+                closest(column.as_str(), fields.into_iter().filter(|column| is_visible(column))),
+            )))
+        }
+    }
+}
+
+fn check_qualifier(qualifier: &str, schema: &Arc<Schema>) -> Result<(), Error> {
+    match schema.fields().iter().any(|field| split_name(field.name()).map(|(field_qualifier, _)| field_qualifier) == Some(qualifier)) {
+        true => Ok(()),
+        false => Err(unknown_qualifier(qualifier, std::iter::once(schema))),
+    }
+}
+
+fn unknown_qualifier<'a>(qualifier: &str, schemas: impl Iterator<Item=&'a Arc<Schema>>) -> Error {
+    let mut qualifiers = vec![];
+    for schema in schemas {
+        for (field_qualifier, _) in schema.fields().iter().filter_map(|field| split_name(field.name())) {
+            if is_visible(field_qualifier) && !qualifiers.contains(&field_qualifier) {
+                qualifiers.push(field_qualifier);
+            }
+        }
+    }
+    Error::BadInput(with_suggestion(
+        format!("unknown table or alias `{}`", qualifier),
+        closest(qualifier, qualifiers.into_iter()),
+    ))
+}
+
+fn expect_boolean(data_type: &DataType, message: impl Fn() -> String) -> Result<(), Error> {
+    match data_type {
+        DataType::Boolean | DataType::Null => Ok(()),
+        _ => Err(Error::BadInput(format!("{}, not {:?}", message(), data_type))),
+    }
+}
+
+// Splits a qualified field name, like c.name, into its qualifier and column.
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    let dot = name.find('.')?;
+    Some((&name[..dot], &name[dot + 1..]))
+}
+
+// Generated fields aren't suggested.
+fn is_visible(name: &str) -> bool {
+    name != RETRACTIONS_FIELD && !name.starts_with(INTERNAL_FIELD_PREFIX)
+}
+
+// How an expression is referred to in error messages.
+fn describe(expr: &Expression) -> String {
+    match expr {
+        Expression::Variable(name) => format!("`{}`", name.to_string()),
+        Expression::Constant(ScalarValue::Utf8(text)) => format!("'{}'", text),
+        Expression::Constant(ScalarValue::Int64(value)) => value.to_string(),
+        Expression::Constant(ScalarValue::Float64(value)) => value.to_string(),
+        Expression::Constant(ScalarValue::Boolean(value)) => value.to_string(),
+        Expression::Constant(ScalarValue::Null) => "NULL".to_string(),
+        Expression::Function(name, _) => format!("the result of {}", name.to_string().to_uppercase()),
+        Expression::Subquery(_) => "the subquery".to_string(),
+        _ => "the expression".to_string(),
+    }
+}
+
+fn with_suggestion(message: String, suggestion: Option<&str>) -> String {
+    match suggestion {
+        Some(suggestion) => format!("{}, did you mean `{}`?", message, suggestion),
+        None => message,
+    }
+}
+
+fn closest<'a>(name: &str, candidates: impl Iterator<Item=&'a str>) -> Option<&'a str> {
+    closest_by(name, candidates, |candidate| candidate)
+}
+
+// The candidate whose key is closest to the name, if it's close enough to be a typo.
+fn closest_by<'a>(name: &str, candidates: impl Iterator<Item=&'a str>, key: impl Fn(&'a str) -> &'a str) -> Option<&'a str> {
+    let max_distance = std::cmp::max(1, name.chars().count() / 3);
+    candidates
+        .map(|candidate| (edit_distance(name, key(candidate)), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// The Levenshtein distance, ignoring case.
+fn edit_distance(left: &str, right: &str) -> usize {
+    let left = left.to_lowercase().chars().collect::<Vec<_>>();
+    let right = right.to_lowercase().chars().collect::<Vec<_>>();
+    let mut previous = (0..=right.len()).collect::<Vec<_>>();
+    for (i, left_char) in left.iter().enumerate() {
+        let mut current = vec![i + 1; right.len() + 1];
+        for (j, right_char) in right.iter().enumerate() {
+            let substitution = previous[j] + if left_char == right_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[right.len()]
+}
+
+fn physical_error(err: physical::Error) -> Error {
+    match err {
+        physical::Error::BadInput(message) => Error::BadInput(message),
+        err => Error::Unexpected(format!("{:?}", err)),
+    }
+}
+
+#[test]
+fn test_validate() {
+    use crate::logical::sql::{query_to_logical_plan, Scope};
+    use crate::parser::parser::parse_sql;
+
+    let validate_sql = |sql: &str| {
+        let plan = query_to_logical_plan(&Scope::default(), parse_sql(sql).unwrap().as_ref());
+        let variable_context = Arc::new(VariableContext { previous: None, schema: Arc::new(Schema::empty()), variables: vec![] });
+        validate(plan.as_ref(), variable_context).map(|plan| format!("{:?}", plan))
+    };
+    let error = |sql: &str| match validate_sql(sql) {
+        Err(Error::BadInput(message)) => message,
+        other => panic!("expected a validation error, got {:?}", other),
+    };
+
+    assert_eq!(error("SELECT c.agee FROM cats.csv c"), "column `agee` not found in `c`, did you mean `age`?");
+    assert_eq!(error("SELECT x.age FROM cats.csv c"), "unknown table or alias `x`, did you mean `c`?");
+    assert_eq!(error("SELECT lenght(c.name) FROM cats.csv c"), "unknown function `lenght`, did you mean `length`?");
+    assert_eq!(error("SELECT nullif(c.age) FROM cats.csv c"), "wrong number of arguments for nullif: 1");
+    assert_eq!(error("SELECT c.name FROM cats.csv c WHERE upper() = 'A'"), "wrong number of arguments for upper: 0");
+    assert_eq!(error("SELECT c.name FROM cats.csv c WHERE c.name = c.age"), "can't compare `c.name` of type Utf8 with `c.age` of type Int64");
+    assert_eq!(error("SELECT c.name FROM cats.csv c WHERE c.age"), "the condition `c.age` has to be a boolean, not Int64");
+    assert_eq!(error("SELECT c.age, rank() OVER (ORDER BY c.age) FROM cats.csv c GROUP BY c.age"), "window function rank can't be used in a query with GROUP BY");
+    assert_eq!(error("SELECT c.age + 1 FROM cats.csv c GROUP BY c.age"), "the result of + has to be a GROUP BY key or an aggregate");
+    assert_eq!(
+        error("SELECT t.age, rank() OVER (ORDER BY t.n) FROM (SELECT c.age, count(c.name) AS n FROM cats.csv c GROUP BY c.age) t"),
+        "window functions over records which may be retracted, like the results of GROUP BY, aren't supported yet",
+    );
+    assert!(error("SELECT c.name FROM missing.csv c").starts_with("couldn't open missing.csv"));
+    assert_eq!(error("SELECT c.name FROM cats.txt c"), "unknown source `cats.txt`, only .csv and .json files can be queried");
+
+    // Unqualified names get qualified and string literals compared with numbers get parsed.
+    let plan = validate_sql("SELECT name FROM cats.csv c WHERE age = '3'").unwrap();
+    assert!(plan.contains("NamespacedIdentifier(\"c\", \"name\")"));
+    assert!(plan.contains("Constant(Int64(3))"));
+
+    assert_eq!(edit_distance("agee", "Age"), 1);
+    assert_eq!(closest("owner", vec!["name", "age"].into_iter()), None);
+}
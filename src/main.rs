@@ -15,31 +15,39 @@
 #[macro_use]
 extern crate lazy_static;
 
+use std::collections::HashMap;
 use std::result::*;
 use std::sync::Arc;
 
-use arrow::datatypes::Schema;
 use arrow::record_batch::RecordBatch;
 
-use crate::logical::logical::MaterializationContext;
-use crate::logical::sql::query_to_logical_plan;
-use crate::parser::parser::parse_sql;
-use crate::physical::physical::{EmptySchemaContext, ExecutionContext, noop_meta_send, ProduceContext, VariableContext};
+use crate::physical::datetime::Zone;
+use crate::physical::physical::{ProduceContext, Session};
 use crate::pretty::pretty_format_batches;
+use crate::query::{parse_parameter_value, PreparedQuery};
 
 #[macro_use]
 mod physical;
 mod logical;
 mod parser;
 mod pretty;
+mod query;
 
-fn record_print(
-    _ctx: &ProduceContext,
-    batch: RecordBatch,
-) -> Result<(), physical::physical::Error> {
-    println!("{}", batch.num_rows());
-    println!("{}", pretty_format_batches(&[batch]).unwrap());
-    Ok(())
+
+const USAGE: &str = "usage: octosql [--param name=value]... query";
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(2);
+}
+
+// Errors caused by the query are printed as they are, the others with their details.
+fn exit_with_error(err: physical::physical::Error) -> ! {
+    match err {
+        physical::physical::Error::BadInput(message) => eprintln!("{}", message),
+        err => eprintln!("{:?}", err),
+    }
+    std::process::exit(1);
 }
 
 // fn var(name: &str) -> Box<Expression> {
@@ -95,30 +103,60 @@ fn main() {
     //     source: Box::new(logical_plan),
     //     filter_column: RETRACTIONS_FIELD.to_string(),
     // };
-    let sql = std::env::args().nth(1).unwrap();
+    // The query, with parameter values given as --param name=value, where $1 is named 1.
+    let mut sql = None;
+    let mut parameters = HashMap::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--param" {
+            let param = args.next().unwrap_or_else(|| exit_with_usage("--param needs a name=value argument"));
+            let (name, value) = match param.find('=') {
+                Some(i) => (&param[..i], &param[i + 1..]),
+                None => exit_with_usage(&format!("--param needs a name=value argument, got {}", param)),
+            };
+            parameters.insert(name.trim_start_matches(|c| c == '$' || c == ':').to_string(), parse_parameter_value(value));
+        } else if sql.is_some() {
+            exit_with_usage(&format!("unexpected argument {}, the query has to be a single argument", arg));
+        } else {
+            sql = Some(arg);
+        }
+    }
+    let sql = sql.unwrap_or_else(|| exit_with_usage("missing query"));
     dbg!(&sql);
 
-    let query = parse_sql(sql.as_str());
-    dbg!(&query);
-    let logical_plan = query_to_logical_plan(query.as_ref());
-    dbg!(&logical_plan);
+    let query = match PreparedQuery::new(sql.as_str()) {
+        Ok(query) => query,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
-    let plan = logical_plan.physical(&MaterializationContext {}).unwrap();
-
-    let schema = plan.schema(Arc::new(EmptySchemaContext{})).unwrap();
+    let schema = query.schema(&parameters).unwrap_or_else(|err| exit_with_error(err));
     dbg!(schema);
 
-    let _res = plan.run(
-        &ExecutionContext {
-            variable_context: Arc::new(VariableContext {
-                previous: None,
-                schema: Arc::new(Schema::new(vec![])), // Potential runtime variables.
-                variables: vec![],
-            }),
-        },
-        &mut record_print,
-        &mut noop_meta_send,
-    ).unwrap();
+    let time_zone = match std::env::var("OCTOSQL_TIME_ZONE") {
+        Ok(name) => Zone::parse(name.as_str()).unwrap_or_else(|_| {
+            eprintln!("OCTOSQL_TIME_ZONE has to be a time zone name or an offset like +02:00, got {}", name);
+            std::process::exit(1);
+        }),
+        Err(_) => Zone::utc(),
+    };
+    let seed = std::env::var("OCTOSQL_SEED").ok().map(|seed| seed.parse().unwrap_or_else(|_| {
+        eprintln!("OCTOSQL_SEED has to be a non-negative integer, got {}", seed);
+        std::process::exit(1);
+    }));
+    let session = Arc::new(Session::new(seed, time_zone));
+
+    let mut record_print = |_ctx: &ProduceContext, batch: RecordBatch| -> Result<(), physical::physical::Error> {
+        println!("{}", batch.num_rows());
+        println!("{}", pretty_format_batches(&[batch], session.time_zone())?);
+        Ok(())
+    };
+
+    if let Err(err) = query.run(session.clone(), &parameters, &mut record_print) {
+        exit_with_error(err);
+    }
     dbg!(start_time.elapsed());
     // println!("{:?}", start_time.elapsed());
 }
@@ -12,19 +12,114 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
+
 pub mod parser;
 mod sqlparser;
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    // The query isn't valid SQL.
+    Syntax,
+    // Valid SQL which isn't supported yet.
+    NotSupported,
+    // A literal or argument which can't be used, like a malformed interval.
+    InvalidValue,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub message: String,
+    // The part of the query the error is about.
+    pub fragment: String,
+    // The line and column of the fragment, both starting at 1, if it could be found in the query.
+    pub position: Option<(u64, u64)>,
+}
+
+impl ParseError {
+    pub fn not_supported(construct: &str, fragment: impl ToString) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::NotSupported,
+            message: format!("not supported yet: {}", construct),
+            fragment: fragment.to_string(),
+            position: None,
+        }
+    }
+
+    pub fn invalid_value(message: String, fragment: impl ToString) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::InvalidValue,
+            message,
+            fragment: fragment.to_string(),
+            position: None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some((line, column)) = self.position {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+        if !self.fragment.is_empty() {
+            write!(f, ": {}", self.fragment)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum Query {
     Select {
         expressions: Vec<SelectExpression>,
+        distinct: bool,
+        // With DISTINCT ON, the latest record for each of these keys is kept.
+        distinct_on: Vec<Box<Expression>>,
         filter: Option<Box<Expression>>,
         from: Box<Source>,
-        order_by:Vec<Box<Expression>>,
+        order_by: Vec<OrderByExpression>,
         group_by: Vec<Box<Expression>>,
-        trigger: Vec<Trigger>
+        trigger: Vec<Trigger>,
+        limit: Option<u64>,
+        offset: Option<u64>,
     },
+    SetOperation {
+        operator: SetOperator,
+        // Without ALL, the result is a set instead of a multiset.
+        all: bool,
+        left: Box<Query>,
+        right: Box<Query>,
+    },
+    // The common table expressions are in scope for the query and all following ones.
+    With {
+        ctes: Vec<CommonTableExpression>,
+        query: Box<Query>,
+    },
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct CommonTableExpression {
+    pub name: String,
+    pub query: Box<Query>,
+    // A materialized one is executed once and shared by all references to it, instead of being executed for each of them.
+    pub materialized: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct OrderByExpression {
+    pub expr: Box<Expression>,
+    pub descending: bool,
+    // Defaults to nulls being larger than any value, so first when descending.
+    pub nulls_first: bool,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -37,6 +132,14 @@ pub enum SelectExpression {
 pub enum Source {
     Table(Identifier, Option<Identifier>),
     Subquery(Box<Query>, Option<Identifier>),
+    // Each row of the source, joined with each element of the list, named by the identifier.
+    Unnest(Box<Source>, Box<Expression>, Identifier),
+    // Each row of the left source with each row of the right one.
+    Join(Box<Source>, Box<Source>),
+    // A subquery which can reference the sources preceding it in the FROM clause.
+    Lateral(Box<Query>, Option<Identifier>),
+    // name(args), with named arguments written as name => value.
+    TableFunction(Identifier, Vec<(Option<String>, Box<Expression>)>, Option<Identifier>),
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -45,8 +148,62 @@ pub enum Expression {
     Constant(Value),
     Function(Identifier, Vec<Box<Expression>>),
     Operator(Box<Expression>, Operator, Box<Expression>),
+    UnaryOperator(UnaryOperator, Box<Expression>),
     Wildcard(Option<String>),
     Subquery(Box<Query>),
+    Case {
+        operand: Option<Box<Expression>>,
+        conditions: Vec<Box<Expression>>,
+        results: Vec<Box<Expression>>,
+        else_result: Option<Box<Expression>>,
+    },
+    Cast(Box<Expression>, Type),
+    TryCast(Box<Expression>, Type),
+    FieldAccess(Box<Expression>, String),
+    Subscript(Box<Expression>, Box<Expression>),
+    InList {
+        expr: Box<Expression>,
+        list: Vec<Box<Expression>>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expression>,
+        negated: bool,
+        low: Box<Expression>,
+        high: Box<Expression>,
+    },
+    // A placeholder, $1 is named 1 and :name is named name.
+    Parameter(String),
+    // function(args) OVER (PARTITION BY ... ORDER BY ... frame)
+    Window {
+        function: Identifier,
+        args: Vec<Box<Expression>>,
+        partition_by: Vec<Box<Expression>>,
+        order_by: Vec<OrderByExpression>,
+        frame: Option<WindowFrame>,
+    },
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct WindowFrame {
+    pub units: WindowFrameUnits,
+    pub start: WindowFrameBound,
+    pub end: WindowFrameBound,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum WindowFrameUnits {
+    Rows,
+    // The frame is made of peers, rows with equal ORDER BY values.
+    Range,
+}
+
+// None is UNBOUNDED.
+#[derive(Debug, Eq, PartialEq)]
+pub enum WindowFrameBound {
+    Preceding(Option<u64>),
+    CurrentRow,
+    Following(Option<u64>),
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -63,7 +220,28 @@ pub enum Identifier {
 #[derive(Debug, Eq, PartialEq)]
 pub enum Value {
     Integer(i64),
+    // Kept in its textual form, so that values stay comparable.
+    Float(String),
     String(String),
+    Boolean(bool),
+    IntervalYearMonth(i32),
+    // In milliseconds.
+    IntervalDayTime(i64),
+    Null,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Type {
+    Boolean,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    String,
+    Date,
+    Timestamp,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -71,10 +249,29 @@ pub enum Operator {
     Lt,
     LtEq,
     Eq,
+    NotEq,
     GtEq,
     Gt,
     Plus,
     Minus,
+    Multiply,
+    Divide,
+    Modulo,
     AND,
     OR,
+    Like,
+    NotLike,
+    ILike,
+    NotILike,
+    RegexpMatches,
+    Concat,
+    AtTimeZone,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum UnaryOperator {
+    NOT,
+    IsNull,
+    IsNotNull,
+    Minus,
 }
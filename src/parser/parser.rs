@@ -12,77 +12,768 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::parser::{Expression, Identifier, Operator, Query, SelectExpression, Source, Trigger, Value};
+use std::convert::TryFrom;
+
+use crate::parser::{CommonTableExpression, Expression, Identifier, Operator, OrderByExpression, ParseError, ParseErrorKind, Query, SelectExpression, SetOperator, Source, Trigger, Type, UnaryOperator, Value, WindowFrame, WindowFrameBound, WindowFrameUnits};
 
 use super::sqlparser;
 use super::sqlparser::ast;
-use super::sqlparser::ast::{BinaryOperator, Expr, Function, FunctionArg, Ident, Select, SelectItem, SetExpr, Statement, TableFactor};
+use super::sqlparser::ast::{BinaryOperator, DateTimeField, Expr, Function, FunctionArg, Ident, Join, JoinOperator, OrderByExpr, Select, SelectItem, SetExpr, Statement, TableAlias, TableFactor};
+use super::sqlparser::ast::DataType as SQLDataType;
+use super::sqlparser::ast::UnaryOperator as SQLUnaryOperator;
 use super::sqlparser::dialect::GenericDialect;
-use super::sqlparser::parser::Parser;
+use super::sqlparser::parser::{Parser, ParserError};
+use super::sqlparser::tokenizer::{Token, Tokenizer, Whitespace};
 
-pub fn parse_sql(text: &str) -> Box<Query> {
+pub fn parse_sql(text: &str) -> Result<Box<Query>, ParseError> {
     let dialect = GenericDialect {}; // or AnsiDialect, or your own dialect ...
 
-    let ast = Parser::parse_sql(&dialect, text).unwrap();
+    let original = Tokenizer::new(&dialect, text).tokenize().map_err(|err| ParseError {
+        kind: ParseErrorKind::Syntax,
+        message: err.message,
+        fragment: text.lines().nth(err.line as usize - 1).unwrap_or("").trim().to_string(),
+        position: Some((err.line, err.col)),
+    })?;
+    let tokens = rewrite_try_cast(original.clone());
+    let tokens = rewrite_subscripts(tokens);
+    let tokens = rewrite_parameters(tokens);
+    let tokens = rewrite_pattern_operators(tokens);
+    let tokens = rewrite_distinct_on(tokens);
+    let tokens = rewrite_materialized(tokens);
 
-    if let Statement::Query(q) = &ast[0] {
-        let parsed = parse_query(q.as_ref());
-        parsed
-    } else {
-        unimplemented!()
+    let positions = token_positions(&original);
+    let origins = token_origins(&original, &tokens);
+    let position = |index: usize| Some(positions[origins.get(index).cloned().unwrap_or(original.len())]);
+    // Errors are reported with the tokens of the query as written, not the rewritten ones.
+    let original_token = |index: usize| original.get(origins.get(index).cloned().unwrap_or(original.len()))
+        .map(|token| token.to_string())
+        .unwrap_or_default();
+
+    let mut parser = Parser::new(tokens.clone(), &dialect);
+    let statement = match parser.parse_statement() {
+        Ok(statement) => statement,
+        Err(ParserError::TokenizerError(message)) | Err(ParserError::ParserError(message)) => {
+            let index = parser_index(&mut parser, tokens.len());
+            let found = message.find("found: ").map(|i| message[i + "found: ".len()..].to_string());
+            let previous = (0..index).rev().find(|i| !matches!(tokens[*i], Token::Whitespace(_)));
+            let next = (index..tokens.len()).find(|i| !matches!(tokens[*i], Token::Whitespace(_))).unwrap_or(tokens.len());
+            // The parser either stops at the unexpected token or right after it.
+            let index = match previous {
+                Some(previous) if found.as_ref() == Some(&tokens[previous].to_string()) => previous,
+                _ => next,
+            };
+            let message = match (found, tokens.get(index)) {
+                (Some(found), Some(token)) if found == token.to_string() => {
+                    message.replace(&format!("found: {}", found), &format!("found: {}", original_token(index)))
+                }
+                _ => message,
+            };
+            return Err(ParseError {
+                kind: ParseErrorKind::Syntax,
+                message,
+                fragment: original_token(index),
+                position: position(index),
+            });
+        }
+    };
+    loop {
+        match parser.next_token() {
+            Token::EOF => break,
+            Token::SemiColon => continue,
+            _ => {
+                parser.prev_token();
+                let index = parser_index(&mut parser, tokens.len());
+                return Err(ParseError {
+                    kind: ParseErrorKind::Syntax,
+                    message: "expected end of statement".to_string(),
+                    fragment: original_token(index),
+                    position: position(index),
+                });
+            }
+        }
     }
+
+    let query = match &statement {
+        Statement::Query(q) => parse_query(q.as_ref()),
+        _ => Err(ParseError::not_supported("statements other than queries", &statement)),
+    };
+    query.map_err(|mut err| {
+        // The fragment is printed from the rewritten syntax tree, so it's looked up in the rewritten tokens
+        // and replaced by the original text it comes from.
+        if let Some((start, end)) = locate(&dialect, &err.fragment, &tokens).filter(|(start, _)| origins[*start] < original.len()) {
+            // Tokens added by the rewrites don't have an original of their own, so the fragment ends with the last one which does.
+            let end = (start..=end).rev()
+                .find(|i| original.get(origins[*i]) == Some(&tokens[*i]))
+                .map(|i| origins[i]);
+            let start = origins[start];
+            err.fragment = original[start..=end.unwrap_or(start).max(start)].iter().map(|token| token.to_string()).collect();
+            err.position = Some(positions[start]);
+        }
+        err
+    })
 }
 
-pub fn parse_query(sql_query: &sqlparser::ast::Query) -> Box<Query> {
-    if let SetExpr::Select(select) = &sql_query.body {
-        let query = parse_select(select.as_ref());
-        query
-    } else {
-        unimplemented!()
+// The line and column, both starting at 1, of each token, followed by the end of the text.
+fn token_positions(tokens: &[Token]) -> Vec<(u64, u64)> {
+    let mut positions = Vec::with_capacity(tokens.len() + 1);
+    let (mut line, mut column) = (1, 1);
+    for token in tokens {
+        positions.push((line, column));
+        for c in token.to_string().chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+    }
+    positions.push((line, column));
+    positions
+}
+
+// The index of the original token each rewritten token comes from. The rewrites only add, drop and rename a few tokens,
+// so the other ones are matched up greedily. Added tokens get the index of the original token following them.
+fn token_origins(original: &[Token], rewritten: &[Token]) -> Vec<usize> {
+    let mut origins = Vec::with_capacity(rewritten.len());
+    let mut next = 0;
+    for token in rewritten {
+        if let Token::Whitespace(_) = token {
+            origins.push(next);
+            continue;
+        }
+        let matched = original[next..].iter()
+            .enumerate()
+            .filter(|(_, original)| !matches!(original, Token::Whitespace(_)))
+            .take(8)
+            .find(|(_, original)| *original == token);
+        match matched {
+            Some((offset, _)) => {
+                origins.push(next + offset);
+                next += offset + 1;
+            }
+            None => origins.push((next..original.len()).find(|i| !matches!(original[*i], Token::Whitespace(_))).unwrap_or(original.len())),
+        }
+    }
+    origins
+}
+
+// The parser doesn't expose its position, so it's found by consuming the rest of its tokens.
+fn parser_index(parser: &mut Parser, len: usize) -> usize {
+    let mut remaining = 0;
+    while parser.next_token_no_skip().is_some() {
+        remaining += 1;
+    }
+    len - remaining
+}
+
+// Finds a fragment of the query by looking for its tokens, returns the indices of its first and last token.
+fn locate(dialect: &GenericDialect, fragment: &str, tokens: &[Token]) -> Option<(usize, usize)> {
+    let non_whitespace = |tokens: &[Token]| tokens.iter().enumerate()
+        .filter(|(_, token)| !matches!(token, Token::Whitespace(_)))
+        .map(|(i, token)| (i, token.clone()))
+        .collect::<Vec<_>>();
+    let fragment = non_whitespace(&Tokenizer::new(dialect, fragment).tokenize().ok()?);
+    let tokens = non_whitespace(tokens);
+    if fragment.is_empty() {
+        return None;
+    }
+
+    let same = |left: &Token, right: &Token| match (left, right) {
+        (Token::Word(left), Token::Word(right)) => left.value.eq_ignore_ascii_case(&right.value),
+        _ => left == right,
+    };
+    tokens.windows(fragment.len())
+        .find(|window| window.iter().zip(fragment.iter()).all(|((_, left), (_, right))| same(left, right)))
+        .map(|window| (window[0].0, window[window.len() - 1].0))
+}
+
+// The sql parser doesn't know TRY_CAST, so TRY_CAST(x AS t) gets rewritten to TRY_CAST(CAST(x AS t)),
+// which parses as a function call with a single CAST argument.
+fn rewrite_try_cast(tokens: Vec<Token>) -> Vec<Token> {
+    let mut output = Vec::with_capacity(tokens.len());
+    let mut depth = 0;
+    // Parenthesis depths at which an additional closing parenthesis has to be inserted.
+    let mut pending_closes: Vec<usize> = Vec::new();
+
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(token) = iter.next() {
+        match &token {
+            Token::Word(word) if word.quote_style.is_none() && word.value.to_uppercase() == "TRY_CAST" => {
+                while let Some(Token::Whitespace(_)) = iter.peek() {
+                    iter.next();
+                }
+                output.push(token);
+                if let Some(Token::LParen) = iter.peek() {
+                    iter.next();
+                    output.push(Token::LParen);
+                    output.push(Token::make_keyword("CAST"));
+                    output.push(Token::LParen);
+                    depth += 1;
+                    pending_closes.push(depth);
+                }
+            }
+            Token::LParen => {
+                depth += 1;
+                output.push(token);
+            }
+            Token::RParen => {
+                output.push(token);
+                if pending_closes.last() == Some(&depth) {
+                    pending_closes.pop();
+                    output.push(Token::RParen);
+                }
+                // Unbalanced parentheses are left for the parser to report.
+                depth = depth.saturating_sub(1);
+            }
+            _ => output.push(token),
+        }
+    }
+
+    output
+}
+
+const SUBSCRIPT_MARKER: &str = "__subscript";
+const FIELD_MARKER: &str = "__field";
+
+// The sql parser doesn't know subscripts, so x[i] gets rewritten to __subscript(x, i),
+// and a field accessed after a subscript, like in x[i].name, to __field(__subscript(x, i), 'name').
+// The subscripted operand has to be a (compound) identifier or a parenthesized expression, possibly a function call.
+fn rewrite_subscripts(tokens: Vec<Token>) -> Vec<Token> {
+    let mut output: Vec<Token> = Vec::with_capacity(tokens.len());
+
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::LBracket => {
+                let end = matching_bracket(&tokens, i);
+                let start = operand_start(&output);
+                match (end, start) {
+                    // Empty brackets are part of array types, like TEXT[].
+                    (Some(end), Some(start)) if end > i + 1 => {
+                        let operand = output.split_off(start);
+                        output.push(Token::make_word(SUBSCRIPT_MARKER, None));
+                        output.push(Token::LParen);
+                        output.extend(operand);
+                        output.push(Token::Comma);
+                        output.extend(rewrite_subscripts(tokens[i + 1..end].to_vec()));
+                        output.push(Token::RParen);
+                        i = end + 1;
+                    }
+                    _ => {
+                        output.push(tokens[i].clone());
+                        i += 1;
+                    }
+                }
+            }
+            Token::Period => {
+                let start = operand_start(&output).filter(|start| match &output[*start] {
+                    Token::Word(word) => word.value == SUBSCRIPT_MARKER || word.value == FIELD_MARKER,
+                    _ => false,
+                });
+                match (start, tokens.get(i + 1)) {
+                    (Some(start), Some(Token::Word(field))) => {
+                        let operand = output.split_off(start);
+                        output.push(Token::make_word(FIELD_MARKER, None));
+                        output.push(Token::LParen);
+                        output.extend(operand);
+                        output.push(Token::Comma);
+                        output.push(Token::SingleQuotedString(field.value.clone()));
+                        output.push(Token::RParen);
+                        i += 2;
+                    }
+                    _ => {
+                        output.push(tokens[i].clone());
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                output.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+    }
+
+    output
+}
+
+fn matching_bracket(tokens: &[Token], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::LBracket => depth += 1,
+            Token::RBracket if depth == 1 => return Some(i),
+            Token::RBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// Returns the index at which the operand the tokens end with starts.
+fn operand_start(tokens: &[Token]) -> Option<usize> {
+    match tokens.last()? {
+        Token::Word(_) => {
+            let mut start = tokens.len() - 1;
+            while start >= 2 && tokens[start - 1] == Token::Period && matches!(tokens[start - 2], Token::Word(_)) {
+                start -= 2;
+            }
+            Some(start)
+        }
+        Token::RParen => {
+            let mut depth = 0;
+            let mut start = tokens.len();
+            loop {
+                start = start.checked_sub(1)?;
+                match tokens[start] {
+                    Token::RParen => depth += 1,
+                    Token::LParen if depth == 1 => break,
+                    Token::LParen => depth -= 1,
+                    _ => {}
+                }
+            }
+            match start.checked_sub(1).map(|i| &tokens[i]) {
+                Some(Token::Word(_)) => Some(start - 1),
+                _ => Some(start),
+            }
+        }
+        _ => None,
+    }
+}
+
+const ILIKE_MARKER: &str = "__ilike_pattern";
+const REGEXP_MARKER: &str = "__regexp_pattern";
+const AT_TIME_ZONE_MARKER: &str = "__at_time_zone";
+
+// The sql parser doesn't know the ILIKE, ~ and AT TIME ZONE infix operators, so x ILIKE p gets rewritten to x LIKE __ilike_pattern(p),
+// x ~ p to x LIKE __regexp_pattern(p) and x AT TIME ZONE z to x LIKE __at_time_zone(z),
+// which parse_expr then turns back into the right operator.
+// The pattern has to be a single literal, (compound) identifier or function call.
+fn rewrite_pattern_operators(tokens: Vec<Token>) -> Vec<Token> {
+    let mut output = Vec::with_capacity(tokens.len());
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let (marker, operator_end) = match &tokens[i] {
+            Token::Word(word) if word.quote_style.is_none() && word.value.to_uppercase() == "ILIKE" => (ILIKE_MARKER, i + 1),
+            Token::Tilde => (REGEXP_MARKER, i + 1),
+            Token::Word(word) if word.quote_style.is_none() && word.value.to_uppercase() == "AT" => {
+                match words_following(&tokens, i + 1, &["TIME", "ZONE"]) {
+                    Some(end) => (AT_TIME_ZONE_MARKER, end),
+                    None => {
+                        output.push(tokens[i].clone());
+                        i += 1;
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                output.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+        };
+
+        let mut pattern_start = operator_end;
+        while let Some(Token::Whitespace(_)) = tokens.get(pattern_start) {
+            pattern_start += 1;
+        }
+        let mut pattern_end = pattern_start;
+        match tokens.get(pattern_start) {
+            Some(Token::SingleQuotedString(_)) => pattern_end += 1,
+            Some(Token::Word(_)) => {
+                pattern_end += 1;
+                while let (Some(Token::Period), Some(Token::Word(_))) = (tokens.get(pattern_end), tokens.get(pattern_end + 1)) {
+                    pattern_end += 2;
+                }
+                // A function call, which also covers rewritten subscripts and parameters.
+                if tokens.get(pattern_end) == Some(&Token::LParen) {
+                    let mut depth = 0;
+                    for (i, token) in tokens.iter().enumerate().skip(pattern_end) {
+                        match token {
+                            Token::LParen => depth += 1,
+                            Token::RParen if depth == 1 => {
+                                pattern_end = i + 1;
+                                break;
+                            }
+                            Token::RParen => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        if pattern_end == pattern_start {
+            // Not a pattern we can rewrite, let the parser report it.
+            output.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+
+        output.push(Token::make_keyword("LIKE"));
+        output.push(Token::Whitespace(Whitespace::Space));
+        output.push(Token::make_word(marker, None));
+        output.push(Token::LParen);
+        output.extend_from_slice(&tokens[pattern_start..pattern_end]);
+        output.push(Token::RParen);
+        i = pattern_end;
+    }
+
+    output
+}
+
+const DISTINCT_ON_MARKER: &str = "__distinct_on";
+
+// The sql parser doesn't know DISTINCT ON, so DISTINCT ON (a, b) x, y gets rewritten to DISTINCT __distinct_on(a, b), x, y,
+// which parse_select then turns back into the keys of the DISTINCT.
+fn rewrite_distinct_on(tokens: Vec<Token>) -> Vec<Token> {
+    let mut output = Vec::with_capacity(tokens.len());
+
+    let mut i = 0;
+    while i < tokens.len() {
+        output.push(tokens[i].clone());
+        i += 1;
+        if !matches!(&tokens[i - 1], Token::Word(word) if word.quote_style.is_none() && word.value.to_uppercase() == "DISTINCT") {
+            continue;
+        }
+        let on_end = match words_following(&tokens, i, &["ON"]) {
+            Some(end) => end,
+            None => continue,
+        };
+        let mut keys_start = on_end;
+        while let Some(Token::Whitespace(_)) = tokens.get(keys_start) {
+            keys_start += 1;
+        }
+        if tokens.get(keys_start) != Some(&Token::LParen) {
+            continue;
+        }
+        let mut depth = 0;
+        let mut keys_end = keys_start;
+        while keys_end < tokens.len() {
+            match tokens[keys_end] {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 1 => break,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+            keys_end += 1;
+        }
+        if keys_end == tokens.len() {
+            // Unbalanced parentheses, let the parser report it.
+            continue;
+        }
+
+        output.push(Token::Whitespace(Whitespace::Space));
+        output.push(Token::make_word(DISTINCT_ON_MARKER, None));
+        output.extend_from_slice(&tokens[keys_start..=keys_end]);
+        output.push(Token::Comma);
+        i = keys_end + 1;
+    }
+
+    output
+}
+
+const MATERIALIZED_MARKER: &str = "__materialized_";
+
+// The sql parser doesn't know WITH x AS [NOT] MATERIALIZED (...), so the keywords get removed,
+// and the name of a materialized common table expression gets prefixed with a marker, which parse_query then strips.
+fn rewrite_materialized(tokens: Vec<Token>) -> Vec<Token> {
+    let is_word = |token: &Token, keyword: &str| matches!(token, Token::Word(word) if word.quote_style.is_none() && word.value.to_uppercase() == keyword);
+    let mut output: Vec<Token> = Vec::with_capacity(tokens.len());
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let (materialized, keywords_end) = if is_word(&tokens[i], "NOT") {
+            (false, words_following(&tokens, i + 1, &["MATERIALIZED"]))
+        } else if is_word(&tokens[i], "MATERIALIZED") {
+            (true, Some(i + 1))
+        } else {
+            (false, None)
+        };
+        let as_index = output.iter().rposition(|token| !matches!(token, Token::Whitespace(_)));
+        let followed_by_query = keywords_end
+            .and_then(|end| tokens[end..].iter().find(|token| !matches!(token, Token::Whitespace(_))))
+            == Some(&Token::LParen);
+        let (keywords_end, as_index) = match (keywords_end, as_index) {
+            (Some(end), Some(as_index)) if followed_by_query && is_word(&output[as_index], "AS") => (end, as_index),
+            _ => {
+                output.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+        };
+
+        if materialized {
+            // The name comes before AS, possibly followed by a parenthesized column list.
+            let mut name_index = as_index;
+            let mut depth = 0;
+            while name_index > 0 {
+                name_index -= 1;
+                match &output[name_index] {
+                    Token::RParen => depth += 1,
+                    Token::LParen => depth -= 1,
+                    Token::Word(word) if depth == 0 => {
+                        output[name_index] = Token::make_word(format!("{}{}", MATERIALIZED_MARKER, word.value).as_str(), word.quote_style);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        i = keywords_end;
+    }
+
+    output
+}
+
+const PARAMETER_MARKER: &str = "__parameter";
+
+// The sql parser doesn't know placeholders, so $1 and :name get rewritten to __parameter('1') and __parameter('name').
+fn rewrite_parameters(tokens: Vec<Token>) -> Vec<Token> {
+    let mut output: Vec<Token> = Vec::with_capacity(tokens.len());
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let name = match (&tokens[i], tokens.get(i + 1)) {
+            (Token::Char('$'), Some(Token::Number(position))) => Some(position.clone()),
+            (Token::Colon, Some(Token::Word(word))) if word.quote_style.is_none() => Some(word.value.clone()),
+            _ => None,
+        };
+        match name {
+            Some(name) => {
+                output.push(Token::make_word(PARAMETER_MARKER, None));
+                output.push(Token::LParen);
+                output.push(Token::SingleQuotedString(name));
+                output.push(Token::RParen);
+                i += 2;
+            }
+            None => {
+                output.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+    }
+
+    output
+}
+
+// Returns the index after the given keywords, if they follow the start index, separated by whitespace.
+fn words_following(tokens: &[Token], start: usize, keywords: &[&str]) -> Option<usize> {
+    let mut i = start;
+    for keyword in keywords {
+        while let Some(Token::Whitespace(_)) = tokens.get(i) {
+            i += 1;
+        }
+        match tokens.get(i) {
+            Some(Token::Word(word)) if word.quote_style.is_none() && word.value.to_uppercase() == *keyword => i += 1,
+            _ => return None,
+        }
+    }
+    Some(i)
+}
+
+pub fn parse_query(sql_query: &sqlparser::ast::Query) -> Result<Box<Query>, ParseError> {
+    let query = parse_ordered_query(sql_query)?;
+    match &sql_query.with {
+        Some(with) => {
+            if with.recursive {
+                return Err(ParseError::not_supported("recursive common table expressions", with));
+            }
+            Ok(Box::new(Query::With {
+                ctes: with.cte_tables.iter().map(parse_cte).collect::<Result<_, _>>()?,
+                query,
+            }))
+        }
+        None => Ok(query),
+    }
+}
+
+pub fn parse_cte(cte: &ast::Cte) -> Result<CommonTableExpression, ParseError> {
+    if !cte.alias.columns.is_empty() {
+        return Err(ParseError::not_supported("column lists of common table expressions", &cte.alias));
+    }
+    let name = cte.alias.name.value.clone();
+    Ok(match name.strip_prefix(MATERIALIZED_MARKER) {
+        Some(name) => CommonTableExpression { name: name.to_string(), query: parse_query(&cte.query)?, materialized: true },
+        None => CommonTableExpression { name, query: parse_query(&cte.query)?, materialized: false },
+    })
+}
+
+fn parse_ordered_query(sql_query: &sqlparser::ast::Query) -> Result<Box<Query>, ParseError> {
+    let mut query = parse_set_expr(&sql_query.body)?;
+    if sql_query.order_by.is_empty() && sql_query.limit.is_none() && sql_query.offset.is_none() {
+        return Ok(query);
+    }
+
+    // The ORDER BY and LIMIT of a set operation apply to all of its records.
+    let set_operation = matches!(query.as_ref(), Query::SetOperation { .. } | Query::With { .. });
+    if set_operation {
+        query = Box::new(Query::Select {
+            expressions: vec![SelectExpression::Wildcard(None)],
+            distinct: false,
+            distinct_on: vec![],
+            filter: None,
+            from: Box::new(Source::Subquery(query, None)),
+            order_by: vec![],
+            group_by: vec![],
+            trigger: vec![],
+            limit: None,
+            offset: None,
+        });
+    }
+    if let Query::Select { expressions, from, order_by, limit, offset, .. } = query.as_mut() {
+        *order_by = sql_query.order_by.iter().map(parse_order_by).collect::<Result<_, _>>()?;
+        // Positions get replaced by the names of the select expressions they refer to when planning,
+        // except for set operations, whose positions refer to the columns of their first query and get replaced right away.
+        let columns = match from.as_ref() {
+            Source::Subquery(subquery, None) if set_operation => first_query_expressions(subquery.as_ref()),
+            _ => expressions.as_slice(),
+        };
+        for (order_by, sql_order_by) in order_by.iter_mut().zip(sql_query.order_by.iter()) {
+            if let Expression::Constant(Value::Integer(position)) = order_by.expr.as_ref() {
+                let index = (*position as usize).wrapping_sub(1);
+                match columns.get(index) {
+                    Some(SelectExpression::Expression(expr, alias)) => if set_operation {
+                        order_by.expr = Box::new(Expression::Variable(column_name(index, expr.as_ref(), alias.as_ref())));
+                    },
+                    Some(SelectExpression::Wildcard(_)) => return Err(ParseError::not_supported("ORDER BY positions of wildcards", &sql_order_by.expr)),
+                    None => return Err(ParseError::invalid_value(format!("ORDER BY position {} doesn't refer to a select expression", position), &sql_order_by.expr)),
+                }
+            }
+        }
+        *limit = sql_query.limit.as_ref().map(parse_row_count).transpose()?;
+        *offset = sql_query.offset.as_ref().map(|offset| parse_row_count(&offset.value)).transpose()?;
+    }
+    Ok(query)
+}
+
+// The select expressions of the first query of a set operation, which name its columns.
+fn first_query_expressions(query: &Query) -> &[SelectExpression] {
+    match query {
+        Query::Select { expressions, .. } => expressions.as_slice(),
+        Query::SetOperation { left, .. } => first_query_expressions(left.as_ref()),
+        Query::With { query, .. } => first_query_expressions(query.as_ref()),
     }
 }
 
-pub fn parse_select(select: &Select) -> Box<Query> {
-    let from = parse_table(&select.from[0].relation);
+// The name of a selected column, as it's named when planning.
+fn column_name(index: usize, expr: &Expression, alias: Option<&Identifier>) -> Identifier {
+    match (alias, expr) {
+        (Some(alias), _) => alias.clone(),
+        (None, Expression::Variable(ident)) => ident.clone(),
+        (None, _) => Identifier::SimpleIdentifier(format!("column_{}", index)),
+    }
+}
+
+pub fn parse_set_expr(set_expr: &SetExpr) -> Result<Box<Query>, ParseError> {
+    match set_expr {
+        SetExpr::Select(select) => parse_select(select.as_ref()),
+        SetExpr::Query(query) => parse_query(query.as_ref()),
+        SetExpr::SetOperation { op, all, left, right } => {
+            Ok(Box::new(Query::SetOperation {
+                operator: parse_set_operator(op),
+                all: *all,
+                left: parse_set_expr(left.as_ref())?,
+                right: parse_set_expr(right.as_ref())?,
+            }))
+        }
+        SetExpr::Values(_) => Err(ParseError::not_supported("VALUES", set_expr)),
+    }
+}
 
-    let expressions = select.projection.iter()
+pub fn parse_set_operator(op: &ast::SetOperator) -> SetOperator {
+    match op {
+        ast::SetOperator::Union => SetOperator::Union,
+        ast::SetOperator::Intersect => SetOperator::Intersect,
+        ast::SetOperator::Except => SetOperator::Except,
+    }
+}
+
+pub fn parse_select(select: &Select) -> Result<Box<Query>, ParseError> {
+    // FROM a, b is the same as FROM a CROSS JOIN b.
+    let mut from = None;
+    for table in &select.from {
+        let mut source = parse_table(&table.relation)?;
+        for join in &table.joins {
+            source = parse_join(source, join)?;
+        }
+        from = Some(match from {
+            None => source,
+            Some(from) => Box::new(Source::Join(from, source)),
+        });
+    }
+    let from = from.ok_or_else(|| ParseError::not_supported("SELECT without FROM", select))?;
+
+    let mut projection = select.projection.as_slice();
+    let mut distinct_on = vec![];
+    if let Some(SelectItem::UnnamedExpr(Expr::Function(Function { name, args, .. }))) = projection.first() {
+        if name.to_string() == DISTINCT_ON_MARKER {
+            distinct_on = args.iter().map(parse_function_arg).collect::<Result<_, _>>()?;
+            projection = &projection[1..];
+        }
+    }
+
+    let expressions = projection.iter()
         .map(parse_select_item)
-        .collect();
+        .collect::<Result<_, _>>()?;
 
-    let filter_expression = select.selection.as_ref().map(parse_expr);
+    let filter_expression = select.selection.as_ref().map(parse_expr).transpose()?;
 
     let group_by_expression = select.group_by.iter()
         .map(parse_expr)
-        .collect();
+        .collect::<Result<_, _>>()?;
 
     let trigger = select.trigger.iter()
         .map(parse_trigger)
-        .collect();
+        .collect::<Result<_, _>>()?;
 
-    Box::new(Query::Select {
+    Ok(Box::new(Query::Select {
         expressions,
+        distinct: select.distinct,
+        distinct_on,
         filter: filter_expression,
         from,
         order_by: vec![],
         group_by: group_by_expression,
-        trigger
+        trigger,
+        limit: None,
+        offset: None,
+    }))
+}
+
+pub fn parse_order_by(order_by: &OrderByExpr) -> Result<OrderByExpression, ParseError> {
+    let descending = order_by.asc == Some(false);
+    Ok(OrderByExpression {
+        expr: parse_expr(&order_by.expr)?,
+        descending,
+        nulls_first: order_by.nulls_first.unwrap_or(descending),
     })
 }
 
-pub fn parse_trigger(trigger: &sqlparser::ast::Trigger) -> Trigger {
+fn parse_row_count(expr: &Expr) -> Result<u64, ParseError> {
+    let invalid = || ParseError::invalid_value("LIMIT and OFFSET must be non-negative integers".to_string(), expr);
+    match expr {
+        Expr::Value(ast::Value::Number(n)) => n.parse().map_err(|_| invalid()),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn parse_trigger(trigger: &sqlparser::ast::Trigger) -> Result<Trigger, ParseError> {
     match trigger {
-        sqlparser::ast::Trigger::Counting(n) => Trigger::Counting(n.clone()),
-        _ => unimplemented!(),
+        sqlparser::ast::Trigger::Counting(n) => Ok(Trigger::Counting(n.clone())),
+        sqlparser::ast::Trigger::Watermark => Err(ParseError::not_supported("watermark triggers", "WATERMARK")),
     }
 }
 
-pub fn parse_select_item(item: &SelectItem) -> SelectExpression {
-    match item {
+pub fn parse_select_item(item: &SelectItem) -> Result<SelectExpression, ParseError> {
+    Ok(match item {
         SelectItem::UnnamedExpr(expr) => {
-            SelectExpression::Expression(parse_expr(expr), None)
+            SelectExpression::Expression(parse_expr(expr)?, None)
         }
         SelectItem::ExprWithAlias { expr, alias } => {
-            SelectExpression::Expression(parse_expr(expr), Some(parse_ident(alias)))
+            SelectExpression::Expression(parse_expr(expr)?, Some(parse_ident(alias)))
         }
         SelectItem::Wildcard => {
             SelectExpression::Wildcard(None)
@@ -90,41 +781,181 @@ pub fn parse_select_item(item: &SelectItem) -> SelectExpression {
         SelectItem::QualifiedWildcard(name) => {
             SelectExpression::Wildcard(Some(name.0[0].value.clone()))
         }
-    }
+    })
 }
 
-pub fn parse_table(table: &TableFactor) -> Box<Source> {
+pub fn parse_table(table: &TableFactor) -> Result<Box<Source>, ParseError> {
     match table {
+        TableFactor::Table { name, alias, args, with_hints: _ } if !args.is_empty() => {
+            let args = args.iter()
+                .map(|arg| match arg {
+                    FunctionArg::Named { name, arg } => Ok((Some(name.value.clone()), parse_expr(arg)?)),
+                    FunctionArg::Unnamed(arg) => Ok((None, parse_expr(arg)?)),
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(Box::new(Source::TableFunction(parse_ident(&name.0[0]), args, alias.clone().map(|alias| parse_ident(&alias.name)))))
+        }
         TableFactor::Table { name, alias, args: _, with_hints: _ } => {
-            return Box::new(Source::Table(parse_compound_ident(&name.0), alias.clone().map(|alias| parse_ident(&alias.name))));
+            Ok(Box::new(Source::Table(parse_compound_ident(&name.0)?, alias.clone().map(|alias| parse_ident(&alias.name)))))
         }
-        TableFactor::Derived { lateral: _, subquery, alias } => {
-            return Box::new(Source::Subquery(parse_query(subquery), alias.clone().map(|alias| parse_ident(&alias.name))));
+        TableFactor::Derived { lateral: true, subquery, alias } => {
+            Ok(Box::new(Source::Lateral(parse_query(subquery)?, alias.clone().map(|alias| parse_ident(&alias.name)))))
         }
-        _ => unimplemented!(),
+        TableFactor::Derived { lateral: false, subquery, alias } => {
+            Ok(Box::new(Source::Subquery(parse_query(subquery)?, alias.clone().map(|alias| parse_ident(&alias.name)))))
+        }
+        _ => Err(ParseError::not_supported("this kind of source", table)),
     }
 }
 
-pub fn parse_expr(expr: &Expr) -> Box<Expression> {
-    match expr {
+pub fn parse_join(source: Box<Source>, join: &Join) -> Result<Box<Source>, ParseError> {
+    match (&join.join_operator, &join.relation) {
+        (JoinOperator::CrossJoin, TableFactor::Table { name, alias, args, with_hints: _ }) if name.to_string().to_uppercase() == "UNNEST" && args.len() == 1 => {
+            // UNNEST(list) AS t(element) names the element t.element, UNNEST(list) AS t just t.
+            let name = match alias {
+                Some(TableAlias { name, columns }) if columns.len() == 1 => Identifier::NamespacedIdentifier(name.value.clone(), columns[0].value.clone()),
+                Some(TableAlias { name, columns: _ }) => parse_ident(name),
+                None => Identifier::SimpleIdentifier("unnest".to_string()),
+            };
+            Ok(Box::new(Source::Unnest(source, parse_function_arg(&args[0])?, name)))
+        }
+        (JoinOperator::CrossJoin, relation) => Ok(Box::new(Source::Join(source, parse_table(relation)?))),
+        _ => Err(ParseError::not_supported("joins other than CROSS JOIN, use WHERE conditions instead", join)),
+    }
+}
+
+pub fn parse_expr(expr: &Expr) -> Result<Box<Expression>, ParseError> {
+    Ok(match expr {
         Expr::Identifier(ident) => {
             Box::new(Expression::Variable(parse_ident(&ident)))
         }
+        // Parts following the qualified name access fields of a struct.
+        Expr::CompoundIdentifier(parts) if parts.len() > 2 => {
+            parts[2..].iter().fold(
+                Box::new(Expression::Variable(parse_compound_ident(&parts[..2].to_vec())?)),
+                |expr, part| Box::new(Expression::FieldAccess(expr, part.value.clone())),
+            )
+        }
         Expr::CompoundIdentifier(parts) => {
-            Box::new(Expression::Variable(parse_compound_ident(parts)))
+            Box::new(Expression::Variable(parse_compound_ident(parts)?))
         }
         Expr::Value(value) => {
-            Box::new(Expression::Constant(parse_value(value)))
+            Box::new(Expression::Constant(parse_value(value)?))
+        }
+        Expr::BinaryOp { left, op: BinaryOperator::Like, right } if rebind_at_time_zone(left, right).is_some() => {
+            parse_expr(&rebind_at_time_zone(left, right).unwrap())?
+        }
+        Expr::BinaryOp { left, op: op @ BinaryOperator::Like, right } | Expr::BinaryOp { left, op: op @ BinaryOperator::NotLike, right } => {
+            let negated = op == &BinaryOperator::NotLike;
+            let (op, pattern) = match right.as_ref() {
+                Expr::Function(Function { name, args, .. }) if name.to_string() == ILIKE_MARKER => {
+                    (if negated { Operator::NotILike } else { Operator::ILike }, parse_function_arg(&args[0])?)
+                }
+                Expr::Function(Function { name, args, .. }) if name.to_string() == REGEXP_MARKER && !negated => {
+                    (Operator::RegexpMatches, parse_function_arg(&args[0])?)
+                }
+                Expr::Function(Function { name, args, .. }) if name.to_string() == AT_TIME_ZONE_MARKER && !negated => {
+                    (Operator::AtTimeZone, parse_function_arg(&args[0])?)
+                }
+                _ => (if negated { Operator::NotLike } else { Operator::Like }, parse_expr(right.as_ref())?),
+            };
+            Box::new(Expression::Operator(parse_expr(left.as_ref())?, op, pattern))
         }
         Expr::BinaryOp { left, op, right } => {
             Box::new(Expression::Operator(
-                parse_expr(left.as_ref()),
-                parse_binary_operator(op),
-                parse_expr(right.as_ref()),
+                parse_expr(left.as_ref())?,
+                parse_binary_operator(op)?,
+                parse_expr(right.as_ref())?,
+            ))
+        }
+        Expr::UnaryOp { op: SQLUnaryOperator::Plus, expr } => {
+            parse_expr(expr.as_ref())?
+        }
+        // Negative numeric literals are parsed as negated positive ones.
+        Expr::UnaryOp { op: SQLUnaryOperator::Minus, expr } if matches!(expr.as_ref(), Expr::Value(ast::Value::Number(_))) => {
+            match expr.as_ref() {
+                Expr::Value(ast::Value::Number(val)) => Box::new(Expression::Constant(parse_number(format!("-{}", val).as_str())?)),
+                _ => unreachable!(),
+            }
+        }
+        Expr::UnaryOp { op, expr } => {
+            Box::new(Expression::UnaryOperator(
+                parse_unary_operator(op)?,
+                parse_expr(expr.as_ref())?,
+            ))
+        }
+        Expr::Nested(expr) => {
+            parse_expr(expr.as_ref())?
+        }
+        Expr::IsNull(expr) => {
+            Box::new(Expression::UnaryOperator(UnaryOperator::IsNull, parse_expr(expr.as_ref())?))
+        }
+        Expr::IsNotNull(expr) => {
+            Box::new(Expression::UnaryOperator(UnaryOperator::IsNotNull, parse_expr(expr.as_ref())?))
+        }
+        Expr::InList { expr, list, negated } => {
+            Box::new(Expression::InList {
+                expr: parse_expr(expr.as_ref())?,
+                list: list.iter().map(parse_expr).collect::<Result<_, _>>()?,
+                negated: *negated,
+            })
+        }
+        Expr::Between { expr, negated, low, high } => {
+            Box::new(Expression::Between {
+                expr: parse_expr(expr.as_ref())?,
+                negated: *negated,
+                low: parse_expr(low.as_ref())?,
+                high: parse_expr(high.as_ref())?,
+            })
+        }
+        Expr::TypedString { data_type, value } => {
+            Box::new(Expression::Cast(Box::new(Expression::Constant(Value::String(value.clone()))), parse_data_type(data_type)?))
+        }
+        Expr::Extract { field, expr } => {
+            Box::new(Expression::Function(
+                Identifier::SimpleIdentifier("date_part".to_string()),
+                vec![Box::new(Expression::Constant(Value::String(field.to_string().to_lowercase()))), parse_expr(expr.as_ref())?],
             ))
         }
-        Expr::Function(Function { name, args, over: _, distinct: _ }) => {
-            Box::new(Expression::Function(parse_ident(&name.0[0]), args.iter().map(parse_function_arg).collect()))
+        Expr::Cast { expr, data_type } => {
+            Box::new(Expression::Cast(parse_expr(expr.as_ref())?, parse_data_type(data_type)?))
+        }
+        Expr::Function(Function { name, args, over: _, distinct: _ }) if name.0.len() == 1 && name.0[0].value.to_uppercase() == "TRY_CAST" => {
+            match args.as_slice() {
+                [FunctionArg::Unnamed(Expr::Cast { expr, data_type })] => {
+                    Box::new(Expression::TryCast(parse_expr(expr.as_ref())?, parse_data_type(data_type)?))
+                }
+                _ => return Err(ParseError::invalid_value("TRY_CAST must be of the form TRY_CAST(expr AS type)".to_string(), expr)),
+            }
+        }
+        Expr::Function(Function { name, args, .. }) if name.to_string() == SUBSCRIPT_MARKER => {
+            Box::new(Expression::Subscript(parse_function_arg(&args[0])?, parse_function_arg(&args[1])?))
+        }
+        Expr::Function(Function { name, args, .. }) if name.to_string() == FIELD_MARKER => {
+            match &args[1] {
+                FunctionArg::Unnamed(Expr::Value(ast::Value::SingleQuotedString(field))) => {
+                    Box::new(Expression::FieldAccess(parse_function_arg(&args[0])?, field.clone()))
+                }
+                _ => unreachable!(),
+            }
+        }
+        Expr::Function(Function { name, args, over: Some(window), distinct: _ }) => {
+            Box::new(Expression::Window {
+                function: parse_ident(&name.0[0]),
+                args: args.iter().map(parse_function_arg).collect::<Result<_, _>>()?,
+                partition_by: window.partition_by.iter().map(parse_expr).collect::<Result<_, _>>()?,
+                order_by: window.order_by.iter().map(parse_order_by).collect::<Result<_, _>>()?,
+                frame: window.window_frame.as_ref().map(parse_window_frame).transpose()?,
+            })
+        }
+        Expr::Function(Function { name, args, over: None, distinct: _ }) if name.to_string() == PARAMETER_MARKER => {
+            match args.as_slice() {
+                [FunctionArg::Unnamed(Expr::Value(ast::Value::SingleQuotedString(name)))] => Box::new(Expression::Parameter(name.clone())),
+                _ => unreachable!(),
+            }
+        }
+        Expr::Function(Function { name, args, over: None, distinct: _ }) => {
+            Box::new(Expression::Function(parse_ident(&name.0[0]), args.iter().map(parse_function_arg).collect::<Result<_, _>>()?))
         }
         Expr::Wildcard => {
             Box::new(Expression::Wildcard(None))
@@ -133,56 +964,198 @@ pub fn parse_expr(expr: &Expr) -> Box<Expression> {
             Box::new(Expression::Wildcard(Some(idents[0].value.clone())))
         }
         Expr::Subquery(subquery) => {
-            Box::new(Expression::Subquery(parse_query(subquery)))
+            Box::new(Expression::Subquery(parse_query(subquery)?))
+        }
+        Expr::Case { operand, conditions, results, else_result } => {
+            Box::new(Expression::Case {
+                operand: operand.as_ref().map(|expr| parse_expr(expr.as_ref())).transpose()?,
+                conditions: conditions.iter().map(parse_expr).collect::<Result<_, _>>()?,
+                results: results.iter().map(parse_expr).collect::<Result<_, _>>()?,
+                else_result: else_result.as_ref().map(|expr| parse_expr(expr.as_ref())).transpose()?,
+            })
+        }
+        _ => return Err(ParseError::not_supported("this expression", expr)),
+    })
+}
+
+// AT TIME ZONE binds tighter than any other operator, but after the rewrite it gets parsed with the precedence of LIKE,
+// so i.e. x AT TIME ZONE z + y ends up as x LIKE (__at_time_zone(z) + y). This moves the other operator back outside.
+fn rebind_at_time_zone(left: &Expr, right: &Expr) -> Option<Expr> {
+    fn starts_with_marker(expr: &Expr) -> bool {
+        match expr {
+            Expr::Function(Function { name, .. }) => name.to_string() == AT_TIME_ZONE_MARKER,
+            Expr::BinaryOp { left, .. } => starts_with_marker(left.as_ref()),
+            _ => false,
+        }
+    }
+    let like = |left: &Expr, right: &Expr| Expr::BinaryOp { left: Box::new(left.clone()), op: BinaryOperator::Like, right: Box::new(right.clone()) };
+
+    match (left, right) {
+        (_, Expr::BinaryOp { left: inner_left, op, right: inner_right }) if starts_with_marker(inner_left.as_ref()) => {
+            Some(Expr::BinaryOp { left: Box::new(like(left, inner_left.as_ref())), op: op.clone(), right: inner_right.clone() })
         }
-        _ => {
-            dbg!(expr);
-            unimplemented!()
+        (Expr::BinaryOp { left: inner_left, op, right: inner_right }, _) if starts_with_marker(right) && op != &BinaryOperator::And && op != &BinaryOperator::Or => {
+            Some(Expr::BinaryOp { left: inner_left.clone(), op: op.clone(), right: Box::new(like(inner_right.as_ref(), right)) })
         }
+        _ => None,
     }
 }
 
-pub fn parse_function_arg(arg: &FunctionArg) -> Box<Expression> {
+pub fn parse_window_frame(frame: &ast::WindowFrame) -> Result<WindowFrame, ParseError> {
+    let units = match frame.units {
+        ast::WindowFrameUnits::Rows => WindowFrameUnits::Rows,
+        ast::WindowFrameUnits::Range => WindowFrameUnits::Range,
+        ast::WindowFrameUnits::Groups => return Err(ParseError::not_supported("GROUPS window frames", &frame.units)),
+    };
+    let parse_bound = |bound: &ast::WindowFrameBound| match bound {
+        ast::WindowFrameBound::Preceding(n) => WindowFrameBound::Preceding(*n),
+        ast::WindowFrameBound::CurrentRow => WindowFrameBound::CurrentRow,
+        ast::WindowFrameBound::Following(n) => WindowFrameBound::Following(*n),
+    };
+    Ok(WindowFrame {
+        units,
+        start: parse_bound(&frame.start_bound),
+        // ROWS 3 PRECEDING is short for ROWS BETWEEN 3 PRECEDING AND CURRENT ROW.
+        end: frame.end_bound.as_ref().map(parse_bound).unwrap_or(WindowFrameBound::CurrentRow),
+    })
+}
+
+pub fn parse_function_arg(arg: &FunctionArg) -> Result<Box<Expression>, ParseError> {
     match arg {
         FunctionArg::Unnamed(expr) => parse_expr(expr),
-        _ => unimplemented!(),
+        FunctionArg::Named { .. } => Err(ParseError::not_supported("named arguments of functions", arg)),
     }
 }
 
-pub fn parse_value(value: &ast::Value) -> Value {
+pub fn parse_value(value: &ast::Value) -> Result<Value, ParseError> {
     match value {
         ast::Value::Number(val) => {
-            let val_str: &str = val.as_str();
-            Value::Integer(val_str.parse::<i64>().unwrap())
+            parse_number(val.as_str())
         },
         ast::Value::SingleQuotedString(val) => {
-            Value::String(val.clone())
+            Ok(Value::String(val.clone()))
+        },
+        ast::Value::Boolean(val) => {
+            Ok(Value::Boolean(*val))
         },
-        _ => {
-            dbg!(value);
-            unimplemented!()
+        ast::Value::Null => {
+            Ok(Value::Null)
+        },
+        ast::Value::Interval { value, leading_field, .. } => {
+            parse_interval(value.as_str(), leading_field.as_ref())
+                .map_err(|message| ParseError::invalid_value(message, format!("INTERVAL '{}'", value)))
+        },
+        _ => Err(ParseError::not_supported("this kind of literal", value)),
+    }
+}
+
+fn parse_number(text: &str) -> Result<Value, ParseError> {
+    match text.parse::<i64>() {
+        Ok(val) => Ok(Value::Integer(val)),
+        Err(_) => match text.parse::<f64>() {
+            Ok(_) => Ok(Value::Float(text.to_string())),
+            Err(_) => Err(ParseError::invalid_value(format!("invalid number: {}", text), text)),
         }
     }
 }
 
-pub fn parse_binary_operator(op: &BinaryOperator) -> Operator {
-    match op {
+// Accepts both INTERVAL '3' DAY and INTERVAL '1 day 2 hours'.
+// Months and years can't be mixed with smaller units, as they don't have a fixed length.
+fn parse_interval(text: &str, leading_field: Option<&DateTimeField>) -> Result<Value, String> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    let pairs: Vec<(&str, String)> = match leading_field {
+        Some(field) if parts.len() == 1 => vec![(parts[0], field.to_string().to_lowercase())],
+        _ if parts.len() % 2 == 0 => parts.chunks(2).map(|pair| (pair[0], pair[1].to_lowercase())).collect(),
+        _ => return Err(format!("invalid interval: '{}'", text)),
+    };
+
+    let mut months: i64 = 0;
+    let mut milliseconds: f64 = 0.0;
+    for (amount, unit) in pairs {
+        let amount: f64 = amount.parse().map_err(|_| format!("invalid interval amount: '{}'", amount))?;
+        let unit = if unit == "ms" { "millisecond" } else { unit.trim_end_matches('s') };
+        match unit {
+            "year" => months += (amount * 12.0) as i64,
+            "month" | "mon" => months += amount as i64,
+            "week" => milliseconds += amount * 604_800_000.0,
+            "day" => milliseconds += amount * 86_400_000.0,
+            "hour" => milliseconds += amount * 3_600_000.0,
+            "minute" | "min" => milliseconds += amount * 60_000.0,
+            "second" | "sec" => milliseconds += amount * 1000.0,
+            "millisecond" => milliseconds += amount,
+            _ => return Err(format!("invalid interval unit: '{}'", unit)),
+        }
+    }
+
+    // Day-time intervals store the number of days in 32 bits.
+    let out_of_range = || format!("interval out of range: '{}'", text);
+    match (months, milliseconds) {
+        (0, milliseconds) if (milliseconds / 86_400_000.0).abs() >= i32::MAX as f64 => Err(out_of_range()),
+        (0, milliseconds) => Ok(Value::IntervalDayTime(milliseconds.round() as i64)),
+        (months, milliseconds) if milliseconds == 0.0 => i32::try_from(months).map(Value::IntervalYearMonth).map_err(|_| out_of_range()),
+        _ => Err(format!("interval '{}' can't mix months with smaller units", text)),
+    }
+}
+
+pub fn parse_binary_operator(op: &BinaryOperator) -> Result<Operator, ParseError> {
+    Ok(match op {
         BinaryOperator::Lt => Operator::Lt,
         BinaryOperator::LtEq => Operator::LtEq,
         BinaryOperator::Eq => Operator::Eq,
+        BinaryOperator::NotEq => Operator::NotEq,
         BinaryOperator::GtEq => Operator::GtEq,
         BinaryOperator::Gt => Operator::Gt,
-        _ => unimplemented!(),
+        BinaryOperator::Plus => Operator::Plus,
+        BinaryOperator::Minus => Operator::Minus,
+        BinaryOperator::Multiply => Operator::Multiply,
+        BinaryOperator::Divide => Operator::Divide,
+        BinaryOperator::Modulus => Operator::Modulo,
+        BinaryOperator::And => Operator::AND,
+        BinaryOperator::Or => Operator::OR,
+        BinaryOperator::StringConcat => Operator::Concat,
+        _ => return Err(ParseError::not_supported(&format!("the {} operator", op), op)),
+    })
+}
+
+pub fn parse_unary_operator(op: &SQLUnaryOperator) -> Result<UnaryOperator, ParseError> {
+    match op {
+        SQLUnaryOperator::Not => Ok(UnaryOperator::NOT),
+        SQLUnaryOperator::Minus => Ok(UnaryOperator::Minus),
+        _ => Err(ParseError::not_supported(&format!("the unary {} operator", op), op)),
     }
 }
 
-pub fn parse_compound_ident(parts: &Vec<Ident>) -> Identifier {
+pub fn parse_data_type(data_type: &SQLDataType) -> Result<Type, ParseError> {
+    Ok(match data_type {
+        SQLDataType::Boolean => Type::Boolean,
+        SQLDataType::SmallInt => Type::Int16,
+        SQLDataType::Int => Type::Int32,
+        SQLDataType::BigInt => Type::Int64,
+        SQLDataType::Real => Type::Float32,
+        SQLDataType::Float(Some(precision)) if *precision <= 24 => Type::Float32,
+        SQLDataType::Float(_) | SQLDataType::Double => Type::Float64,
+        SQLDataType::Char(_) | SQLDataType::Varchar(_) | SQLDataType::Text => Type::String,
+        SQLDataType::Date => Type::Date,
+        SQLDataType::Timestamp => Type::Timestamp,
+        SQLDataType::Custom(name) => {
+            match name.to_string().to_lowercase().as_str() {
+                "bool" => Type::Boolean,
+                "tinyint" => Type::Int8,
+                "string" => Type::String,
+                _ => return Err(ParseError::not_supported(&format!("the type {}", data_type), data_type)),
+            }
+        }
+        _ => return Err(ParseError::not_supported(&format!("the type {}", data_type), data_type)),
+    })
+}
+
+pub fn parse_compound_ident(parts: &Vec<Ident>) -> Result<Identifier, ParseError> {
     if parts.len() == 1 {
-        Identifier::SimpleIdentifier(parts[0].value.clone())
+        Ok(Identifier::SimpleIdentifier(parts[0].value.clone()))
     } else if parts.len() == 2 {
-        Identifier::NamespacedIdentifier(parts[0].value.clone(), parts[1].value.clone())
+        Ok(Identifier::NamespacedIdentifier(parts[0].value.clone(), parts[1].value.clone()))
     } else {
-        unimplemented!()
+        Err(ParseError::not_supported("identifiers with more than two parts", ast::ObjectName(parts.clone())))
     }
 }
 
@@ -190,11 +1163,180 @@ pub fn parse_ident(ident: &Ident) -> Identifier {
     Identifier::SimpleIdentifier(ident.value.clone())
 }
 
+// Parses a SELECT query and binds the given fields of it, failing the test for any other kind of query.
+#[cfg(test)]
+macro_rules! parse_select_fields {
+    ($sql: expr, $($field: ident),+) => {
+        let query = parse_sql($sql).unwrap();
+        let ($($field,)+) = match *query {
+            Query::Select { $($field,)+ .. } => ($($field,)+),
+            other => panic!("expected a SELECT query, got {:?}", other),
+        };
+    }
+}
+
 #[test]
 fn test() {
     let sql = "SELECT c2.name as name, c2.livesleft, 3 as myconst \
     FROM (SELECT c.name, c.livesleft, c.age FROM cats c) as c2 \
     WHERE c2.age = c2.livesleft";
 
-    parse_sql(sql);
+    parse_sql(sql).unwrap();
+}
+
+#[test]
+fn test_try_cast() {
+    let sql = "SELECT TRY_CAST(c.age AS INT), CAST(c.age AS TEXT), c.age::bigint FROM cats c";
+
+    parse_select_fields!(sql, expressions);
+    let age = || Box::new(Expression::Variable(Identifier::NamespacedIdentifier("c".to_string(), "age".to_string())));
+    assert_eq!(expressions, vec![
+        SelectExpression::Expression(Box::new(Expression::TryCast(age(), Type::Int32)), None),
+        SelectExpression::Expression(Box::new(Expression::Cast(age(), Type::String)), None),
+        SelectExpression::Expression(Box::new(Expression::Cast(age(), Type::Int64)), None),
+    ]);
+}
+
+#[test]
+fn test_nested_access() {
+    let sql = "SELECT e.payload.user.id, e.tags[1], e.items[e.n + 1].name, t.tag FROM events e CROSS JOIN UNNEST(e.tags) AS t(tag)";
+
+    parse_select_fields!(sql, expressions, from);
+    let variable = |namespace: &str, name: &str| Box::new(Expression::Variable(Identifier::NamespacedIdentifier(namespace.to_string(), name.to_string())));
+    let field = |expr, name: &str| Box::new(Expression::FieldAccess(expr, name.to_string()));
+    let index = Box::new(Expression::Operator(variable("e", "n"), Operator::Plus, Box::new(Expression::Constant(Value::Integer(1)))));
+    assert_eq!(expressions, vec![
+        SelectExpression::Expression(field(field(variable("e", "payload"), "user"), "id"), None),
+        SelectExpression::Expression(Box::new(Expression::Subscript(variable("e", "tags"), Box::new(Expression::Constant(Value::Integer(1))))), None),
+        SelectExpression::Expression(field(Box::new(Expression::Subscript(variable("e", "items"), index)), "name"), None),
+        SelectExpression::Expression(variable("t", "tag"), None),
+    ]);
+    let events = Box::new(Source::Table(Identifier::SimpleIdentifier("events".to_string()), Some(Identifier::SimpleIdentifier("e".to_string()))));
+    assert_eq!(from, Box::new(Source::Unnest(events, variable("e", "tags"), Identifier::NamespacedIdentifier("t".to_string(), "tag".to_string()))));
+}
+
+#[test]
+fn test_distinct_on() {
+    let sql = "SELECT DISTINCT ON (c.name, lower(c.color)) c.name, c.age FROM cats c";
+
+    parse_select_fields!(sql, expressions, distinct, distinct_on);
+    let variable = |name: &str| Box::new(Expression::Variable(Identifier::NamespacedIdentifier("c".to_string(), name.to_string())));
+    assert!(distinct);
+    assert_eq!(distinct_on, vec![
+        variable("name"),
+        Box::new(Expression::Function(Identifier::SimpleIdentifier("lower".to_string()), vec![variable("color")])),
+    ]);
+    assert_eq!(expressions, vec![
+        SelectExpression::Expression(variable("name"), None),
+        SelectExpression::Expression(variable("age"), None),
+    ]);
+}
+
+#[test]
+fn test_with() {
+    let sql = "WITH a AS MATERIALIZED (SELECT c.name FROM cats c), b AS NOT MATERIALIZED (SELECT a.name FROM a) SELECT b.name FROM b";
+
+    let query = parse_sql(sql).unwrap();
+    if let Query::With { ctes, .. } = query.as_ref() {
+        let names = ctes.iter().map(|cte| (cte.name.as_str(), cte.materialized)).collect::<Vec<_>>();
+        assert_eq!(names, vec![("a", true), ("b", false)]);
+    } else {
+        panic!("expected a WITH query, got {:?}", query);
+    }
+}
+
+#[test]
+fn test_cross_join() {
+    let sql = "SELECT a.id FROM teams a, goals b CROSS JOIN cats c";
+
+    parse_select_fields!(sql, from);
+    let table = |name: &str, alias: &str| Box::new(Source::Table(
+        Identifier::SimpleIdentifier(name.to_string()),
+        Some(Identifier::SimpleIdentifier(alias.to_string())),
+    ));
+    let expected = Source::Join(table("teams", "a"), Box::new(Source::Join(table("goals", "b"), table("cats", "c"))));
+    assert_eq!(*from, expected);
+}
+
+#[test]
+fn test_lateral_and_table_function() {
+    let sql = "SELECT r.range FROM range(1, 10, step => 2) r, LATERAL (SELECT c.name FROM cats c WHERE c.id = r.range) x";
+
+    parse_select_fields!(sql, from);
+    match from.as_ref() {
+        Source::Join(left, right) => {
+            let arg = |name: Option<&str>, value| (name.map(str::to_string), Box::new(Expression::Constant(Value::Integer(value))));
+            assert_eq!(left.as_ref(), &Source::TableFunction(
+                Identifier::SimpleIdentifier("range".to_string()),
+                vec![arg(None, 1), arg(None, 10), arg(Some("step"), 2)],
+                Some(Identifier::SimpleIdentifier("r".to_string())),
+            ));
+            assert!(matches!(right.as_ref(), Source::Lateral(_, Some(_))));
+        }
+        other => panic!("expected a join, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parameters() {
+    let sql = "SELECT c.name FROM cats c WHERE c.age > $1 AND c.name = :name AND c.id::INT = 3";
+
+    parse_select_fields!(sql, filter);
+    let filter = filter.expect("the query has a filter");
+    let mut parameters = vec![];
+    let mut conditions = vec![filter.as_ref()];
+    while let Some(condition) = conditions.pop() {
+        match condition {
+            Expression::Operator(left, _, right) => conditions.extend(vec![right.as_ref(), left.as_ref()]),
+            Expression::Parameter(name) => parameters.push(name.as_str()),
+            _ => {}
+        }
+    }
+    assert_eq!(parameters, vec!["1", "name"]);
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = parse_sql("SELECT c.name\nFROM cats c\nWHERE c.age & 1 = 1").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::NotSupported);
+    assert_eq!(err.message, "not supported yet: the & operator");
+    assert_eq!(err.position, Some((3, 13)));
+
+    let err = parse_sql("SELECT c.name FROM cats c WHERE c.age > INTERVAL '3 lives'").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::InvalidValue);
+    assert_eq!(err.position, Some((1, 41)));
+
+    let err = parse_sql("SELECT c.name FROM cats c WHERE c.age > INTERVAL '9999999999 days'").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::InvalidValue);
+    assert_eq!(err.message, "interval out of range: '9999999999 days'");
+
+    let err = parse_sql("SELECT c.name, c.age FROM cats c ORDER BY 3").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::InvalidValue);
+    assert_eq!(err.message, "ORDER BY position 3 doesn't refer to a select expression");
+    assert_eq!(err.position, Some((1, 43)));
+
+    let err = parse_sql("SELECT c.name FROM cats c WHERE c.age > )").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::Syntax);
+    assert_eq!(err.fragment, ")");
+    assert_eq!(err.position, Some((1, 41)));
+
+    let err = parse_sql("SELECT c.name FROM cats c c.age").unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::Syntax);
+    assert_eq!(err.position, Some((1, 27)));
+
+    // Errors in constructs which get rewritten before parsing are reported with the query as written.
+    let err = parse_sql("SELECT c.tags[1] COLLATE x FROM cats c").unwrap_err();
+    assert_eq!(err.fragment, "c.tags[1] COLLATE x");
+    assert_eq!(err.position, Some((1, 8)));
+
+    let err = parse_sql("SELECT TRY_CAST(c.age AS FOO), c.name ILIKE :name FROM cats c").unwrap_err();
+    assert_eq!(err.fragment, "FOO");
+    assert_eq!(err.position, Some((1, 26)));
+
+    let err = parse_sql("SELECT DISTINCT ON (c.name) c.name FROM cats c WHERE c.t AT TIME ZONE 'UTC' & $1").unwrap_err();
+    assert_eq!(err.fragment, "&");
+    assert_eq!(err.position, Some((1, 77)));
+
+    let err = parse_sql("WITH a AS MATERIALIZED (SELECT c.name FROM cats c) SELECT a.name FROM a WHERE a.name ~ a.x[1 FROM a").unwrap_err();
+    assert!(!err.to_string().contains("__"), "{}", err);
 }
@@ -83,6 +83,10 @@ macro_rules! impl_sum_accumulator {
                     ScalarValue::Boolean(x) => x,
                     _ => panic!("retraction shall be boolean"),
                 };
+                // Nulls are skipped, as in SQL.
+                if value == ScalarValue::Null {
+                    return self.count != 0;
+                }
                 if !is_retraction {
                     self.count += 1;
                 } else {
@@ -101,6 +105,10 @@ macro_rules! impl_sum_accumulator {
             }
 
             fn trigger(&self) -> ScalarValue {
+                // The sum of no values (or only nulls) is null.
+                if self.count == 0 {
+                    return ScalarValue::Null;
+                }
                 return ScalarValue::$scalar_value_type(self.sum);
             }
         }
@@ -136,11 +144,15 @@ struct CountAccumulator {
 }
 
 impl Accumulator for CountAccumulator {
-    fn add(&mut self, _value: ScalarValue, retract: ScalarValue) -> bool {
+    fn add(&mut self, value: ScalarValue, retract: ScalarValue) -> bool {
         let is_retraction = match retract {
             ScalarValue::Boolean(x) => x,
             _ => panic!("retraction shall be boolean"),
         };
+        // Nulls aren't counted, as in SQL.
+        if value == ScalarValue::Null {
+            return self.count != 0;
+        }
         let _multiplier = if !is_retraction { 1 } else { -1 };
         if is_retraction {
             self.count -= 1;
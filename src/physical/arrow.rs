@@ -29,15 +29,23 @@
 use std::sync::Arc;
 
 use arrow::array;
-use arrow::array::{BooleanArray, Int8Array, Int16Array, Int32Array, Int64Array, UInt8Array, UInt16Array, UInt32Array, UInt64Array, Float32Array, Float64Array, Date32Array, Date64Array, Time32SecondArray, Time32MillisecondArray, Time64MicrosecondArray, Time64NanosecondArray, TimestampSecondArray, TimestampMillisecondArray, TimestampMicrosecondArray, TimestampNanosecondArray, IntervalYearMonthArray, IntervalDayTimeArray, DurationSecondArray, DurationMillisecondArray, DurationMicrosecondArray, DurationNanosecondArray, BinaryArray, LargeBinaryArray, FixedSizeBinaryArray, StringArray, LargeStringArray, ListArray, LargeListArray, StructArray, UnionArray, FixedSizeListArray, NullArray, DictionaryArray, ArrayRef, ArrayDataRef};
-use arrow::datatypes::{DataType, TimeUnit, DateUnit, Int8Type, Int16Type, Int32Type, Int64Type, UInt8Type, UInt16Type, UInt32Type, UInt64Type, IntervalUnit};
+use arrow::array::{Array, BooleanArray, Int8Array, Int16Array, Int32Array, Int64Array, UInt8Array, UInt16Array, UInt32Array, UInt64Array, Float32Array, Float64Array, Date32Array, Date64Array, Time32SecondArray, Time32MillisecondArray, Time64MicrosecondArray, Time64NanosecondArray, TimestampSecondArray, TimestampMillisecondArray, TimestampMicrosecondArray, TimestampNanosecondArray, IntervalYearMonthArray, IntervalDayTimeArray, DurationSecondArray, DurationMillisecondArray, DurationMicrosecondArray, DurationNanosecondArray, BinaryArray, LargeBinaryArray, FixedSizeBinaryArray, StringArray, LargeStringArray, ListArray, LargeListArray, StructArray, UnionArray, FixedSizeListArray, NullArray, DictionaryArray, ArrayRef, ArrayDataRef, ArrayDataBuilder, BooleanBufferBuilder, BooleanBuilder, BufferBuilderTrait, StringBuilder, UInt32Builder};
+use arrow::buffer::MutableBuffer;
+use arrow::compute::kernels::concat::concat;
+use arrow::compute::kernels::filter::filter;
+use arrow::compute::kernels::take::take;
+use arrow::datatypes::{DataType, Schema, TimeUnit, DateUnit, Int8Type, Int16Type, Int32Type, Int64Type, UInt8Type, UInt16Type, UInt32Type, UInt64Type, IntervalUnit};
+use arrow::record_batch::RecordBatch;
 
+use crate::physical::datetime::{timestamp_type, with_time_zone};
 use crate::physical::physical::{Error, ScalarValue};
 
 /// Enumeration of types that can be used in a GROUP BY expression (all primitives except
 /// for floating point numerics)
+/// All null values are equal to each other here, so that they form a single group.
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Ord, PartialOrd)]
 pub enum GroupByScalar {
+    Null,
     Boolean(bool),
     UInt8(u8),
     UInt16(u16),
@@ -48,6 +56,8 @@ pub enum GroupByScalar {
     Int32(i32),
     Int64(i64),
     Utf8(String),
+    Date32(i32),
+    Timestamp(i64),
 }
 
 /// Create a Vec<GroupByScalar> that can be used as a map key
@@ -58,6 +68,10 @@ pub fn create_key(
 ) -> Result<(), Error> {
     for i in 0..group_by_keys.len() {
         let col = &group_by_keys[i];
+        if col.is_null(row) {
+            vec[i] = GroupByScalar::Null;
+            continue;
+        }
         match col.data_type() {
             DataType::Boolean => {
                 let array = col.as_any().downcast_ref::<BooleanArray>().unwrap();
@@ -99,61 +113,13 @@ pub fn create_key(
                 let array = col.as_any().downcast_ref::<StringArray>().unwrap();
                 vec[i] = GroupByScalar::Utf8(String::from(array.value(row)))
             }
-            _ => {
-                return Err(Error::Unexpected);
-            }
-        }
-    }
-    Ok(())
-}
-
-pub fn create_row(
-    columns: &[ArrayRef],
-    row: usize,
-    vec: &mut Vec<ScalarValue>,
-) -> Result<(), Error> {
-    for i in 0..columns.len() {
-        let col = &columns[i];
-        match col.data_type() {
-            DataType::Boolean => {
-                let array = col.as_any().downcast_ref::<BooleanArray>().unwrap();
-                vec[i] = ScalarValue::Boolean(array.value(row))
-            }
-            DataType::UInt8 => {
-                let array = col.as_any().downcast_ref::<UInt8Array>().unwrap();
-                vec[i] = ScalarValue::UInt8(array.value(row))
-            }
-            DataType::UInt16 => {
-                let array = col.as_any().downcast_ref::<UInt16Array>().unwrap();
-                vec[i] = ScalarValue::UInt16(array.value(row))
-            }
-            DataType::UInt32 => {
-                let array = col.as_any().downcast_ref::<UInt32Array>().unwrap();
-                vec[i] = ScalarValue::UInt32(array.value(row))
-            }
-            DataType::UInt64 => {
-                let array = col.as_any().downcast_ref::<UInt64Array>().unwrap();
-                vec[i] = ScalarValue::UInt64(array.value(row))
-            }
-            DataType::Int8 => {
-                let array = col.as_any().downcast_ref::<Int8Array>().unwrap();
-                vec[i] = ScalarValue::Int8(array.value(row))
-            }
-            DataType::Int16 => {
-                let array = col.as_any().downcast_ref::<Int16Array>().unwrap();
-                vec[i] = ScalarValue::Int16(array.value(row))
-            }
-            DataType::Int32 => {
-                let array = col.as_any().downcast_ref::<Int32Array>().unwrap();
-                vec[i] = ScalarValue::Int32(array.value(row))
+            DataType::Date32(DateUnit::Day) => {
+                let array = col.as_any().downcast_ref::<Date32Array>().unwrap();
+                vec[i] = GroupByScalar::Date32(array.value(row))
             }
-            DataType::Int64 => {
-                let array = col.as_any().downcast_ref::<Int64Array>().unwrap();
-                vec[i] = ScalarValue::Int64(array.value(row))
-            }
-            DataType::Utf8 => {
-                let array = col.as_any().downcast_ref::<StringArray>().unwrap();
-                vec[i] = ScalarValue::Utf8(String::from(array.value(row)))
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                let array = col.as_any().downcast_ref::<TimestampNanosecondArray>().unwrap();
+                vec[i] = GroupByScalar::Timestamp(array.value(row))
             }
             _ => {
                 return Err(Error::Unexpected);
@@ -253,6 +219,34 @@ pub fn get_scalar_value(array: &ArrayRef, row: usize) -> Result<ScalarValue, Err
                 .unwrap();
             ScalarValue::Utf8(array.value(row).to_string())
         }
+        DataType::Date32(DateUnit::Day) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<array::Date32Array>()
+                .unwrap();
+            ScalarValue::Date32(array.value(row))
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<array::TimestampNanosecondArray>()
+                .unwrap();
+            ScalarValue::Timestamp(array.value(row))
+        }
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<array::IntervalYearMonthArray>()
+                .unwrap();
+            ScalarValue::IntervalYearMonth(array.value(row))
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<array::IntervalDayTimeArray>()
+                .unwrap();
+            ScalarValue::IntervalDayTime(array.value(row))
+        }
         DataType::Struct(_fields) => {
             let array = array
                 .as_any()
@@ -273,23 +267,432 @@ pub fn get_scalar_value(array: &ArrayRef, row: usize) -> Result<ScalarValue, Err
     Ok(value)
 }
 
-macro_rules! compute_single_arg {
-    ($arg:expr, $input_type:ident, $output_builder:ident, $op:expr) => {{
-        let arg = $arg
-            .as_any()
-            .downcast_ref::<$input_type>()
-            .expect("compute_single_arg failed to downcast array");
-
-        let mut result = $output_builder::new($arg.len());
-        for i in 0..$arg.len() {
-            result.append_value($op(arg.value(i))?)?;
+macro_rules! repeat_primitive {
+    ($builder:ident, $value:expr, $len:expr) => {{
+        let mut array = array::$builder::new($len);
+        for _i in 0..$len {
+            array.append_value($value)?;
         }
+        Arc::new(array.finish()) as ArrayRef
+    }};
+}
 
-        Ok(Arc::new(result.finish()) as ArrayRef)
+/// Creates an array of the given length, where each value is the given scalar.
+pub fn scalar_to_array(value: &ScalarValue, len: usize) -> Result<ArrayRef, Error> {
+    Ok(match value {
+        ScalarValue::Null => Arc::new(NullArray::new(len)) as ArrayRef,
+        ScalarValue::Boolean(v) => repeat_primitive!(BooleanBuilder, *v, len),
+        ScalarValue::Float32(v) => repeat_primitive!(Float32Builder, *v, len),
+        ScalarValue::Float64(v) => repeat_primitive!(Float64Builder, *v, len),
+        ScalarValue::Int8(v) => repeat_primitive!(Int8Builder, *v, len),
+        ScalarValue::Int16(v) => repeat_primitive!(Int16Builder, *v, len),
+        ScalarValue::Int32(v) => repeat_primitive!(Int32Builder, *v, len),
+        ScalarValue::Int64(v) => repeat_primitive!(Int64Builder, *v, len),
+        ScalarValue::UInt8(v) => repeat_primitive!(UInt8Builder, *v, len),
+        ScalarValue::UInt16(v) => repeat_primitive!(UInt16Builder, *v, len),
+        ScalarValue::UInt32(v) => repeat_primitive!(UInt32Builder, *v, len),
+        ScalarValue::UInt64(v) => repeat_primitive!(UInt64Builder, *v, len),
+        ScalarValue::Utf8(v) => repeat_primitive!(StringBuilder, v.as_str(), len),
+        ScalarValue::Date32(v) => repeat_primitive!(Date32Builder, *v, len),
+        ScalarValue::Timestamp(v) => repeat_primitive!(TimestampNanosecondBuilder, *v, len),
+        ScalarValue::IntervalYearMonth(v) => repeat_primitive!(IntervalYearMonthBuilder, *v, len),
+        ScalarValue::IntervalDayTime(v) => repeat_primitive!(IntervalDayTimeBuilder, *v, len),
+        ScalarValue::Struct(values) => {
+            let fields = match value.data_type() {
+                DataType::Struct(fields) => fields,
+                _ => unreachable!(),
+            };
+            let columns = values.iter()
+                .map(|value| scalar_to_array(value, len))
+                .collect::<Result<Vec<_>, Error>>()?;
+            Arc::new(StructArray::from(fields.into_iter().zip(columns).collect::<Vec<_>>())) as ArrayRef
+        }
+    })
+}
+
+macro_rules! build_primitive {
+    ($builder:ident, $variant:ident, $values:expr, $data_type:expr, $value:ident => $converted:expr) => {{
+        let mut array = array::$builder::new($values.len());
+        for value in $values {
+            match value {
+                ScalarValue::$variant($value) => array.append_value($converted)?,
+                ScalarValue::Null => array.append_null()?,
+                other => return Err(Error::BadInput(format!("can't put {:?} into an array of {:?}", other, $data_type))),
+            }
+        }
+        Arc::new(array.finish()) as ArrayRef
     }};
 }
 
-macro_rules! compute_single_arg_str {
+/// Creates an array of the given type out of the scalars, which have to be of that type or null.
+pub fn scalars_to_array(values: &[ScalarValue], data_type: &DataType) -> Result<ArrayRef, Error> {
+    let array = match data_type {
+        DataType::Null => Arc::new(NullArray::new(values.len())) as ArrayRef,
+        DataType::Boolean => build_primitive!(BooleanBuilder, Boolean, values, data_type, v => *v),
+        DataType::Float32 => build_primitive!(Float32Builder, Float32, values, data_type, v => *v),
+        DataType::Float64 => build_primitive!(Float64Builder, Float64, values, data_type, v => *v),
+        DataType::Int8 => build_primitive!(Int8Builder, Int8, values, data_type, v => *v),
+        DataType::Int16 => build_primitive!(Int16Builder, Int16, values, data_type, v => *v),
+        DataType::Int32 => build_primitive!(Int32Builder, Int32, values, data_type, v => *v),
+        DataType::Int64 => build_primitive!(Int64Builder, Int64, values, data_type, v => *v),
+        DataType::UInt8 => build_primitive!(UInt8Builder, UInt8, values, data_type, v => *v),
+        DataType::UInt16 => build_primitive!(UInt16Builder, UInt16, values, data_type, v => *v),
+        DataType::UInt32 => build_primitive!(UInt32Builder, UInt32, values, data_type, v => *v),
+        DataType::UInt64 => build_primitive!(UInt64Builder, UInt64, values, data_type, v => *v),
+        DataType::Utf8 => build_primitive!(StringBuilder, Utf8, values, data_type, v => v.as_str()),
+        DataType::Date32(DateUnit::Day) => build_primitive!(Date32Builder, Date32, values, data_type, v => *v),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => build_primitive!(TimestampNanosecondBuilder, Timestamp, values, data_type, v => *v),
+        DataType::Interval(IntervalUnit::YearMonth) => build_primitive!(IntervalYearMonthBuilder, IntervalYearMonth, values, data_type, v => *v),
+        DataType::Interval(IntervalUnit::DayTime) => build_primitive!(IntervalDayTimeBuilder, IntervalDayTime, values, data_type, v => *v),
+        DataType::Struct(fields) if !fields.is_empty() => {
+            let mut null_bitmap = BooleanBufferBuilder::new(values.len());
+            for value in values {
+                null_bitmap.append(value != &ScalarValue::Null)?;
+            }
+            let null_count = values.iter().filter(|value| *value == &ScalarValue::Null).count();
+            let children = fields.iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let field_values = values.iter()
+                        .map(|value| match value {
+                            ScalarValue::Struct(field_values) => field_values[i].clone(),
+                            _ => ScalarValue::Null,
+                        })
+                        .collect::<Vec<_>>();
+                    Ok((field.clone(), scalars_to_array(&field_values, field.data_type())?))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            Arc::new(StructArray::from((children, null_bitmap.finish(), null_count))) as ArrayRef
+        }
+        other => return Err(Error::BadInput(format!("can't create an array of {:?} from scalars", other))),
+    };
+    Ok(with_time_zone(&array, data_type))
+}
+
+/// A single value of an argument of an n-ary function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Datum<'a> {
+    Null,
+    Boolean(bool),
+    Int64(i64),
+    Float64(f64),
+    Utf8(&'a str),
+    // Nanoseconds since the epoch.
+    Timestamp(i64),
+}
+
+impl<'a> Datum<'a> {
+    pub fn is_null(&self) -> bool {
+        *self == Datum::Null
+    }
+
+    pub fn as_str(&self) -> Result<&'a str, Error> {
+        match self {
+            Datum::Utf8(v) => Ok(v),
+            other => Err(Error::BadInput(format!("expected string argument, got {:?}", other))),
+        }
+    }
+
+    pub fn as_i64(&self) -> Result<i64, Error> {
+        match self {
+            Datum::Int64(v) => Ok(*v),
+            other => Err(Error::BadInput(format!("expected integer argument, got {:?}", other))),
+        }
+    }
+
+    pub fn as_f64(&self) -> Result<f64, Error> {
+        match self {
+            Datum::Float64(v) => Ok(*v),
+            other => Err(Error::BadInput(format!("expected floating point argument, got {:?}", other))),
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Result<i64, Error> {
+        match self {
+            Datum::Timestamp(v) => Ok(*v),
+            other => Err(Error::BadInput(format!("expected timestamp argument, got {:?}", other))),
+        }
+    }
+}
+
+/// An argument of an n-ary function, either a whole column, or a constant,
+/// which doesn't have to be materialized for each row.
+pub enum ArgColumn {
+    Column(ArrayRef),
+    Constant(ScalarValue),
+}
+
+impl ArgColumn {
+    pub fn datum(&self, row: usize) -> Datum<'_> {
+        match self {
+            ArgColumn::Constant(ScalarValue::Boolean(v)) => Datum::Boolean(*v),
+            ArgColumn::Constant(ScalarValue::Int64(v)) => Datum::Int64(*v),
+            ArgColumn::Constant(ScalarValue::Float64(v)) => Datum::Float64(*v),
+            ArgColumn::Constant(ScalarValue::Utf8(v)) => Datum::Utf8(v.as_str()),
+            ArgColumn::Constant(ScalarValue::Timestamp(v)) => Datum::Timestamp(*v),
+            ArgColumn::Constant(_) => Datum::Null,
+            ArgColumn::Column(array) => {
+                if array.is_null(row) {
+                    return Datum::Null;
+                }
+                match array.data_type() {
+                    DataType::Boolean => Datum::Boolean(array.as_any().downcast_ref::<BooleanArray>().unwrap().value(row)),
+                    DataType::Int64 => Datum::Int64(array.as_any().downcast_ref::<Int64Array>().unwrap().value(row)),
+                    DataType::Float64 => Datum::Float64(array.as_any().downcast_ref::<Float64Array>().unwrap().value(row)),
+                    DataType::Utf8 => Datum::Utf8(array.as_any().downcast_ref::<StringArray>().unwrap().value(row)),
+                    DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                        Datum::Timestamp(array.as_any().downcast_ref::<TimestampNanosecondArray>().unwrap().value(row))
+                    }
+                    _ => Datum::Null,
+                }
+            }
+        }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        match self {
+            ArgColumn::Constant(_) => true,
+            ArgColumn::Column(_) => false,
+        }
+    }
+}
+
+/// Generalization of compute_single_arg and compute_two_arg to any number of arguments.
+/// If strict, rows where any argument is null produce null without calling op.
+/// If all arguments are constant, op is only called once, and its result repeated.
+pub fn compute_n_arg<F>(args: &[ArgColumn], len: usize, output_type: &DataType, strict: bool, mut op: F) -> Result<ArrayRef, Error>
+    where F: FnMut(&[Datum]) -> Result<ScalarValue, Error> {
+    let all_constant = args.iter().all(ArgColumn::is_constant);
+    let rows = if all_constant { len.min(1) } else { len };
+
+    let mut values = Vec::with_capacity(rows);
+    let mut datums = Vec::with_capacity(args.len());
+    for row in 0..rows {
+        datums.clear();
+        datums.extend(args.iter().map(|arg| arg.datum(row)));
+        if strict && datums.iter().any(Datum::is_null) {
+            values.push(ScalarValue::Null);
+        } else {
+            values.push(op(datums.as_slice())?);
+        }
+    }
+
+    if all_constant {
+        return match values.pop() {
+            None | Some(ScalarValue::Null) => new_null_array(output_type, len),
+            Some(value) => scalar_to_array(&value, len),
+        };
+    }
+
+    macro_rules! build_output {
+        ($builder:ident, $variant:ident, $value:ident => $append:expr) => {{
+            let mut output = array::$builder::new(rows);
+            for value in &values {
+                match value {
+                    ScalarValue::$variant($value) => output.append_value($append)?,
+                    ScalarValue::Null => output.append_null()?,
+                    other => return Err(Error::BadInput(format!("unexpected function result {:?}, expected {:?}", other, output_type))),
+                }
+            }
+            Arc::new(output.finish()) as ArrayRef
+        }};
+    }
+
+    Ok(match output_type {
+        DataType::Boolean => build_output!(BooleanBuilder, Boolean, v => *v),
+        DataType::Int64 => build_output!(Int64Builder, Int64, v => *v),
+        DataType::Float64 => build_output!(Float64Builder, Float64, v => *v),
+        DataType::Utf8 => build_output!(StringBuilder, Utf8, v => v.as_str()),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => build_output!(TimestampNanosecondBuilder, Timestamp, v => *v),
+        other => return Err(Error::BadInput(format!("unsupported function output type {:?}", other))),
+    })
+}
+
+/// Builds an array where the value at each row is taken from the same row of the array
+/// selected for it, or null if no array is selected.
+/// All arrays have to be of the same type and length.
+pub fn interleave(arrays: &[ArrayRef], selection: &[Option<usize>]) -> Result<ArrayRef, Error> {
+    let len = selection.len();
+    let mut indices = UInt32Builder::new(len);
+    for (row, selected) in selection.iter().enumerate() {
+        match selected {
+            Some(array_index) => indices.append_value((array_index * len + row) as u32)?,
+            None => indices.append_null()?,
+        }
+    }
+    take_array(&concat_arrays(arrays)?, &indices.finish())
+}
+
+/// Takes the values at the given indices, null for null indices.
+/// Timestamps keep their time zone, which the arrow kernel drops.
+pub fn take_array(array: &ArrayRef, indices: &UInt32Array) -> Result<ArrayRef, Error> {
+    // The arrow kernels don't support null arrays, i.e. the result of (SELECT NULL).
+    if array.data_type() == &DataType::Null {
+        return Ok(Arc::new(NullArray::new(indices.len())) as ArrayRef);
+    }
+    Ok(with_time_zone(&take(array, indices, None)?, array.data_type()))
+}
+
+/// Concatenates arrays of the same type, also nulls and timestamps with a time zone, which the arrow kernel doesn't accept.
+pub fn concat_arrays(arrays: &[ArrayRef]) -> Result<ArrayRef, Error> {
+    if arrays[0].data_type() == &DataType::Null {
+        return Ok(Arc::new(NullArray::new(arrays.iter().map(|array| array.len()).sum())) as ArrayRef);
+    }
+    let without_time_zone = arrays.iter()
+        .map(|array| with_time_zone(array, &timestamp_type()))
+        .collect::<Vec<_>>();
+    Ok(with_time_zone(&concat(&without_time_zone)?, arrays[0].data_type()))
+}
+
+/// Creates an array of the given type and length, where all values are null.
+pub fn new_null_array(data_type: &DataType, len: usize) -> Result<ArrayRef, Error> {
+    let empty = match data_type {
+        DataType::Null => return Ok(Arc::new(NullArray::new(len)) as ArrayRef),
+        DataType::Boolean => Arc::new(BooleanBuilder::new(0).finish()) as ArrayRef,
+        DataType::Utf8 => Arc::new(StringBuilder::new(0).finish()) as ArrayRef,
+        other => make_array(ArrayDataBuilder::new(other.clone())
+            .len(0)
+            .add_buffer(MutableBuffer::new(0).freeze())
+            .build()),
+    };
+    let mut indices = UInt32Builder::new(len);
+    for _i in 0..len {
+        indices.append_null()?;
+    }
+    take_array(&empty, &indices.finish())
+}
+
+/// Creates a boolean array of the given length, where all values are null.
+pub fn null_boolean_array(len: usize) -> ArrayRef {
+    let mut builder = BooleanBuilder::new(len);
+    for _i in 0..len {
+        builder.append_null().unwrap();
+    }
+    Arc::new(builder.finish()) as ArrayRef
+}
+
+/// Filters all columns of the record batch using the given predicate.
+pub fn filter_record_batch(batch: &RecordBatch, predicate: &BooleanArray) -> Result<RecordBatch, Error> {
+    let columns = batch.columns()
+        .iter()
+        .map(|column| filter_array(column, predicate))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+/// Creates a record batch out of records of scalars and their retraction flags, which make up the last column.
+pub fn records_to_batch<R: AsRef<[ScalarValue]>>(schema: &Arc<Schema>, records: &[(R, bool)]) -> Result<RecordBatch, Error> {
+    let mut columns = schema.fields().iter()
+        .take(schema.fields().len() - 1)
+        .enumerate()
+        .map(|(i, field)| {
+            let values = records.iter().map(|(record, _)| record.as_ref()[i].clone()).collect::<Vec<_>>();
+            scalars_to_array(&values, field.data_type())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let retractions = records.iter()
+        .map(|(_, retraction)| Some(*retraction))
+        .collect::<Vec<_>>();
+    columns.push(Arc::new(BooleanArray::from(retractions)) as ArrayRef);
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Keeps the values for which the predicate is true. Nested arrays, which the filter kernel
+/// doesn't support, and strings, whose nulls it drops, are filtered by taking the selected rows.
+pub fn filter_array(array: &ArrayRef, predicate: &BooleanArray) -> Result<ArrayRef, Error> {
+    match array.data_type() {
+        DataType::List(_) | DataType::Struct(_) | DataType::Utf8 => {
+            let indices: UInt32Array = (0..predicate.len())
+                .filter(|i| predicate.is_valid(*i) && predicate.value(*i))
+                .map(|i| i as u32)
+                .collect::<Vec<_>>()
+                .into();
+            take_array(array, &indices)
+        }
+        DataType::Null => {
+            let len = (0..predicate.len()).filter(|i| predicate.is_valid(*i) && predicate.value(*i)).count();
+            Ok(Arc::new(NullArray::new(len)) as ArrayRef)
+        }
+        _ => Ok(with_time_zone(&filter(array.as_ref(), predicate)?, array.data_type())),
+    }
+}
+
+pub fn is_numeric(data_type: &DataType) -> bool {
+    is_integer(data_type) || is_float(data_type)
+}
+
+pub fn is_integer(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => true,
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => true,
+        _ => false,
+    }
+}
+
+pub fn is_float(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Float32 | DataType::Float64 => true,
+        _ => false,
+    }
+}
+
+fn integer_width(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::Int8 | DataType::UInt8 => 8,
+        DataType::Int16 | DataType::UInt16 => 16,
+        DataType::Int32 | DataType::UInt32 => 32,
+        _ => 64,
+    }
+}
+
+fn is_signed_integer(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => true,
+        _ => false,
+    }
+}
+
+/// Returns the type values of both types can be converted to without losing information
+/// (or as little as possible for mixed integer and floating point types), if there is one.
+pub fn common_supertype(left: &DataType, right: &DataType) -> Option<DataType> {
+    if left == right {
+        return Some(left.clone());
+    }
+    match (left, right) {
+        (DataType::Null, other) | (other, DataType::Null) => Some(other.clone()),
+        // Timestamps in different time zones are the same instants, the time zone of the left one is kept.
+        (timestamp @ DataType::Timestamp(TimeUnit::Nanosecond, _), DataType::Timestamp(TimeUnit::Nanosecond, _))
+        | (DataType::Date32(DateUnit::Day), timestamp @ DataType::Timestamp(TimeUnit::Nanosecond, _))
+        | (timestamp @ DataType::Timestamp(TimeUnit::Nanosecond, _), DataType::Date32(DateUnit::Day)) => {
+            Some(timestamp.clone())
+        }
+        // Strings are parsed, so that i.e. a timestamp can be compared with a literal.
+        (temporal @ DataType::Date32(DateUnit::Day), DataType::Utf8)
+        | (temporal @ DataType::Timestamp(TimeUnit::Nanosecond, _), DataType::Utf8)
+        | (DataType::Utf8, temporal @ DataType::Date32(DateUnit::Day))
+        | (DataType::Utf8, temporal @ DataType::Timestamp(TimeUnit::Nanosecond, _)) => Some(temporal.clone()),
+        (left, right) if is_float(left) || is_float(right) => {
+            if is_numeric(left) && is_numeric(right) {
+                Some(DataType::Float64)
+            } else {
+                None
+            }
+        }
+        (left, right) if is_integer(left) && is_integer(right) => {
+            if is_signed_integer(left) != is_signed_integer(right) {
+                return Some(DataType::Int64);
+            }
+            if integer_width(left) >= integer_width(right) {
+                Some(left.clone())
+            } else {
+                Some(right.clone())
+            }
+        }
+        _ => None,
+    }
+}
+
+macro_rules! compute_single_arg {
     ($arg:expr, $input_type:ident, $output_builder:ident, $op:expr) => {{
         let arg = $arg
             .as_any()
@@ -298,28 +701,29 @@ macro_rules! compute_single_arg_str {
 
         let mut result = $output_builder::new($arg.len());
         for i in 0..$arg.len() {
-            result.append_value($op(arg.value(i)).as_str())?;
+            result.append_value($op(arg.value(i))?)?;
         }
 
         Ok(Arc::new(result.finish()) as ArrayRef)
     }};
 }
 
-macro_rules! compute_two_arg {
-    ($arg1:expr, $arg2:expr, $input_type1:ident, $input_type2:ident, $output_builder:ident, $op:expr) => {{
-        let arg1 = $arg1
-            .as_any()
-            .downcast_ref::<$input_type1>()
-            .expect("compute_single_arg failed to downcast array");
-
-        let arg2 = $arg2
+// Applies a function of a string to each value of a string array, without going through a scalar for each row.
+// Nulls stay null.
+macro_rules! compute_single_arg_str {
+    ($arg:expr, $op:expr) => {{
+        let arg = $arg
             .as_any()
-            .downcast_ref::<$input_type2>()
-            .expect("compute_single_arg failed to downcast array");
+            .downcast_ref::<StringArray>()
+            .expect("compute_single_arg_str failed to downcast array");
 
-        let mut result = $output_builder::new($arg1.len());
-        for i in 0..$arg1.len() {
-            result.append_value($op(arg1.value(i), arg2.value(i))?)?;
+        let mut result = StringBuilder::new($arg.len());
+        for i in 0..$arg.len() {
+            if arg.is_null(i) {
+                result.append_null()?;
+            } else {
+                result.append_value($op(arg.value(i)).as_str())?;
+            }
         }
 
         Ok(Arc::new(result.finish()) as ArrayRef)
@@ -328,7 +732,7 @@ macro_rules! compute_two_arg {
 
 /// Invoke a compute kernel on a pair of arrays
 macro_rules! compute_op {
-    ($LEFT:expr, $RIGHT:expr, $OP:ident, $DT:ident) => {{
+    ($LEFT:expr, $RIGHT:expr, $OP:ident, $DT:ty) => {{
         let ll = $LEFT
             .as_any()
             .downcast_ref::<$DT>()
@@ -408,9 +812,18 @@ macro_rules! binary_array_op {
             DataType::Float32 => compute_op!($LEFT, $RIGHT, $OP, Float32Array),
             DataType::Float64 => compute_op!($LEFT, $RIGHT, $OP, Float64Array),
             DataType::Utf8 => compute_utf8_op!($LEFT, $RIGHT, $OP, StringArray),
-            DataType::Timestamp(TimeUnit::Nanosecond, None) => {
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
                 compute_op!($LEFT, $RIGHT, $OP, TimestampNanosecondArray)
             }
+            DataType::Date32(arrow::datatypes::DateUnit::Day) => {
+                compute_op!($LEFT, $RIGHT, $OP, arrow::array::Date32Array)
+            }
+            DataType::Interval(arrow::datatypes::IntervalUnit::YearMonth) => {
+                compute_op!($LEFT, $RIGHT, $OP, arrow::array::IntervalYearMonthArray)
+            }
+            DataType::Interval(arrow::datatypes::IntervalUnit::DayTime) => {
+                compute_op!($LEFT, $RIGHT, $OP, arrow::array::IntervalDayTimeArray)
+            }
             other => {
                 dbg!(other);
                 unimplemented!()
@@ -0,0 +1,273 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BinaryArray, BinaryBuilder, BooleanArray, BooleanBuilder, Date32Array, Date32Builder, StringArray, StringBuilder, TimestampNanosecondArray, TimestampNanosecondBuilder};
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::{DataType, DateUnit, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::physical::arrow::{is_numeric, new_null_array};
+use crate::physical::datetime::{from_datetime, NANOSECONDS_PER_DAY, resolve_time_zone, timestamp_type, with_time_zone, Zone};
+use crate::physical::expression::Expression;
+use crate::physical::physical::{Error, ExecutionContext, SchemaContext};
+
+pub struct Cast {
+    expr: Arc<dyn Expression>,
+    data_type: DataType,
+    // TRY_CAST, values which can't be converted become null instead of failing the query.
+    try_cast: bool,
+}
+
+impl Cast {
+    pub fn new(expr: Arc<dyn Expression>, data_type: DataType, try_cast: bool) -> Cast {
+        Cast {
+            expr,
+            data_type,
+            try_cast,
+        }
+    }
+}
+
+impl Expression for Cast {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let source_field = self.expr.field_meta(schema_context, record_schema)?;
+        if !can_cast(source_field.data_type(), &self.data_type) {
+            return Err(Error::BadInput(format!("cannot cast {:?} to {:?}", source_field.data_type(), self.data_type)));
+        }
+        let nullable = source_field.is_nullable() || self.try_cast || source_field.data_type() == &DataType::Null;
+        Ok(Field::new(source_field.name().as_str(), self.data_type.clone(), nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        // Timestamps without a time zone are parsed and formatted in the session time zone.
+        let session_zone = ctx.session.time_zone();
+        let array = self.expr.evaluate(ctx, record)?;
+        let array = with_time_zone(&array, &resolve_time_zone(array.data_type(), session_zone));
+        let output = cast_array(&array, &resolve_time_zone(&self.data_type, session_zone))?;
+        let output = with_time_zone(&output, &self.data_type);
+        if !self.try_cast {
+            // The conversion kernels return null for values they can't convert.
+            for i in 0..array.len() {
+                if array.is_valid(i) && output.is_null(i) {
+                    let value = cast_array(&array.slice(i, 1), &DataType::Utf8)?;
+                    let value = value.as_any().downcast_ref::<StringArray>().unwrap();
+                    return Err(Error::BadInput(format!("cannot cast '{}' to {:?}", value.value(0), self.data_type)));
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+pub fn can_cast(from: &DataType, to: &DataType) -> bool {
+    let is_temporal = |data_type: &DataType| match data_type {
+        DataType::Date32(DateUnit::Day) | DataType::Timestamp(TimeUnit::Nanosecond, _) => true,
+        _ => false,
+    };
+    let is_scalar = |data_type: &DataType| is_numeric(data_type) || data_type == &DataType::Boolean || data_type == &DataType::Utf8;
+
+    from == to
+        || from == &DataType::Null
+        || (is_scalar(from) && is_scalar(to))
+        || (is_temporal(from) && (is_temporal(to) || to == &DataType::Utf8))
+        || (from == &DataType::Utf8 && is_temporal(to))
+        || (from == &DataType::Utf8 && to == &DataType::Binary)
+        || (from == &DataType::Binary && to == &DataType::Utf8)
+}
+
+macro_rules! cast_values {
+    ($array:expr, $input_type:ident, StringBuilder, $op:expr) => {{
+        let input = $array
+            .as_any()
+            .downcast_ref::<$input_type>()
+            .expect("cast_values failed to downcast array");
+
+        let mut result = StringBuilder::new(input.len());
+        for i in 0..input.len() {
+            if input.is_null(i) {
+                result.append_null()?;
+                continue;
+            }
+            match $op(input.value(i)) {
+                Some(value) => {
+                    let value: &str = value.as_ref();
+                    result.append_value(value)?
+                }
+                None => result.append_null()?,
+            }
+        }
+
+        Ok(Arc::new(result.finish()) as ArrayRef)
+    }};
+    ($array:expr, $input_type:ident, BinaryBuilder, $op:expr) => {{
+        let input = $array
+            .as_any()
+            .downcast_ref::<$input_type>()
+            .expect("cast_values failed to downcast array");
+
+        let mut result = BinaryBuilder::new(input.len());
+        for i in 0..input.len() {
+            if input.is_null(i) {
+                result.append_null()?;
+                continue;
+            }
+            match $op(input.value(i)) {
+                Some(value) => {
+                    let value: &[u8] = value.as_ref();
+                    result.append_value(value)?
+                }
+                None => result.append_null()?,
+            }
+        }
+
+        Ok(Arc::new(result.finish()) as ArrayRef)
+    }};
+    ($array:expr, $input_type:ident, $output_builder:ident, $op:expr) => {{
+        let input = $array
+            .as_any()
+            .downcast_ref::<$input_type>()
+            .expect("cast_values failed to downcast array");
+
+        let mut result = $output_builder::new(input.len());
+        for i in 0..input.len() {
+            if input.is_null(i) {
+                result.append_null()?;
+                continue;
+            }
+            match $op(input.value(i)) {
+                Some(value) => result.append_value(value)?,
+                None => result.append_null()?,
+            }
+        }
+
+        Ok(Arc::new(result.finish()) as ArrayRef)
+    }};
+}
+
+/// Converts the array to the given type, values which can't be converted become null.
+pub fn cast_array(array: &ArrayRef, data_type: &DataType) -> Result<ArrayRef, Error> {
+    match (array.data_type(), data_type) {
+        (from, to) if from == to => Ok(array.clone()),
+        (DataType::Null, to) => new_null_array(to, array.len()),
+        (DataType::Utf8, DataType::Boolean) => {
+            cast_values!(array, StringArray, BooleanBuilder, parse_bool)
+        }
+        (DataType::Boolean, DataType::Utf8) => {
+            cast_values!(array, BooleanArray, StringBuilder, |v: bool| Some(if v { "true" } else { "false" }))
+        }
+        (DataType::Utf8, DataType::Date32(DateUnit::Day)) => {
+            cast_values!(array, StringArray, Date32Builder, |text: &str| parse_date(text).map(|date| days_since_epoch(&date)))
+        }
+        (DataType::Utf8, DataType::Binary) => {
+            cast_values!(array, StringArray, BinaryBuilder, |text: &str| Some(text.as_bytes().to_vec()))
+        }
+        // Bytes which aren't valid UTF-8 can't be converted.
+        (DataType::Binary, DataType::Utf8) => {
+            cast_values!(array, BinaryArray, StringBuilder, |bytes: &[u8]| String::from_utf8(bytes.to_vec()).ok())
+        }
+        (DataType::Date32(DateUnit::Day), DataType::Utf8) => {
+            cast_values!(array, Date32Array, StringBuilder, |days: i32| Some(date_from_days(days).to_string()))
+        }
+        (DataType::Timestamp(TimeUnit::Nanosecond, _), DataType::Timestamp(TimeUnit::Nanosecond, _)) => {
+            Ok(with_time_zone(array, data_type))
+        }
+        // Timestamps without a time zone are parsed and formatted as UTC here.
+        (DataType::Utf8, DataType::Timestamp(TimeUnit::Nanosecond, zone)) => {
+            let zone = zone_or_utc(zone)?;
+            let output: Result<ArrayRef, Error> = cast_values!(array, StringArray, TimestampNanosecondBuilder, |text: &str| parse_timestamp(text, &zone));
+            Ok(with_time_zone(&output?, data_type))
+        }
+        (DataType::Timestamp(TimeUnit::Nanosecond, zone), DataType::Utf8) => {
+            let (zoned, zone) = (zone.is_some(), zone_or_utc(zone)?);
+            cast_values!(array, TimestampNanosecondArray, StringBuilder, |nanos: i64| {
+                let datetime = zone.to_datetime(nanos);
+                Some(if zoned { datetime.format("%Y-%m-%d %H:%M:%S%.f%:z") } else { datetime.naive_local().format("%Y-%m-%d %H:%M:%S%.f") }.to_string())
+            })
+        }
+        (DataType::Date32(DateUnit::Day), DataType::Timestamp(TimeUnit::Nanosecond, zone)) => {
+            let zone = zone_or_utc(zone)?;
+            let output: Result<ArrayRef, Error> = cast_values!(array, Date32Array, TimestampNanosecondBuilder, |days: i32| {
+                (days as i64).checked_mul(NANOSECONDS_PER_DAY).and_then(|local| zone.from_local(local).ok())
+            });
+            Ok(with_time_zone(&output?, data_type))
+        }
+        (DataType::Timestamp(TimeUnit::Nanosecond, zone), DataType::Date32(DateUnit::Day)) => {
+            let zone = zone_or_utc(zone)?;
+            cast_values!(array, TimestampNanosecondArray, Date32Builder, |nanos: i64| {
+                let days = zone.to_local(nanos).div_euclid(NANOSECONDS_PER_DAY);
+                if days < i32::MIN as i64 || days > i32::MAX as i64 { None } else { Some(days as i32) }
+            })
+        }
+        (_, to) => Ok(cast(array, to)?),
+    }
+}
+
+/// Parses the name of an SQL type, i.e. given as a function argument.
+pub fn parse_type_name(name: &str) -> Result<DataType, Error> {
+    match name.trim().to_lowercase().as_str() {
+        "boolean" | "bool" => Ok(DataType::Boolean),
+        "tinyint" => Ok(DataType::Int8),
+        "smallint" => Ok(DataType::Int16),
+        "int" | "integer" => Ok(DataType::Int32),
+        "bigint" => Ok(DataType::Int64),
+        "real" => Ok(DataType::Float32),
+        "float" | "double" => Ok(DataType::Float64),
+        "varchar" | "text" | "string" => Ok(DataType::Utf8),
+        "date" => Ok(DataType::Date32(DateUnit::Day)),
+        "timestamp" => Ok(timestamp_type()),
+        other => Err(Error::BadInput(format!("unknown type '{}'", other))),
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "t" | "yes" | "y" | "1" => Some(true),
+        "false" | "f" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok()
+}
+
+fn zone_or_utc(zone: &Option<Arc<String>>) -> Result<Zone, Error> {
+    match zone {
+        Some(name) => Zone::parse(name.as_str()),
+        None => Ok(Zone::utc()),
+    }
+}
+
+// Accepts RFC 3339 timestamps, timestamps without an offset, which are local time in the given zone, and plain dates.
+fn parse_timestamp(text: &str, zone: &Zone) -> Option<i64> {
+    let text = text.trim();
+    if let Ok(ts) = DateTime::parse_from_rfc3339(text) {
+        return from_datetime(&ts.naive_utc()).ok();
+    }
+    let local = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| parse_date(text).map(|date| date.and_hms(0, 0, 0)))?;
+    zone.from_local(from_datetime(&local).ok()?).ok()
+}
+
+fn days_since_epoch(date: &NaiveDate) -> i32 {
+    date.signed_duration_since(NaiveDate::from_ymd(1970, 1, 1)).num_days() as i32
+}
+
+fn date_from_days(days: i32) -> NaiveDate {
+    NaiveDate::from_ymd(1970, 1, 1) + chrono::Duration::days(days as i64)
+}
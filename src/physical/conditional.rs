@@ -0,0 +1,221 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, StringArray, TimestampNanosecondArray, UInt32Builder};
+use arrow::array::{Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array};
+use arrow::compute::kernels::cast::cast;
+use arrow::compute::kernels::comparison::{eq, eq_utf8};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{common_supertype, concat_arrays, filter_array, filter_record_batch, new_null_array, take_array};
+use crate::physical::datetime::with_time_zone;
+use crate::physical::expression::Expression;
+use crate::physical::physical::{Error, ExecutionContext, SchemaContext};
+
+/// Both the searched `CASE WHEN cond THEN ...` and the simple `CASE operand WHEN value THEN ...`.
+/// `IF(cond, a, b)` is a searched case with a single branch.
+///
+/// Each branch is only evaluated for the rows which haven't been matched by any of the previous branches,
+/// and each result only for the rows which matched its condition.
+pub struct Case {
+    operand: Option<Arc<dyn Expression>>,
+    conditions: Vec<Arc<dyn Expression>>,
+    results: Vec<Arc<dyn Expression>>,
+    else_result: Option<Arc<dyn Expression>>,
+}
+
+impl Case {
+    pub fn new(
+        operand: Option<Arc<dyn Expression>>,
+        conditions: Vec<Arc<dyn Expression>>,
+        results: Vec<Arc<dyn Expression>>,
+        else_result: Option<Arc<dyn Expression>>,
+    ) -> Case {
+        Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        }
+    }
+
+    // Returns which rows match the condition. Null is treated as not matching.
+    fn evaluate_condition(
+        &self,
+        ctx: &ExecutionContext,
+        condition: &Arc<dyn Expression>,
+        record: &RecordBatch,
+        operand: &Option<ArrayRef>,
+    ) -> Result<BooleanArray, Error> {
+        let condition_value = condition.evaluate(ctx, record)?;
+        let matched = match operand {
+            None => condition_value,
+            Some(operand) => {
+                if operand.data_type() == &DataType::Null || condition_value.data_type() == &DataType::Null {
+                    return Ok(vec![false; record.num_rows()].into());
+                }
+                let output: Result<_, ArrowError> = binary_array_op!(operand, condition_value, eq);
+                output? as ArrayRef
+            }
+        };
+        if matched.data_type() == &DataType::Null {
+            return Ok(vec![false; record.num_rows()].into());
+        }
+        let matched = matched
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .ok_or_else(|| Error::BadInput(format!("CASE condition must be boolean, is {:?}", matched.data_type())))?;
+        Ok((0..matched.len())
+            .map(|i| matched.is_valid(i) && matched.value(i))
+            .collect::<Vec<_>>()
+            .into())
+    }
+}
+
+impl Expression for Case {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let mut result_fields = self.results.iter()
+            .chain(self.else_result.iter())
+            .map(|expr| expr.field_meta(schema_context.clone(), record_schema))
+            .collect::<Result<Vec<Field>, Error>>()?;
+        if self.else_result.is_none() {
+            result_fields.push(Field::new("", DataType::Null, true));
+        }
+
+        let mut output_type = DataType::Null;
+        for field in &result_fields {
+            output_type = match common_supertype(&output_type, field.data_type()) {
+                Some(data_type) => data_type,
+                None => return Err(Error::BadInput(format!("CASE branches have incompatible types {:?} and {:?}", output_type, field.data_type()))),
+            };
+        }
+        let nullable = result_fields.iter().any(|f| f.is_nullable() || f.data_type() == &DataType::Null);
+
+        Ok(Field::new("", output_type, nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let output_type = self.field_meta(ctx.variable_context.clone(), &record.schema())?.data_type().clone();
+
+        let mut branch_outputs: Vec<ArrayRef> = Vec::with_capacity(self.results.len() + 1);
+        // For each row, the branch output it's taken from, and its index in there.
+        let mut selection: Vec<Option<(usize, usize)>> = vec![None; record.num_rows()];
+
+        let mut remaining_batch = record.clone();
+        let mut remaining_rows: Vec<usize> = (0..record.num_rows()).collect();
+        let mut remaining_operand = match &self.operand {
+            None => None,
+            Some(operand) => Some(operand.evaluate(ctx, record)?),
+        };
+
+        for (condition, result) in self.conditions.iter().zip(self.results.iter()) {
+            if remaining_rows.is_empty() {
+                break;
+            }
+            let matched = self.evaluate_condition(ctx, condition, &remaining_batch, &remaining_operand)?;
+            let matched_count = (0..matched.len()).filter(|i| matched.value(*i)).count();
+            if matched_count == 0 {
+                continue;
+            }
+
+            let branch_index = branch_outputs.len();
+            if matched_count == remaining_rows.len() {
+                // No need to filter, all remaining rows go to this branch.
+                let output = result.evaluate(ctx, &remaining_batch)?;
+                branch_outputs.push(cast_if_needed(output, &output_type)?);
+                for (i, row) in remaining_rows.iter().enumerate() {
+                    selection[*row] = Some((branch_index, i));
+                }
+                remaining_rows.clear();
+                break;
+            }
+
+            let matched_batch = filter_record_batch(&remaining_batch, &matched)?;
+            let output = result.evaluate(ctx, &matched_batch)?;
+            branch_outputs.push(cast_if_needed(output, &output_type)?);
+
+            let mut still_remaining_rows = Vec::with_capacity(remaining_rows.len() - matched_count);
+            let mut output_index = 0;
+            for (i, row) in remaining_rows.iter().enumerate() {
+                if matched.value(i) {
+                    selection[*row] = Some((branch_index, output_index));
+                    output_index += 1;
+                } else {
+                    still_remaining_rows.push(*row);
+                }
+            }
+
+            let not_matched: BooleanArray = (0..matched.len())
+                .map(|i| !matched.value(i))
+                .collect::<Vec<_>>()
+                .into();
+            remaining_batch = filter_record_batch(&remaining_batch, &not_matched)?;
+            remaining_operand = match remaining_operand {
+                None => None,
+                Some(operand) => Some(filter_array(&operand, &not_matched)?),
+            };
+            remaining_rows = still_remaining_rows;
+        }
+
+        if let Some(else_result) = &self.else_result {
+            if !remaining_rows.is_empty() {
+                let branch_index = branch_outputs.len();
+                let output = else_result.evaluate(ctx, &remaining_batch)?;
+                branch_outputs.push(cast_if_needed(output, &output_type)?);
+                for (i, row) in remaining_rows.iter().enumerate() {
+                    selection[*row] = Some((branch_index, i));
+                }
+            }
+        }
+
+        if branch_outputs.is_empty() {
+            return new_null_array(&output_type, record.num_rows());
+        }
+
+        // Translate the per-branch indices into indices of the concatenated branch outputs.
+        let mut offsets = Vec::with_capacity(branch_outputs.len());
+        let mut total_len = 0;
+        for output in &branch_outputs {
+            offsets.push(total_len);
+            total_len += output.len();
+        }
+        let values = concat_arrays(branch_outputs.as_slice())?;
+        let mut indices = UInt32Builder::new(selection.len());
+        for selected in selection {
+            match selected {
+                Some((branch_index, i)) => indices.append_value((offsets[branch_index] + i) as u32)?,
+                None => indices.append_null()?,
+            }
+        }
+
+        take_array(&values, &indices.finish())
+    }
+}
+
+fn cast_if_needed(array: ArrayRef, data_type: &DataType) -> Result<ArrayRef, Error> {
+    if array.data_type() == data_type {
+        return Ok(array);
+    }
+    if array.data_type() == &DataType::Null {
+        return new_null_array(data_type, array.len());
+    }
+    if let (DataType::Timestamp(_, _), DataType::Timestamp(_, _)) = (array.data_type(), data_type) {
+        return Ok(with_time_zone(&array, data_type));
+    }
+    Ok(cast(&array, data_type)?)
+}
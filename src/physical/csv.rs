@@ -24,19 +24,26 @@ use crate::physical::physical::*;
 
 pub struct CSVSource {
     path: String,
+    delimiter: u8,
 }
 
 impl CSVSource {
     pub fn new(path: String) -> CSVSource {
-        CSVSource { path }
+        CSVSource { path, delimiter: b',' }
+    }
+
+    pub fn with_delimiter(self, delimiter: u8) -> CSVSource {
+        CSVSource { delimiter, ..self }
     }
 }
 
 impl Node for CSVSource {
     fn schema(&self, _schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
-        let file = File::open(self.path.as_str()).unwrap();
+        let file = File::open(self.path.as_str())
+            .map_err(|err| Error::BadInput(format!("couldn't open {}: {}", self.path, err)))?;
         let r = csv::ReaderBuilder::new()
             .has_header(true)
+            .with_delimiter(self.delimiter)
             .infer_schema(Some(10))
             .with_batch_size(BATCH_SIZE)
             .build(file)
@@ -53,9 +60,11 @@ impl Node for CSVSource {
         produce: ProduceFn,
         _meta_send: MetaSendFn,
     ) -> Result<(), Error> {
-        let file = File::open(self.path.as_str()).unwrap();
+        let file = File::open(self.path.as_str())
+            .map_err(|err| Error::BadInput(format!("couldn't open {}: {}", self.path, err)))?;
         let mut r = csv::ReaderBuilder::new()
             .has_header(true)
+            .with_delimiter(self.delimiter)
             .infer_schema(Some(10))
             .with_batch_size(BATCH_SIZE)
             .build(file)
@@ -0,0 +1,769 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayData, ArrayRef, PrimitiveArray, PrimitiveBuilder, StringArray};
+use arrow::datatypes::{ArrowNumericType, DataType, DateUnit, Date32Type, Field, Int64Type, IntervalDayTimeType, IntervalUnit, IntervalYearMonthType, Schema, TimeUnit, TimestampNanosecondType};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Datelike, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Timelike};
+use chrono::format::{Item, StrftimeItems};
+use chrono_tz::Tz;
+
+use crate::physical::arrow::{is_integer, make_array, new_null_array, scalar_to_array};
+use crate::physical::cast::cast_array;
+use crate::physical::expression::Expression;
+use crate::physical::math::ArithmeticOp;
+use crate::physical::physical::{Error, ExecutionContext, ScalarValue, SchemaContext};
+
+pub const NANOSECONDS_PER_MICROSECOND: i64 = 1_000;
+pub const NANOSECONDS_PER_MILLISECOND: i64 = 1_000_000;
+pub const NANOSECONDS_PER_SECOND: i64 = 1_000_000_000;
+pub const NANOSECONDS_PER_DAY: i64 = 86_400 * NANOSECONDS_PER_SECOND;
+const MILLISECONDS_PER_DAY: i64 = 86_400_000;
+
+/// Timestamps are stored as the number of nanoseconds since the Unix epoch.
+/// A timestamp may carry the name of a time zone, it's then shown and truncated in that time zone,
+/// otherwise in the time zone of the session.
+pub fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Nanosecond, None)
+}
+
+pub fn zoned_timestamp_type(zone: &Zone) -> DataType {
+    DataType::Timestamp(TimeUnit::Nanosecond, Some(Arc::new(zone.name())))
+}
+
+pub fn is_timestamp(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => true,
+        _ => false,
+    }
+}
+
+pub fn is_temporal(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Date32(DateUnit::Day) | DataType::Timestamp(TimeUnit::Nanosecond, _) => true,
+        DataType::Interval(_) => true,
+        _ => false,
+    }
+}
+
+pub fn to_datetime(nanos: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp(nanos.div_euclid(NANOSECONDS_PER_SECOND), nanos.rem_euclid(NANOSECONDS_PER_SECOND) as u32)
+}
+
+pub fn from_datetime(datetime: &NaiveDateTime) -> Result<i64, Error> {
+    datetime.timestamp()
+        .checked_mul(NANOSECONDS_PER_SECOND)
+        .and_then(|nanos| nanos.checked_add(datetime.timestamp_subsec_nanos() as i64))
+        .ok_or_else(|| Error::BadInput(format!("timestamp out of range: {}", datetime)))
+}
+
+/// A time zone, either a fixed offset from UTC, or a named zone from the embedded tz database.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Zone {
+    pub fn utc() -> Zone {
+        Zone::Named(Tz::UTC)
+    }
+
+    /// Accepts names from the tz database, like `Europe/Warsaw`, and offsets like `+02:00`, `-0530` or `+01`.
+    pub fn parse(name: &str) -> Result<Zone, Error> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("utc") || name.eq_ignore_ascii_case("z") {
+            return Ok(Zone::utc());
+        }
+        if name.starts_with('+') || name.starts_with('-') {
+            return parse_offset(name).map(Zone::Fixed).ok_or_else(|| Error::BadInput(format!("invalid time zone offset '{}'", name)));
+        }
+        name.parse::<Tz>()
+            .map(Zone::Named)
+            .map_err(|_| Error::BadInput(format!("unknown time zone '{}'", name)))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Zone::Fixed(offset) => offset.to_string(),
+            Zone::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    /// The offset from UTC at the given instant.
+    pub fn offset(&self, nanos: i64) -> FixedOffset {
+        match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::Named(tz) => tz.offset_from_utc_datetime(&to_datetime(nanos)).fix(),
+        }
+    }
+
+    /// The local time at the given instant, in nanoseconds since the local epoch.
+    pub fn to_local(&self, nanos: i64) -> i64 {
+        nanos.saturating_add(self.offset(nanos).local_minus_utc() as i64 * NANOSECONDS_PER_SECOND)
+    }
+
+    /// The instant of the given local time.
+    /// A local time which happens twice, when the clocks go back, resolves to the earlier instant.
+    /// A local time which gets skipped, when the clocks go forward, is interpreted with the offset from before the change.
+    pub fn from_local(&self, local: i64) -> Result<i64, Error> {
+        let offset = match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::Named(tz) => match tz.offset_from_local_datetime(&to_datetime(local)) {
+                LocalResult::Single(offset) | LocalResult::Ambiguous(offset, _) => offset.fix(),
+                LocalResult::None => self.offset(local.saturating_sub(NANOSECONDS_PER_DAY)),
+            },
+        };
+        local.checked_sub(offset.local_minus_utc() as i64 * NANOSECONDS_PER_SECOND)
+            .ok_or_else(|| Error::BadInput(format!("timestamp out of range: {}", to_datetime(local))))
+    }
+
+    pub fn to_datetime(&self, nanos: i64) -> DateTime<FixedOffset> {
+        DateTime::from_utc(to_datetime(nanos), self.offset(nanos))
+    }
+}
+
+fn parse_offset(text: &str) -> Option<FixedOffset> {
+    let sign = if text.starts_with('-') { -1 } else { 1 };
+    let digits = text[1..].replace(':', "");
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes): (i32, i32) = match digits.len() {
+        1 | 2 => (digits.parse().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The time zone timestamps of the given type are shown in.
+pub fn time_zone(data_type: &DataType, session_zone: &Zone) -> Result<Zone, Error> {
+    match data_type {
+        DataType::Timestamp(_, Some(name)) => Zone::parse(name.as_str()),
+        _ => Ok(*session_zone),
+    }
+}
+
+/// The time zone of the first timestamp type carrying one, or else the session time zone.
+pub fn common_time_zone<'a, I>(data_types: I, session_zone: &Zone) -> Result<Zone, Error>
+    where I: IntoIterator<Item=&'a DataType> {
+    match data_types.into_iter().find(|data_type| match data_type {
+        DataType::Timestamp(TimeUnit::Nanosecond, Some(_)) => true,
+        _ => false,
+    }) {
+        Some(data_type) => time_zone(data_type, session_zone),
+        None => Ok(*session_zone),
+    }
+}
+
+/// Timestamps without a time zone are given the session time zone.
+pub fn resolve_time_zone(data_type: &DataType, session_zone: &Zone) -> DataType {
+    match data_type {
+        DataType::Timestamp(TimeUnit::Nanosecond, None) => zoned_timestamp_type(session_zone),
+        other => other.clone(),
+    }
+}
+
+/// Changes the time zone of a timestamp array to the one of the given type, the instants stay the same.
+/// Other arrays are returned as they are.
+pub fn with_time_zone(array: &ArrayRef, data_type: &DataType) -> ArrayRef {
+    match (array.data_type(), data_type) {
+        (DataType::Timestamp(TimeUnit::Nanosecond, from), DataType::Timestamp(TimeUnit::Nanosecond, to)) if from != to => {
+            let data = array.data();
+            make_array(Arc::new(ArrayData::new(
+                data_type.clone(),
+                data.len(),
+                Some(data.null_count()),
+                data.null_buffer().cloned(),
+                data.offset(),
+                data.buffers().to_vec(),
+                vec![],
+            )))
+        }
+        _ => array.clone(),
+    }
+}
+
+/// Day-time intervals are stored as the number of days in the upper,
+/// and the number of milliseconds in the lower 32 bits.
+/// The milliseconds are always within a single day, so that packed intervals compare correctly.
+pub fn interval_day_time(milliseconds: i64) -> Result<i64, Error> {
+    let days = milliseconds.div_euclid(MILLISECONDS_PER_DAY);
+    if days < i32::MIN as i64 || days > i32::MAX as i64 {
+        return Err(Error::BadInput(format!("interval out of range: {} milliseconds", milliseconds)));
+    }
+    Ok((days << 32) | milliseconds.rem_euclid(MILLISECONDS_PER_DAY))
+}
+
+pub fn interval_milliseconds(interval: i64) -> i64 {
+    (interval >> 32) * MILLISECONDS_PER_DAY + (interval & 0xFFFF_FFFF)
+}
+
+pub fn format_interval_day_time(interval: i64) -> String {
+    let milliseconds = interval_milliseconds(interval);
+    let sign = if milliseconds < 0 { "-" } else { "" };
+    let milliseconds = milliseconds.abs();
+    let days = milliseconds / MILLISECONDS_PER_DAY;
+    let time = milliseconds % MILLISECONDS_PER_DAY;
+    let mut text = format!("{}{:02}:{:02}:{:02}", sign, time / 3_600_000, time / 60_000 % 60, time / 1000 % 60);
+    if time % 1000 != 0 {
+        text += format!(".{:03}", time % 1000).as_str();
+    }
+    match days {
+        0 => text,
+        1 => format!("{}1 day {}", sign, text),
+        days => format!("{}{} days {}", sign, days, text),
+    }
+}
+
+pub fn format_interval_year_month(months: i32) -> String {
+    let sign = if months < 0 { "-" } else { "" };
+    let months = months.abs();
+    match (months / 12, months % 12) {
+        (0, months) => format!("{}{} mons", sign, months),
+        (years, 0) => format!("{}{} years", sign, years),
+        (years, months) => format!("{}{} years {} mons", sign, years, months),
+    }
+}
+
+/// The parts of a timestamp used by date_trunc, date_part, date_add and date_diff.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeField {
+    Microsecond,
+    Millisecond,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+    // Only for date_part.
+    DayOfWeek,
+    DayOfYear,
+    Epoch,
+}
+
+impl TimeField {
+    pub fn parse(name: &str) -> Result<TimeField, Error> {
+        Ok(match name.trim().to_lowercase().as_str() {
+            "microsecond" | "microseconds" | "us" => TimeField::Microsecond,
+            "millisecond" | "milliseconds" | "ms" => TimeField::Millisecond,
+            "second" | "seconds" | "s" => TimeField::Second,
+            "minute" | "minutes" | "m" => TimeField::Minute,
+            "hour" | "hours" | "h" => TimeField::Hour,
+            "day" | "days" | "d" => TimeField::Day,
+            "week" | "weeks" | "w" => TimeField::Week,
+            "month" | "months" => TimeField::Month,
+            "quarter" | "quarters" => TimeField::Quarter,
+            "year" | "years" | "y" => TimeField::Year,
+            "dow" | "dayofweek" => TimeField::DayOfWeek,
+            "doy" | "dayofyear" => TimeField::DayOfYear,
+            "epoch" => TimeField::Epoch,
+            other => return Err(Error::BadInput(format!("unknown date field '{}'", other))),
+        })
+    }
+
+    // The length of fields which don't depend on the calendar.
+    fn nanoseconds(&self) -> Option<i64> {
+        match self {
+            TimeField::Microsecond => Some(NANOSECONDS_PER_MICROSECOND),
+            TimeField::Millisecond => Some(NANOSECONDS_PER_MILLISECOND),
+            TimeField::Second => Some(NANOSECONDS_PER_SECOND),
+            TimeField::Minute => Some(60 * NANOSECONDS_PER_SECOND),
+            TimeField::Hour => Some(3600 * NANOSECONDS_PER_SECOND),
+            TimeField::Day => Some(NANOSECONDS_PER_DAY),
+            TimeField::Week => Some(7 * NANOSECONDS_PER_DAY),
+            _ => None,
+        }
+    }
+
+    fn months(&self) -> Option<i64> {
+        match self {
+            TimeField::Month => Some(1),
+            TimeField::Quarter => Some(3),
+            TimeField::Year => Some(12),
+            _ => None,
+        }
+    }
+}
+
+/// Truncates the timestamp in the given time zone, so that i.e. days start at local midnight, also on days with a DST change.
+pub fn date_trunc(field: TimeField, nanos: i64, zone: &Zone) -> Result<i64, Error> {
+    let local = zone.to_local(nanos);
+    let date = to_datetime(local).date();
+    let truncated_date = match field {
+        TimeField::Day => date,
+        TimeField::Week => date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64),
+        TimeField::Month => NaiveDate::from_ymd(date.year(), date.month(), 1),
+        TimeField::Quarter => NaiveDate::from_ymd(date.year(), (date.month() - 1) / 3 * 3 + 1, 1),
+        TimeField::Year => NaiveDate::from_ymd(date.year(), 1, 1),
+        TimeField::DayOfWeek | TimeField::DayOfYear | TimeField::Epoch => {
+            return Err(Error::BadInput(format!("can't truncate a timestamp to {:?}", field)));
+        }
+        _ => {
+            // The part to drop is computed in local time, for zones with offsets which aren't whole hours,
+            // but dropped from the instant, so that a time which happens twice when the clocks go back stays on the right side.
+            let length = field.nanoseconds().unwrap();
+            return Ok(nanos - local.rem_euclid(length));
+        }
+    };
+    zone.from_local(from_datetime(&truncated_date.and_hms(0, 0, 0))?)
+}
+
+pub fn date_part(field: TimeField, nanos: i64, zone: &Zone) -> Result<i64, Error> {
+    let datetime = to_datetime(zone.to_local(nanos));
+    Ok(match field {
+        TimeField::Microsecond => datetime.second() as i64 * 1_000_000 + datetime.nanosecond() as i64 / 1000,
+        TimeField::Millisecond => datetime.second() as i64 * 1000 + datetime.nanosecond() as i64 / 1_000_000,
+        TimeField::Second => datetime.second() as i64,
+        TimeField::Minute => datetime.minute() as i64,
+        TimeField::Hour => datetime.hour() as i64,
+        TimeField::Day => datetime.day() as i64,
+        TimeField::Week => datetime.iso_week().week() as i64,
+        TimeField::Month => datetime.month() as i64,
+        TimeField::Quarter => (datetime.month() as i64 - 1) / 3 + 1,
+        TimeField::Year => datetime.year() as i64,
+        TimeField::DayOfWeek => datetime.weekday().num_days_from_sunday() as i64,
+        TimeField::DayOfYear => datetime.ordinal() as i64,
+        TimeField::Epoch => nanos.div_euclid(NANOSECONDS_PER_SECOND),
+    })
+}
+
+/// Adds the months, the day of the month is clamped to the length of the resulting month.
+pub fn add_months(nanos: i64, months: i64, zone: &Zone) -> Result<i64, Error> {
+    let datetime = to_datetime(zone.to_local(nanos));
+    let month_index = datetime.year() as i64 * 12 + datetime.month0() as i64 + months;
+    let (year, month) = (month_index.div_euclid(12), month_index.rem_euclid(12) as u32 + 1);
+    if year < -200_000 || year > 200_000 {
+        return Err(Error::BadInput(format!("timestamp out of range: {} plus {} months", datetime, months)));
+    }
+    let mut day = datetime.day();
+    let date = loop {
+        if let Some(date) = NaiveDate::from_ymd_opt(year as i32, month, day) {
+            break date;
+        }
+        day -= 1;
+    };
+    zone.from_local(from_datetime(&date.and_time(datetime.time()))?)
+}
+
+/// Days and weeks are added in local time, so that the time of day stays the same across DST changes.
+pub fn date_add(field: TimeField, amount: i64, nanos: i64, zone: &Zone) -> Result<i64, Error> {
+    let out_of_range = || Error::BadInput(format!("timestamp out of range: {} plus {} {:?}", to_datetime(nanos), amount, field));
+    if let Some(length) = field.nanoseconds() {
+        let delta = amount.checked_mul(length).ok_or_else(out_of_range)?;
+        return match field {
+            TimeField::Day | TimeField::Week => zone.from_local(zone.to_local(nanos).checked_add(delta).ok_or_else(out_of_range)?),
+            _ => nanos.checked_add(delta).ok_or_else(out_of_range),
+        };
+    }
+    match field.months() {
+        Some(months) => add_months(nanos, amount.checked_mul(months).ok_or_else(out_of_range)?, zone),
+        None => Err(Error::BadInput(format!("can't add {:?} to a timestamp", field))),
+    }
+}
+
+/// The number of whole fields between start and end, negative if end is before start.
+pub fn date_diff(field: TimeField, start: i64, end: i64, zone: &Zone) -> Result<i64, Error> {
+    if let Some(length) = field.nanoseconds() {
+        let (start, end) = match field {
+            TimeField::Day | TimeField::Week => (zone.to_local(start), zone.to_local(end)),
+            _ => (start, end),
+        };
+        // Both are within the range of i64, so their difference fits in an i128.
+        return Ok(((end as i128 - start as i128) / length as i128) as i64);
+    }
+    let unit = field.months().ok_or_else(|| Error::BadInput(format!("can't compute the difference in {:?}", field)))?;
+    let (start_datetime, end_datetime) = (to_datetime(zone.to_local(start)), to_datetime(zone.to_local(end)));
+    let mut months = (end_datetime.year() as i64 * 12 + end_datetime.month0() as i64)
+        - (start_datetime.year() as i64 * 12 + start_datetime.month0() as i64);
+    // The last month only counts if it has been completed, as in adding it to start doesn't go past end.
+    if months > 0 && add_months(start, months, zone)? > end {
+        months -= 1;
+    } else if months < 0 && add_months(start, months, zone)? < end {
+        months += 1;
+    }
+    Ok(months / unit)
+}
+
+/// Formats the timestamp using a strftime format string.
+pub fn strftime(nanos: i64, format: &str, zone: &Zone) -> Result<String, Error> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(Error::BadInput(format!("invalid format string '{}'", format)));
+    }
+    Ok(zone.to_datetime(nanos).format(format).to_string())
+}
+
+// Longer patterns come first, so that i.e. MONTH isn't matched as MON.
+const TO_CHAR_PATTERNS: &[(&str, &str)] = &[
+    ("YYYY", "%Y"),
+    ("YY", "%y"),
+    ("Month", "%B"),
+    ("Mon", "%b"),
+    ("MM", "%m"),
+    ("Day", "%A"),
+    ("Dy", "%a"),
+    ("DDD", "%j"),
+    ("DD", "%d"),
+    ("HH24", "%H"),
+    ("HH12", "%I"),
+    ("HH", "%I"),
+    ("MI", "%M"),
+    ("SS", "%S"),
+    ("MS", "%3f"),
+    ("US", "%6f"),
+    ("AM", "%p"),
+    ("PM", "%p"),
+    ("TZH:TZM", "%:z"),
+    ("TZ", "%Z"),
+];
+
+/// Formats the timestamp using a PostgreSQL style pattern, like `YYYY-MM-DD HH24:MI:SS`.
+/// Text in double quotes is copied verbatim.
+pub fn to_char(nanos: i64, pattern: &str, zone: &Zone) -> Result<String, Error> {
+    let mut format = String::with_capacity(pattern.len() * 2);
+    let mut rest = pattern;
+    'outer: while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let end = rest[1..].find('"').map(|i| i + 1).unwrap_or_else(|| rest.len());
+            format += rest[1..end].replace('%', "%%").as_str();
+            rest = &rest[(end + 1).min(rest.len())..];
+            continue;
+        }
+        for (pg_pattern, strftime_pattern) in TO_CHAR_PATTERNS {
+            if rest.starts_with(pg_pattern) {
+                format += strftime_pattern;
+                rest = &rest[pg_pattern.len()..];
+                continue 'outer;
+            }
+        }
+        if c == '%' {
+            format += "%%";
+        } else {
+            format.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    strftime(nanos, format.as_str(), zone)
+}
+
+fn combine<L, R, O, F>(left: &ArrayRef, right: &ArrayRef, op: F) -> Result<ArrayRef, Error>
+    where L: ArrowNumericType, R: ArrowNumericType, O: ArrowNumericType, F: Fn(L::Native, R::Native) -> Result<O::Native, Error> {
+    let left = left.as_any().downcast_ref::<PrimitiveArray<L>>().unwrap();
+    let right = right.as_any().downcast_ref::<PrimitiveArray<R>>().unwrap();
+    let mut output = PrimitiveBuilder::<O>::new(left.len());
+    for i in 0..left.len() {
+        if left.is_null(i) || right.is_null(i) {
+            output.append_null()?;
+        } else {
+            output.append_value(op(left.value(i), right.value(i))?)?;
+        }
+    }
+    Ok(Arc::new(output.finish()) as ArrayRef)
+}
+
+fn is_date_or_timestamp(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Date32(DateUnit::Day) | DataType::Timestamp(TimeUnit::Nanosecond, _) => true,
+        _ => false,
+    }
+}
+
+// Timestamps keep their time zone, dates become timestamps without one.
+fn as_timestamp_type(data_type: &DataType) -> DataType {
+    if is_timestamp(data_type) { data_type.clone() } else { timestamp_type() }
+}
+
+fn is_interval(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Interval(_) => true,
+        _ => false,
+    }
+}
+
+/// The output type of arithmetic with dates, timestamps and intervals:
+/// - timestamp ± interval is a timestamp, dates are converted to timestamps first,
+/// - date ± integer is a date, the integer being a number of days,
+/// - timestamp - timestamp is a day-time interval, date - date is a number of days,
+/// - interval ± interval is an interval of the same kind.
+pub fn arithmetic_type(op: ArithmeticOp, left: &DataType, right: &DataType) -> Option<DataType> {
+    let additive = op == ArithmeticOp::Add || op == ArithmeticOp::Subtract;
+    match (left, right) {
+        _ if !additive => None,
+        (DataType::Null, other) | (other, DataType::Null) => Some(other.clone()),
+        (datetime, interval) if is_date_or_timestamp(datetime) && is_interval(interval) => Some(as_timestamp_type(datetime)),
+        (interval, datetime) if is_interval(interval) && is_date_or_timestamp(datetime) && op == ArithmeticOp::Add => Some(as_timestamp_type(datetime)),
+        (DataType::Date32(DateUnit::Day), integer) if is_integer(integer) => Some(left.clone()),
+        (integer, DataType::Date32(DateUnit::Day)) if is_integer(integer) && op == ArithmeticOp::Add => Some(right.clone()),
+        (DataType::Date32(DateUnit::Day), DataType::Date32(DateUnit::Day)) if op == ArithmeticOp::Subtract => Some(DataType::Int64),
+        (left, right) if is_date_or_timestamp(left) && is_date_or_timestamp(right) && op == ArithmeticOp::Subtract => {
+            Some(DataType::Interval(IntervalUnit::DayTime))
+        }
+        (DataType::Interval(left_unit), DataType::Interval(right_unit)) if left_unit == right_unit => Some(left.clone()),
+        _ => None,
+    }
+}
+
+/// Months are added in the given time zone, dates are converted to timestamps at local midnight.
+pub fn arithmetic(op: ArithmeticOp, left: &ArrayRef, right: &ArrayRef, zone: &Zone) -> Result<ArrayRef, Error> {
+    let output_type = arithmetic_type(op, left.data_type(), right.data_type())
+        .ok_or_else(|| Error::BadInput(format!("can't apply {:?} to {:?} and {:?}", op, left.data_type(), right.data_type())))?;
+    if left.data_type() == &DataType::Null || right.data_type() == &DataType::Null {
+        return new_null_array(&output_type, left.len());
+    }
+    let sign = if op == ArithmeticOp::Subtract { -1 } else { 1 };
+    let overflow = || Error::BadInput(format!("numeric overflow in {:?}", op));
+
+    match (left.data_type(), right.data_type()) {
+        (interval, datetime) if is_interval(interval) && is_date_or_timestamp(datetime) => arithmetic(op, right, left, zone),
+        (integer, DataType::Date32(DateUnit::Day)) if is_integer(integer) => arithmetic(op, right, left, zone),
+        (_, DataType::Interval(IntervalUnit::DayTime)) if is_date_or_timestamp(left.data_type()) => {
+            let output = combine::<TimestampNanosecondType, IntervalDayTimeType, TimestampNanosecondType, _>(&to_timestamps(left, zone)?, right, |ts, interval| {
+                (sign * interval_milliseconds(interval))
+                    .checked_mul(NANOSECONDS_PER_MILLISECOND)
+                    .and_then(|delta| ts.checked_add(delta))
+                    .ok_or_else(overflow)
+            })?;
+            Ok(with_time_zone(&output, &output_type))
+        }
+        (_, DataType::Interval(IntervalUnit::YearMonth)) if is_date_or_timestamp(left.data_type()) => {
+            let output = combine::<TimestampNanosecondType, IntervalYearMonthType, TimestampNanosecondType, _>(&to_timestamps(left, zone)?, right, |ts, months| {
+                add_months(ts, sign * months as i64, zone)
+            })?;
+            Ok(with_time_zone(&output, &output_type))
+        }
+        (DataType::Date32(DateUnit::Day), DataType::Date32(DateUnit::Day)) => {
+            combine::<Date32Type, Date32Type, Int64Type, _>(left, right, |l, r| Ok(l as i64 - r as i64))
+        }
+        (DataType::Date32(DateUnit::Day), _) => {
+            combine::<Date32Type, Int64Type, Date32Type, _>(left, &cast_array(right, &DataType::Int64)?, |date, days| {
+                let result = date as i64 + sign * days;
+                if result < i32::MIN as i64 || result > i32::MAX as i64 {
+                    Err(overflow())
+                } else {
+                    Ok(result as i32)
+                }
+            })
+        }
+        (_, _) if is_date_or_timestamp(right.data_type()) => {
+            let (left, right) = (to_timestamps(left, zone)?, to_timestamps(right, zone)?);
+            combine::<TimestampNanosecondType, TimestampNanosecondType, IntervalDayTimeType, _>(&left, &right, |l, r| {
+                interval_day_time(l.checked_sub(r).ok_or_else(overflow)?.div_euclid(NANOSECONDS_PER_MILLISECOND))
+            })
+        }
+        (DataType::Interval(IntervalUnit::DayTime), _) => {
+            combine::<IntervalDayTimeType, IntervalDayTimeType, IntervalDayTimeType, _>(left, right, |l, r| {
+                interval_day_time(interval_milliseconds(l) + sign * interval_milliseconds(r))
+            })
+        }
+        (DataType::Interval(IntervalUnit::YearMonth), _) => {
+            combine::<IntervalYearMonthType, IntervalYearMonthType, IntervalYearMonthType, _>(left, right, |l, r| {
+                (if sign < 0 { l.checked_sub(r) } else { l.checked_add(r) }).ok_or_else(overflow)
+            })
+        }
+        _ => Err(Error::BadInput(format!("can't apply {:?} to {:?} and {:?}", op, left.data_type(), right.data_type()))),
+    }
+}
+
+// Dates are converted at local midnight.
+fn to_timestamps(array: &ArrayRef, zone: &Zone) -> Result<ArrayRef, Error> {
+    match array.data_type() {
+        DataType::Date32(DateUnit::Day) => cast_array(array, &zoned_timestamp_type(zone)),
+        _ => Ok(array.clone()),
+    }
+}
+
+/// `expr AT TIME ZONE zone`, the same instant, shown in the given time zone.
+/// Dates and strings, which don't describe an instant, are interpreted as local time in that zone.
+pub struct AtTimeZone {
+    expr: Arc<dyn Expression>,
+    zone: Arc<dyn Expression>,
+}
+
+impl AtTimeZone {
+    pub fn new(expr: Arc<dyn Expression>, zone: Arc<dyn Expression>) -> AtTimeZone {
+        AtTimeZone {
+            expr,
+            zone,
+        }
+    }
+
+    fn zone(&self) -> Result<Zone, Error> {
+        match self.zone.constant_value() {
+            Some(ScalarValue::Utf8(name)) => Zone::parse(name.as_str()),
+            _ => Err(Error::BadInput("the time zone in AT TIME ZONE must be a constant string".to_string())),
+        }
+    }
+}
+
+impl Expression for AtTimeZone {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let field = self.expr.field_meta(schema_context, record_schema)?;
+        match field.data_type() {
+            DataType::Null | DataType::Utf8 | DataType::Date32(DateUnit::Day) | DataType::Timestamp(TimeUnit::Nanosecond, _) => {}
+            other => return Err(Error::BadInput(format!("AT TIME ZONE expects a timestamp, got {:?}", other))),
+        }
+        let nullable = field.is_nullable() || field.data_type() == &DataType::Null;
+        Ok(Field::new(field.name().as_str(), zoned_timestamp_type(&self.zone()?), nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let output_type = zoned_timestamp_type(&self.zone()?);
+        let array = self.expr.evaluate(ctx, record)?;
+        if let DataType::Timestamp(TimeUnit::Nanosecond, _) = array.data_type() {
+            return Ok(with_time_zone(&array, &output_type));
+        }
+        let output = cast_array(&array, &output_type)?;
+        if let Some(row) = (0..array.len()).find(|row| array.is_valid(*row) && output.is_null(*row)) {
+            let value = cast_array(&array.slice(row, 1), &DataType::Utf8)?;
+            let value = value.as_any().downcast_ref::<StringArray>().unwrap();
+            return Err(Error::BadInput(format!("cannot convert '{}' to a timestamp", value.value(0))));
+        }
+        Ok(output)
+    }
+}
+
+/// `parse_datetime_tz(format, text)`, a timestamp parsed together with its offset from UTC.
+/// All values of a column share one time zone, so the result is shown at the parsed offset
+/// if the text is a constant, and in UTC otherwise. AT TIME ZONE can be used to show it in another one.
+pub struct ParseDateTimeTz {
+    format: Arc<dyn Expression>,
+    text: Arc<dyn Expression>,
+}
+
+impl ParseDateTimeTz {
+    pub fn new(format: Arc<dyn Expression>, text: Arc<dyn Expression>) -> ParseDateTimeTz {
+        ParseDateTimeTz {
+            format,
+            text,
+        }
+    }
+
+    fn output_type(&self) -> Result<DataType, Error> {
+        let zone = match (self.format.constant_value(), self.text.constant_value()) {
+            (Some(ScalarValue::Utf8(format)), Some(ScalarValue::Utf8(text))) => Zone::Fixed(*parse_datetime_tz(format.as_str(), text.as_str())?.offset()),
+            _ => Zone::utc(),
+        };
+        Ok(zoned_timestamp_type(&zone))
+    }
+}
+
+fn parse_datetime_tz(format: &str, text: &str) -> Result<DateTime<FixedOffset>, Error> {
+    DateTime::parse_from_str(text, format)
+        .map_err(|err| Error::BadInput(format!("cannot parse '{}' with format '{}': {}", text, format, err)))
+}
+
+impl Expression for ParseDateTimeTz {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let mut nullable = false;
+        for arg in &[&self.format, &self.text] {
+            let field = arg.field_meta(schema_context.clone(), record_schema)?;
+            match field.data_type() {
+                DataType::Null | DataType::Utf8 => {}
+                other => return Err(Error::BadInput(format!("parse_datetime_tz expects strings, got {:?}", other))),
+            }
+            nullable = nullable || field.is_nullable() || field.data_type() == &DataType::Null;
+        }
+        Ok(Field::new("", self.output_type()?, nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let output_type = self.output_type()?;
+        let format = cast_array(&self.format.evaluate(ctx, record)?, &DataType::Utf8)?;
+        let text = cast_array(&self.text.evaluate(ctx, record)?, &DataType::Utf8)?;
+        let format = format.as_any().downcast_ref::<StringArray>().unwrap();
+        let text = text.as_any().downcast_ref::<StringArray>().unwrap();
+        let mut builder = PrimitiveBuilder::<TimestampNanosecondType>::new(record.num_rows());
+        for row in 0..record.num_rows() {
+            if format.is_null(row) || text.is_null(row) {
+                builder.append_null()?;
+            } else {
+                builder.append_value(parse_datetime_tz(format.value(row), text.value(row))?.timestamp_nanos())?;
+            }
+        }
+        Ok(with_time_zone(&(Arc::new(builder.finish()) as ArrayRef), &output_type))
+    }
+}
+
+/// `now()`, the time the session started, so that it's the same for all rows.
+pub struct Now {}
+
+impl Expression for Now {
+    fn field_meta(&self, _schema_context: Arc<dyn SchemaContext>, _record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        Ok(Field::new("", timestamp_type(), false))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        scalar_to_array(&ScalarValue::Timestamp(ctx.session.now()), record.num_rows())
+    }
+}
+
+#[test]
+fn test_datetime() {
+    let ts = |text: &str| from_datetime(&NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()).unwrap();
+    let utc = Zone::utc();
+    let t = ts("2020-05-31 13:45:10");
+    assert_eq!(date_trunc(TimeField::Month, t, &utc).unwrap(), ts("2020-05-01 00:00:00"));
+    assert_eq!(date_trunc(TimeField::Week, t, &utc).unwrap(), ts("2020-05-25 00:00:00"));
+    assert_eq!(date_trunc(TimeField::Hour, t, &utc).unwrap(), ts("2020-05-31 13:00:00"));
+    assert_eq!(date_part(TimeField::Quarter, t, &utc).unwrap(), 2);
+    assert_eq!(date_part(TimeField::DayOfWeek, t, &utc).unwrap(), 0);
+    assert_eq!(date_add(TimeField::Month, 1, t, &utc).unwrap(), ts("2020-06-30 13:45:10"));
+    assert_eq!(date_add(TimeField::Day, -31, t, &utc).unwrap(), ts("2020-04-30 13:45:10"));
+    assert_eq!(date_diff(TimeField::Month, ts("2020-01-31 00:00:00"), ts("2020-02-28 00:00:00"), &utc).unwrap(), 0);
+    assert_eq!(date_diff(TimeField::Month, ts("2020-01-15 00:00:00"), ts("2020-03-15 00:00:00"), &utc).unwrap(), 2);
+    assert_eq!(date_diff(TimeField::Day, t, ts("2020-05-30 14:00:00"), &utc).unwrap(), 0);
+    assert_eq!(to_char(t, "YYYY-MM-DD HH24:MI:SS \"at\" Day", &utc).unwrap(), "2020-05-31 13:45:10 at Sunday");
+    assert!(strftime(t, "%Q", &utc).is_err());
+    assert_eq!(interval_milliseconds(interval_day_time(-1500).unwrap()), -1500);
+    assert_eq!(format_interval_day_time(interval_day_time(90_061_000).unwrap()), "1 day 01:01:01");
+
+    // The clocks in Warsaw went forward from 02:00 to 03:00 on 2020-03-29, and back from 03:00 to 02:00 on 2020-10-25.
+    let warsaw = Zone::parse("Europe/Warsaw").unwrap();
+    assert_eq!(date_trunc(TimeField::Day, ts("2020-03-29 12:00:00"), &warsaw).unwrap(), ts("2020-03-28 23:00:00"));
+    assert_eq!(date_trunc(TimeField::Day, ts("2020-03-30 12:00:00"), &warsaw).unwrap(), ts("2020-03-29 22:00:00"));
+    assert_eq!(date_trunc(TimeField::Hour, ts("2020-10-25 01:30:00"), &warsaw).unwrap(), ts("2020-10-25 01:00:00"));
+    assert_eq!(date_add(TimeField::Day, 1, ts("2020-03-28 11:00:00"), &warsaw).unwrap(), ts("2020-03-29 10:00:00"));
+    assert_eq!(to_char(ts("2020-10-25 00:30:00"), "HH24:MI TZH:TZM", &warsaw).unwrap(), "02:30 +02:00");
+    assert_eq!(to_char(ts("2020-10-25 01:30:00"), "HH24:MI TZH:TZM", &warsaw).unwrap(), "02:30 +01:00");
+    assert_eq!(Zone::parse("+05:30").unwrap().name(), "+05:30");
+    assert!(Zone::parse("Mars/Olympus_Mons").is_err());
+}
+
+#[test]
+fn test_time_zones() {
+    use std::collections::HashMap;
+    use crate::physical::testing::{net, query};
+    use crate::query::PreparedQuery;
+
+    let ts = |text: &str| ScalarValue::Timestamp(from_datetime(&NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()).unwrap());
+    let sql = "SELECT parse_datetime_tz('%Y-%m-%d %H:%M %z', '2020-05-31 13:45 +0200') AS t FROM range(0, 1) r";
+    let schema = PreparedQuery::new(sql).unwrap().schema(&HashMap::new()).unwrap();
+    assert_eq!(schema.field(0).data_type(), &zoned_timestamp_type(&Zone::parse("+02:00").unwrap()));
+    assert_eq!(net(query(sql).unwrap()), vec![vec![ts("2020-05-31 11:45:00")]]);
+
+    // Zoned timestamps keep their time zone when sorted and filtered.
+    let sql = "SELECT s.t FROM (SELECT c.id, parse_datetime_tz('%Y-%m-%d %H:%M %z', '2020-05-31 13:45 +0200') AT TIME ZONE 'Europe/Warsaw' AS t FROM cats.csv c WHERE c.id < 3) s ORDER BY s.id DESC";
+    assert_eq!(net(query(sql).unwrap()), vec![vec![ts("2020-05-31 11:45:00")], vec![ts("2020-05-31 11:45:00")]]);
+
+    assert!(query("SELECT parse_datetime_tz('%Y-%m-%d %z', 'yesterday') FROM range(0, 1) r").is_err());
+}
@@ -0,0 +1,171 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::BooleanArray;
+use arrow::datatypes::Schema;
+
+use crate::physical::arrow::{get_scalar_value, records_to_batch};
+use crate::physical::expression::Expression;
+use crate::physical::physical::*;
+
+/// Emits a single record for each key, the latest one present, retracting the previous one when it changes.
+/// Without keys the whole record is the key, so each distinct record is emitted
+/// once its count goes from 0 to 1 and retracted once it goes back to 0.
+pub struct Distinct {
+    source: Arc<dyn Node>,
+    keys: Vec<Arc<dyn Expression>>,
+}
+
+impl Distinct {
+    pub fn new(source: Arc<dyn Node>, keys: Vec<Arc<dyn Expression>>) -> Distinct {
+        Distinct {
+            source,
+            keys,
+        }
+    }
+}
+
+impl Node for Distinct {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        self.source.schema(schema_context)
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let output_schema = self.schema(ctx.variable_context.clone())?;
+
+        // The distinct records present for each key with their counts, the latest inserted one last.
+        let mut state: HashMap<Vec<ScalarValue>, Vec<(Vec<ScalarValue>, i64)>> = HashMap::new();
+
+        self.source.run(
+            ctx,
+            &mut |produce_ctx, batch| {
+                let keys = self.keys.iter()
+                    .map(|key| key.evaluate(ctx, &batch))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let retractions = batch.column(batch.num_columns() - 1).as_any().downcast_ref::<BooleanArray>().unwrap();
+
+                let mut output: Vec<(Vec<ScalarValue>, bool)> = Vec::new();
+                for row in 0..batch.num_rows() {
+                    let record = (0..batch.num_columns() - 1)
+                        .map(|column| get_scalar_value(batch.column(column), row))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let key = if self.keys.is_empty() {
+                        record.clone()
+                    } else {
+                        keys.iter()
+                            .map(|key| get_scalar_value(key, row))
+                            .collect::<Result<Vec<_>, _>>()?
+                    };
+
+                    let records = state.entry(key.clone()).or_default();
+                    let previous = records.last().map(|(record, _)| record.clone());
+                    let position = records.iter().position(|(other, _)| other == &record);
+                    if !retractions.value(row) {
+                        let count = position.map(|i| records.remove(i).1).unwrap_or(0);
+                        records.push((record, count + 1));
+                    } else {
+                        let i = position.ok_or_else(|| Error::BadInput("retraction of a record which isn't present".to_string()))?;
+                        records[i].1 -= 1;
+                        if records[i].1 == 0 {
+                            records.remove(i);
+                        }
+                    }
+                    let current = records.last().map(|(record, _)| record.clone());
+                    if records.is_empty() {
+                        state.remove(&key);
+                    }
+
+                    if previous != current {
+                        if let Some(previous) = previous {
+                            output.push((previous, true));
+                        }
+                        if let Some(current) = current {
+                            output.push((current, false));
+                        }
+                    }
+                }
+
+                if output.is_empty() {
+                    return Ok(());
+                }
+                produce(produce_ctx, records_to_batch(&output_schema, &output)?)
+            },
+            &mut noop_meta_send,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_distinct(keys: &[&str], batches: Vec<Vec<Vec<ScalarValue>>>) -> Result<Vec<Vec<ScalarValue>>, Error> {
+    use arrow::datatypes::DataType;
+
+    use crate::physical::expression::FieldExpression;
+    use crate::physical::testing::{collect, Values};
+
+    let source = Values::new(vec![("a.name", DataType::Utf8), ("a.x", DataType::Float64)], batches);
+    let keys = keys.iter()
+        .map(|name| Arc::new(FieldExpression::new(Identifier::SimpleIdentifier(name.to_string()))) as Arc<dyn Expression>)
+        .collect();
+    collect(&Distinct::new(Arc::new(source), keys))
+}
+
+#[test]
+fn test_distinct_retractions() {
+    use crate::physical::testing::row;
+
+    let (text, float) = (|text: &str| ScalarValue::Utf8(text.to_string()), ScalarValue::Float64);
+    let rows = test_distinct(&[], vec![
+        vec![row(&[text("a"), float(1.5)], false), row(&[text("a"), float(1.5)], false), row(&[text("b"), float(f64::NAN)], false)],
+        vec![row(&[text("a"), float(1.5)], true), row(&[text("a"), float(1.5)], true), row(&[text("b"), float(f64::NAN)], false), row(&[text("b"), float(f64::NAN)], true)],
+    ]).unwrap();
+    assert_eq!(rows, vec![
+        row(&[text("a"), float(1.5)], false),
+        row(&[text("b"), float(f64::NAN)], false),
+        // Only retracted once both of its copies are.
+        row(&[text("a"), float(1.5)], true),
+    ]);
+}
+
+#[test]
+fn test_distinct_on_retractions() {
+    use crate::physical::testing::row;
+
+    let (text, float) = (|text: &str| ScalarValue::Utf8(text.to_string()), ScalarValue::Float64);
+    let rows = test_distinct(&["a.name"], vec![
+        vec![row(&[text("a"), float(1.0)], false), row(&[text("a"), float(2.0)], false), row(&[text("b"), float(3.0)], false)],
+        vec![row(&[text("a"), float(2.0)], true), row(&[text("a"), float(1.0)], true), row(&[text("b"), float(-0.0)], false)],
+    ]).unwrap();
+    assert_eq!(rows, vec![
+        row(&[text("a"), float(1.0)], false),
+        // The latest record of a key replaces the previous one.
+        row(&[text("a"), float(1.0)], true),
+        row(&[text("a"), float(2.0)], false),
+        row(&[text("b"), float(3.0)], false),
+        // Once the latest one is retracted, the previous one is back.
+        row(&[text("a"), float(2.0)], true),
+        row(&[text("a"), float(1.0)], false),
+        row(&[text("a"), float(1.0)], true),
+        row(&[text("b"), float(3.0)], true),
+        row(&[text("b"), float(-0.0)], false),
+    ]);
+}
@@ -0,0 +1,226 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BinaryArray, Int64Builder, StringArray, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use md5::Md5;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use sha2::{Digest, Sha256};
+use twox_hash::XxHash64;
+
+use crate::physical::arrow::new_null_array;
+use crate::physical::expression::Expression;
+use crate::physical::physical::{Error, ExecutionContext, SchemaContext};
+
+// Unreserved characters of RFC 3986 are left as they are.
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+/// The bytes of a string or binary array.
+enum Bytes<'a> {
+    Utf8(&'a StringArray),
+    Binary(&'a BinaryArray),
+}
+
+impl<'a> Bytes<'a> {
+    fn new(array: &'a ArrayRef) -> Result<Bytes<'a>, Error> {
+        match array.data_type() {
+            DataType::Utf8 => Ok(Bytes::Utf8(array.as_any().downcast_ref::<StringArray>().unwrap())),
+            DataType::Binary => Ok(Bytes::Binary(array.as_any().downcast_ref::<BinaryArray>().unwrap())),
+            other => Err(Error::BadInput(format!("expected a string or binary argument, got {:?}", other))),
+        }
+    }
+
+    fn get(&self, i: usize) -> Option<&'a [u8]> {
+        match self {
+            Bytes::Utf8(array) if array.is_valid(i) => Some(array.value(i).as_bytes()),
+            Bytes::Binary(array) if array.is_valid(i) => Some(array.value(i)),
+            _ => None,
+        }
+    }
+}
+
+/// Checks the argument of a function over bytes, which also accepts strings.
+pub fn bytes_field_meta(name: &str, arg_fields: &[Field], output_type: DataType) -> Result<Field, Error> {
+    match arg_fields.first().map(Field::data_type) {
+        Some(DataType::Utf8) | Some(DataType::Binary) | Some(DataType::Null) => {}
+        Some(other) => return Err(Error::BadInput(format!("{} expects a string or binary argument, got {:?}", name, other))),
+        None => return Err(Error::BadInput(format!("wrong number of arguments for {}: 0", name))),
+    }
+    let nullable = arg_fields[0].is_nullable() || arg_fields[0].data_type() == &DataType::Null;
+    Ok(Field::new("", output_type, nullable))
+}
+
+/// Evaluates a function of the bytes of each value, returning a string.
+pub fn bytes_to_string(array: &ArrayRef, f: fn(&[u8]) -> Result<String, Error>) -> Result<ArrayRef, Error> {
+    if array.data_type() == &DataType::Null {
+        return new_null_array(&DataType::Utf8, array.len());
+    }
+    let input = Bytes::new(array)?;
+    let mut output = StringBuilder::new(array.len());
+    for i in 0..array.len() {
+        match input.get(i) {
+            Some(bytes) => output.append_value(f(bytes)?.as_str())?,
+            None => output.append_null()?,
+        }
+    }
+    Ok(Arc::new(output.finish()) as ArrayRef)
+}
+
+/// Evaluates a function of the bytes of each value, returning an integer.
+pub fn bytes_to_i64(array: &ArrayRef, f: fn(&[u8]) -> i64) -> Result<ArrayRef, Error> {
+    if array.data_type() == &DataType::Null {
+        return new_null_array(&DataType::Int64, array.len());
+    }
+    let input = Bytes::new(array)?;
+    let mut output = Int64Builder::new(array.len());
+    for i in 0..array.len() {
+        match input.get(i) {
+            Some(bytes) => output.append_value(f(bytes))?,
+            None => output.append_null()?,
+        }
+    }
+    Ok(Arc::new(output.finish()) as ArrayRef)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut output = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        output.push(DIGITS[(byte >> 4) as usize] as char);
+        output.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    output
+}
+
+pub fn unhex(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::BadInput(format!("invalid hexadecimal string '{}'", String::from_utf8_lossy(bytes)));
+    if bytes.len() % 2 != 0 {
+        return Err(invalid());
+    }
+    bytes.chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok()).ok_or_else(invalid))
+        .collect()
+}
+
+/// Decoded bytes are returned as strings, which they have to be valid UTF-8 for.
+pub fn decoded_text(bytes: Vec<u8>) -> Result<String, Error> {
+    String::from_utf8(bytes).map_err(|err| Error::BadInput(format!("decoded bytes {} are not valid UTF-8", hex(err.as_bytes()))))
+}
+
+pub fn md5(bytes: &[u8]) -> String {
+    hex(&Md5::digest(bytes))
+}
+
+pub fn sha256(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+// The 64-bit hashes are returned as signed integers with the same bits, as there are no unsigned integers in SQL.
+pub fn xxhash64(bytes: &[u8]) -> i64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(bytes);
+    hasher.finish() as i64
+}
+
+/// 64-bit FNV-1a.
+pub fn fnv(bytes: &[u8]) -> i64 {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+    hash as i64
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    base64::encode(bytes)
+}
+
+pub fn base64_decode(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    base64::decode(bytes).map_err(|err| Error::BadInput(format!("invalid base64 string '{}': {}", String::from_utf8_lossy(bytes), err)))
+}
+
+pub fn url_encode(text: &str) -> String {
+    utf8_percent_encode(text, URL_ENCODE_SET).to_string()
+}
+
+// A '+' is decoded as a space, like in query strings.
+pub fn url_decode(text: &str) -> Result<String, Error> {
+    let text = text.replace('+', " ");
+    percent_decode_str(text.as_str()).decode_utf8()
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| Error::BadInput(format!("url decoded '{}' is not valid UTF-8", text)))
+}
+
+/// Formats 16 random bytes as a version 4 UUID.
+pub fn uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex(&bytes);
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// `uuid()`, a random version 4 UUID for each row, drawn from the session's generator.
+pub struct Uuid {}
+
+impl Expression for Uuid {
+    fn field_meta(&self, _schema_context: Arc<dyn SchemaContext>, _record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        Ok(Field::new("", DataType::Utf8, false))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let uuids = ctx.session.random_bytes(record.num_rows()).into_iter()
+            .map(uuid_v4)
+            .collect::<Vec<_>>();
+        Ok(Arc::new(StringArray::from(uuids.iter().map(String::as_str).collect::<Vec<_>>())) as ArrayRef)
+    }
+}
+
+#[test]
+fn test_encoding() {
+    assert_eq!(md5(b"octosql"), "07f71c6f26b685cbdf13eb1071ab6f04");
+    assert_eq!(sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(fnv(b"a"), 0xaf63dc4c8601ec8cu64 as i64);
+    assert_eq!(xxhash64(b""), 0xef46db3751d8e999u64 as i64);
+    assert_eq!(base64_decode(base64_encode(b"\x00\xffab").as_bytes()).unwrap(), b"\x00\xffab");
+    assert_eq!(unhex(hex(b"\x01\xab").as_bytes()).unwrap(), b"\x01\xab");
+    assert!(unhex(b"abc").is_err());
+    assert!(decoded_text(unhex(b"ff").unwrap()).is_err());
+    assert_eq!(url_encode("a b&c=ł"), "a%20b%26c%3D%C5%82");
+    assert_eq!(url_decode("a+b%26c%3D%C5%82").unwrap(), "a b&c=ł");
+    assert_eq!(uuid_v4([0xff; 16]), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+}
+
+#[test]
+fn test_decoded_values() {
+    use crate::physical::physical::ScalarValue;
+    use crate::physical::testing::{net, query};
+
+    // The decoded values can be grouped and sorted like any other strings.
+    let rows = net(query("SELECT s.name, count(s.name) AS n FROM (SELECT unhex(hex(c.name)) AS name FROM cats.csv c WHERE c.id < 6) s GROUP BY s.name ORDER BY s.name").unwrap());
+    let names = rows.iter().map(|row| (row[0].clone(), row[1].clone())).collect::<Vec<_>>();
+    assert_eq!(names, vec![
+        (ScalarValue::Utf8("Buster".to_string()), ScalarValue::Int64(1)),
+        (ScalarValue::Utf8("Lucy".to_string()), ScalarValue::Int64(1)),
+        (ScalarValue::Utf8("Pepper".to_string()), ScalarValue::Int64(1)),
+        (ScalarValue::Utf8("Tiger".to_string()), ScalarValue::Int64(2)),
+    ]);
+    let rows = net(query("SELECT hex(c.name), base64_decode(base64_encode(c.name)), md5(c.name) FROM cats.csv c WHERE c.id = 1").unwrap());
+    assert_eq!(rows, vec![vec![
+        ScalarValue::Utf8("427573746572".to_string()),
+        ScalarValue::Utf8("Buster".to_string()),
+        ScalarValue::Utf8(md5(b"Buster")),
+    ]]);
+    assert!(query("SELECT unhex('ff') FROM range(0, 1) r").is_err());
+}
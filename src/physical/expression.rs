@@ -1,11 +1,11 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use arrow::array::{ArrayDataBuilder, ArrayRef, BooleanBufferBuilder, BufferBuilderTrait, Int64Builder, StringBuilder, StructArray};
-use arrow::buffer::MutableBuffer;
+use arrow::array::{ArrayRef, BooleanArray, StructArray, UInt32Array};
 use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
 
-use crate::physical::arrow::{create_row, make_array};
+use crate::physical::arrow::{concat_arrays, get_scalar_value, new_null_array, scalar_to_array, take_array};
+use crate::physical::nested::struct_field;
 use crate::physical::physical::{Error, ExecutionContext, Identifier, Node, noop_meta_send, ScalarValue, SchemaContext, SchemaContextWithSchema, VariableContext};
 
 pub trait Expression: Send + Sync {
@@ -15,6 +15,11 @@ pub trait Expression: Send + Sync {
         record_schema: &Arc<Schema>,
     ) -> Result<Field, Error>;
     fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error>;
+    /// Returns the value of the expression if it's known to be the same for all records,
+    /// so that it can be preprocessed once, instead of for each record batch.
+    fn constant_value(&self) -> Option<ScalarValue> {
+        None
+    }
 }
 
 pub struct FieldExpression {
@@ -25,6 +30,23 @@ impl FieldExpression {
     pub fn new(field: Identifier) -> FieldExpression {
         FieldExpression { field }
     }
+
+    // A qualified name which isn't a column may be a field of a struct column, i.e. payload.user.
+    fn struct_column(&self, schema: &Schema) -> Option<(usize, Field)> {
+        match &self.field {
+            Identifier::NamespacedIdentifier(column, name) => {
+                let index = schema.index_of(column).ok()?;
+                let column_field = schema.field(index);
+                match column_field.data_type() {
+                    DataType::Struct(fields) => fields.iter()
+                        .find(|field| field.name() == name)
+                        .map(|field| (index, Field::new(name, field.data_type().clone(), field.is_nullable() || column_field.is_nullable()))),
+                    _ => None,
+                }
+            }
+            Identifier::SimpleIdentifier(_) => None,
+        }
+    }
 }
 
 // TODO: Two phases, FieldExpression and RunningFieldExpression. First gets the schema and produces the second.
@@ -38,6 +60,7 @@ impl Expression for FieldExpression {
         let field_name = field_name_string.as_str();
         match record_schema.field_with_name(field_name) {
             Ok(field) => Ok(field.clone()),
+            Err(_) if self.struct_column(record_schema).is_some() => Ok(self.struct_column(record_schema).unwrap().1),
             Err(arrow_err) => {
                 match schema_context.field_with_name(field_name).map(|field| field.clone()) {
                     Ok(field) => Ok(field),
@@ -51,6 +74,9 @@ impl Expression for FieldExpression {
         let field_name_string = self.field.to_string();
         let field_name = field_name_string.as_str();
         let field_index = record_schema.index_of(field_name);
+        if let (Err(_), Some((index, field))) = (&field_index, self.struct_column(&record_schema)) {
+            return struct_field(record.column(index), field.name());
+        }
         if let Err(err) = field_index {
             let mut variable_context = Some(ctx.variable_context.clone());
             loop {
@@ -91,36 +117,97 @@ impl Expression for Constant {
         Ok(Field::new("", self.value.data_type(), self.value == ScalarValue::Null))
     }
     fn evaluate(&self, _ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
-        match &self.value {
-            ScalarValue::Int64(n) => {
-                let mut array = Int64Builder::new(record.num_rows());
-                for _i in 0..record.num_rows() {
-                    array.append_value(*n).unwrap();
-                }
-                Ok(Arc::new(array.finish()) as ArrayRef)
-            }
-            ScalarValue::Utf8(v) => {
-                let mut array = StringBuilder::new(record.num_rows());
-                for _i in 0..record.num_rows() {
-                    array.append_value(v.as_str()).unwrap();
-                }
-                Ok(Arc::new(array.finish()) as ArrayRef)
-            }
-            _ => {
-                dbg!(self.value.data_type());
-                unimplemented!()
-            }
-        }
+        scalar_to_array(&self.value, record.num_rows())
+    }
+    fn constant_value(&self) -> Option<ScalarValue> {
+        Some(self.value.clone())
     }
 }
 
+/// Whether the field may be referenced by one of the variables of a subquery.
+/// Names which are also present in the enclosing scopes are conservatively treated as references to them.
+pub fn references(variables: &[Identifier], field: &Field) -> bool {
+    variables.iter().any(|variable| {
+        field.name() == &variable.to_string() || match variable {
+            Identifier::NamespacedIdentifier(column, _) => field.name() == column,
+            Identifier::SimpleIdentifier(_) => false,
+        }
+    })
+}
+
+/// A scalar subquery, null if it yields no rows and an error if it yields more than one.
+/// A subquery which doesn't reference any variable of the enclosing queries is only run once.
 pub struct Subquery {
     query: Arc<dyn Node>,
+    // All variables referenced inside of the subquery, used to tell whether it's correlated.
+    variables: Vec<Identifier>,
+    cached: Mutex<Option<ArrayRef>>,
 }
 
 impl Subquery {
-    pub fn new(query: Arc<dyn Node>) -> Subquery {
-        Subquery { query }
+    pub fn new(query: Arc<dyn Node>, variables: Vec<Identifier>) -> Subquery {
+        Subquery {
+            query,
+            variables,
+            cached: Mutex::new(None),
+        }
+    }
+
+    fn is_correlated(&self, ctx: &ExecutionContext, record_schema: &Schema) -> bool {
+        let references_any = |schema: &Schema| schema.fields().iter().any(|field| references(&self.variables, field));
+        if references_any(record_schema) {
+            return true;
+        }
+        let mut variable_context = Some(ctx.variable_context.clone());
+        while let Some(var_ctx) = variable_context {
+            if references_any(&var_ctx.schema) {
+                return true;
+            }
+            variable_context = var_ctx.previous.clone();
+        }
+        false
+    }
+
+    /// Runs the subquery, returning its single value as an array of length 1.
+    fn run_query(&self, ctx: &ExecutionContext, output_type: &DataType) -> Result<ArrayRef, Error> {
+        let mut batches = vec![];
+        self.query.run(
+            ctx,
+            &mut |_produce_ctx, batch| {
+                batches.push(batch);
+                Ok(())
+            },
+            &mut noop_meta_send,
+        )?;
+
+        // The rows which are left after applying retractions, as (batch, row) pairs.
+        let mut rows: Vec<(usize, usize)> = vec![];
+        for (batch_index, batch) in batches.iter().enumerate() {
+            let retractions = batch.column(batch.num_columns() - 1).as_any().downcast_ref::<BooleanArray>().unwrap();
+            for row in 0..batch.num_rows() {
+                if !retractions.value(row) {
+                    rows.push((batch_index, row));
+                    continue;
+                }
+                let value = get_scalar_value(batch.column(0), row)?;
+                let mut retracted = None;
+                for (i, (other_batch, other_row)) in rows.iter().enumerate() {
+                    if get_scalar_value(batches[*other_batch].column(0), *other_row)? == value {
+                        retracted = Some(i);
+                        break;
+                    }
+                }
+                if let Some(i) = retracted {
+                    rows.remove(i);
+                }
+            }
+        }
+
+        match rows.as_slice() {
+            [] => new_null_array(output_type, 1),
+            [(batch, row)] => Ok(batches[*batch].column(0).slice(*row, 1)),
+            _ => Err(Error::BadInput(format!("scalar subquery returned {} rows, expected at most one", rows.len()))),
+        }
     }
 }
 
@@ -141,7 +228,6 @@ impl Expression for Subquery {
         Ok(Field::new(field_base.name().as_str(), field_base.data_type().clone(), true))
     }
 
-    // TODO: Would probably be more elegant to gather vectors of record batches, and then do a type switch later, creating the final array in a typesafe way.
     fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
         let source_schema = self.query.schema(Arc::new(SchemaContextWithSchema {
             previous: ctx.variable_context.clone(),
@@ -149,76 +235,42 @@ impl Expression for Subquery {
         }))?;
         let output_type = source_schema.field(0).data_type().clone();
 
-        let single_value_byte_size: usize = match output_type {
-            DataType::Int64 => 8,
-            _ => unimplemented!(),
-        };
-
-        let builder = ArrayDataBuilder::new(output_type);
-        let mut null_bitmap_builder = BooleanBufferBuilder::new(record.num_rows() * single_value_byte_size);
-        let mut buffer = MutableBuffer::new(record.num_rows() * single_value_byte_size);
-
-        for i in 0..record.num_rows() {
-            let mut row = Vec::with_capacity(record.num_columns());
-            for _i in 0..record.num_columns() {
-                row.push(ScalarValue::Null);
+        if !self.is_correlated(ctx, &record.schema()) {
+            let mut cached = self.cached.lock().unwrap();
+            if cached.is_none() {
+                *cached = Some(self.run_query(ctx, &output_type)?);
             }
+            let indices = UInt32Array::from(vec![0; record.num_rows()]);
+            return take_array(cached.as_ref().unwrap(), &indices);
+        }
 
-            create_row(record.columns(), i, &mut row)?;
+        // Only the columns referenced by the subquery are bound, the others may not even be representable as scalars.
+        let referenced = record.schema().fields().iter()
+            .map(|field| references(&self.variables, field))
+            .collect::<Vec<_>>();
+        let mut values = Vec::with_capacity(record.num_rows());
+        for i in 0..record.num_rows() {
+            let row = record.columns().iter()
+                .zip(referenced.iter())
+                .map(|(column, referenced)| if *referenced { get_scalar_value(column, i) } else { Ok(ScalarValue::Null) })
+                .collect::<Result<Vec<_>, _>>()?;
 
             let ctx = ExecutionContext {
                 variable_context: Arc::new(VariableContext {
                     previous: Some(ctx.variable_context.clone()),
                     schema: record.schema().clone(),
                     variables: row,
-                })
+                }),
+                session: ctx.session.clone(),
             };
 
-            let mut batches = vec![];
-
-            self.query.run(
-                &ctx,
-                &mut |_produce_ctx, batch| {
-                    batches.push(batch);
-                    Ok(())
-                },
-                &mut noop_meta_send,
-            )?;
-
-            if batches.len() == 0 || batches[0].num_rows() == 0 {
-                null_bitmap_builder.append(false)?;
-                buffer.resize(buffer.len() + single_value_byte_size)?;
-                continue;
-            }
-
-            null_bitmap_builder.append(true)?;
-
-            if batches.len() != 1 {
-                unimplemented!()
-            }
-
-            if batches[0].num_rows() != 1 {
-                unimplemented!()
-            }
-
-            let cur_data = batches[0].column(0).data();
-            let cur_buffer = &cur_data.buffers()[0];
-
-            if cur_buffer.len() != single_value_byte_size {
-                unimplemented!();
-            }
-
-            buffer.reserve(buffer.len() + single_value_byte_size).unwrap();
-            buffer.write_bytes(cur_buffer.data(), 0).unwrap();
+            values.push(self.run_query(&ctx, &output_type)?);
         }
 
-        let builder = builder.add_buffer(buffer.freeze());
-        let builder = builder.null_bit_buffer(null_bitmap_builder.finish());
-        let builder = builder.len(record.num_rows());
-
-        let output_array = make_array(builder.build());
-
-        Ok(output_array)
+        if values.is_empty() {
+            return new_null_array(&output_type, 0);
+        }
+        concat_arrays(&values)
     }
 }
 
@@ -281,3 +333,16 @@ impl Expression for WildcardExpression {
         Ok(Arc::new(StructArray::from(tuple_elements)))
     }
 }
+
+#[test]
+fn test_null_subquery() {
+    use crate::physical::testing::{net, query};
+
+    for sql in &[
+        "SELECT (SELECT NULL FROM range(0, 1) r) FROM cats.csv c WHERE c.id < 3",
+        "SELECT (SELECT NULL FROM range(0, 1) r WHERE r.range = c.id) FROM cats.csv c WHERE c.id < 3",
+        "SELECT s.x FROM (SELECT (SELECT NULL FROM range(0, 1) r) AS x, c.id FROM cats.csv c WHERE c.id < 3) s ORDER BY s.id",
+    ] {
+        assert_eq!(net(query(sql).unwrap()), vec![vec![ScalarValue::Null], vec![ScalarValue::Null]], "{}", sql);
+    }
+}
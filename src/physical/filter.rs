@@ -14,11 +14,11 @@
 
 use std::sync::Arc;
 
-use arrow::array::BooleanArray;
-use arrow::compute::kernels::filter;
-use arrow::datatypes::Schema;
+use arrow::array::{Array, BooleanArray};
+use arrow::datatypes::{DataType, Schema};
 use arrow::record_batch::RecordBatch;
 
+use crate::physical::arrow::filter_array;
 use crate::physical::expression::Expression;
 use crate::physical::physical::*;
 
@@ -51,15 +51,28 @@ impl Node for Filter {
             &mut |ctx, batch| {
                 let predicate_column_untyped = self.filter_expr
                     .evaluate(exec_ctx, &batch)?;
-                let predicate_column = predicate_column_untyped
+                // A null predicate never passes.
+                if predicate_column_untyped.data_type() == &DataType::Null {
+                    return Ok(());
+                }
+                let mut predicate_column = predicate_column_untyped
                     .as_any()
                     .downcast_ref::<BooleanArray>()
                     .unwrap();
+                // Rows for which the predicate is null don't pass the filter.
+                let predicate_without_nulls: BooleanArray;
+                if predicate_column.null_count() > 0 {
+                    predicate_without_nulls = (0..predicate_column.len())
+                        .map(|i| Some(predicate_column.is_valid(i) && predicate_column.value(i)))
+                        .collect::<Vec<_>>()
+                        .into();
+                    predicate_column = &predicate_without_nulls;
+                }
                 let new_columns = batch
                     .columns()
                     .into_iter()
-                    .map(|array_ref| filter::filter(array_ref.as_ref(), predicate_column).unwrap())
-                    .collect();
+                    .map(|array_ref| filter_array(array_ref, predicate_column))
+                    .collect::<Result<_, _>>()?;
                 let new_batch = RecordBatch::try_new(source_schema.clone(), new_columns).unwrap();
                 if new_batch.num_rows() > 0 {
                     produce(ctx, new_batch)?;
@@ -71,3 +84,11 @@ impl Node for Filter {
         Ok(())
     }
 }
+
+#[test]
+fn test_filter_keeps_nulls() {
+    use crate::physical::testing::{net, query};
+
+    let rows = net(query("SELECT s.x IS NULL FROM (SELECT IF(c.age > 5, c.name) AS x FROM cats.csv c WHERE c.id < 4) s").unwrap());
+    assert_eq!(rows, vec![vec![ScalarValue::Boolean(true)], vec![ScalarValue::Boolean(true)], vec![ScalarValue::Boolean(false)]]);
+}
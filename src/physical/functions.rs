@@ -14,16 +14,35 @@
 
 use std::sync::Arc;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
-use arrow::array::{Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, StringArray, TimestampNanosecondArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array, StringBuilder, TimestampNanosecondBuilder};
-use arrow::array::ArrayRef;
-use arrow::compute::kernels::comparison::{lt, lt_eq, eq, gt_eq, gt, lt_utf8, lt_eq_utf8, eq_utf8, gt_eq_utf8, gt_utf8};
+use arrow::array::{BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, StringArray, StringBuilder, TimestampNanosecondArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array, TimestampNanosecondBuilder};
+use arrow::array::{Array, ArrayRef, NullArray};
+use arrow::compute::kernels::cast::cast;
+use arrow::compute::kernels::comparison::{lt, lt_eq, eq, neq, gt_eq, gt, lt_utf8, lt_eq_utf8, eq_utf8, neq_utf8, gt_eq_utf8, gt_utf8};
 use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::datatypes::DataType::{Boolean, Float64, Int64, Utf8};
 use arrow::error::ArrowError;
 use arrow::record_batch::RecordBatch;
 
+use crate::physical::arrow::{ArgColumn, common_supertype, compute_n_arg, Datum, get_scalar_value, interleave, null_boolean_array, scalar_to_array};
+use crate::physical::cast::{can_cast, cast_array};
+use crate::physical::conditional::Case;
+use crate::physical::datetime;
+use crate::physical::datetime::{AtTimeZone, Now, ParseDateTimeTz, resolve_time_zone, TimeField, timestamp_type, with_time_zone, Zone};
+use crate::physical::encoding;
+use crate::physical::encoding::Uuid;
 use crate::physical::expression::Expression;
-use crate::physical::physical::{Error, ExecutionContext, SchemaContext};
+use crate::physical::json;
+use crate::physical::json::TypedJsonExtract;
+use crate::physical::nested;
+use crate::physical::nested::Subscript;
+use crate::physical::logic::{And, and_kleene, boolean_operand, not_kleene, Or};
+use crate::physical::math::{Arithmetic, ArithmeticOp, Extremum, float_domain, Random, Round, UnaryMath, UnaryMathOp};
+use crate::physical::predicate::{InList, PatternKind, PatternMatch};
+use crate::physical::string;
+use crate::physical::string::RegexCache;
+use crate::physical::physical::{Error, ExecutionContext, ScalarValue, SchemaContext};
 
 use chrono::{DateTime};
 use arrow::datatypes::TimeUnit::Nanosecond;
@@ -31,7 +50,7 @@ use arrow::datatypes::TimeUnit::Nanosecond;
 // TODO: Add "custom function" expression.
 
 type EvaluateFunction = Arc<dyn Fn(Vec<ArrayRef>) -> Result<ArrayRef, Error> + Send + Sync>;
-type MetaFunction = Arc<dyn Fn(&Arc<dyn SchemaContext>, &Arc<Schema>) -> Result<Field, Error> + Send + Sync>;
+type MetaFunction = Arc<dyn Fn(&Arc<dyn SchemaContext>, &Arc<Schema>, &[Field]) -> Result<Field, Error> + Send + Sync>;
 
 pub struct FunctionExpression {
     meta_function: MetaFunction,
@@ -57,7 +76,11 @@ impl FunctionExpression
 impl Expression for FunctionExpression
 {
     fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
-        (self.meta_function)(&schema_context, record_schema)
+        let arg_fields = self.args.iter()
+            .map(|expr| expr.field_meta(schema_context.clone(), record_schema))
+            .collect::<Result<Vec<Field>, Error>>()?;
+
+        (self.meta_function)(&schema_context, record_schema, &arg_fields)
     }
 
     fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
@@ -69,14 +92,300 @@ impl Expression for FunctionExpression
     }
 }
 
+/// Stands in for a call with the wrong number of arguments, so that it's reported instead of panicking.
+pub struct WrongArity {
+    name: &'static str,
+    count: usize,
+}
+
+impl WrongArity {
+    pub fn new(name: &'static str, count: usize) -> WrongArity {
+        WrongArity { name, count }
+    }
+
+    fn error(&self) -> Error {
+        Error::BadInput(format!("wrong number of arguments for {}: {}", self.name, self.count))
+    }
+}
+
+impl Expression for WrongArity {
+    fn field_meta(&self, _schema_context: Arc<dyn SchemaContext>, _record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        Err(self.error())
+    }
+
+    fn evaluate(&self, _ctx: &ExecutionContext, _record: &RecordBatch) -> Result<ArrayRef, Error> {
+        Err(self.error())
+    }
+}
+
+pub type RowFunction = Box<dyn FnMut(&[Datum]) -> Result<ScalarValue, Error>>;
+
+/// Creates the function evaluated for each row, given the values of the constant arguments (None for the others)
+/// and the time zone of the timestamp arguments.
+/// This way, i.e. a constant regular expression gets compiled once per record batch, instead of once per row.
+pub type PrepareFunction = Arc<dyn Fn(&[Option<ScalarValue>], &Zone) -> Result<RowFunction, Error> + Send + Sync>;
+
+pub struct Signature {
+    // The last type is repeated for additional arguments of variadic functions.
+    arg_types: Vec<DataType>,
+    required_args: usize,
+    variadic: bool,
+    output_type: DataType,
+    // Strict functions return null if any argument is null, without being called.
+    strict: bool,
+    // Whether the function may return null for non-null arguments.
+    nullable: bool,
+}
+
+impl Signature {
+    pub fn new(arg_types: Vec<DataType>, output_type: DataType) -> Signature {
+        Signature {
+            required_args: arg_types.len(),
+            arg_types,
+            variadic: false,
+            output_type,
+            strict: true,
+            nullable: false,
+        }
+    }
+
+    pub fn with_optional(arg_types: Vec<DataType>, required_args: usize, output_type: DataType) -> Signature {
+        Signature {
+            required_args,
+            ..Signature::new(arg_types, output_type)
+        }
+    }
+
+    pub fn variadic(arg_type: DataType, output_type: DataType) -> Signature {
+        Signature {
+            variadic: true,
+            ..Signature::new(vec![arg_type], output_type)
+        }
+    }
+
+    pub fn non_strict(self) -> Signature {
+        Signature { strict: false, ..self }
+    }
+
+    pub fn nullable(self) -> Signature {
+        Signature { nullable: true, ..self }
+    }
+
+    fn arg_type(&self, i: usize) -> Option<&DataType> {
+        match self.arg_types.get(i) {
+            Some(data_type) => Some(data_type),
+            None if self.variadic => self.arg_types.last(),
+            None => None,
+        }
+    }
+
+    pub fn arity(&self) -> RangeInclusive<usize> {
+        self.required_args..=if self.variadic { usize::MAX } else { self.arg_types.len() }
+    }
+
+    /// Checks the number and the types of the arguments of the function with the given name.
+    pub fn check_args(&self, name: &str, arg_fields: &[Field]) -> Result<(), Error> {
+        if arg_fields.len() < self.required_args || (!arg_fields.is_empty() && self.arg_type(arg_fields.len() - 1).is_none()) {
+            return Err(Error::BadInput(format!("wrong number of arguments for {}: {}", name, arg_fields.len())));
+        }
+        for (i, field) in arg_fields.iter().enumerate() {
+            let arg_type = self.arg_type(i).unwrap();
+            if !can_cast(field.data_type(), arg_type) {
+                return Err(Error::BadInput(format!("argument {} of {} must be {:?}, is {:?}", i + 1, name, arg_type, field.data_type())));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A scalar function, which gets evaluated row by row, with its arguments cast to the types of its signature.
+/// Timestamps are handled in the time zone of the first timestamp argument which has one, or else the session time zone.
+/// A timestamp output gets the time zone of the arguments.
+pub struct ScalarFunction {
+    name: &'static str,
+    signature: Signature,
+    prepare: PrepareFunction,
+    args: Vec<Arc<dyn Expression>>,
+    // A function of a single string, which is applied to string columns directly.
+    string_op: Option<StringOp>,
+}
+
+pub type StringOp = fn(&str) -> String;
+
+impl ScalarFunction {
+    pub fn new(name: &'static str, signature: Signature, prepare: PrepareFunction, args: Vec<Arc<dyn Expression>>) -> ScalarFunction {
+        ScalarFunction {
+            name,
+            signature,
+            prepare,
+            args,
+            string_op: None,
+        }
+    }
+
+    /// A function from a string to a string.
+    pub fn string(name: &'static str, op: StringOp, args: Vec<Arc<dyn Expression>>) -> ScalarFunction {
+        let prepare = row_function(move |args: &[Datum]| Ok(ScalarValue::Utf8(op(args[0].as_str()?))));
+        ScalarFunction {
+            string_op: Some(op),
+            ..ScalarFunction::new(name, Signature::new(vec![Utf8], Utf8), prepare, args)
+        }
+    }
+}
+
+impl Expression for ScalarFunction {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let arg_fields = self.args.iter()
+            .map(|arg| arg.field_meta(schema_context.clone(), record_schema))
+            .collect::<Result<Vec<_>, Error>>()?;
+        self.signature.check_args(self.name, arg_fields.as_slice())?;
+
+        let mut nullable = self.signature.nullable;
+        let mut zoned_type = None;
+        for field in &arg_fields {
+            if self.signature.strict {
+                nullable = nullable || field.is_nullable() || field.data_type() == &DataType::Null;
+            }
+            if let DataType::Timestamp(_, Some(_)) = field.data_type() {
+                zoned_type = zoned_type.or_else(|| Some(field.data_type().clone()));
+            }
+        }
+        let output_type = match (&self.signature.output_type, zoned_type) {
+            (DataType::Timestamp(_, None), Some(zoned_type)) => zoned_type,
+            (output_type, _) => output_type.clone(),
+        };
+        Ok(Field::new("", output_type, nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let values = self.args.iter()
+            .map(|arg| match arg.constant_value() {
+                Some(value) => Ok((Some(value), None)),
+                None => Ok((None, Some(arg.evaluate(ctx, record)?))),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let zoned_type = values.iter()
+            .filter_map(|(_, array)| array.as_ref().map(|array| array.data_type()))
+            .find(|data_type| match data_type {
+                DataType::Timestamp(_, Some(_)) => true,
+                _ => false,
+            });
+        let zone = match zoned_type {
+            Some(data_type) => datetime::time_zone(data_type, ctx.session.time_zone())?,
+            None => *ctx.session.time_zone(),
+        };
+        let output_type = match (&self.signature.output_type, zoned_type) {
+            (DataType::Timestamp(_, None), Some(zoned_type)) => zoned_type.clone(),
+            (output_type, _) => output_type.clone(),
+        };
+
+        let mut args = Vec::with_capacity(self.args.len());
+        let mut constants = Vec::with_capacity(self.args.len());
+        for (i, (value, array)) in values.into_iter().enumerate() {
+            let arg_type = self.signature.arg_type(i)
+                .ok_or_else(|| Error::BadInput(format!("wrong number of arguments for {}: {}", self.name, self.args.len())))?;
+            let arg_type = resolve_time_zone(arg_type, &zone);
+            match (value, array) {
+                (Some(value), _) => {
+                    let value = get_scalar_value(&cast_array(&scalar_to_array(&value, 1)?, &arg_type)?, 0)?;
+                    constants.push(Some(value.clone()));
+                    args.push(ArgColumn::Constant(value));
+                }
+                (None, Some(array)) => {
+                    constants.push(None);
+                    args.push(ArgColumn::Column(cast_array(&array, &arg_type)?));
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+
+        if let (Some(op), [ArgColumn::Column(array)]) = (self.string_op, args.as_slice()) {
+            let output: Result<_, ArrowError> = compute_single_arg_str!(array, op);
+            return Ok(output?);
+        }
+
+        let row_function = (self.prepare)(constants.as_slice(), &zone)?;
+        let output = compute_n_arg(args.as_slice(), record.num_rows(), &self.signature.output_type, self.signature.strict, row_function)?;
+        Ok(with_time_zone(&output, &output_type))
+    }
+}
+
+/// Wraps a function which doesn't need any preparation.
+pub fn row_function<F>(f: F) -> PrepareFunction
+    where F: Fn(&[Datum]) -> Result<ScalarValue, Error> + Clone + Send + Sync + 'static {
+    Arc::new(move |_constants: &[Option<ScalarValue>], _zone: &Zone| Ok(Box::new(f.clone()) as RowFunction))
+}
+
+/// Wraps a function which depends on the time zone of its timestamp arguments.
+pub fn zoned_row_function<F>(f: F) -> PrepareFunction
+    where F: Fn(&[Datum], &Zone) -> Result<ScalarValue, Error> + Clone + Send + Sync + 'static {
+    Arc::new(move |_constants: &[Option<ScalarValue>], zone: &Zone| {
+        let (f, zone) = (f.clone(), *zone);
+        Ok(Box::new(move |args: &[Datum]| f(args, &zone)) as RowFunction)
+    })
+}
+
+/// Wraps a function of the JSON value selected by the optional second argument, a path parsed once if it's constant.
+fn json_path_function(f: fn(&serde_json::Value) -> Result<ScalarValue, Error>) -> PrepareFunction {
+    Arc::new(move |constants: &[Option<ScalarValue>], _zone: &Zone| {
+        let mut paths = json::JsonPathCache::new(constants.get(1))?;
+        Ok(Box::new(move |args: &[Datum]| {
+            let path = match args.get(1) {
+                Some(path) => Some(paths.get(path.as_str()?)?),
+                None => None,
+            };
+            match json::select_json(args[0].as_str()?, path) {
+                Some(value) => f(&value),
+                None => Ok(ScalarValue::Null),
+            }
+        }) as RowFunction)
+    })
+}
+
+/// Wraps a function whose first argument is the name of a date field, parsed once if it's constant.
+fn time_field_function(f: fn(TimeField, &[Datum], &Zone) -> Result<ScalarValue, Error>) -> PrepareFunction {
+    Arc::new(move |constants: &[Option<ScalarValue>], zone: &Zone| {
+        let zone = *zone;
+        let constant_field = match constants.first() {
+            Some(Some(ScalarValue::Utf8(name))) => Some(TimeField::parse(name)?),
+            _ => None,
+        };
+        Ok(Box::new(move |args: &[Datum]| {
+            let field = match constant_field {
+                Some(field) => field,
+                None => TimeField::parse(args[0].as_str()?)?,
+            };
+            f(field, args, &zone)
+        }) as RowFunction)
+    })
+}
+
 macro_rules! make_const_meta_body {
     ($data_type: expr) => {
-        Arc::new(|_schema_context, _record_schema| {
+        Arc::new(|_schema_context, _record_schema, _arg_fields| {
             Ok(Field::new("", $data_type, false))
         })
     }
 }
 
+// The output is nullable if any of the arguments is.
+macro_rules! make_nullable_meta_body {
+    ($data_type: expr) => {
+        Arc::new(|_schema_context, _record_schema, arg_fields: &[Field]| {
+            Ok(Field::new("", $data_type, arg_fields.iter().any(|f| f.is_nullable() || f.data_type() == &DataType::Null)))
+        })
+    }
+}
+
+// Functions over bytes accept both strings and binary values.
+macro_rules! make_bytes_meta_body {
+    ($name: expr, $data_type: expr) => {
+        Arc::new(|_schema_context, _record_schema, arg_fields: &[Field]| {
+            encoding::bytes_field_meta($name, arg_fields, $data_type)
+        })
+    }
+}
+
 // macro_rules! make_binary_primitive_array_evaluate_function {
 //     ($function: ident) => {
 //         Arc::new(|args: Vec<ArrayRef>| {
@@ -89,7 +398,15 @@ macro_rules! make_const_meta_body {
 macro_rules! make_binary_array_evaluate_function {
     ($function: ident) => {
         Arc::new(|args: Vec<ArrayRef>| {
-            let output: Result<_, ArrowError> = binary_array_op!(args[0], args[1], $function);
+            // Comparing anything with null results in null.
+            if args[0].data_type() == &DataType::Null || args[1].data_type() == &DataType::Null {
+                return Ok(null_boolean_array(args[0].len()));
+            }
+            // Values of different types get compared in their common supertype, like in arithmetic.
+            let data_type = common_supertype(args[0].data_type(), args[1].data_type())
+                .ok_or_else(|| Error::BadInput(format!("can't compare {:?} with {:?}", args[0].data_type(), args[1].data_type())))?;
+            let (left, right) = (cast_array(&args[0], &data_type)?, cast_array(&args[1], &data_type)?);
+            let output: Result<_, ArrowError> = binary_array_op!(left, right, $function);
             Ok(output? as ArrayRef)
         })
     }
@@ -105,26 +422,356 @@ macro_rules! make_binary_array_evaluate_function {
 // }
 
 macro_rules! register_function {
-    ($map: expr, $name: expr, $meta_fn: expr, $eval_fn: expr) => {
-        $map.insert($name, Arc::new(|args: Vec<Arc<dyn Expression>>| Arc::new(FunctionExpression::new($meta_fn, $eval_fn, args))));
+    ($map: expr, $name: expr, $arity: expr, $meta_fn: expr, $eval_fn: expr) => {
+        $map.insert($name, BuiltinFunction::new($name, $arity, Arc::new(|args: Vec<Arc<dyn Expression>>| Arc::new(FunctionExpression::new($meta_fn, $eval_fn, args)) as Arc<dyn Expression>)));
+    }
+}
+
+// Functions which have to control the evaluation of their arguments themselves (i.e. to short-circuit)
+// are registered with a constructor of their own expression.
+macro_rules! register_expression {
+    ($map: expr, $name: expr, $arity: expr, $constructor: expr) => {
+        $map.insert($name, BuiltinFunction::new($name, $arity, Arc::new(|args: Vec<Arc<dyn Expression>>| Arc::new(($constructor)(args)) as Arc<dyn Expression>)));
+    }
+}
+
+macro_rules! register_scalar_function {
+    ($map: expr, $name: expr, $signature: expr, $prepare: expr) => {
+        $map.insert($name, BuiltinFunction::new($name, ($signature).arity(), Arc::new(|args: Vec<Arc<dyn Expression>>| Arc::new(ScalarFunction::new($name, $signature, $prepare, args)) as Arc<dyn Expression>)));
+    }
+}
+
+macro_rules! register_string_function {
+    ($map: expr, $name: expr, $op: expr) => {
+        $map.insert($name, BuiltinFunction::new($name, 1..=1, Arc::new(|args: Vec<Arc<dyn Expression>>| Arc::new(ScalarFunction::string($name, $op, args)) as Arc<dyn Expression>)));
+    }
+}
+
+type FunctionConstructor = Arc<dyn Fn(Vec<Arc<dyn Expression>>) -> Arc<dyn Expression> + Send + Sync>;
+
+/// A builtin function, which can be called with a number of arguments in its arity.
+pub struct BuiltinFunction {
+    name: &'static str,
+    pub arity: RangeInclusive<usize>,
+    constructor: FunctionConstructor,
+}
+
+impl BuiltinFunction {
+    fn new(name: &'static str, arity: RangeInclusive<usize>, constructor: FunctionConstructor) -> BuiltinFunction {
+        BuiltinFunction { name, arity, constructor }
+    }
+
+    /// Creates the expression of a call, which reports a wrong number of arguments instead of panicking.
+    pub fn call(&self, args: Vec<Arc<dyn Expression>>) -> Arc<dyn Expression> {
+        if !self.arity.contains(&args.len()) {
+            return Arc::new(WrongArity::new(self.name, args.len()));
+        }
+        (self.constructor)(args)
     }
 }
 
 lazy_static! {
-    pub static ref BUILTIN_FUNCTIONS: HashMap<&'static str, Arc<dyn Fn(Vec<Arc<dyn Expression>>)-> Arc<FunctionExpression> + Send + Sync>> = {
-        let mut m: HashMap<&'static str, Arc<dyn Fn(Vec<Arc<dyn Expression>>)-> Arc<FunctionExpression> + Send + Sync>> = HashMap::new();
-        register_function!(m, "<", make_const_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(lt));
-        register_function!(m, "<=", make_const_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(lt_eq));
-        register_function!(m, "=", make_const_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(eq));
-        register_function!(m, ">=", make_const_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(gt_eq));
-        register_function!(m, ">", make_const_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(gt));
-        register_function!(m, "upper", make_const_meta_body!(DataType::Utf8), Arc::new(|args: Vec<ArrayRef>| {
-            let output: Result<_, ArrowError> = compute_single_arg_str!(args[0], StringArray, StringBuilder, |text: &str| {
-                text.to_uppercase()
-            });
-            Ok(output? as ArrayRef)
+    pub static ref BUILTIN_FUNCTIONS: HashMap<&'static str, BuiltinFunction> = {
+        let mut m: HashMap<&'static str, BuiltinFunction> = HashMap::new();
+        register_function!(m, "<", 2..=2, make_nullable_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(lt));
+        register_function!(m, "<=", 2..=2, make_nullable_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(lt_eq));
+        register_function!(m, "=", 2..=2, make_nullable_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(eq));
+        register_function!(m, "<>", 2..=2, make_nullable_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(neq));
+        register_function!(m, ">=", 2..=2, make_nullable_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(gt_eq));
+        register_function!(m, ">", 2..=2, make_nullable_meta_body!(DataType::Boolean), make_binary_array_evaluate_function!(gt));
+        register_function!(m, "is null", 1..=1, make_const_meta_body!(DataType::Boolean), Arc::new(|args: Vec<ArrayRef>| {
+            let output: BooleanArray = (0..args[0].len()).map(|i| Some(args[0].is_null(i))).collect::<Vec<_>>().into();
+            Ok(Arc::new(output) as ArrayRef)
+        }));
+        register_function!(m, "is not null", 1..=1, make_const_meta_body!(DataType::Boolean), Arc::new(|args: Vec<ArrayRef>| {
+            let output: BooleanArray = (0..args[0].len()).map(|i| Some(args[0].is_valid(i))).collect::<Vec<_>>().into();
+            Ok(Arc::new(output) as ArrayRef)
+        }));
+        register_function!(m, "coalesce", 1..=usize::MAX, Arc::new(|_schema_context, _record_schema, arg_fields: &[Field]| {
+            // The first argument which isn't a null literal decides the output type.
+            match arg_fields.iter().find(|f| f.data_type() != &DataType::Null) {
+                None => Ok(Field::new("", DataType::Null, true)),
+                Some(field) => Ok(Field::new("", field.data_type().clone(), arg_fields.iter().all(|f| f.is_nullable()))),
+            }
+        }), Arc::new(|args: Vec<ArrayRef>| {
+            let len = args[0].len();
+            let args = args.into_iter()
+                .filter(|arg| arg.data_type() != &DataType::Null)
+                .collect::<Vec<_>>();
+            if args.is_empty() {
+                return Ok(Arc::new(NullArray::new(len)) as ArrayRef);
+            }
+            let output_type = args[0].data_type().clone();
+            let args = args.iter()
+                .map(|arg| if arg.data_type() == &output_type { Ok(arg.clone()) } else { cast(arg, &output_type) })
+                .collect::<Result<Vec<_>, ArrowError>>()?;
+            let selection = (0..len)
+                .map(|row| args.iter().position(|arg| arg.is_valid(row)))
+                .collect::<Vec<_>>();
+            interleave(args.as_slice(), selection.as_slice())
+        }));
+        register_function!(m, "nullif", 2..=2, Arc::new(|_schema_context, _record_schema, arg_fields: &[Field]| {
+            Ok(Field::new("", arg_fields[0].data_type().clone(), true))
+        }), Arc::new(|args: Vec<ArrayRef>| {
+            if args[0].data_type() == &DataType::Null || args[1].data_type() == &DataType::Null {
+                return Ok(args[0].clone());
+            }
+            let equal: Result<_, ArrowError> = binary_array_op!(args[0], args[1], eq);
+            let equal = equal?;
+            let selection = (0..args[0].len())
+                .map(|row| if equal.is_valid(row) && equal.value(row) { None } else { Some(0) })
+                .collect::<Vec<_>>();
+            interleave(&args[0..1], selection.as_slice())
+        }));
+        register_expression!(m, "and", 2..=2, |args: Vec<Arc<dyn Expression>>| And::new(args[0].clone(), args[1].clone()));
+        register_expression!(m, "or", 2..=2, |args: Vec<Arc<dyn Expression>>| Or::new(args[0].clone(), args[1].clone()));
+        register_expression!(m, "in", 2..=usize::MAX, |args: Vec<Arc<dyn Expression>>| InList::new(args[0].clone(), args[1..].to_vec()));
+        register_function!(m, "between", 3..=3, make_nullable_meta_body!(DataType::Boolean), Arc::new(|args: Vec<ArrayRef>| {
+            if args.iter().any(|arg| arg.data_type() == &DataType::Null) {
+                return Ok(null_boolean_array(args[0].len()));
+            }
+            let data_type = args[1..].iter()
+                .try_fold(args[0].data_type().clone(), |acc, arg| common_supertype(&acc, arg.data_type()))
+                .ok_or_else(|| Error::BadInput(format!("can't compare {:?} with {:?} and {:?}", args[0].data_type(), args[1].data_type(), args[2].data_type())))?;
+            let args = args.iter().map(|arg| cast_array(arg, &data_type)).collect::<Result<Vec<_>, Error>>()?;
+            let lower: Result<Arc<BooleanArray>, ArrowError> = binary_array_op!(args[0], args[1], gt_eq);
+            let upper: Result<Arc<BooleanArray>, ArrowError> = binary_array_op!(args[0], args[2], lt_eq);
+            let (lower, upper) = (lower?, upper?);
+            Ok(Arc::new(and_kleene(lower.as_ref(), upper.as_ref())?) as ArrayRef)
+        }));
+        register_expression!(m, "like", 2..=2, |args: Vec<Arc<dyn Expression>>| PatternMatch::new(args[0].clone(), args[1].clone(), PatternKind::Like, false));
+        register_expression!(m, "not like", 2..=2, |args: Vec<Arc<dyn Expression>>| PatternMatch::new(args[0].clone(), args[1].clone(), PatternKind::Like, true));
+        register_expression!(m, "ilike", 2..=2, |args: Vec<Arc<dyn Expression>>| PatternMatch::new(args[0].clone(), args[1].clone(), PatternKind::ILike, false));
+        register_expression!(m, "not ilike", 2..=2, |args: Vec<Arc<dyn Expression>>| PatternMatch::new(args[0].clone(), args[1].clone(), PatternKind::ILike, true));
+        register_expression!(m, "regexp_matches", 2..=2, |args: Vec<Arc<dyn Expression>>| PatternMatch::new(args[0].clone(), args[1].clone(), PatternKind::Regexp, false));
+        register_expression!(m, "if", 2..=3, |args: Vec<Arc<dyn Expression>>| Case::new(None, vec![args[0].clone()], vec![args[1].clone()], args.get(2).cloned()));
+        register_function!(m, "not", 1..=1, make_nullable_meta_body!(DataType::Boolean), Arc::new(|args: Vec<ArrayRef>| {
+            let arg = boolean_operand(args[0].clone())?;
+            Ok(Arc::new(not_kleene(arg.as_any().downcast_ref::<BooleanArray>().unwrap())?) as ArrayRef)
+        }));
+        register_string_function!(m, "lower", |text| text.to_lowercase());
+        register_string_function!(m, "upper", |text| text.to_uppercase());
+        register_scalar_function!(m, "length", Signature::new(vec![Utf8], Int64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Int64(args[0].as_str()?.chars().count() as i64))
+        }));
+        register_scalar_function!(m, "substr", Signature::with_optional(vec![Utf8, Int64, Int64], 2, Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::substr(args[0].as_str()?, args[1].as_i64()?, args.get(2).map(Datum::as_i64).transpose()?)?))
+        }));
+        register_scalar_function!(m, "substring", Signature::with_optional(vec![Utf8, Int64, Int64], 2, Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::substr(args[0].as_str()?, args[1].as_i64()?, args.get(2).map(Datum::as_i64).transpose()?)?))
+        }));
+        // concat skips null arguments, while || returns null if any of them is null.
+        register_scalar_function!(m, "concat", Signature::variadic(Utf8, Utf8).non_strict(), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(args.iter().filter(|arg| !arg.is_null()).map(Datum::as_str).collect::<Result<String, Error>>()?))
+        }));
+        register_scalar_function!(m, "||", Signature::new(vec![Utf8, Utf8], Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(args[0].as_str()?.to_string() + args[1].as_str()?))
+        }));
+        register_scalar_function!(m, "trim", Signature::with_optional(vec![Utf8, Utf8], 1, Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::trim(args[0].as_str()?, args.get(1).map(Datum::as_str).transpose()?, true, true)))
+        }));
+        register_scalar_function!(m, "ltrim", Signature::with_optional(vec![Utf8, Utf8], 1, Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::trim(args[0].as_str()?, args.get(1).map(Datum::as_str).transpose()?, true, false)))
+        }));
+        register_scalar_function!(m, "rtrim", Signature::with_optional(vec![Utf8, Utf8], 1, Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::trim(args[0].as_str()?, args.get(1).map(Datum::as_str).transpose()?, false, true)))
+        }));
+        register_scalar_function!(m, "replace", Signature::new(vec![Utf8, Utf8, Utf8], Utf8), row_function(|args: &[Datum]| {
+            if args[1].as_str()?.is_empty() {
+                return Ok(ScalarValue::Utf8(args[0].as_str()?.to_string()));
+            }
+            Ok(ScalarValue::Utf8(args[0].as_str()?.replace(args[1].as_str()?, args[2].as_str()?)))
+        }));
+        register_scalar_function!(m, "split_part", Signature::new(vec![Utf8, Utf8, Int64], Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::split_part(args[0].as_str()?, args[1].as_str()?, args[2].as_i64()?)?))
+        }));
+        register_scalar_function!(m, "starts_with", Signature::new(vec![Utf8, Utf8], Boolean), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Boolean(args[0].as_str()?.starts_with(args[1].as_str()?)))
+        }));
+        // position(substring, text), 1-based, 0 if not found.
+        register_scalar_function!(m, "position", Signature::new(vec![Utf8, Utf8], Int64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Int64(string::position(args[0].as_str()?, args[1].as_str()?)))
+        }));
+        register_scalar_function!(m, "lpad", Signature::with_optional(vec![Utf8, Int64, Utf8], 2, Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::pad(args[0].as_str()?, args[1].as_i64()?, args.get(2).map(Datum::as_str).transpose()?.unwrap_or(" "), true)))
+        }));
+        register_scalar_function!(m, "rpad", Signature::with_optional(vec![Utf8, Int64, Utf8], 2, Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(string::pad(args[0].as_str()?, args[1].as_i64()?, args.get(2).map(Datum::as_str).transpose()?.unwrap_or(" "), false)))
+        }));
+        register_string_function!(m, "reverse", |text| text.chars().rev().collect());
+        // regexp_replace(text, pattern, replacement[, flags]), the replacement may reference groups as $1 or ${name}.
+        // Only the first match is replaced, unless the flags contain 'g'.
+        register_scalar_function!(m, "regexp_replace", Signature::with_optional(vec![Utf8, Utf8, Utf8, Utf8], 3, Utf8), Arc::new(|constants: &[Option<ScalarValue>], _zone: &Zone| {
+            let mut regexes = RegexCache::new(&constants[1], constants.get(3))?;
+            Ok(Box::new(move |args: &[Datum]| {
+                let flags = args.get(3).map(Datum::as_str).transpose()?.unwrap_or("");
+                let regex = regexes.get(args[1].as_str()?, flags)?;
+                let output = if flags.contains('g') {
+                    regex.replace_all(args[0].as_str()?, args[2].as_str()?)
+                } else {
+                    regex.replace(args[0].as_str()?, args[2].as_str()?)
+                };
+                Ok(ScalarValue::Utf8(output.into_owned()))
+            }) as RowFunction)
+        }));
+        // regexp_extract(text, pattern[, group]), null if there's no match.
+        register_scalar_function!(m, "regexp_extract", Signature::with_optional(vec![Utf8, Utf8, Int64], 2, Utf8).nullable(), Arc::new(|constants: &[Option<ScalarValue>], _zone: &Zone| {
+            let mut regexes = RegexCache::new(&constants[1], None)?;
+            Ok(Box::new(move |args: &[Datum]| {
+                let regex = regexes.get(args[1].as_str()?, "")?;
+                let group = args.get(2).map(Datum::as_i64).transpose()?.unwrap_or(0);
+                let matched = regex.captures(args[0].as_str()?)
+                    .and_then(|captures| captures.get(group.max(0) as usize))
+                    .map(|matched| ScalarValue::Utf8(matched.as_str().to_string()));
+                Ok(matched.unwrap_or(ScalarValue::Null))
+            }) as RowFunction)
+        }));
+        // JSON documents stored as strings. Invalid documents and missing paths result in null.
+        register_scalar_function!(m, "json_valid", Signature::new(vec![Utf8], Boolean), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Boolean(json::parse_json(args[0].as_str()?).is_some()))
+        }));
+        // json_extract(json, path) returns the selected value as JSON.
+        register_scalar_function!(m, "json_extract", Signature::new(vec![Utf8, Utf8], Utf8).nullable(), json_path_function(|value| {
+            Ok(ScalarValue::Utf8(value.to_string()))
+        }));
+        // json_extract_scalar(json, path[, type]) returns the selected scalar as a string, or converted to the given type.
+        m.insert("json_extract_scalar", BuiltinFunction::new("json_extract_scalar", 2..=3, Arc::new(|args: Vec<Arc<dyn Expression>>| {
+            if args.len() == 3 {
+                return Arc::new(TypedJsonExtract::new(args[0].clone(), args[1].clone(), args[2].clone())) as Arc<dyn Expression>;
+            }
+            Arc::new(ScalarFunction::new("json_extract_scalar", Signature::new(vec![Utf8, Utf8], Utf8).nullable(), json_path_function(|value| {
+                Ok(json::json_scalar_text(value).map(ScalarValue::Utf8).unwrap_or(ScalarValue::Null))
+            }), args))
+        })));
+        register_scalar_function!(m, "json_array_length", Signature::with_optional(vec![Utf8, Utf8], 1, Int64).nullable(), json_path_function(|value| {
+            Ok(value.as_array().map(|array| ScalarValue::Int64(array.len() as i64)).unwrap_or(ScalarValue::Null))
+        }));
+        // json_keys(json[, path]) returns the keys of an object as a JSON array.
+        register_scalar_function!(m, "json_keys", Signature::with_optional(vec![Utf8, Utf8], 1, Utf8).nullable(), json_path_function(|value| {
+            Ok(match value.as_object() {
+                Some(object) => ScalarValue::Utf8(serde_json::Value::from(object.keys().cloned().collect::<Vec<_>>()).to_string()),
+                None => ScalarValue::Null,
+            })
+        }));
+        // Hashes are returned as lowercase hexadecimal strings, the 64-bit ones as integers, i.e. for bucketing.
+        register_function!(m, "md5", 1..=1, make_bytes_meta_body!("md5", DataType::Utf8), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_string(&args[0], |bytes| Ok(encoding::md5(bytes)))
+        }));
+        register_function!(m, "sha256", 1..=1, make_bytes_meta_body!("sha256", DataType::Utf8), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_string(&args[0], |bytes| Ok(encoding::sha256(bytes)))
         }));
-        register_function!(m, "parse_datetime_rfc3339", make_const_meta_body!(DataType::Timestamp(Nanosecond, None)), Arc::new(|args: Vec<ArrayRef>| {
+        register_function!(m, "xxhash64", 1..=1, make_bytes_meta_body!("xxhash64", DataType::Int64), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_i64(&args[0], encoding::xxhash64)
+        }));
+        register_function!(m, "fnv", 1..=1, make_bytes_meta_body!("fnv", DataType::Int64), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_i64(&args[0], encoding::fnv)
+        }));
+        register_function!(m, "hex", 1..=1, make_bytes_meta_body!("hex", DataType::Utf8), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_string(&args[0], |bytes| Ok(encoding::hex(bytes)))
+        }));
+        register_function!(m, "unhex", 1..=1, make_bytes_meta_body!("unhex", DataType::Utf8), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_string(&args[0], |bytes| encoding::decoded_text(encoding::unhex(bytes)?))
+        }));
+        register_function!(m, "base64_encode", 1..=1, make_bytes_meta_body!("base64_encode", DataType::Utf8), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_string(&args[0], |bytes| Ok(encoding::base64_encode(bytes)))
+        }));
+        register_function!(m, "base64_decode", 1..=1, make_bytes_meta_body!("base64_decode", DataType::Utf8), Arc::new(|args: Vec<ArrayRef>| {
+            encoding::bytes_to_string(&args[0], |bytes| encoding::decoded_text(encoding::base64_decode(bytes)?))
+        }));
+        register_string_function!(m, "url_encode", encoding::url_encode);
+        register_scalar_function!(m, "url_decode", Signature::new(vec![Utf8], Utf8), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Utf8(encoding::url_decode(args[0].as_str()?)?))
+        }));
+        register_expression!(m, "uuid", 0..=0, |_args: Vec<Arc<dyn Expression>>| Uuid {});
+        register_function!(m, "cardinality", 1..=1, make_nullable_meta_body!(DataType::Int64), Arc::new(|args: Vec<ArrayRef>| {
+            nested::cardinality(&args[0])
+        }));
+        register_expression!(m, "element_at", 2..=2, |args: Vec<Arc<dyn Expression>>| Subscript::new(args[0].clone(), args[1].clone()));
+        register_expression!(m, "+", 2..=2, |args: Vec<Arc<dyn Expression>>| Arithmetic::new(ArithmeticOp::Add, args[0].clone(), args[1].clone()));
+        register_expression!(m, "-", 2..=2, |args: Vec<Arc<dyn Expression>>| Arithmetic::new(ArithmeticOp::Subtract, args[0].clone(), args[1].clone()));
+        register_expression!(m, "*", 2..=2, |args: Vec<Arc<dyn Expression>>| Arithmetic::new(ArithmeticOp::Multiply, args[0].clone(), args[1].clone()));
+        register_expression!(m, "/", 2..=2, |args: Vec<Arc<dyn Expression>>| Arithmetic::new(ArithmeticOp::Divide, args[0].clone(), args[1].clone()));
+        register_expression!(m, "%", 2..=2, |args: Vec<Arc<dyn Expression>>| Arithmetic::new(ArithmeticOp::Modulo, args[0].clone(), args[1].clone()));
+        register_expression!(m, "negate", 1..=1, |args: Vec<Arc<dyn Expression>>| UnaryMath::new(UnaryMathOp::Negate, args[0].clone()));
+        register_expression!(m, "abs", 1..=1, |args: Vec<Arc<dyn Expression>>| UnaryMath::new(UnaryMathOp::Abs, args[0].clone()));
+        register_expression!(m, "sign", 1..=1, |args: Vec<Arc<dyn Expression>>| UnaryMath::new(UnaryMathOp::Sign, args[0].clone()));
+        register_expression!(m, "floor", 1..=1, |args: Vec<Arc<dyn Expression>>| UnaryMath::new(UnaryMathOp::Floor, args[0].clone()));
+        register_expression!(m, "ceil", 1..=1, |args: Vec<Arc<dyn Expression>>| UnaryMath::new(UnaryMathOp::Ceil, args[0].clone()));
+        register_expression!(m, "round", 1..=2, |args: Vec<Arc<dyn Expression>>| Round::new(args[0].clone(), args.get(1).cloned()));
+        register_expression!(m, "greatest", 1..=usize::MAX, |args: Vec<Arc<dyn Expression>>| Extremum::new(args, true));
+        register_expression!(m, "least", 1..=usize::MAX, |args: Vec<Arc<dyn Expression>>| Extremum::new(args, false));
+        register_expression!(m, "random", 0..=0, |_args: Vec<Arc<dyn Expression>>| Random {});
+        register_scalar_function!(m, "sqrt", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            let x = args[0].as_f64()?;
+            Ok(ScalarValue::Float64(float_domain("sqrt", x, x >= 0.0)?.sqrt()))
+        }));
+        register_scalar_function!(m, "pow", Signature::new(vec![Float64, Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.powf(args[1].as_f64()?)))
+        }));
+        register_scalar_function!(m, "ln", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            let x = args[0].as_f64()?;
+            Ok(ScalarValue::Float64(float_domain("ln", x, x > 0.0)?.ln()))
+        }));
+        register_scalar_function!(m, "log10", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            let x = args[0].as_f64()?;
+            Ok(ScalarValue::Float64(float_domain("log10", x, x > 0.0)?.log10()))
+        }));
+        register_scalar_function!(m, "exp", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.exp()))
+        }));
+        register_scalar_function!(m, "sin", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.sin()))
+        }));
+        register_scalar_function!(m, "cos", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.cos()))
+        }));
+        register_scalar_function!(m, "tan", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.tan()))
+        }));
+        register_scalar_function!(m, "asin", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            let x = args[0].as_f64()?;
+            Ok(ScalarValue::Float64(float_domain("asin", x, x >= -1.0 && x <= 1.0)?.asin()))
+        }));
+        register_scalar_function!(m, "acos", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            let x = args[0].as_f64()?;
+            Ok(ScalarValue::Float64(float_domain("acos", x, x >= -1.0 && x <= 1.0)?.acos()))
+        }));
+        register_scalar_function!(m, "atan", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.atan()))
+        }));
+        register_scalar_function!(m, "atan2", Signature::new(vec![Float64, Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.atan2(args[1].as_f64()?)))
+        }));
+        register_scalar_function!(m, "degrees", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.to_degrees()))
+        }));
+        register_scalar_function!(m, "radians", Signature::new(vec![Float64], Float64), row_function(|args: &[Datum]| {
+            Ok(ScalarValue::Float64(args[0].as_f64()?.to_radians()))
+        }));
+        register_scalar_function!(m, "pi", Signature::new(vec![], Float64), row_function(|_args: &[Datum]| {
+            Ok(ScalarValue::Float64(std::f64::consts::PI))
+        }));
+        register_expression!(m, "now", 0..=0, |_args: Vec<Arc<dyn Expression>>| Now {});
+        register_expression!(m, "at time zone", 2..=2, |args: Vec<Arc<dyn Expression>>| AtTimeZone::new(args[0].clone(), args[1].clone()));
+        register_scalar_function!(m, "date_trunc", Signature::new(vec![Utf8, timestamp_type()], timestamp_type()), time_field_function(|field, args, zone| {
+            Ok(ScalarValue::Timestamp(datetime::date_trunc(field, args[1].as_timestamp()?, zone)?))
+        }));
+        register_scalar_function!(m, "date_part", Signature::new(vec![Utf8, timestamp_type()], Int64), time_field_function(|field, args, zone| {
+            Ok(ScalarValue::Int64(datetime::date_part(field, args[1].as_timestamp()?, zone)?))
+        }));
+        register_scalar_function!(m, "date_add", Signature::new(vec![Utf8, Int64, timestamp_type()], timestamp_type()), time_field_function(|field, args, zone| {
+            Ok(ScalarValue::Timestamp(datetime::date_add(field, args[1].as_i64()?, args[2].as_timestamp()?, zone)?))
+        }));
+        register_scalar_function!(m, "date_diff", Signature::new(vec![Utf8, timestamp_type(), timestamp_type()], Int64), time_field_function(|field, args, zone| {
+            Ok(ScalarValue::Int64(datetime::date_diff(field, args[1].as_timestamp()?, args[2].as_timestamp()?, zone)?))
+        }));
+        register_scalar_function!(m, "to_char", Signature::new(vec![timestamp_type(), Utf8], Utf8), zoned_row_function(|args: &[Datum], zone: &Zone| {
+            Ok(ScalarValue::Utf8(datetime::to_char(args[0].as_timestamp()?, args[1].as_str()?, zone)?))
+        }));
+        register_scalar_function!(m, "strftime", Signature::new(vec![timestamp_type(), Utf8], Utf8), zoned_row_function(|args: &[Datum], zone: &Zone| {
+            Ok(ScalarValue::Utf8(datetime::strftime(args[0].as_timestamp()?, args[1].as_str()?, zone)?))
+        }));
+        register_function!(m, "parse_datetime_rfc3339", 1..=1, make_const_meta_body!(DataType::Timestamp(Nanosecond, None)), Arc::new(|args: Vec<ArrayRef>| {
             let output: Result<_, ArrowError> = compute_single_arg!(args[0], StringArray, TimestampNanosecondBuilder, |text: &str| {
                 match DateTime::parse_from_rfc3339(text) {
                     Ok(dt) => Ok(dt.timestamp_nanos()),
@@ -133,15 +780,7 @@ lazy_static! {
             });
             Ok(output? as ArrayRef)
         }));
-        register_function!(m, "parse_datetime_tz", make_const_meta_body!(DataType::Timestamp(Nanosecond, None)), Arc::new(|args: Vec<ArrayRef>| {
-            let output: Result<_, ArrowError> = compute_two_arg!(args[0], args[1], StringArray, StringArray, TimestampNanosecondBuilder, |fmt: &str, text: &str| {
-                match DateTime::parse_from_str(text, fmt) {
-                    Ok(dt) => Ok(dt.timestamp_nanos()),
-                    Err(err) => Err(Error::Wrapped(format!("{}", err), Box::new(Error::Unexpected))),
-                }
-            });
-            Ok(output? as ArrayRef)
-        }));
+        register_expression!(m, "parse_datetime_tz", 2..=2, |args: Vec<Arc<dyn Expression>>| ParseDateTimeTz::new(args[0].clone(), args[1].clone()));
         m
     };
 }
@@ -152,3 +791,40 @@ lazy_static! {
 //     builder.append_value(dt.timestamp_nanos());
 //
 // }
+
+#[test]
+fn test_arity() {
+    use crate::physical::testing::{net, query};
+
+    let rows = net(query("SELECT IF(c.age > 5, c.name) FROM cats.csv c").unwrap());
+    assert_eq!(rows[0..3].to_vec(), vec![vec![ScalarValue::Null], vec![ScalarValue::Null], vec![ScalarValue::Utf8("Lucy".to_string())]]);
+    let calls = [
+        "SELECT IF(c.age > 5) FROM cats.csv c",
+        "SELECT abs() FROM cats.csv c",
+        "SELECT greatest() FROM cats.csv c",
+        "SELECT coalesce() FROM cats.csv c",
+        "SELECT nullif(1) FROM cats.csv c",
+        "SELECT between(c.age, 1) FROM cats.csv c",
+        "SELECT cardinality() FROM cats.csv c",
+    ];
+    for sql in &calls {
+        match query(sql) {
+            Err(Error::BadInput(message)) => assert!(message.contains("wrong number of arguments"), "{}: {}", sql, message),
+            other => panic!("{}: {:?}", sql, other),
+        }
+    }
+}
+
+#[test]
+fn test_string_functions() {
+    use crate::physical::testing::{net, query};
+
+    let rows = net(query("SELECT upper(c.name), reverse(IF(c.age > 5, c.name)), lower('ABC') FROM cats.csv c").unwrap());
+    let text = |value: &str| ScalarValue::Utf8(value.to_string());
+    assert_eq!(rows[0..3].to_vec(), vec![
+        vec![text("BUSTER"), ScalarValue::Null, text("abc")],
+        vec![text("TIGER"), ScalarValue::Null, text("abc")],
+        vec![text("LUCY"), text("ycuL"), text("abc")],
+    ]);
+    assert!(Datum::Int64(1).as_str().is_err());
+}
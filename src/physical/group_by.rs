@@ -16,12 +16,13 @@ use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use arrow::array::{ArrayBuilder, ArrayRef, BooleanArray};
-use arrow::array::{BooleanBuilder, Float32Builder, Float64Builder, Int16Builder, Int32Builder, Int64Builder, Int8Builder, StringBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder};
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::array::{BooleanBuilder, Date32Builder, Float32Builder, Float64Builder, Int16Builder, Int32Builder, Int64Builder, Int8Builder, StringBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder, TimestampNanosecondBuilder};
+use arrow::datatypes::{DataType, DateUnit, Field, Schema, TimeUnit};
 use arrow::record_batch::RecordBatch;
 
 use crate::physical::aggregate::{Accumulator, Aggregate};
 use crate::physical::arrow::{create_key, get_scalar_value, GroupByScalar};
+use crate::physical::datetime::{timestamp_type, with_time_zone};
 use crate::physical::expression::Expression;
 use crate::physical::physical::*;
 use crate::physical::trigger::*;
@@ -75,6 +76,9 @@ macro_rules! push_retraction_keys {
                     GroupByScalar::$data_type(v) => {
                         array.append_value(v).unwrap()
                     }
+                    GroupByScalar::Null => {
+                        array.append_null().unwrap()
+                    }
                     _ => {
                         dbg!(&$key_vec[$key_index]);
                         panic!("bug: key doesn't match schema")
@@ -102,6 +106,9 @@ macro_rules! push_retraction_keys_utf8 {
                     GroupByScalar::$data_type(v) => {
                         array.append_value(v.as_str()).unwrap()
                     }
+                    GroupByScalar::Null => {
+                        array.append_null().unwrap()
+                    }
                     _ => {
                         dbg!(&$key_vec[$key_index]);
                         panic!("bug: key doesn't match schema")
@@ -129,6 +136,7 @@ macro_rules! push_retraction_values {
 
                 match last_triggered_row[$aggregate_index] {
                     ScalarValue::$data_type(n) => array.append_value(n).unwrap(),
+                    ScalarValue::Null => array.append_null().unwrap(),
                     _ => panic!("bug: key doesn't match schema"),
                     // TODO: Maybe use as_any -> downcast?
                 }
@@ -150,6 +158,7 @@ macro_rules! push_values {
 
                 match row_accumulators[$aggregate_index].trigger() {
                     ScalarValue::$data_type(n) => array.append_value(n).unwrap(),
+                    ScalarValue::Null => array.append_null().unwrap(),
                     _ => panic!("bug: key doesn't match schema"),
                     // TODO: Maybe use as_any -> downcast?
                 }
@@ -200,7 +209,7 @@ impl Node for GroupBy {
             .iter()
             .cloned()
             .enumerate()
-            .map(|(i, t)| Field::new(self.output_names[i].to_string().as_str(), t, false))
+            .map(|(i, t)| Field::new(self.output_names[i].to_string().as_str(), t, true))
             .collect();
 
         key_fields.append(&mut new_fields);
@@ -299,6 +308,8 @@ impl Node for GroupBy {
                         DataType::UInt16 => push_retraction_keys!(UInt16, UInt16Builder, key_columns, key_vec, last_triggered_values, key_index, retraction_key_columns),
                         DataType::UInt32 => push_retraction_keys!(UInt32, UInt32Builder, key_columns, key_vec, last_triggered_values, key_index, retraction_key_columns),
                         DataType::UInt64 => push_retraction_keys!(UInt64, UInt64Builder, key_columns, key_vec, last_triggered_values, key_index, retraction_key_columns),
+                        DataType::Date32(DateUnit::Day) => push_retraction_keys!(Date32, Date32Builder, key_columns, key_vec, last_triggered_values, key_index, retraction_key_columns),
+                        DataType::Timestamp(TimeUnit::Nanosecond, _) => push_retraction_keys!(Timestamp, TimestampNanosecondBuilder, key_columns, key_vec, last_triggered_values, key_index, retraction_key_columns),
                         _ => unimplemented!(),
                     }
                 }
@@ -378,6 +389,14 @@ impl Node for GroupBy {
                         DataType::Float32 => combine_columns!(Float32Builder, retraction_columns, output_columns, column_index),
                         DataType::Float64 => combine_columns!(Float64Builder, retraction_columns, output_columns, column_index),
                         DataType::Utf8 => combine_columns!(StringBuilder, retraction_columns, output_columns, column_index),
+                        DataType::Date32(DateUnit::Day) => combine_columns!(Date32Builder, retraction_columns, output_columns, column_index),
+                        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                            // The builder only accepts timestamps without a time zone, it's restored afterwards.
+                            retraction_columns[column_index] = with_time_zone(&retraction_columns[column_index], &timestamp_type());
+                            output_columns[column_index] = with_time_zone(&output_columns[column_index], &timestamp_type());
+                            combine_columns!(TimestampNanosecondBuilder, retraction_columns, output_columns, column_index);
+                            output_columns[column_index] = with_time_zone(&output_columns[column_index], output_schema.fields()[column_index].data_type());
+                        }
                         _ => unimplemented!(),
                     }
                 }
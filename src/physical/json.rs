@@ -13,15 +13,22 @@
 // limitations under the License.
 
 use std::fs::File;
+use std::io::{BufRead, BufReader};
 use std::sync::Arc;
 
-use arrow::array::{ArrayRef, BooleanBuilder};
+use arrow::array::{Array, ArrayData, ArrayRef, BooleanArray, BooleanBufferBuilder, BufferBuilderTrait, Float64Array, Int32BufferBuilder, Int64Array, ListArray, StringArray, StringBuilder, StructArray};
 use arrow::datatypes::{DataType, Field, Schema};
-use arrow::json;
 use arrow::record_batch::RecordBatch;
+use serde_json::Value;
 
+use crate::physical::cast::{cast_array, parse_type_name};
+use crate::physical::datetime::{resolve_time_zone, with_time_zone};
+use crate::physical::expression::Expression;
+use crate::physical::functions::Signature;
 use crate::physical::physical::*;
 
+/// Reads a file with a JSON object on each line. Nested objects become structs and arrays become lists,
+/// the schema is inferred from the first lines of the file.
 pub struct JSONSource {
     path: String,
 }
@@ -30,17 +37,29 @@ impl JSONSource {
     pub fn new(path: String) -> JSONSource {
         JSONSource { path }
     }
+
+    fn lines(&self) -> Result<impl Iterator<Item=Result<Value, Error>>, Error> {
+        let file = File::open(self.path.as_str())
+            .map_err(|err| Error::BadInput(format!("couldn't open {}: {}", self.path, err)))?;
+        Ok(BufReader::new(file).lines()
+            .filter(|line| line.as_ref().map(|line| !line.trim().is_empty()).unwrap_or(true))
+            .map(|line| {
+                let line = line.map_err(|err| Error::BadInput(format!("couldn't read line: {}", err)))?;
+                serde_json::from_str(line.as_str()).map_err(|err| Error::BadInput(format!("invalid JSON line: {}", err)))
+            }))
+    }
 }
 
 impl Node for JSONSource {
     fn schema(&self, _schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
-        let file = File::open(self.path.as_str()).unwrap();
-        let r = json::ReaderBuilder::new()
-            .infer_schema(Some(10))
-            .with_batch_size(BATCH_SIZE)
-            .build(file)
-            .unwrap();
-        let mut fields = r.schema().fields().clone();
+        let mut row_type = None;
+        for value in self.lines()?.take(SCHEMA_INFERENCE_LINES) {
+            row_type = merge_json_types(row_type, infer_json_type(&value?));
+        }
+        let mut fields = match row_type {
+            Some(DataType::Struct(fields)) => fields,
+            _ => vec![],
+        };
         fields.push(Field::new(RETRACTIONS_FIELD, DataType::Boolean, false));
 
         Ok(Arc::new(Schema::new(fields)))
@@ -52,41 +71,364 @@ impl Node for JSONSource {
         produce: ProduceFn,
         _meta_send: MetaSendFn,
     ) -> Result<(), Error> {
-        let file = File::open(self.path.as_str()).unwrap();
-        let mut r = json::ReaderBuilder::new()
-            .infer_schema(Some(10))
-            .with_batch_size(BATCH_SIZE)
-            .build(file)
-            .unwrap();
-        let mut retraction_array_builder = BooleanBuilder::new(BATCH_SIZE);
-        for _i in 0..BATCH_SIZE {
-            retraction_array_builder.append_value(false)?;
-        }
-        let retraction_array = Arc::new(retraction_array_builder.finish());
         let schema = self.schema(ctx.variable_context.clone())?;
-        loop {
-            let maybe_rec = r.next().unwrap();
-            match maybe_rec {
-                None => break,
-                Some(rec) => {
-                    let mut columns: Vec<ArrayRef> = rec.columns().iter().cloned().collect();
-                    if columns[0].len() == BATCH_SIZE {
-                        columns.push(retraction_array.clone() as ArrayRef)
-                    } else {
-                        let mut retraction_array_builder = BooleanBuilder::new(BATCH_SIZE);
-                        for _i in 0..columns[0].len() {
-                            retraction_array_builder.append_value(false)?;
+        let row_fields = &schema.fields()[0..schema.fields().len() - 1];
+        let mut lines = self.lines()?.peekable();
+        while lines.peek().is_some() {
+            let rows = lines.by_ref().take(BATCH_SIZE).collect::<Result<Vec<_>, _>>()?;
+            let rows = rows.iter().map(Some).collect::<Vec<_>>();
+
+            let mut columns = row_fields.iter()
+                .map(|field| {
+                    let values = rows.iter()
+                        .map(|row| row.and_then(|row| row.get(field.name())))
+                        .collect::<Vec<_>>();
+                    json_values_to_array(&values, field.data_type())
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            columns.push(Arc::new(BooleanArray::from(vec![false; rows.len()])) as ArrayRef);
+            produce(
+                &ProduceContext {},
+                RecordBatch::try_new(schema.clone(), columns)?,
+            )?
+        }
+        Ok(())
+    }
+}
+
+const SCHEMA_INFERENCE_LINES: usize = 10;
+
+/// The type of a JSON value, None for nulls and empty objects, about which nothing is known.
+fn infer_json_type(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some(DataType::Boolean),
+        Value::Number(number) if number.is_i64() => Some(DataType::Int64),
+        Value::Number(_) => Some(DataType::Float64),
+        Value::String(_) => Some(DataType::Utf8),
+        Value::Array(elements) => {
+            let element_type = elements.iter()
+                .fold(None, |element_type, element| merge_json_types(element_type, infer_json_type(element)));
+            Some(DataType::List(Box::new(element_type.unwrap_or(DataType::Utf8))))
+        }
+        Value::Object(object) => {
+            let fields = object.iter()
+                .filter_map(|(key, value)| infer_json_type(value).map(|data_type| Field::new(key.as_str(), data_type, true)))
+                .collect::<Vec<_>>();
+            if fields.is_empty() {
+                None
+            } else {
+                Some(DataType::Struct(fields))
+            }
+        }
+    }
+}
+
+/// Integers and floats become floats, structs get the fields of both, other conflicts fall back to strings.
+fn merge_json_types(left: Option<DataType>, right: Option<DataType>) -> Option<DataType> {
+    let (left, right) = match (left, right) {
+        (None, other) | (other, None) => return other,
+        (Some(left), Some(right)) => (left, right),
+    };
+    Some(match (left, right) {
+        (left, right) if left == right => left,
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => DataType::Float64,
+        (DataType::List(left), DataType::List(right)) => {
+            DataType::List(Box::new(merge_json_types(Some(*left), Some(*right)).unwrap()))
+        }
+        (DataType::Struct(mut fields), DataType::Struct(right_fields)) => {
+            for right_field in right_fields {
+                match fields.iter().position(|field| field.name() == right_field.name()) {
+                    Some(i) => {
+                        let data_type = merge_json_types(Some(fields[i].data_type().clone()), Some(right_field.data_type().clone())).unwrap();
+                        fields[i] = Field::new(right_field.name(), data_type, true);
+                    }
+                    None => fields.push(right_field),
+                }
+            }
+            DataType::Struct(fields)
+        }
+        _ => DataType::Utf8,
+    })
+}
+
+/// Builds an array of the given type from JSON values. Missing values and values of another type become null,
+/// apart from strings, which hold the JSON text of anything that isn't a string.
+fn json_values_to_array(values: &[Option<&Value>], data_type: &DataType) -> Result<ArrayRef, Error> {
+    let values = values.iter()
+        .map(|value| value.filter(|value| !value.is_null()))
+        .collect::<Vec<_>>();
+    Ok(match data_type {
+        DataType::Boolean => Arc::new(BooleanArray::from(values.iter()
+            .map(|value| value.and_then(Value::as_bool))
+            .collect::<Vec<_>>())) as ArrayRef,
+        DataType::Int64 => Arc::new(Int64Array::from(values.iter()
+            .map(|value| value.and_then(Value::as_i64))
+            .collect::<Vec<_>>())) as ArrayRef,
+        DataType::Float64 => Arc::new(Float64Array::from(values.iter()
+            .map(|value| value.and_then(Value::as_f64))
+            .collect::<Vec<_>>())) as ArrayRef,
+        DataType::Utf8 => {
+            let mut builder = StringBuilder::new(values.len());
+            for value in &values {
+                match value {
+                    Some(Value::String(text)) => builder.append_value(text.as_str())?,
+                    Some(value) => builder.append_value(value.to_string().as_str())?,
+                    None => builder.append_null()?,
+                }
+            }
+            Arc::new(builder.finish()) as ArrayRef
+        }
+        DataType::List(element_type) => {
+            let mut offsets = Int32BufferBuilder::new(values.len() + 1);
+            let mut null_bitmap = BooleanBufferBuilder::new(values.len());
+            let mut elements = Vec::new();
+            offsets.append(0)?;
+            for value in &values {
+                let list = value.and_then(Value::as_array);
+                if let Some(list) = list {
+                    elements.extend(list.iter().map(Some));
+                }
+                offsets.append(elements.len() as i32)?;
+                null_bitmap.append(list.is_some())?;
+            }
+            let elements = json_values_to_array(&elements, element_type)?;
+            let data = ArrayData::builder(data_type.clone())
+                .len(values.len())
+                .add_buffer(offsets.finish())
+                .add_child_data(elements.data())
+                .null_bit_buffer(null_bitmap.finish())
+                .build();
+            Arc::new(ListArray::from(data)) as ArrayRef
+        }
+        DataType::Struct(fields) => {
+            let objects = values.iter().map(|value| value.and_then(Value::as_object)).collect::<Vec<_>>();
+            let mut null_bitmap = BooleanBufferBuilder::new(values.len());
+            for object in &objects {
+                null_bitmap.append(object.is_some())?;
+            }
+            let null_count = objects.iter().filter(|object| object.is_none()).count();
+            let children = fields.iter()
+                .map(|field| {
+                    let values = objects.iter()
+                        .map(|object| object.and_then(|object| object.get(field.name())))
+                        .collect::<Vec<_>>();
+                    Ok((field.clone(), json_values_to_array(&values, field.data_type())?))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            Arc::new(StructArray::from((children, null_bitmap.finish(), null_count))) as ArrayRef
+        }
+        other => return Err(Error::BadInput(format!("can't read JSON values as {:?}", other))),
+    })
+}
+
+/// A JSON path, like `$.a.b[0]` or `$['a key'][1]`, selecting a single value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonPath {
+    elements: Vec<JsonPathElement>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum JsonPathElement {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(text: &str) -> Result<JsonPath, Error> {
+        let invalid = || Error::BadInput(format!("invalid JSON path '{}'", text));
+        let mut chars = text.trim().chars().peekable();
+        if chars.next() != Some('$') {
+            return Err(invalid());
+        }
+        let mut elements = Vec::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let mut key = String::new();
+                    while let Some(c) = chars.peek() {
+                        if *c == '.' || *c == '[' {
+                            break;
                         }
-                        let retraction_array = Arc::new(retraction_array_builder.finish());
-                        columns.push(retraction_array as ArrayRef)
+                        key.push(*c);
+                        chars.next();
                     }
-                    produce(
-                        &ProduceContext {},
-                        RecordBatch::try_new(schema.clone(), columns).unwrap(),
-                    )?
+                    if key.is_empty() {
+                        return Err(invalid());
+                    }
+                    elements.push(JsonPathElement::Key(key));
                 }
-            };
+                '[' => match chars.peek().cloned() {
+                    Some(quote) if quote == '\'' || quote == '"' => {
+                        chars.next();
+                        let key = chars.by_ref().take_while(|c| *c != quote).collect::<String>();
+                        if chars.next() != Some(']') {
+                            return Err(invalid());
+                        }
+                        elements.push(JsonPathElement::Key(key));
+                    }
+                    _ if chars.clone().any(|c| c == ']') => {
+                        let index = chars.by_ref().take_while(|c| *c != ']').collect::<String>();
+                        elements.push(JsonPathElement::Index(index.trim().parse().map_err(|_| invalid())?));
+                    }
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(JsonPath { elements })
+    }
+
+    pub fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.elements.iter().try_fold(value, |value, element| match element {
+            JsonPathElement::Key(key) => value.as_object()?.get(key),
+            JsonPathElement::Index(index) => value.as_array()?.get(*index),
+        })
+    }
+}
+
+/// Caches parsed JSON paths. A constant path is parsed only once.
+pub struct JsonPathCache {
+    constant: Option<JsonPath>,
+    last: Option<(String, JsonPath)>,
+}
+
+impl JsonPathCache {
+    pub fn new(path: Option<&Option<ScalarValue>>) -> Result<JsonPathCache, Error> {
+        let constant = match path {
+            Some(Some(ScalarValue::Utf8(path))) => Some(JsonPath::parse(path)?),
+            _ => None,
+        };
+        Ok(JsonPathCache {
+            constant,
+            last: None,
+        })
+    }
+
+    pub fn get(&mut self, path: &str) -> Result<&JsonPath, Error> {
+        if let Some(path) = &self.constant {
+            return Ok(path);
+        }
+        match &self.last {
+            Some((text, _)) if text == path => {}
+            _ => self.last = Some((path.to_string(), JsonPath::parse(path)?)),
+        }
+        Ok(&self.last.as_ref().unwrap().1)
+    }
+}
+
+/// Invalid JSON documents are treated like null, so that a few malformed rows don't fail the whole query.
+pub fn parse_json(text: &str) -> Option<Value> {
+    serde_json::from_str(text).ok()
+}
+
+/// Selects the value at the path (the whole document if there's none), None if the document is invalid or the path doesn't exist.
+pub fn select_json(text: &str, path: Option<&JsonPath>) -> Option<Value> {
+    let value = parse_json(text)?;
+    match path {
+        None => Some(value),
+        Some(path) => path.select(&value).cloned(),
+    }
+}
+
+/// The text of a scalar value, strings without their quotes. None for null, arrays and objects.
+pub fn json_scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Bool(v) => Some(v.to_string()),
+        Value::Number(v) => Some(v.to_string()),
+        Value::String(v) => Some(v.clone()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+/// `json_extract_scalar(json, path, type)`, which converts the extracted scalar to the declared type.
+/// Values which can't be converted become null, like with TRY_CAST.
+pub struct TypedJsonExtract {
+    json: Arc<dyn Expression>,
+    path: Arc<dyn Expression>,
+    data_type: Arc<dyn Expression>,
+}
+
+impl TypedJsonExtract {
+    pub fn new(json: Arc<dyn Expression>, path: Arc<dyn Expression>, data_type: Arc<dyn Expression>) -> TypedJsonExtract {
+        TypedJsonExtract {
+            json,
+            path,
+            data_type,
+        }
+    }
+
+    fn data_type(&self) -> Result<DataType, Error> {
+        match self.data_type.constant_value() {
+            Some(ScalarValue::Utf8(name)) => parse_type_name(name.as_str()),
+            _ => Err(Error::BadInput("the type in json_extract_scalar must be a constant string".to_string())),
+        }
+    }
+}
+
+impl Expression for TypedJsonExtract {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let arg_fields = [&self.json, &self.path, &self.data_type].iter()
+            .map(|arg| arg.field_meta(schema_context.clone(), record_schema))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Signature::new(vec![DataType::Utf8, DataType::Utf8, DataType::Utf8], DataType::Utf8).check_args("json_extract_scalar", arg_fields.as_slice())?;
+        Ok(Field::new("", self.data_type()?, true))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let data_type = resolve_time_zone(&self.data_type()?, ctx.session.time_zone());
+        let json = cast_array(&self.json.evaluate(ctx, record)?, &DataType::Utf8)?;
+        let path = cast_array(&self.path.evaluate(ctx, record)?, &DataType::Utf8)?;
+        let json = json.as_any().downcast_ref::<StringArray>().unwrap();
+        let path = path.as_any().downcast_ref::<StringArray>().unwrap();
+
+        let mut paths = JsonPathCache::new(Some(&self.path.constant_value()))?;
+        let mut builder = StringBuilder::new(json.len());
+        for i in 0..json.len() {
+            if json.is_null(i) || path.is_null(i) {
+                builder.append_null()?;
+                continue;
+            }
+            let path = paths.get(path.value(i))?;
+            match select_json(json.value(i), Some(path)).as_ref().and_then(json_scalar_text) {
+                Some(text) => builder.append_value(text.as_str())?,
+                None => builder.append_null()?,
+            }
+        }
+        let text = Arc::new(builder.finish()) as ArrayRef;
+        let output = cast_array(&text, &data_type)?;
+        Ok(with_time_zone(&output, &self.data_type()?))
+    }
+}
+
+#[test]
+fn test_json_path() {
+    let document = parse_json(r#"{"a": {"b": [10, {"c": "x"}]}, "d e": true}"#).unwrap();
+    let select = |path: &str| JsonPath::parse(path).unwrap().select(&document).cloned();
+    assert_eq!(select("$.a.b[0]"), Some(Value::from(10)));
+    assert_eq!(select("$.a.b[1].c"), Some(Value::from("x")));
+    assert_eq!(select("$['d e']"), Some(Value::from(true)));
+    assert_eq!(select("$.a[\"b\"][2]"), None);
+    assert_eq!(select("$.missing"), None);
+    assert!(JsonPath::parse("a.b").is_err());
+    assert!(JsonPath::parse("$.a[x]").is_err());
+    assert_eq!(json_scalar_text(&select("$.a.b[1].c").unwrap()), Some("x".to_string()));
+    assert_eq!(parse_json("{\"a\": "), None);
+}
+
+#[test]
+fn test_typed_json_extract() {
+    use crate::physical::testing::{net, query};
+
+    let rows = net(query(r#"SELECT json_extract_scalar('{"a": {"b": 42}}', '$.a.b', 'bigint'), json_extract_scalar('{"a": 1}', '$.b', 'bigint') FROM range(0, 1) r"#).unwrap());
+    assert_eq!(rows, vec![vec![ScalarValue::Int64(42), ScalarValue::Null]]);
+    for (sql, error) in &[
+        (r#"SELECT json_extract_scalar('{}', '$.a', 'int', 'int') FROM range(0, 1) r"#, "wrong number of arguments for json_extract_scalar: 4"),
+        (r#"SELECT json_extract_scalar('{}', '$.a', c.name) FROM cats.csv c"#, "must be a constant string"),
+    ] {
+        match query(sql) {
+            Err(Error::BadInput(message)) => assert!(message.contains(error), "{}: {}", sql, message),
+            other => panic!("{}: {:?}", sql, other),
         }
-        Ok(())
     }
 }
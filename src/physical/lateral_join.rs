@@ -0,0 +1,196 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, BooleanArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{get_scalar_value, take_array};
+use crate::physical::expression::references;
+use crate::physical::physical::*;
+
+/// Joins each record of the source with the records of the lateral query, run with the source record bound
+/// as variables. A retracted source record retracts the records it was joined with.
+pub struct LateralJoin {
+    source: Arc<dyn Node>,
+    lateral: Arc<dyn Node>,
+    // All variables referenced inside of the lateral query, only these are bound.
+    variables: Vec<Identifier>,
+    // Whether the source produces retractions, only then the joined records are kept to retract them later.
+    retractions: bool,
+}
+
+impl LateralJoin {
+    pub fn new(source: Arc<dyn Node>, lateral: Arc<dyn Node>, variables: Vec<Identifier>, retractions: bool) -> LateralJoin {
+        LateralJoin {
+            source,
+            lateral,
+            variables,
+            retractions,
+        }
+    }
+}
+
+impl Node for LateralJoin {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        let source_schema = self.source.schema(schema_context.clone())?;
+        let lateral_schema = self.lateral.schema(Arc::new(SchemaContextWithSchema {
+            previous: schema_context,
+            schema: source_schema.clone(),
+        }))?;
+
+        let mut fields = source_schema.fields().clone();
+        fields.pop();
+        fields.extend(lateral_schema.fields().iter().take(lateral_schema.fields().len() - 1).cloned());
+        fields.push(Field::new(RETRACTIONS_FIELD, DataType::Boolean, false));
+        Ok(Arc::new(Schema::new(fields)))
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let output_schema = self.schema(ctx.variable_context.clone())?;
+        // The joined records of each present source record, as running the lateral query again
+        // for its retraction could give different ones, i.e. with random().
+        let mut joined: HashMap<Vec<ScalarValue>, Vec<Vec<RecordBatch>>> = HashMap::new();
+
+        self.source.run(
+            ctx,
+            &mut |produce_ctx, batch| {
+                let source_columns = batch.num_columns() - 1;
+                let source_retractions = batch.column(source_columns).as_any().downcast_ref::<BooleanArray>().unwrap();
+                let referenced = batch.schema().fields().iter()
+                    .map(|field| references(&self.variables, field))
+                    .collect::<Vec<_>>();
+
+                for row in 0..batch.num_rows() {
+                    let key = if self.retractions {
+                        (0..source_columns)
+                            .map(|column| get_scalar_value(batch.column(column), row))
+                            .collect::<Result<Vec<_>, _>>()?
+                    } else {
+                        vec![]
+                    };
+                    if source_retractions.value(row) {
+                        // Records which weren't joined with anything don't retract anything either.
+                        let records = joined.get_mut(&key).and_then(|records| records.pop()).unwrap_or_default();
+                        if joined.get(&key).map(|records| records.is_empty()).unwrap_or(false) {
+                            joined.remove(&key);
+                        }
+                        for record_batch in records.iter().rev() {
+                            produce(produce_ctx, undo(record_batch)?)?;
+                        }
+                        continue;
+                    }
+
+                    let variables = batch.columns().iter()
+                        .zip(referenced.iter())
+                        .map(|(column, referenced)| if *referenced { get_scalar_value(column, row) } else { Ok(ScalarValue::Null) })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let lateral_ctx = ExecutionContext {
+                        variable_context: Arc::new(VariableContext {
+                            previous: Some(ctx.variable_context.clone()),
+                            schema: batch.schema(),
+                            variables,
+                        }),
+                        session: ctx.session.clone(),
+                    };
+
+                    let mut records = vec![];
+                    self.lateral.run(
+                        &lateral_ctx,
+                        &mut |_lateral_produce_ctx, lateral_batch| {
+                            let indices = UInt32Array::from(vec![row as u32; lateral_batch.num_rows()]);
+                            let mut columns = batch.columns()[..source_columns].iter()
+                                .map(|column| take_array(column, &indices))
+                                .collect::<Result<Vec<_>, _>>()?;
+                            columns.extend(lateral_batch.columns().iter().cloned());
+
+                            let record_batch = RecordBatch::try_new(output_schema.clone(), columns)?;
+                            if self.retractions {
+                                records.push(record_batch.clone());
+                            }
+                            produce(produce_ctx, record_batch)
+                        },
+                        &mut noop_meta_send,
+                    )?;
+                    if self.retractions {
+                        joined.entry(key).or_default().push(records);
+                    }
+                }
+                Ok(())
+            },
+            &mut noop_meta_send,
+        )?;
+        Ok(())
+    }
+}
+
+// The records of the batch in reverse order with their retraction flags flipped, which undoes them.
+fn undo(batch: &RecordBatch) -> Result<RecordBatch, Error> {
+    let indices = UInt32Array::from((0..batch.num_rows() as u32).rev().collect::<Vec<_>>());
+    let mut columns = batch.columns().iter()
+        .map(|column| take_array(column, &indices))
+        .collect::<Result<Vec<_>, _>>()?;
+    let retractions = columns.pop().unwrap();
+    let retractions = retractions.as_any().downcast_ref::<BooleanArray>().unwrap();
+    let flipped = (0..batch.num_rows())
+        .map(|i| Some(!retractions.value(i)))
+        .collect::<Vec<_>>();
+    columns.push(Arc::new(BooleanArray::from(flipped)) as ArrayRef);
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+#[test]
+fn test_lateral_join_retractions() {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use crate::physical::testing::{collect, row, Values};
+
+    // Produces the number of times it was run before, so running it again for a retraction would show.
+    struct Runs(AtomicI64);
+
+    impl Node for Runs {
+        fn schema(&self, _schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+            Ok(Arc::new(Schema::new(vec![
+                Field::new("r.runs", DataType::Int64, false),
+                Field::new(RETRACTIONS_FIELD, DataType::Boolean, false),
+            ])))
+        }
+
+        fn run(&self, ctx: &ExecutionContext, produce: ProduceFn, _meta_send: MetaSendFn) -> Result<(), Error> {
+            let runs = self.0.fetch_add(1, Ordering::SeqCst);
+            let columns: Vec<ArrayRef> = vec![Arc::new(arrow::array::Int64Array::from(vec![runs])), Arc::new(BooleanArray::from(vec![false]))];
+            produce(&ProduceContext {}, RecordBatch::try_new(self.schema(ctx.variable_context.clone())?, columns)?)
+        }
+    }
+
+    let int = ScalarValue::Int64;
+    let source = Values::new(vec![("a.x", DataType::Int64)], vec![
+        vec![row(&[int(1)], false), row(&[int(2)], false)],
+        vec![row(&[int(1)], true), row(&[int(3)], true)],
+    ]);
+    let join = LateralJoin::new(Arc::new(source), Arc::new(Runs(AtomicI64::new(0))), vec![], true);
+    assert_eq!(collect(&join).unwrap(), vec![
+        row(&[int(1), int(0)], false),
+        row(&[int(2), int(1)], false),
+        row(&[int(1), int(0)], true),
+    ]);
+}
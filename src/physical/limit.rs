@@ -0,0 +1,87 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::physical::*;
+
+/// Skips the first `offset` records and emits at most `limit` of the following ones.
+/// Once the limit is reached, the source is stopped.
+/// Its source mustn't produce retractions, the planner sorts those first, which nets them out.
+pub struct Limit {
+    source: Arc<dyn Node>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl Limit {
+    pub fn new(source: Arc<dyn Node>, limit: Option<usize>, offset: usize) -> Limit {
+        Limit {
+            source,
+            limit,
+            offset,
+        }
+    }
+}
+
+impl Node for Limit {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        self.source.schema(schema_context)
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        if self.limit == Some(0) {
+            return Ok(());
+        }
+        let mut to_skip = self.offset;
+        let mut remaining = self.limit;
+
+        let res = self.source.run(
+            ctx,
+            &mut |produce_ctx, batch| {
+                let skipped = to_skip.min(batch.num_rows());
+                to_skip -= skipped;
+                let mut length = batch.num_rows() - skipped;
+                if let Some(remaining) = remaining.as_mut() {
+                    length = length.min(*remaining);
+                    *remaining -= length;
+                }
+
+                if length > 0 {
+                    let columns = batch.columns().iter()
+                        .map(|column| column.slice(skipped, length))
+                        .collect();
+                    produce(produce_ctx, RecordBatch::try_new(batch.schema(), columns)?)?;
+                }
+                if remaining == Some(0) {
+                    return Err(Error::Stop);
+                }
+                Ok(())
+            },
+            &mut noop_meta_send,
+        );
+        match res {
+            Err(Error::Stop) if remaining == Some(0) => Ok(()),
+            res => res,
+        }
+    }
+}
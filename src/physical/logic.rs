@@ -0,0 +1,223 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, BooleanBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::null_boolean_array;
+use crate::physical::expression::Expression;
+use crate::physical::physical::{Error, ExecutionContext, SchemaContext};
+
+// The kernels below follow SQL three-valued logic, which differs from the Arrow ones,
+// where a null on either side always results in a null.
+// Null slots always get a false value bit, so the results can be used for filtering directly.
+
+/// `false AND NULL` is false, `true AND NULL` is null.
+pub fn and_kleene(left: &BooleanArray, right: &BooleanArray) -> Result<BooleanArray, ArrowError> {
+    if left.len() != right.len() {
+        return Err(ArrowError::ComputeError("Cannot perform boolean operation on arrays of different length".to_string()));
+    }
+    let mut builder = BooleanBuilder::new(left.len());
+    for i in 0..left.len() {
+        let left_false = left.is_valid(i) && !left.value(i);
+        let right_false = right.is_valid(i) && !right.value(i);
+        if left_false || right_false {
+            builder.append_value(false)?;
+        } else if left.is_null(i) || right.is_null(i) {
+            builder.append_null()?;
+        } else {
+            builder.append_value(true)?;
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// `true OR NULL` is true, `false OR NULL` is null.
+pub fn or_kleene(left: &BooleanArray, right: &BooleanArray) -> Result<BooleanArray, ArrowError> {
+    if left.len() != right.len() {
+        return Err(ArrowError::ComputeError("Cannot perform boolean operation on arrays of different length".to_string()));
+    }
+    let mut builder = BooleanBuilder::new(left.len());
+    for i in 0..left.len() {
+        let left_true = left.is_valid(i) && left.value(i);
+        let right_true = right.is_valid(i) && right.value(i);
+        if left_true || right_true {
+            builder.append_value(true)?;
+        } else if left.is_null(i) || right.is_null(i) {
+            builder.append_null()?;
+        } else {
+            builder.append_value(false)?;
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// `NOT NULL` is null.
+pub fn not_kleene(arg: &BooleanArray) -> Result<BooleanArray, ArrowError> {
+    let mut builder = BooleanBuilder::new(arg.len());
+    for i in 0..arg.len() {
+        if arg.is_null(i) {
+            builder.append_null()?;
+        } else {
+            builder.append_value(!arg.value(i))?;
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// A NULL literal operand is treated as a boolean array of nulls.
+pub fn boolean_operand(array: ArrayRef) -> Result<ArrayRef, Error> {
+    match array.data_type() {
+        DataType::Boolean => Ok(array),
+        DataType::Null => Ok(null_boolean_array(array.len())),
+        other => Err(Error::BadInput(format!("boolean operator expects boolean arguments, got {:?}", other))),
+    }
+}
+
+/// Returns true if every slot of the array is non-null and equal to value.
+fn all_equal(array: &BooleanArray, value: bool) -> bool {
+    array.null_count() == 0 && (0..array.len()).all(|i| array.value(i) == value)
+}
+
+fn boolean_field_meta(
+    left: &Arc<dyn Expression>,
+    right: &Arc<dyn Expression>,
+    schema_context: Arc<dyn SchemaContext>,
+    record_schema: &Arc<Schema>,
+) -> Result<Field, Error> {
+    let left_field = left.field_meta(schema_context.clone(), record_schema)?;
+    let right_field = right.field_meta(schema_context, record_schema)?;
+    for field in &[&left_field, &right_field] {
+        if field.data_type() != &DataType::Boolean && field.data_type() != &DataType::Null {
+            return Err(Error::BadInput(format!("boolean operator expects boolean arguments, got {:?}", field.data_type())));
+        }
+    }
+    Ok(Field::new("", DataType::Boolean, left_field.is_nullable() || right_field.is_nullable()))
+}
+
+pub struct And {
+    left: Arc<dyn Expression>,
+    right: Arc<dyn Expression>,
+}
+
+impl And {
+    pub fn new(left: Arc<dyn Expression>, right: Arc<dyn Expression>) -> And {
+        And { left, right }
+    }
+}
+
+impl Expression for And {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        boolean_field_meta(&self.left, &self.right, schema_context, record_schema)
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let left = boolean_operand(self.left.evaluate(ctx, record)?)?;
+        let left_typed = left.as_any().downcast_ref::<BooleanArray>().unwrap();
+        // If the whole batch is already false, the right side can't change the result.
+        if all_equal(left_typed, false) {
+            return Ok(left);
+        }
+
+        let right = boolean_operand(self.right.evaluate(ctx, record)?)?;
+        let right_typed = right.as_any().downcast_ref::<BooleanArray>().unwrap();
+        if all_equal(right_typed, false) {
+            return Ok(right);
+        }
+
+        Ok(Arc::new(and_kleene(left_typed, right_typed)?) as ArrayRef)
+    }
+}
+
+pub struct Or {
+    left: Arc<dyn Expression>,
+    right: Arc<dyn Expression>,
+}
+
+impl Or {
+    pub fn new(left: Arc<dyn Expression>, right: Arc<dyn Expression>) -> Or {
+        Or { left, right }
+    }
+}
+
+impl Expression for Or {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        boolean_field_meta(&self.left, &self.right, schema_context, record_schema)
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let left = boolean_operand(self.left.evaluate(ctx, record)?)?;
+        let left_typed = left.as_any().downcast_ref::<BooleanArray>().unwrap();
+        // If the whole batch is already true, the right side can't change the result.
+        if all_equal(left_typed, true) {
+            return Ok(left);
+        }
+
+        let right = boolean_operand(self.right.evaluate(ctx, record)?)?;
+        let right_typed = right.as_any().downcast_ref::<BooleanArray>().unwrap();
+        if all_equal(right_typed, true) {
+            return Ok(right);
+        }
+
+        Ok(Arc::new(or_kleene(left_typed, right_typed)?) as ArrayRef)
+    }
+}
+
+#[test]
+fn test_kleene() {
+    let left = BooleanArray::from(vec![Some(true), Some(true), Some(true), Some(false), Some(false), Some(false), None, None, None]);
+    let right = BooleanArray::from(vec![Some(true), Some(false), None, Some(true), Some(false), None, Some(true), Some(false), None]);
+
+    let and = and_kleene(&left, &right).unwrap();
+    let and_expected = vec![Some(true), Some(false), None, Some(false), Some(false), Some(false), None, Some(false), None];
+    let or = or_kleene(&left, &right).unwrap();
+    let or_expected = vec![Some(true), Some(true), Some(true), Some(true), Some(false), None, Some(true), None, None];
+    for i in 0..left.len() {
+        assert_eq!(and_expected[i], if and.is_null(i) { None } else { Some(and.value(i)) });
+        assert_eq!(or_expected[i], if or.is_null(i) { None } else { Some(or.value(i)) });
+    }
+
+    let not = not_kleene(&left).unwrap();
+    assert!(not.is_null(8));
+    assert!(!not.value(8));
+    assert!(!not.value(0));
+}
+
+#[test]
+fn test_null_operands() {
+    use crate::physical::testing::{net, query};
+    use crate::physical::physical::ScalarValue;
+
+    let rows = query("SELECT NULL AND FALSE, NULL AND TRUE, NULL OR TRUE, NULL OR FALSE, NOT NULL, TRUE AND NULL FROM range(0, 1) r").unwrap();
+    assert_eq!(net(rows), vec![vec![
+        ScalarValue::Boolean(false),
+        ScalarValue::Null,
+        ScalarValue::Boolean(true),
+        ScalarValue::Null,
+        ScalarValue::Null,
+        ScalarValue::Null,
+    ]]);
+    assert!(net(query("SELECT c.name FROM cats.csv c WHERE NULL AND c.age > 3").unwrap()).is_empty());
+
+    // NOT is nullable like AND and OR, but only for nullable operands.
+    let schema = crate::query::PreparedQuery::new("SELECT NOT NULL, NOT r.range > 0 FROM range(0, 1) r").unwrap()
+        .schema(&std::collections::HashMap::new())
+        .unwrap();
+    assert!(schema.field(0).is_nullable());
+    assert!(!schema.field(1).is_nullable());
+}
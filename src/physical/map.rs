@@ -69,7 +69,7 @@ impl Node for Map {
                     }
                 }
             })
-            .filter(|f| f.name() != RETRACTIONS_FIELD)
+            .filter(|f| f.name() != RETRACTIONS_FIELD && !f.name().starts_with(INTERNAL_FIELD_PREFIX))
             .collect();
 
         new_schema_fields.extend(self
@@ -118,7 +118,7 @@ impl Node for Map {
                     }
                 }
             })
-            .filter(|(_column_index, f)| f.name() != RETRACTIONS_FIELD)
+            .filter(|(_column_index, f)| f.name() != RETRACTIONS_FIELD && !f.name().starts_with(INTERNAL_FIELD_PREFIX))
             .map(|(column_index, _f)| column_index)
             .collect();
 
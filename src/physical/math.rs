@@ -0,0 +1,519 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, Float64Array, Int64Array, PrimitiveArray, PrimitiveBuilder, StringArray, TimestampNanosecondArray};
+use arrow::array::{Float32Array, Int16Array, Int32Array, Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array};
+use arrow::compute::kernels::comparison::{gt, gt_utf8, lt, lt_utf8};
+use arrow::datatypes::{ArrowNumericType, DataType, Field, Schema, TimeUnit};
+use arrow::datatypes::{Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt32Type, UInt64Type, UInt8Type};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{common_supertype, interleave, is_numeric, new_null_array};
+use crate::physical::cast::cast_array;
+use crate::physical::datetime;
+use crate::physical::expression::Expression;
+use crate::physical::physical::{Error, ExecutionContext, SchemaContext};
+
+/// Operations on the native values of all numeric Arrow types.
+/// Integer operations fail on overflow instead of wrapping around.
+pub trait Number: Copy {
+    fn add(self, other: Self) -> Option<Self>;
+    fn subtract(self, other: Self) -> Option<Self>;
+    fn multiply(self, other: Self) -> Option<Self>;
+    // Only called with a non-zero divisor.
+    fn divide(self, other: Self) -> Option<Self>;
+    fn modulo(self, other: Self) -> Option<Self>;
+    fn is_zero(self) -> bool;
+    fn abs(self) -> Option<Self>;
+    fn negate(self) -> Option<Self>;
+    fn sign(self) -> Self;
+    fn floor(self) -> Self;
+    fn ceil(self) -> Self;
+    // Rounds half away from zero, negative digits round to the left of the decimal point.
+    fn round(self, digits: i64) -> Option<Self>;
+}
+
+macro_rules! impl_integer_number {
+    ($t:ty, $signed:expr) => {
+        impl Number for $t {
+            fn add(self, other: Self) -> Option<Self> { self.checked_add(other) }
+            fn subtract(self, other: Self) -> Option<Self> { self.checked_sub(other) }
+            fn multiply(self, other: Self) -> Option<Self> { self.checked_mul(other) }
+            fn divide(self, other: Self) -> Option<Self> { self.checked_div(other) }
+            fn modulo(self, other: Self) -> Option<Self> { self.checked_rem(other) }
+            fn is_zero(self) -> bool { self == 0 }
+            #[allow(unused_comparisons)]
+            fn abs(self) -> Option<Self> { if self < 0 { self.negate() } else { Some(self) } }
+            fn negate(self) -> Option<Self> { if $signed { (0 as $t).checked_sub(self) } else if self == 0 { Some(0) } else { None } }
+            #[allow(unused_comparisons)]
+            fn sign(self) -> Self { if self > 0 { 1 } else if self < 0 { (0 as $t).wrapping_sub(1) } else { 0 } }
+            fn floor(self) -> Self { self }
+            fn ceil(self) -> Self { self }
+            fn round(self, digits: i64) -> Option<Self> {
+                if digits >= 0 {
+                    return Some(self);
+                }
+                if digits < -38 {
+                    return Some(0);
+                }
+                let factor = 10i128.pow((-digits) as u32);
+                let value = self as i128;
+                let remainder = value % factor;
+                let mut rounded = value - remainder;
+                if remainder.abs() * 2 >= factor {
+                    rounded += if value < 0 { -factor } else { factor };
+                }
+                if rounded < <$t>::MIN as i128 || rounded > <$t>::MAX as i128 {
+                    None
+                } else {
+                    Some(rounded as $t)
+                }
+            }
+        }
+    };
+}
+
+impl_integer_number!(i8, true);
+impl_integer_number!(i16, true);
+impl_integer_number!(i32, true);
+impl_integer_number!(i64, true);
+impl_integer_number!(u8, false);
+impl_integer_number!(u16, false);
+impl_integer_number!(u32, false);
+impl_integer_number!(u64, false);
+
+macro_rules! impl_float_number {
+    ($t:ty) => {
+        impl Number for $t {
+            fn add(self, other: Self) -> Option<Self> { Some(self + other) }
+            fn subtract(self, other: Self) -> Option<Self> { Some(self - other) }
+            fn multiply(self, other: Self) -> Option<Self> { Some(self * other) }
+            fn divide(self, other: Self) -> Option<Self> { Some(self / other) }
+            fn modulo(self, other: Self) -> Option<Self> { Some(self % other) }
+            fn is_zero(self) -> bool { self == 0.0 }
+            fn abs(self) -> Option<Self> { Some(self.abs()) }
+            fn negate(self) -> Option<Self> { Some(-self) }
+            fn sign(self) -> Self { if self > 0.0 { 1.0 } else if self < 0.0 { -1.0 } else { self } }
+            fn floor(self) -> Self { self.floor() }
+            fn ceil(self) -> Self { self.ceil() }
+            fn round(self, digits: i64) -> Option<Self> {
+                let factor = (10.0 as $t).powi(digits.max(-64).min(64) as i32);
+                let rounded = (self * factor).round() / factor;
+                // Scaling may overflow for large values, which have no fractional digits anyway.
+                Some(if rounded.is_finite() { rounded } else { self })
+            }
+        }
+    };
+}
+
+impl_float_number!(f32);
+impl_float_number!(f64);
+
+/// Calls the generic function with the Arrow type corresponding to the numeric data type.
+macro_rules! numeric_dispatch {
+    ($data_type:expr, $function:ident, $($arg:expr),*) => {
+        match $data_type {
+            DataType::Int8 => $function::<Int8Type>($($arg),*),
+            DataType::Int16 => $function::<Int16Type>($($arg),*),
+            DataType::Int32 => $function::<Int32Type>($($arg),*),
+            DataType::Int64 => $function::<Int64Type>($($arg),*),
+            DataType::UInt8 => $function::<UInt8Type>($($arg),*),
+            DataType::UInt16 => $function::<UInt16Type>($($arg),*),
+            DataType::UInt32 => $function::<UInt32Type>($($arg),*),
+            DataType::UInt64 => $function::<UInt64Type>($($arg),*),
+            DataType::Float32 => $function::<Float32Type>($($arg),*),
+            DataType::Float64 => $function::<Float64Type>($($arg),*),
+            other => Err(Error::BadInput(format!("expected a numeric value, got {:?}", other))),
+        }
+    };
+}
+
+fn binary<T, F>(left: &ArrayRef, right: &ArrayRef, op: F) -> Result<ArrayRef, Error>
+    where T: ArrowNumericType, T::Native: Number, F: Fn(T::Native, T::Native) -> Result<T::Native, Error> {
+    let left = left.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+    let right = right.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+    let mut output = PrimitiveBuilder::<T>::new(left.len());
+    for i in 0..left.len() {
+        if left.is_null(i) || right.is_null(i) {
+            output.append_null()?;
+        } else {
+            output.append_value(op(left.value(i), right.value(i))?)?;
+        }
+    }
+    Ok(Arc::new(output.finish()) as ArrayRef)
+}
+
+fn unary<T, F>(array: &ArrayRef, op: F) -> Result<ArrayRef, Error>
+    where T: ArrowNumericType, T::Native: Number, F: Fn(T::Native) -> Result<T::Native, Error> {
+    let array = array.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+    let mut output = PrimitiveBuilder::<T>::new(array.len());
+    for i in 0..array.len() {
+        if array.is_null(i) {
+            output.append_null()?;
+        } else {
+            output.append_value(op(array.value(i))?)?;
+        }
+    }
+    Ok(Arc::new(output.finish()) as ArrayRef)
+}
+
+fn overflow(op: &str) -> Error {
+    Error::BadInput(format!("numeric overflow in {}", op))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+impl ArithmeticOp {
+    fn name(&self) -> &'static str {
+        match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+            ArithmeticOp::Modulo => "%",
+        }
+    }
+
+    fn apply<T: Number>(&self, left: T, right: T) -> Result<T, Error> {
+        let result = match self {
+            ArithmeticOp::Add => left.add(right),
+            ArithmeticOp::Subtract => left.subtract(right),
+            ArithmeticOp::Multiply => left.multiply(right),
+            ArithmeticOp::Divide | ArithmeticOp::Modulo if right.is_zero() => return Err(Error::BadInput("division by zero".to_string())),
+            ArithmeticOp::Divide => left.divide(right),
+            ArithmeticOp::Modulo => left.modulo(right),
+        };
+        result.ok_or_else(|| overflow(self.name()))
+    }
+}
+
+fn arithmetic<T>(op: ArithmeticOp, left: &ArrayRef, right: &ArrayRef) -> Result<ArrayRef, Error>
+    where T: ArrowNumericType, T::Native: Number {
+    binary::<T, _>(left, right, |l, r| op.apply(l, r))
+}
+
+/// Binary arithmetic, both sides are converted to their common supertype first.
+/// Integer division truncates.
+pub struct Arithmetic {
+    op: ArithmeticOp,
+    left: Arc<dyn Expression>,
+    right: Arc<dyn Expression>,
+}
+
+impl Arithmetic {
+    pub fn new(op: ArithmeticOp, left: Arc<dyn Expression>, right: Arc<dyn Expression>) -> Arithmetic {
+        Arithmetic {
+            op,
+            left,
+            right,
+        }
+    }
+}
+
+impl Arithmetic {
+    fn output_type(&self, left: &DataType, right: &DataType) -> Result<DataType, Error> {
+        let output_type = if datetime::is_temporal(left) || datetime::is_temporal(right) {
+            datetime::arithmetic_type(self.op, left, right)
+        } else {
+            common_supertype(left, right).filter(|data_type| is_numeric(data_type) || data_type == &DataType::Null)
+        };
+        output_type.ok_or_else(|| Error::BadInput(format!("can't apply {} to {:?} and {:?}", self.op.name(), left, right)))
+    }
+}
+
+impl Expression for Arithmetic {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let left = self.left.field_meta(schema_context.clone(), record_schema)?;
+        let right = self.right.field_meta(schema_context, record_schema)?;
+        let data_type = self.output_type(left.data_type(), right.data_type())?;
+        let nullable = left.is_nullable() || right.is_nullable() || left.data_type() == &DataType::Null || right.data_type() == &DataType::Null;
+        Ok(Field::new("", data_type, nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let left = self.left.evaluate(ctx, record)?;
+        let right = self.right.evaluate(ctx, record)?;
+        let data_type = self.output_type(left.data_type(), right.data_type())?;
+        if datetime::is_temporal(left.data_type()) || datetime::is_temporal(right.data_type()) {
+            let zone = datetime::common_time_zone(vec![left.data_type(), right.data_type()], ctx.session.time_zone())?;
+            return datetime::arithmetic(self.op, &left, &right, &zone);
+        }
+        if left.data_type() == &DataType::Null || right.data_type() == &DataType::Null {
+            return new_null_array(&data_type, left.len());
+        }
+        let left = cast_array(&left, &data_type)?;
+        let right = cast_array(&right, &data_type)?;
+        numeric_dispatch!(&data_type, arithmetic, self.op, &left, &right)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryMathOp {
+    Abs,
+    Negate,
+    Sign,
+    Floor,
+    Ceil,
+}
+
+impl UnaryMathOp {
+    fn name(&self) -> &'static str {
+        match self {
+            UnaryMathOp::Abs => "abs",
+            UnaryMathOp::Negate => "-",
+            UnaryMathOp::Sign => "sign",
+            UnaryMathOp::Floor => "floor",
+            UnaryMathOp::Ceil => "ceil",
+        }
+    }
+
+    fn apply<T: Number>(&self, value: T) -> Result<T, Error> {
+        match self {
+            UnaryMathOp::Abs => value.abs().ok_or_else(|| overflow(self.name())),
+            UnaryMathOp::Negate => value.negate().ok_or_else(|| overflow(self.name())),
+            UnaryMathOp::Sign => Ok(value.sign()),
+            UnaryMathOp::Floor => Ok(value.floor()),
+            UnaryMathOp::Ceil => Ok(value.ceil()),
+        }
+    }
+}
+
+fn unary_math<T>(op: UnaryMathOp, array: &ArrayRef) -> Result<ArrayRef, Error>
+    where T: ArrowNumericType, T::Native: Number {
+    unary::<T, _>(array, |value| op.apply(value))
+}
+
+fn round<T>(array: &ArrayRef, digits: &ArrayRef) -> Result<ArrayRef, Error>
+    where T: ArrowNumericType, T::Native: Number {
+    let digits = digits.as_any().downcast_ref::<Int64Array>().unwrap();
+    let array = array.as_any().downcast_ref::<PrimitiveArray<T>>().unwrap();
+    let mut output = PrimitiveBuilder::<T>::new(array.len());
+    for i in 0..array.len() {
+        if array.is_null(i) || digits.is_null(i) {
+            output.append_null()?;
+        } else {
+            output.append_value(array.value(i).round(digits.value(i)).ok_or_else(|| overflow("round"))?)?;
+        }
+    }
+    Ok(Arc::new(output.finish()) as ArrayRef)
+}
+
+fn numeric_field(name: &str, field: &Field) -> Result<(), Error> {
+    if is_numeric(field.data_type()) || field.data_type() == &DataType::Null {
+        Ok(())
+    } else {
+        Err(Error::BadInput(format!("argument of {} must be numeric, is {:?}", name, field.data_type())))
+    }
+}
+
+/// Unary numeric functions which return a value of the same type as their argument.
+pub struct UnaryMath {
+    op: UnaryMathOp,
+    expr: Arc<dyn Expression>,
+}
+
+impl UnaryMath {
+    pub fn new(op: UnaryMathOp, expr: Arc<dyn Expression>) -> UnaryMath {
+        UnaryMath {
+            op,
+            expr,
+        }
+    }
+}
+
+impl Expression for UnaryMath {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let field = self.expr.field_meta(schema_context, record_schema)?;
+        numeric_field(self.op.name(), &field)?;
+        Ok(Field::new("", field.data_type().clone(), field.is_nullable()))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let array = self.expr.evaluate(ctx, record)?;
+        if array.data_type() == &DataType::Null {
+            return Ok(array);
+        }
+        numeric_dispatch!(array.data_type(), unary_math, self.op, &array)
+    }
+}
+
+/// `round(x)` or `round(x, digits)`, keeps the type of x.
+pub struct Round {
+    expr: Arc<dyn Expression>,
+    digits: Option<Arc<dyn Expression>>,
+}
+
+impl Round {
+    pub fn new(expr: Arc<dyn Expression>, digits: Option<Arc<dyn Expression>>) -> Round {
+        Round {
+            expr,
+            digits,
+        }
+    }
+}
+
+impl Expression for Round {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let field = self.expr.field_meta(schema_context.clone(), record_schema)?;
+        numeric_field("round", &field)?;
+        let mut nullable = field.is_nullable();
+        if let Some(digits) = &self.digits {
+            let digits = digits.field_meta(schema_context, record_schema)?;
+            if !is_numeric(digits.data_type()) && digits.data_type() != &DataType::Null {
+                return Err(Error::BadInput(format!("digits of round must be an integer, are {:?}", digits.data_type())));
+            }
+            nullable = nullable || digits.is_nullable() || digits.data_type() == &DataType::Null;
+        }
+        Ok(Field::new("", field.data_type().clone(), nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let array = self.expr.evaluate(ctx, record)?;
+        let digits = match &self.digits {
+            Some(digits) => cast_array(&digits.evaluate(ctx, record)?, &DataType::Int64)?,
+            None => Arc::new(Int64Array::from(vec![0; array.len()])) as ArrayRef,
+        };
+        if array.data_type() == &DataType::Null {
+            return Ok(array);
+        }
+        numeric_dispatch!(array.data_type(), round, &array, &digits)
+    }
+}
+
+/// `greatest(...)` and `least(...)`, null arguments are ignored.
+pub struct Extremum {
+    args: Vec<Arc<dyn Expression>>,
+    greatest: bool,
+}
+
+impl Extremum {
+    pub fn new(args: Vec<Arc<dyn Expression>>, greatest: bool) -> Extremum {
+        Extremum {
+            args,
+            greatest,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        if self.greatest { "greatest" } else { "least" }
+    }
+
+    fn supertype<'a>(&self, types: impl Iterator<Item=&'a DataType>) -> Result<DataType, Error> {
+        let mut supertype = DataType::Null;
+        for data_type in types {
+            supertype = common_supertype(&supertype, data_type)
+                .ok_or_else(|| Error::BadInput(format!("can't compare {:?} with {:?} in {}", supertype, data_type, self.name())))?;
+        }
+        match &supertype {
+            DataType::Null | DataType::Utf8 | DataType::Timestamp(TimeUnit::Nanosecond, _) => Ok(supertype),
+            data_type if is_numeric(data_type) => Ok(supertype),
+            other => Err(Error::BadInput(format!("{} is not supported for {:?}", self.name(), other))),
+        }
+    }
+}
+
+impl Expression for Extremum {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        if self.args.is_empty() {
+            return Err(Error::BadInput(format!("{} requires at least one argument", self.name())));
+        }
+        let fields = self.args.iter()
+            .map(|arg| arg.field_meta(schema_context.clone(), record_schema))
+            .collect::<Result<Vec<_>, _>>()?;
+        let data_type = self.supertype(fields.iter().map(|field| field.data_type()))?;
+        let nullable = fields.iter().all(|field| field.is_nullable() || field.data_type() == &DataType::Null);
+        Ok(Field::new("", data_type, nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let args = self.args.iter()
+            .map(|arg| arg.evaluate(ctx, record))
+            .collect::<Result<Vec<_>, _>>()?;
+        let data_type = self.supertype(args.iter().map(|arg| arg.data_type()))?;
+        let mut args = args.iter()
+            .map(|arg| cast_array(arg, &data_type))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut result = args.remove(0);
+        for arg in args {
+            if data_type == DataType::Null {
+                break;
+            }
+            let better: Result<_, ArrowError> = if self.greatest {
+                binary_array_op!(arg, result, gt)
+            } else {
+                binary_array_op!(arg, result, lt)
+            };
+            let better: Arc<BooleanArray> = better?;
+            let selection = (0..result.len())
+                .map(|row| {
+                    if arg.is_null(row) || (result.is_valid(row) && !better.value(row)) {
+                        Some(0)
+                    } else {
+                        Some(1)
+                    }
+                })
+                .collect::<Vec<_>>();
+            result = interleave(&[result, arg], selection.as_slice())?;
+        }
+        Ok(result)
+    }
+}
+
+/// `random()`, a value uniformly distributed in [0, 1) for each row, drawn from the session's generator.
+pub struct Random {}
+
+impl Expression for Random {
+    fn field_meta(&self, _schema_context: Arc<dyn SchemaContext>, _record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        Ok(Field::new("", DataType::Float64, false))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        Ok(Arc::new(Float64Array::from(ctx.session.random_values(record.num_rows()))) as ArrayRef)
+    }
+}
+
+/// Checks the argument is in the domain of a floating point function.
+pub fn float_domain(name: &str, value: f64, valid: bool) -> Result<f64, Error> {
+    if valid {
+        Ok(value)
+    } else {
+        Err(Error::BadInput(format!("{} is undefined for {}", name, value)))
+    }
+}
+
+#[test]
+fn test_number() {
+    assert_eq!(ArithmeticOp::Divide.apply(7i64, 2).unwrap(), 3);
+    assert!(ArithmeticOp::Modulo.apply(7i32, 0).is_err());
+    assert!(ArithmeticOp::Add.apply(i64::MAX, 1).is_err());
+    assert!(UnaryMathOp::Abs.apply(i8::MIN).is_err());
+    assert_eq!(UnaryMathOp::Sign.apply(-3i16).unwrap(), -1);
+    assert_eq!(UnaryMathOp::Sign.apply(3u8).unwrap(), 1);
+    assert_eq!(Number::round(1250i64, -2), Some(1300));
+    assert_eq!(Number::round(-1250i64, -2), Some(-1300));
+    assert_eq!(Number::round(2.345f64, 1), Some(2.3));
+    assert_eq!(Number::round(-2.5f64, 0), Some(-3.0));
+    assert_eq!(Number::round(250u8, -2), None);
+}
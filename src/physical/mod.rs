@@ -27,3 +27,23 @@ pub mod requalifier;
 pub mod json;
 pub mod aggregate;
 pub mod expression;
+pub mod logic;
+pub mod conditional;
+pub mod cast;
+pub mod predicate;
+pub mod string;
+pub mod math;
+pub mod datetime;
+pub mod encoding;
+pub mod nested;
+pub mod sort;
+pub mod limit;
+pub mod top_n;
+pub mod distinct;
+pub mod set_operation;
+pub mod shared;
+pub mod window;
+pub mod lateral_join;
+pub mod table_functions;
+#[cfg(test)]
+pub mod testing;
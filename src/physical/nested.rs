@@ -0,0 +1,282 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, Int64Array, ListArray, StructArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{is_integer, new_null_array, take_array};
+use crate::physical::cast::cast_array;
+use crate::physical::expression::Expression;
+use crate::physical::physical::*;
+
+/// The field of a struct with the given name, null if the struct is null.
+pub fn struct_field(array: &ArrayRef, name: &str) -> Result<ArrayRef, Error> {
+    let array = array.as_any().downcast_ref::<StructArray>()
+        .ok_or_else(|| Error::BadInput(format!("can't access field {} of a {:?}", name, array.data_type())))?;
+    let index = match array.data_type() {
+        DataType::Struct(fields) => fields.iter()
+            .position(|field| field.name() == name)
+            .ok_or_else(|| Error::BadInput(format!("no field {} in struct", name)))?,
+        _ => unreachable!(),
+    };
+    let column = array.column(index);
+    if array.null_count() == 0 {
+        return Ok(column.clone());
+    }
+    let indices: UInt32Array = (0..array.len())
+        .map(|i| if array.is_valid(i) { Some(i as u32) } else { None })
+        .collect::<Vec<_>>()
+        .into();
+    take_array(column, &indices)
+}
+
+/// The elements at the given 1-based positions, null if the list is null or too short.
+pub fn list_element(array: &ArrayRef, positions: &Int64Array) -> Result<ArrayRef, Error> {
+    let list = array.as_any().downcast_ref::<ListArray>().unwrap();
+    let indices: UInt32Array = (0..list.len())
+        .map(|i| {
+            if list.is_null(i) || positions.is_null(i) || positions.value(i) < 1 || positions.value(i) > list.value_length(i) as i64 {
+                None
+            } else {
+                Some((list.value_offset(i) as i64 + positions.value(i) - 1) as u32)
+            }
+        })
+        .collect::<Vec<_>>()
+        .into();
+    take_array(&list.values(), &indices)
+}
+
+/// The number of elements of each list.
+pub fn cardinality(array: &ArrayRef) -> Result<ArrayRef, Error> {
+    if array.data_type() == &DataType::Null {
+        return new_null_array(&DataType::Int64, array.len());
+    }
+    let list = array.as_any().downcast_ref::<ListArray>()
+        .ok_or_else(|| Error::BadInput(format!("cardinality expects a list, got {:?}", array.data_type())))?;
+    let output: Int64Array = (0..list.len())
+        .map(|i| if list.is_valid(i) { Some(list.value_length(i) as i64) } else { None })
+        .collect::<Vec<_>>()
+        .into();
+    Ok(Arc::new(output) as ArrayRef)
+}
+
+/// `expr.name`, accessing a field of a struct.
+pub struct FieldAccess {
+    expr: Arc<dyn Expression>,
+    name: String,
+}
+
+impl FieldAccess {
+    pub fn new(expr: Arc<dyn Expression>, name: String) -> FieldAccess {
+        FieldAccess {
+            expr,
+            name,
+        }
+    }
+}
+
+impl Expression for FieldAccess {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let source_field = self.expr.field_meta(schema_context, record_schema)?;
+        match source_field.data_type() {
+            DataType::Struct(fields) => {
+                let field = fields.iter()
+                    .find(|field| field.name() == &self.name)
+                    .ok_or_else(|| Error::BadInput(format!("no field {} in struct {}", self.name, source_field.name())))?;
+                Ok(Field::new(self.name.as_str(), field.data_type().clone(), field.is_nullable() || source_field.is_nullable()))
+            }
+            DataType::Null => Ok(Field::new(self.name.as_str(), DataType::Null, true)),
+            other => Err(Error::BadInput(format!("can't access field {} of a {:?}", self.name, other))),
+        }
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let array = self.expr.evaluate(ctx, record)?;
+        if array.data_type() == &DataType::Null {
+            return Ok(array);
+        }
+        struct_field(&array, self.name.as_str())
+    }
+}
+
+/// `expr[index]`, the element of a list at a 1-based position, or the field of a struct with a constant name.
+pub struct Subscript {
+    expr: Arc<dyn Expression>,
+    index: Arc<dyn Expression>,
+}
+
+impl Subscript {
+    pub fn new(expr: Arc<dyn Expression>, index: Arc<dyn Expression>) -> Subscript {
+        Subscript {
+            expr,
+            index,
+        }
+    }
+
+    fn field_name(&self) -> Result<String, Error> {
+        match self.index.constant_value() {
+            Some(ScalarValue::Utf8(name)) => Ok(name),
+            _ => Err(Error::BadInput("struct fields can only be accessed with a constant name".to_string())),
+        }
+    }
+}
+
+impl Expression for Subscript {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let source_field = self.expr.field_meta(schema_context.clone(), record_schema)?;
+        match source_field.data_type() {
+            DataType::List(element) => {
+                let index_type = self.index.field_meta(schema_context, record_schema)?.data_type().clone();
+                if !is_integer(&index_type) && index_type != DataType::Null {
+                    return Err(Error::BadInput(format!("list index has to be an integer, is {:?}", index_type)));
+                }
+                Ok(Field::new("", element.as_ref().clone(), true))
+            }
+            DataType::Struct(_) => {
+                FieldAccess::new(self.expr.clone(), self.field_name()?).field_meta(schema_context, record_schema)
+            }
+            DataType::Null => Ok(Field::new("", DataType::Null, true)),
+            other => Err(Error::BadInput(format!("can't subscript a {:?}", other))),
+        }
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let array = self.expr.evaluate(ctx, record)?;
+        match array.data_type() {
+            DataType::List(_) => {
+                let positions = cast_array(&self.index.evaluate(ctx, record)?, &DataType::Int64)?;
+                list_element(&array, positions.as_any().downcast_ref::<Int64Array>().unwrap())
+            }
+            DataType::Struct(_) => struct_field(&array, self.field_name()?.as_str()),
+            DataType::Null => Ok(array),
+            other => Err(Error::BadInput(format!("can't subscript a {:?}", other))),
+        }
+    }
+}
+
+/// Emits a row for each element of the list, with the columns of the source row it comes from.
+/// Source rows with an empty or null list produce no rows, the rows of a retraction are retractions too.
+pub struct Unnest {
+    source: Arc<dyn Node>,
+    expr: Arc<dyn Expression>,
+    name: Identifier,
+}
+
+impl Unnest {
+    pub fn new(source: Arc<dyn Node>, expr: Arc<dyn Expression>, name: Identifier) -> Unnest {
+        Unnest {
+            source,
+            expr,
+            name,
+        }
+    }
+}
+
+impl Node for Unnest {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        let source_schema = self.source.schema(schema_context.clone())?;
+        let list_field = self.expr.field_meta(schema_context, &source_schema)?;
+        let element_type = match list_field.data_type() {
+            DataType::List(element) => element.as_ref().clone(),
+            DataType::Null => DataType::Null,
+            other => return Err(Error::BadInput(format!("UNNEST expects a list, got {:?}", other))),
+        };
+
+        let mut fields = source_schema.fields().clone();
+        let retraction_field = fields.pop().unwrap();
+        fields.push(Field::new(self.name.to_string().as_str(), element_type, true));
+        fields.push(retraction_field);
+        Ok(Arc::new(Schema::new(fields)))
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let output_schema = self.schema(ctx.variable_context.clone())?;
+
+        self.source.run(
+            ctx,
+            &mut |produce_ctx, batch| {
+                let lists = self.expr.evaluate(ctx, &batch)?;
+                if lists.data_type() == &DataType::Null {
+                    return Ok(());
+                }
+                let list = lists.as_any().downcast_ref::<ListArray>().unwrap();
+
+                let mut source_indices = Vec::new();
+                let mut element_indices = Vec::new();
+                for row in 0..list.len() {
+                    if list.is_null(row) {
+                        continue;
+                    }
+                    let offset = list.value_offset(row) as u32;
+                    for i in 0..list.value_length(row) as u32 {
+                        source_indices.push(row as u32);
+                        element_indices.push(offset + i);
+                    }
+                }
+                if source_indices.is_empty() {
+                    return Ok(());
+                }
+                let source_indices = UInt32Array::from(source_indices);
+                let element_indices = UInt32Array::from(element_indices);
+
+                let mut columns = batch.columns().iter()
+                    .map(|column| take_array(column, &source_indices))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let retractions = columns.pop().unwrap();
+                columns.push(take_array(&list.values(), &element_indices)?);
+                columns.push(retractions);
+
+                produce(produce_ctx, RecordBatch::try_new(output_schema.clone(), columns)?)
+            },
+            &mut noop_meta_send,
+        )?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_nested() {
+    use arrow::array::{Int64Builder, ListBuilder, StringArray};
+
+    use crate::physical::arrow::get_scalar_value;
+
+    let mut builder = ListBuilder::new(Int64Builder::new(8));
+    builder.values().append_slice(&[1, 2, 3]).unwrap();
+    builder.append(true).unwrap();
+    builder.append(false).unwrap();
+    builder.values().append_value(4).unwrap();
+    builder.append(true).unwrap();
+    let lists = Arc::new(builder.finish()) as ArrayRef;
+
+    let elements = list_element(&lists, &Int64Array::from(vec![Some(2), Some(1), Some(2)])).unwrap();
+    let elements = (0..3).map(|i| get_scalar_value(&elements, i).unwrap()).collect::<Vec<_>>();
+    assert_eq!(elements, vec![ScalarValue::Int64(2), ScalarValue::Null, ScalarValue::Null]);
+    let lengths = cardinality(&lists).unwrap();
+    assert_eq!(get_scalar_value(&lengths, 0).unwrap(), ScalarValue::Int64(3));
+    assert_eq!(get_scalar_value(&lengths, 1).unwrap(), ScalarValue::Null);
+
+    let names = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+    let structs = Arc::new(StructArray::from(vec![(Field::new("name", DataType::Utf8, false), names)])) as ArrayRef;
+    let field = struct_field(&structs, "name").unwrap();
+    assert_eq!(get_scalar_value(&field, 2).unwrap(), ScalarValue::Utf8("c".to_string()));
+    assert!(struct_field(&structs, "missing").is_err());
+}
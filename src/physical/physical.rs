@@ -13,14 +13,22 @@
 // limitations under the License.
 
 use std::hash::Hash;
-use std::sync::Arc;
+use std::sync::{Arc, mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::datatypes::{DataType, DateUnit, Field, IntervalUnit, Schema, TimeUnit};
 use arrow::error::ArrowError;
 use arrow::record_batch::RecordBatch;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::physical::datetime::Zone;
 
 pub const BATCH_SIZE: usize = 8192;
 pub const RETRACTIONS_FIELD: &str = "retraction";
+// Fields added by the planner start with this prefix, they aren't part of wildcards.
+pub const INTERNAL_FIELD_PREFIX: &str = "__";
 
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub enum Identifier {
@@ -45,7 +53,7 @@ impl ToString for Identifier {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum ScalarValue {
     Null,
     Boolean(bool),
@@ -60,6 +68,12 @@ pub enum ScalarValue {
     UInt32(u32),
     UInt64(u64),
     Utf8(String),
+    // Days since the epoch.
+    Date32(i32),
+    // Nanoseconds since the epoch.
+    Timestamp(i64),
+    IntervalYearMonth(i32),
+    IntervalDayTime(i64),
     Struct(Vec<ScalarValue>),
 }
 
@@ -79,18 +93,67 @@ impl ScalarValue {
             ScalarValue::UInt32(_) => DataType::UInt32,
             ScalarValue::UInt64(_) => DataType::UInt64,
             ScalarValue::Utf8(_) => DataType::Utf8,
-            ScalarValue::Struct(_) => /*DataType::Struct*/ unimplemented!(),
+            ScalarValue::Date32(_) => DataType::Date32(DateUnit::Day),
+            ScalarValue::Timestamp(_) => DataType::Timestamp(TimeUnit::Nanosecond, None),
+            ScalarValue::IntervalYearMonth(_) => DataType::Interval(IntervalUnit::YearMonth),
+            ScalarValue::IntervalDayTime(_) => DataType::Interval(IntervalUnit::DayTime),
+            // The fields are named by their position, like the ones of a wildcard.
+            ScalarValue::Struct(values) => DataType::Struct(values.iter()
+                .enumerate()
+                .map(|(i, value)| Field::new(i.to_string().as_str(), value.data_type(), true))
+                .collect()),
         }
     }
 }
 
+// Floats are compared and hashed by their bits, so that they can be used in keys, with all NaNs equal
+// to each other, like in grouping, and 0.0 equal to -0.0.
+fn float_bits(value: f64) -> u64 {
+    if value.is_nan() {
+        f64::NAN.to_bits()
+    } else if value == 0.0 {
+        0
+    } else {
+        value.to_bits()
+    }
+}
+
+impl PartialEq for ScalarValue {
+    fn eq(&self, other: &ScalarValue) -> bool {
+        match (self, other) {
+            (ScalarValue::Null, ScalarValue::Null) => true,
+            (ScalarValue::Boolean(x), ScalarValue::Boolean(y)) => x == y,
+            (ScalarValue::Float32(x), ScalarValue::Float32(y)) => float_bits(*x as f64) == float_bits(*y as f64),
+            (ScalarValue::Float64(x), ScalarValue::Float64(y)) => float_bits(*x) == float_bits(*y),
+            (ScalarValue::Int8(x), ScalarValue::Int8(y)) => x == y,
+            (ScalarValue::Int16(x), ScalarValue::Int16(y)) => x == y,
+            (ScalarValue::Int32(x), ScalarValue::Int32(y)) => x == y,
+            (ScalarValue::Int64(x), ScalarValue::Int64(y)) => x == y,
+            (ScalarValue::UInt8(x), ScalarValue::UInt8(y)) => x == y,
+            (ScalarValue::UInt16(x), ScalarValue::UInt16(y)) => x == y,
+            (ScalarValue::UInt32(x), ScalarValue::UInt32(y)) => x == y,
+            (ScalarValue::UInt64(x), ScalarValue::UInt64(y)) => x == y,
+            (ScalarValue::Utf8(x), ScalarValue::Utf8(y)) => x == y,
+            (ScalarValue::Date32(x), ScalarValue::Date32(y)) => x == y,
+            (ScalarValue::Timestamp(x), ScalarValue::Timestamp(y)) => x == y,
+            (ScalarValue::IntervalYearMonth(x), ScalarValue::IntervalYearMonth(y)) => x == y,
+            (ScalarValue::IntervalDayTime(x), ScalarValue::IntervalDayTime(y)) => x == y,
+            (ScalarValue::Struct(x), ScalarValue::Struct(y)) => x == y,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ScalarValue {}
+
 impl Hash for ScalarValue {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
         match self {
             ScalarValue::Null => (),
             ScalarValue::Boolean(x) => x.hash(state),
-            ScalarValue::Float32(_x) => unimplemented!(),
-            ScalarValue::Float64(_x) => unimplemented!(),
+            ScalarValue::Float32(x) => float_bits(*x as f64).hash(state),
+            ScalarValue::Float64(x) => float_bits(*x).hash(state),
             ScalarValue::Int8(x) => x.hash(state),
             ScalarValue::Int16(x) => x.hash(state),
             ScalarValue::Int32(x) => x.hash(state),
@@ -100,13 +163,15 @@ impl Hash for ScalarValue {
             ScalarValue::UInt32(x) => x.hash(state),
             ScalarValue::UInt64(x) => x.hash(state),
             ScalarValue::Utf8(x) => x.hash(state),
+            ScalarValue::Date32(x) => x.hash(state),
+            ScalarValue::Timestamp(x) => x.hash(state),
+            ScalarValue::IntervalYearMonth(x) => x.hash(state),
+            ScalarValue::IntervalDayTime(x) => x.hash(state),
             ScalarValue::Struct(x) => x.hash(state),
         }
     }
 }
 
-impl Eq for ScalarValue {}
-
 pub struct ProduceContext {}
 
 pub trait SchemaContext {
@@ -167,14 +232,62 @@ impl SchemaContext for VariableContext {
 
 pub struct ExecutionContext {
     pub variable_context: Arc<VariableContext>,
+    pub session: Arc<Session>,
 }
 
 impl Clone for ExecutionContext {
     fn clone(&self) -> Self {
         ExecutionContext {
             variable_context: self.variable_context.clone(),
+            session: self.session.clone(),
+        }
+    }
+}
+
+/// State shared by everything executed in a session.
+pub struct Session {
+    random: Mutex<StdRng>,
+    // Nanoseconds since the epoch, fixed when the session starts.
+    now: i64,
+    time_zone: Zone,
+}
+
+impl Session {
+    /// With a seed, random values are reproducible across sessions.
+    /// Timestamps without a time zone are parsed and shown in the time zone of the session.
+    pub fn new(seed: Option<u64>, time_zone: Zone) -> Session {
+        let random = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Session {
+            random: Mutex::new(random),
+            now: now.as_nanos() as i64,
+            time_zone,
         }
     }
+
+    pub fn time_zone(&self) -> &Zone {
+        &self.time_zone
+    }
+
+    /// The current time, which stays the same for the whole session.
+    pub fn now(&self) -> i64 {
+        self.now
+    }
+
+    /// Returns n random values uniformly distributed in [0, 1).
+    pub fn random_values(&self, n: usize) -> Vec<f64> {
+        let mut random = self.random.lock().unwrap();
+        (0..n).map(|_| random.gen::<f64>()).collect()
+    }
+
+    /// Returns n random 16 byte values.
+    pub fn random_bytes(&self, n: usize) -> Vec<[u8; 16]> {
+        let mut random = self.random.lock().unwrap();
+        (0..n).map(|_| random.gen::<[u8; 16]>()).collect()
+    }
 }
 
 #[derive(Debug)]
@@ -183,6 +296,8 @@ pub enum Error {
     Unexpected,
     Wrapped(String, Box<Error>),
     BadInput(String),
+    // Returned by a produce function which doesn't need any more records, so that the source stops early.
+    Stop,
 }
 
 impl From<arrow::error::ArrowError> for Error {
@@ -202,6 +317,57 @@ pub enum MetadataMessage {
     EndOfStream,
 }
 
+/// Runs nodes in threads of their own, so that their record batches can be received as they come,
+/// each with the index of the node it comes from. The nodes are stopped once this is dropped.
+pub struct ConcurrentRun {
+    receiver: mpsc::Receiver<Result<(usize, RecordBatch), Error>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl ConcurrentRun {
+    pub fn new(ctx: &ExecutionContext, nodes: Vec<Arc<dyn Node>>) -> ConcurrentRun {
+        let (sender, receiver) = mpsc::sync_channel(32);
+        let handles = nodes.into_iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let sender: mpsc::SyncSender<Result<(usize, RecordBatch), Error>> = sender.clone();
+                let ctx = ctx.clone();
+                std::thread::spawn(move || {
+                    // The receiver is gone if the run stopped early, then the node stops too.
+                    let res = node.run(
+                        &ctx,
+                        &mut |_ctx, batch| sender.send(Ok((index, batch))).map_err(|_| Error::Stop),
+                        &mut noop_meta_send,
+                    );
+                    match res {
+                        Ok(()) | Err(Error::Stop) => {}
+                        Err(err) => {
+                            let _ = sender.send(Err(err));
+                        }
+                    }
+                })
+            })
+            .collect();
+        ConcurrentRun { receiver, handles }
+    }
+
+    /// The next record batch of any of the nodes, None once all of them are done.
+    /// An error of any of the nodes is returned as soon as it's received.
+    pub fn next(&mut self) -> Result<Option<(usize, RecordBatch)>, Error> {
+        match self.receiver.recv() {
+            Ok(message) => message.map(Some),
+            Err(mpsc::RecvError) => {
+                for handle in self.handles.drain(..) {
+                    if let Err(panic) = handle.join() {
+                        std::panic::resume_unwind(panic);
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
 pub trait Node: Send + Sync {
     fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error>;
     fn run(
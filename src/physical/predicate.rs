@@ -0,0 +1,306 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use arrow::array::{Array, ArrayRef, BooleanArray, BooleanBuilder, StringArray, TimestampNanosecondArray};
+use arrow::array::{Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array};
+use arrow::compute::kernels::comparison::{eq, eq_utf8};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use regex::Regex;
+
+use crate::physical::arrow::{common_supertype, create_key, GroupByScalar, null_boolean_array, scalar_to_array};
+use crate::physical::cast::cast_array;
+use crate::physical::expression::Expression;
+use crate::physical::logic::or_kleene;
+use crate::physical::physical::{Error, ExecutionContext, ScalarValue, SchemaContext};
+
+/// `expr IN (list...)`.
+///
+/// If the list consists only of constants, it gets compiled into a hash set on first use.
+pub struct InList {
+    expr: Arc<dyn Expression>,
+    list: Vec<Arc<dyn Expression>>,
+    constants: Option<Vec<ScalarValue>>,
+    compiled: Mutex<Option<Arc<CompiledSet>>>,
+}
+
+struct CompiledSet {
+    data_type: DataType,
+    values: HashSet<GroupByScalar>,
+    contains_null: bool,
+}
+
+impl InList {
+    pub fn new(expr: Arc<dyn Expression>, list: Vec<Arc<dyn Expression>>) -> InList {
+        let constants = list.iter()
+            .map(|item| item.constant_value())
+            .collect::<Option<Vec<_>>>();
+        InList {
+            expr,
+            list,
+            constants,
+            compiled: Mutex::new(None),
+        }
+    }
+
+    fn compiled_set(&self, data_type: &DataType) -> Result<Option<Arc<CompiledSet>>, Error> {
+        let constants = match &self.constants {
+            None => return Ok(None),
+            Some(constants) => constants,
+        };
+        match data_type {
+            DataType::Boolean | DataType::Utf8 => {}
+            data_type if is_hashable_integer(data_type) => {}
+            _ => return Ok(None),
+        }
+
+        let mut compiled = self.compiled.lock().unwrap();
+        if let Some(set) = compiled.as_ref() {
+            if &set.data_type == data_type {
+                return Ok(Some(set.clone()));
+            }
+        }
+
+        let mut values = HashSet::with_capacity(constants.len());
+        let mut contains_null = false;
+        let mut key = vec![GroupByScalar::Null];
+        for constant in constants {
+            if constant == &ScalarValue::Null {
+                contains_null = true;
+                continue;
+            }
+            let value = cast_array(&scalar_to_array(constant, 1)?, data_type)?;
+            // A constant which isn't representable in the type of the expression can't be equal to any of its values.
+            if value.is_null(0) {
+                continue;
+            }
+            create_key(&[value], 0, &mut key)?;
+            values.insert(key[0].clone());
+        }
+
+        let set = Arc::new(CompiledSet { data_type: data_type.clone(), values, contains_null });
+        *compiled = Some(set.clone());
+        Ok(Some(set))
+    }
+}
+
+fn is_hashable_integer(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => true,
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => true,
+        _ => false,
+    }
+}
+
+impl Expression for InList {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let mut nullable = false;
+        for expr in std::iter::once(&self.expr).chain(self.list.iter()) {
+            let field = expr.field_meta(schema_context.clone(), record_schema)?;
+            nullable = nullable || field.is_nullable() || field.data_type() == &DataType::Null;
+        }
+        Ok(Field::new("", DataType::Boolean, nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let value = self.expr.evaluate(ctx, record)?;
+        if value.data_type() == &DataType::Null {
+            return Ok(null_boolean_array(value.len()));
+        }
+
+        if let Some(set) = self.compiled_set(value.data_type())? {
+            let mut output = BooleanBuilder::new(value.len());
+            let mut key = vec![GroupByScalar::Null];
+            for row in 0..value.len() {
+                if value.is_null(row) {
+                    output.append_null()?;
+                    continue;
+                }
+                create_key(&[value.clone()], row, &mut key)?;
+                if set.values.contains(&key[0]) {
+                    output.append_value(true)?;
+                } else if set.contains_null {
+                    output.append_null()?;
+                } else {
+                    output.append_value(false)?;
+                }
+            }
+            return Ok(Arc::new(output.finish()) as ArrayRef);
+        }
+
+        // The general case, compare with each element of the list and combine the results.
+        let mut result: Option<BooleanArray> = None;
+        for item in &self.list {
+            let item = item.evaluate(ctx, record)?;
+            let matched = if item.data_type() == &DataType::Null {
+                vec![None; value.len()].into()
+            } else {
+                let data_type = common_supertype(value.data_type(), item.data_type())
+                    .ok_or_else(|| Error::BadInput(format!("can't compare {:?} with {:?} in IN list", value.data_type(), item.data_type())))?;
+                let left = cast_array(&value, &data_type)?;
+                let right = cast_array(&item, &data_type)?;
+                let output: Result<_, ArrowError> = binary_array_op!(left, right, eq);
+                let output: Arc<BooleanArray> = output?;
+                BooleanArray::from(output.data())
+            };
+            result = Some(match result {
+                None => matched,
+                Some(acc) => or_kleene(&acc, &matched)?,
+            });
+        }
+
+        Ok(Arc::new(result.unwrap_or_else(|| vec![false; value.len()].into())) as ArrayRef)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatternKind {
+    Like,
+    ILike,
+    Regexp,
+}
+
+/// LIKE, ILIKE and regular expression matching.
+///
+/// A constant pattern gets compiled once when the expression is created,
+/// other patterns are compiled once per distinct pattern in a record batch.
+pub struct PatternMatch {
+    expr: Arc<dyn Expression>,
+    pattern: Arc<dyn Expression>,
+    kind: PatternKind,
+    negated: bool,
+    compiled: Option<Result<Regex, String>>,
+}
+
+impl PatternMatch {
+    pub fn new(expr: Arc<dyn Expression>, pattern: Arc<dyn Expression>, kind: PatternKind, negated: bool) -> PatternMatch {
+        let compiled = match pattern.constant_value() {
+            Some(ScalarValue::Utf8(pattern)) => Some(compile_pattern(kind, pattern.as_str())),
+            _ => None,
+        };
+        PatternMatch {
+            expr,
+            pattern,
+            kind,
+            negated,
+            compiled,
+        }
+    }
+}
+
+impl Expression for PatternMatch {
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let mut nullable = false;
+        for expr in &[&self.expr, &self.pattern] {
+            let field = expr.field_meta(schema_context.clone(), record_schema)?;
+            match field.data_type() {
+                DataType::Utf8 => {}
+                DataType::Null => nullable = true,
+                other => return Err(Error::BadInput(format!("pattern matching is only supported for strings, got {:?}", other))),
+            }
+            nullable = nullable || field.is_nullable();
+        }
+        Ok(Field::new("", DataType::Boolean, nullable))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch) -> Result<ArrayRef, Error> {
+        let value = self.expr.evaluate(ctx, record)?;
+        if value.data_type() == &DataType::Null {
+            return Ok(null_boolean_array(value.len()));
+        }
+        let value = value.as_any().downcast_ref::<StringArray>().unwrap();
+
+        let mut output = BooleanBuilder::new(value.len());
+
+        if let Some(compiled) = &self.compiled {
+            let regex = compiled.as_ref().map_err(|err| Error::BadInput(err.clone()))?;
+            for row in 0..value.len() {
+                if value.is_null(row) {
+                    output.append_null()?;
+                } else {
+                    output.append_value(regex.is_match(value.value(row)) != self.negated)?;
+                }
+            }
+            return Ok(Arc::new(output.finish()) as ArrayRef);
+        }
+
+        let pattern = self.pattern.evaluate(ctx, record)?;
+        if pattern.data_type() == &DataType::Null {
+            return Ok(null_boolean_array(value.len()));
+        }
+        let pattern = pattern.as_any().downcast_ref::<StringArray>().unwrap();
+
+        let mut regexes: HashMap<&str, Regex> = HashMap::new();
+        for row in 0..value.len() {
+            if value.is_null(row) || pattern.is_null(row) {
+                output.append_null()?;
+                continue;
+            }
+            let pattern_text = pattern.value(row);
+            if !regexes.contains_key(pattern_text) {
+                let regex = compile_pattern(self.kind, pattern_text).map_err(Error::BadInput)?;
+                regexes.insert(pattern_text, regex);
+            }
+            output.append_value(regexes[pattern_text].is_match(value.value(row)) != self.negated)?;
+        }
+        Ok(Arc::new(output.finish()) as ArrayRef)
+    }
+}
+
+fn compile_pattern(kind: PatternKind, pattern: &str) -> Result<Regex, String> {
+    let regex = match kind {
+        PatternKind::Regexp => pattern.to_string(),
+        PatternKind::Like => like_to_regex(pattern),
+        PatternKind::ILike => format!("(?i){}", like_to_regex(pattern)),
+    };
+    Regex::new(regex.as_str()).map_err(|err| format!("invalid pattern '{}': {}", pattern, err))
+}
+
+// % matches any sequence of characters, _ matches any single character, and a backslash escapes the next character.
+fn like_to_regex(pattern: &str) -> String {
+    let mut regex = String::with_capacity(pattern.len() + 8);
+    regex.push_str("(?s)^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(regex::escape(escaped.to_string().as_str()).as_str());
+                }
+            }
+            c => regex.push_str(regex::escape(c.to_string().as_str()).as_str()),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[test]
+fn test_like_to_regex() {
+    let matches = |pattern: &str, text: &str| compile_pattern(PatternKind::Like, pattern).unwrap().is_match(text);
+    assert!(matches("a%c", "abbbc"));
+    assert!(matches("a_c", "abc"));
+    assert!(!matches("a_c", "abbc"));
+    assert!(matches("100\\%", "100%"));
+    assert!(!matches("100\\%", "1000"));
+    assert!(matches("(a.b)%", "(a.b) and more"));
+    assert!(!matches("(a.b)%", "(axb)"));
+    assert!(compile_pattern(PatternKind::ILike, "ab%").unwrap().is_match("ABC"));
+}
@@ -0,0 +1,218 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::BooleanArray;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{common_supertype, get_scalar_value, records_to_batch};
+use crate::physical::cast::cast_array;
+use crate::physical::physical::*;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    // All sources after the first one are subtracted from it.
+    Except,
+}
+
+/// UNION, INTERSECT or EXCEPT of any number of sources, which run concurrently.
+/// The columns of the sources are matched by position, named after the first source and converted to a common type.
+///
+/// UNION ALL just merges the sources. The other operations count the occurrences of each record in each source,
+/// and emit or retract a record whenever the number of times it's part of the result changes.
+pub struct SetOperation {
+    sources: Vec<Arc<dyn Node>>,
+    operator: SetOperator,
+    all: bool,
+}
+
+impl SetOperation {
+    pub fn new(sources: Vec<Arc<dyn Node>>, operator: SetOperator, all: bool) -> SetOperation {
+        SetOperation {
+            sources,
+            operator,
+            all,
+        }
+    }
+
+    // How many times a record is part of the result, given its count in each source.
+    fn multiplicity(&self, counts: &[i64]) -> i64 {
+        let present = |count: &i64| *count > 0;
+        match (self.operator, self.all) {
+            (SetOperator::Union, true) => counts.iter().sum(),
+            (SetOperator::Union, false) => counts.iter().any(present) as i64,
+            (SetOperator::Intersect, true) => counts.iter().copied().min().unwrap_or(0),
+            (SetOperator::Intersect, false) => counts.iter().all(present) as i64,
+            (SetOperator::Except, true) => (counts[0] - counts[1..].iter().sum::<i64>()).max(0),
+            (SetOperator::Except, false) => (present(&counts[0]) && !counts[1..].iter().any(present)) as i64,
+        }
+    }
+}
+
+impl Node for SetOperation {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        let schemas = self.sources.iter()
+            .map(|source| source.schema(schema_context.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut fields = schemas[0].fields().clone();
+        for schema in &schemas[1..] {
+            if schema.fields().len() != fields.len() {
+                return Err(Error::BadInput(format!(
+                    "{:?} sources have different numbers of columns: {} and {}",
+                    self.operator,
+                    fields.len() - 1,
+                    schema.fields().len() - 1,
+                )));
+            }
+            for (field, other) in fields.iter_mut().zip(schema.fields().iter()) {
+                let data_type = common_supertype(field.data_type(), other.data_type())
+                    .ok_or_else(|| Error::BadInput(format!(
+                        "column {} has incompatible types in {:?} sources: {:?} and {:?}",
+                        field.name(),
+                        self.operator,
+                        field.data_type(),
+                        other.data_type(),
+                    )))?;
+                let nullable = field.is_nullable() || other.is_nullable() || other.data_type() == &DataType::Null;
+                *field = Field::new(field.name(), data_type, nullable);
+            }
+        }
+        Ok(Arc::new(Schema::new(fields)))
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let output_schema = self.schema(ctx.variable_context.clone())?;
+
+        let mut sources = ConcurrentRun::new(ctx, self.sources.clone());
+
+        let mut counts: HashMap<Vec<ScalarValue>, Vec<i64>> = HashMap::new();
+
+        while let Some((source_index, batch)) = sources.next()? {
+            let columns = batch.columns().iter()
+                .zip(output_schema.fields().iter())
+                .map(|(column, field)| if column.data_type() == field.data_type() {
+                    Ok(column.clone())
+                } else {
+                    cast_array(column, field.data_type())
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let batch = RecordBatch::try_new(output_schema.clone(), columns)?;
+
+            if self.operator == SetOperator::Union && self.all {
+                produce(&ProduceContext {}, batch)?;
+                continue;
+            }
+
+            let retractions = batch.column(batch.num_columns() - 1).as_any().downcast_ref::<BooleanArray>().unwrap();
+            let mut output: Vec<(Vec<ScalarValue>, bool)> = Vec::new();
+            for row in 0..batch.num_rows() {
+                let record = (0..batch.num_columns() - 1)
+                    .map(|column| get_scalar_value(batch.column(column), row))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let record_counts = counts.entry(record.clone()).or_insert_with(|| vec![0; self.sources.len()]);
+                let previous = self.multiplicity(record_counts);
+                record_counts[source_index] += if retractions.value(row) { -1 } else { 1 };
+                let current = self.multiplicity(record_counts);
+                if record_counts.iter().all(|count| *count == 0) {
+                    counts.remove(&record);
+                }
+
+                for _ in current..previous {
+                    output.push((record.clone(), true));
+                }
+                for _ in previous..current {
+                    output.push((record.clone(), false));
+                }
+            }
+
+            if output.is_empty() {
+                continue;
+            }
+            produce(&ProduceContext {}, records_to_batch(&output_schema, &output)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_multiplicity() {
+    let operation = |operator, all| SetOperation::new(vec![], operator, all);
+    assert_eq!(operation(SetOperator::Union, false).multiplicity(&[0, 3]), 1);
+    assert_eq!(operation(SetOperator::Intersect, true).multiplicity(&[2, 3, 4]), 2);
+    assert_eq!(operation(SetOperator::Intersect, false).multiplicity(&[2, 0]), 0);
+    assert_eq!(operation(SetOperator::Except, true).multiplicity(&[5, 1, 2]), 2);
+    assert_eq!(operation(SetOperator::Except, true).multiplicity(&[1, 3]), 0);
+    assert_eq!(operation(SetOperator::Except, false).multiplicity(&[5, 0, 0]), 1);
+}
+
+#[test]
+fn test_intersect_float_records() {
+    use crate::physical::testing::{collect, Latch, row, Values};
+
+    let float = ScalarValue::Float64;
+    let latch = Latch::default();
+    let left = Values::new(vec![("a.x", DataType::Float64)], vec![
+        vec![row(&[float(1.5)], false), row(&[float(f64::NAN)], false), row(&[float(-0.0)], false)],
+    ]).opens(&latch);
+    let right = Values::new(vec![("b.x", DataType::Float64)], vec![
+        vec![row(&[float(f64::NAN)], false), row(&[float(0.0)], false), row(&[float(2.5)], false)],
+        vec![row(&[float(f64::NAN)], true)],
+    ]).waits_for(&latch);
+    let intersect = SetOperation::new(vec![Arc::new(left), Arc::new(right)], SetOperator::Intersect, false);
+    assert_eq!(collect(&intersect).unwrap(), vec![
+        // The records are emitted as they come from the source which completes them, 0.0 is equal to -0.0.
+        row(&[float(f64::NAN)], false),
+        row(&[float(0.0)], false),
+        row(&[float(f64::NAN)], true),
+    ]);
+}
+
+#[test]
+fn test_set_operation_error() {
+    use crate::physical::testing::{collect, row, Values};
+
+    // A row which doesn't match the schema of its source makes it fail.
+    let left = Values::new(vec![("a.x", DataType::Int64)], vec![vec![row(&[ScalarValue::Int64(1)], false)]]);
+    let right = Values::new(vec![("b.x", DataType::Int64)], vec![vec![row(&[ScalarValue::Utf8("x".to_string())], false)]]);
+    let except = SetOperation::new(vec![Arc::new(left), Arc::new(right)], SetOperator::Except, false);
+    match collect(&except) {
+        Err(Error::BadInput(message)) => assert!(message.contains("can't put"), "{}", message),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_set_operation_order_by() {
+    use crate::physical::testing::{net, query};
+
+    // ORDER BY positions refer to the columns of the first query, whatever they are named.
+    let int = |values: &[i64]| values.iter().map(|value| vec![ScalarValue::Int64(*value)]).collect::<Vec<_>>();
+    let rows = query("SELECT r.range FROM range(0, 3) r UNION SELECT r.range + 5 AS x FROM range(0, 2) r ORDER BY 1 DESC LIMIT 3").unwrap();
+    assert_eq!(net(rows), int(&[6, 5, 2]));
+    let rows = query("SELECT r.range * 2 FROM range(0, 4) r EXCEPT SELECT r.range FROM range(0, 3) r ORDER BY 1 DESC").unwrap();
+    assert_eq!(net(rows), int(&[6, 4]));
+}
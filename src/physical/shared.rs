@@ -0,0 +1,142 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::ThreadId;
+
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::physical::*;
+
+/// Runs its source only once, for the first consumer, and replays its records to all other consumers,
+/// also to ones running concurrently, which wait for the records as they come.
+/// All records are kept for as long as the node exists.
+/// A consumer on the thread running the source, like a subquery evaluated for the records of the first consumer,
+/// can't wait for it, so the source is run again for it.
+pub struct Shared {
+    source: Arc<dyn Node>,
+    state: Mutex<SharedState>,
+    progress: Condvar,
+}
+
+#[derive(Default)]
+struct SharedState {
+    started: bool,
+    // The thread of the first consumer, which runs the source.
+    runner: Option<ThreadId>,
+    batches: Vec<RecordBatch>,
+    done: bool,
+    // Errors can't be cloned, so the other consumers only get the message.
+    error: Option<String>,
+}
+
+impl Shared {
+    pub fn new(source: Arc<dyn Node>) -> Shared {
+        Shared {
+            source,
+            state: Mutex::new(SharedState::default()),
+            progress: Condvar::new(),
+        }
+    }
+
+    // Runs the source, saving all records. It runs until the end even if this consumer stops early,
+    // as other consumers may still need the records.
+    fn run_source(&self, ctx: &ExecutionContext, produce: ProduceFn) -> Result<(), Error> {
+        let mut stopped = false;
+        let res = self.source.run(
+            ctx,
+            &mut |produce_ctx, batch| {
+                self.state.lock().unwrap().batches.push(batch.clone());
+                self.progress.notify_all();
+                if !stopped {
+                    match produce(produce_ctx, batch) {
+                        Ok(()) => {}
+                        Err(Error::Stop) => stopped = true,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Ok(())
+            },
+            &mut noop_meta_send,
+        );
+
+        let mut state = self.state.lock().unwrap();
+        state.done = true;
+        if let Err(err) = &res {
+            state.error = Some(format!("{:?}", err));
+        }
+        self.progress.notify_all();
+        res
+    }
+}
+
+impl Node for Shared {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        self.source.schema(schema_context)
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if !state.started {
+            state.started = true;
+            state.runner = Some(std::thread::current().id());
+            std::mem::drop(state);
+            return self.run_source(ctx, produce);
+        }
+        if !state.done && state.runner == Some(std::thread::current().id()) {
+            std::mem::drop(state);
+            return self.source.run(ctx, produce, &mut noop_meta_send);
+        }
+
+        let mut next = 0;
+        loop {
+            while next == state.batches.len() && !state.done {
+                state = self.progress.wait(state).unwrap();
+            }
+            if let Some(err) = &state.error {
+                return Err(Error::BadInput(format!("shared source failed: {}", err)));
+            }
+            if next == state.batches.len() {
+                return Ok(());
+            }
+            let batch = state.batches[next].clone();
+            next += 1;
+
+            std::mem::drop(state);
+            produce(&ProduceContext {}, batch)?;
+            state = self.state.lock().unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_shared_reentry() {
+    use crate::physical::testing::query;
+
+    // The subquery reads the common table expression while the outer query is still reading it, on the same thread.
+    let rows = query("WITH a AS MATERIALIZED (SELECT r.range FROM range(0, 3) r) \
+        SELECT x.range, (SELECT b.range + 10 FROM a b WHERE b.range = x.range) AS y FROM a x").unwrap();
+    let int = ScalarValue::Int64;
+    assert_eq!(rows, vec![
+        vec![int(0), int(10), ScalarValue::Boolean(false)],
+        vec![int(1), int(11), ScalarValue::Boolean(false)],
+        vec![int(2), int(12), ScalarValue::Boolean(false)],
+    ]);
+}
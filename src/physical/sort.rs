@@ -0,0 +1,198 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, Int64Array, UInt32Array};
+use arrow::compute::kernels::sort::{lexsort_to_indices, sort_to_indices, SortColumn, SortOptions};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{concat_arrays, get_scalar_value, new_null_array, take_array};
+use crate::physical::expression::Expression;
+use crate::physical::physical::*;
+
+/// Buffers all records of the source and emits them sorted once it's done.
+pub struct Sort {
+    source: Arc<dyn Node>,
+    keys: Vec<Arc<dyn Expression>>,
+    options: Vec<SortOptions>,
+}
+
+impl Sort {
+    pub fn new(source: Arc<dyn Node>, keys: Vec<Arc<dyn Expression>>, options: Vec<SortOptions>) -> Sort {
+        Sort {
+            source,
+            keys,
+            options,
+        }
+    }
+}
+
+impl Node for Sort {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        self.source.schema(schema_context)
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let schema = self.schema(ctx.variable_context.clone())?;
+        let mut batches = vec![];
+        self.source.run(
+            ctx,
+            &mut |_produce_ctx, batch| {
+                batches.push(batch);
+                Ok(())
+            },
+            &mut noop_meta_send,
+        )?;
+
+        let batch = net_retractions(&schema, &batches)?;
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        let key_columns = self.keys.iter()
+            .map(|key| key.evaluate(ctx, &batch))
+            .collect::<Result<Vec<_>, _>>()?;
+        let indices = sort_indices(&key_columns, &self.options, batch.num_rows())?;
+        let columns = batch.columns().iter()
+            .map(|column| take_array(column, &indices))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        produce(&ProduceContext {}, RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+/// Combines the batches into one with the rows which remain after applying the retractions, in the order they came in.
+pub fn net_retractions(schema: &Arc<Schema>, batches: &[RecordBatch]) -> Result<RecordBatch, Error> {
+    let columns = if batches.is_empty() {
+        schema.fields().iter()
+            .map(|field| new_null_array(field.data_type(), 0))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        (0..schema.fields().len())
+            .map(|i| concat_arrays(&batches.iter().map(|batch| batch.column(i).clone()).collect::<Vec<_>>()))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let retractions = batch.column(batch.num_columns() - 1).as_any().downcast_ref::<BooleanArray>().unwrap();
+    if (0..retractions.len()).all(|row| !retractions.value(row)) {
+        return Ok(batch);
+    }
+
+    // The indices of the rows which are still present, for each distinct row.
+    let mut present: HashMap<Vec<ScalarValue>, Vec<usize>> = HashMap::new();
+    for row in 0..batch.num_rows() {
+        let values = (0..batch.num_columns() - 1)
+            .map(|column| get_scalar_value(batch.column(column), row))
+            .collect::<Result<Vec<_>, _>>()?;
+        let indices = present.entry(values).or_default();
+        if !retractions.value(row) {
+            indices.push(row);
+        } else {
+            indices.pop();
+        }
+    }
+    let mut remaining = present.into_iter().flat_map(|(_, indices)| indices).collect::<Vec<_>>();
+    remaining.sort();
+
+    let indices = UInt32Array::from(remaining.into_iter().map(|row| row as u32).collect::<Vec<_>>());
+    let columns = batch.columns().iter()
+        .map(|column| take_array(column, &indices))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// The indices which sort the rows by the key columns, ties keep their order.
+pub fn sort_indices(keys: &[ArrayRef], options: &[SortOptions], len: usize) -> Result<UInt32Array, Error> {
+    match keys {
+        [] => Ok(UInt32Array::from((0..len as u32).collect::<Vec<_>>())),
+        [key] => Ok(sort_to_indices(key, Some(options[0]))?),
+        _ => {
+            // The lexical sort only handles types with a total order, floats are replaced by their rank.
+            let keys = keys.iter()
+                .map(|key| match key.data_type() {
+                    DataType::Float32 | DataType::Float64 => rank(key),
+                    _ => Ok(key.clone()),
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let columns = keys.into_iter()
+                .zip(options.iter())
+                .map(|(values, options)| SortColumn { values, options: Some(*options) })
+                .collect::<Vec<_>>();
+            Ok(lexsort_to_indices(&columns)?)
+        }
+    }
+}
+
+// Equal values get the same rank, nulls stay null.
+fn rank(values: &ArrayRef) -> Result<ArrayRef, Error> {
+    let order = sort_to_indices(values, Some(SortOptions { descending: false, nulls_first: false }))?;
+    let mut ranks = vec![None; values.len()];
+    let mut rank = 0;
+    let mut previous: Option<ScalarValue> = None;
+    for i in 0..order.len() {
+        let row = order.value(i) as usize;
+        if values.is_null(row) {
+            continue;
+        }
+        let value = get_scalar_value(values, row)?;
+        if previous.as_ref().map(|previous| previous != &value).unwrap_or(false) {
+            rank += 1;
+        }
+        ranks[row] = Some(rank);
+        previous = Some(value);
+    }
+    Ok(Arc::new(Int64Array::from(ranks)) as ArrayRef)
+}
+
+#[test]
+fn test_sort_indices() {
+    use arrow::array::{Float64Array, StringBuilder};
+
+    let mut names = StringBuilder::new(4);
+    names.append_value("b").unwrap();
+    names.append_null().unwrap();
+    names.append_value("a").unwrap();
+    names.append_value("b").unwrap();
+    let names = Arc::new(names.finish()) as ArrayRef;
+    let scores = Arc::new(Float64Array::from(vec![Some(1.5), Some(2.0), None, Some(3.0)])) as ArrayRef;
+    let options = vec![
+        SortOptions { descending: false, nulls_first: false },
+        SortOptions { descending: true, nulls_first: true },
+    ];
+    let indices = sort_indices(&[names, scores], &options, 4).unwrap();
+    assert_eq!(indices.value_slice(0, 4), &[2, 3, 0, 1]);
+}
+
+#[test]
+fn test_sort_float_retractions() {
+    use crate::physical::expression::FieldExpression;
+    use crate::physical::testing::{collect, row, Values};
+
+    let float = ScalarValue::Float64;
+    let source = Values::new(vec![("a.x", DataType::Float64)], vec![
+        vec![row(&[float(2.5)], false), row(&[float(f64::NAN)], false), row(&[float(-0.0)], false)],
+        vec![row(&[float(f64::NAN)], true), row(&[float(0.0)], true), row(&[float(1.5)], false)],
+    ]);
+    let key = Arc::new(FieldExpression::new(Identifier::SimpleIdentifier("a.x".to_string()))) as Arc<dyn Expression>;
+    let sort = Sort::new(Arc::new(source), vec![key], vec![SortOptions { descending: false, nulls_first: false }]);
+    assert_eq!(collect(&sort).unwrap(), vec![row(&[float(1.5)], false), row(&[float(2.5)], false)]);
+}
@@ -12,22 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeMap, HashMap};
-use std::sync::{Arc, mpsc};
+use std::collections::HashMap;
+use std::sync::Arc;
 
-use arrow::array::{ArrayRef, BooleanBuilder, Int64Builder, StringBuilder};
+use arrow::array::{ArrayRef, BooleanArray};
 use arrow::datatypes::{DataType, Field, Schema};
-use arrow::record_batch::RecordBatch;
 
-use crate::physical::arrow::{create_key, create_row, GroupByScalar};
+use crate::physical::arrow::{common_supertype, get_scalar_value, records_to_batch};
+use crate::physical::cast::cast_array;
 use crate::physical::expression::Expression;
 use crate::physical::physical::*;
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JoinType {
+    Inner,
+    // Source rows without a matching joined row are padded with nulls.
+    LeftOuter,
+}
+
+/// Joins the records of both sources with equal keys, emitting and retracting the joined records as either side changes.
+/// Without keys all records have the same key, so this is the cross product of the sources.
+/// Keys of different types are converted to their common type before being compared.
 pub struct StreamJoin {
     source: Arc<dyn Node>,
     source_key_exprs: Vec<Arc<dyn Expression>>,
     joined: Arc<dyn Node>,
     joined_key_exprs: Vec<Arc<dyn Expression>>,
+    join_type: JoinType,
 }
 
 impl StreamJoin {
@@ -36,33 +47,69 @@ impl StreamJoin {
         source_key_exprs: Vec<Arc<dyn Expression>>,
         joined: Arc<dyn Node>,
         joined_key_exprs: Vec<Arc<dyn Expression>>,
+        join_type: JoinType,
     ) -> StreamJoin {
         StreamJoin {
             source,
             source_key_exprs,
             joined,
             joined_key_exprs,
+            join_type,
         }
     }
+
+    // The common type of each pair of source and joined keys.
+    fn key_types(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Vec<DataType>, Error> {
+        let source_schema = self.source.schema(schema_context.clone())?;
+        let joined_schema = self.joined.schema(schema_context.clone())?;
+        self.source_key_exprs.iter()
+            .zip(self.joined_key_exprs.iter())
+            .map(|(source_expr, joined_expr)| {
+                let source_type = source_expr.field_meta(schema_context.clone(), &source_schema)?.data_type().clone();
+                let joined_type = joined_expr.field_meta(schema_context.clone(), &joined_schema)?.data_type().clone();
+                common_supertype(&source_type, &joined_type).ok_or_else(|| Error::BadInput(format!(
+                    "can't join on keys of types {:?} and {:?}", source_type, joined_type,
+                )))
+            })
+            .collect()
+    }
+}
+
+// The rows of one side with a given key, with the number of times each of them is present.
+type SideState = HashMap<Vec<ScalarValue>, i64>;
+
+fn update_side_state(state: &mut SideState, row: Vec<ScalarValue>, retraction: bool) {
+    let count = state.entry(row.clone()).or_default();
+    *count += if retraction { -1 } else { 1 };
+    if *count == 0 {
+        state.remove(&row);
+    }
+}
+
+// An output row, the joined part is None for null-padded rows of a left outer join.
+type OutputRow = (Vec<ScalarValue>, Option<Vec<ScalarValue>>, bool);
+
+fn push_repeated(output: &mut Vec<OutputRow>, row: OutputRow, count: i64) {
+    for _i in 0..count {
+        output.push(row.clone());
+    }
 }
 
 impl Node for StreamJoin {
     fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
-        // Both without last row, and retraction added at end.
+        // Both without their retraction fields, with the retraction field added at the end.
         let mut source_schema_fields = self.source.schema(schema_context.clone())?.fields().clone();
         source_schema_fields.truncate(source_schema_fields.len() - 1);
-        let joined_schema_fields = self.joined.schema(schema_context.clone())?.fields().clone();
-        let new_fields: Vec<Field> = source_schema_fields
+        let mut joined_schema_fields = self.joined.schema(schema_context.clone())?.fields().clone();
+        joined_schema_fields.truncate(joined_schema_fields.len() - 1);
+        let mut new_fields: Vec<Field> = source_schema_fields
             .iter()
+            .chain(joined_schema_fields.iter())
             .map(|f| Field::new(f.name(), f.data_type().clone(), true))
-            .chain(
-                joined_schema_fields
-                    .iter()
-                    .map(|f| Field::new(f.name(), f.data_type().clone(), true)),
-            )
             .collect();
+        new_fields.push(Field::new(RETRACTIONS_FIELD, DataType::Boolean, false));
 
-        // TODO: Check if source and joined key types match.
+        self.key_types(schema_context)?;
 
         Ok(Arc::new(Schema::new(new_fields)))
     }
@@ -73,361 +120,205 @@ impl Node for StreamJoin {
         produce: ProduceFn,
         _meta_send: MetaSendFn,
     ) -> Result<(), Error> {
-        let source_schema = self.source.schema(ctx.variable_context.clone())?;
-        let joined_schema = self.joined.schema(ctx.variable_context.clone())?;
         let output_schema = self.schema(ctx.variable_context.clone())?;
+        let source_field_count = self.source.schema(ctx.variable_context.clone())?.fields().len() - 1;
+        let left_outer = self.join_type == JoinType::LeftOuter;
+        let key_types = self.key_types(ctx.variable_context.clone())?;
 
-        // TODO: Fixme HashMap => BTreeMap
-        let mut state_map: BTreeMap<
-            Vec<GroupByScalar>,
-            (
-                HashMap<Vec<ScalarValue>, i64>,
-                HashMap<Vec<ScalarValue>, i64>,
-            ),
-        > = BTreeMap::new();
-
-        let _key_types: Vec<DataType> = match self.source.schema(ctx.variable_context.clone()) {
-            Ok(_schema) => self
-                .source_key_exprs
-                .iter()
-                .map(|field| field.field_meta(ctx.variable_context.clone(), &source_schema).unwrap().data_type().clone())
-                .collect(),
-            _ => panic!("aaa"),
-        };
-
-        let (sender, receiver) = mpsc::sync_channel::<(usize, RecordBatch)>(32);
-
-        let sender1 = sender.clone();
-        let source = self.source.clone();
-        let ctx1 = ctx.clone();
-        let handle1 = std::thread::spawn(move || {
-            let _res = source.run(
-                &ctx1,
-                &mut |_ctx, batch| {
-                    sender1.send((0, batch)).unwrap();
-                    Ok(())
-                },
-                &mut noop_meta_send,
-            ).unwrap();
-        });
-        let sender2 = sender.clone();
-        let joined = self.joined.clone();
-        let ctx2 = ctx.clone();
-        let handle2 = std::thread::spawn(move || {
-            let _res = joined.run(
-                &ctx2,
-                &mut |_ctx, batch| {
-                    sender2.send((1, batch)).unwrap();
-                    Ok(())
-                },
-                &mut noop_meta_send,
-            ).unwrap();
-        });
-
-        std::mem::drop(sender);
+        let mut state_map: HashMap<Vec<ScalarValue>, (SideState, SideState)> = HashMap::new();
+
+        let mut sides = ConcurrentRun::new(ctx, vec![self.source.clone(), self.joined.clone()]);
 
         let key_exprs = vec![self.source_key_exprs.clone(), self.joined_key_exprs.clone()];
 
-        for (source_index, batch) in receiver {
+        while let Some((source_index, batch)) = sides.next()? {
             let key_columns: Vec<ArrayRef> = key_exprs[source_index]
                 .iter()
-                .map(|expr| expr.evaluate(ctx, &batch))
+                .zip(key_types.iter())
+                .map(|(expr, key_type)| {
+                    let column = expr.evaluate(ctx, &batch)?;
+                    if column.data_type() == key_type {
+                        Ok(column)
+                    } else {
+                        cast_array(&column, key_type)
+                    }
+                })
                 .collect::<Result<_, _>>()?;
+            let retractions = batch.column(batch.num_columns() - 1)
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .unwrap();
 
-            let mut new_rows: BTreeMap<Vec<GroupByScalar>, Vec<Vec<ScalarValue>>> = BTreeMap::new();
-
-            let mut required_capacity: usize = 0;
+            let mut output: Vec<OutputRow> = vec![];
 
             for row in 0..batch.num_rows() {
-                let mut key_vec = Vec::with_capacity(self.source_key_exprs.len());
-                for _i in 0..key_columns.len() {
-                    key_vec.push(GroupByScalar::Int64(0))
-                }
-                create_key(&key_columns, row, &mut key_vec)?;
-
-                if let Some((source_rows, joined_rows)) = state_map.get(&key_vec) {
-                    // The row will later be joined with the other source rows.
-                    required_capacity += if source_index == 0 {
-                        joined_rows.len()
-                    } else {
-                        source_rows.len()
-                    };
-                }
-
-                let mut row_vec = Vec::with_capacity(batch.num_columns());
-                for _i in 0..batch.num_columns() {
-                    row_vec.push(ScalarValue::Int64(0))
-                }
-                create_row(batch.columns(), row, &mut row_vec)?;
-
-                let new_rows_for_key = new_rows.entry(key_vec).or_default();
-                new_rows_for_key.push(row_vec);
-            }
-
-            let mut output_columns: Vec<ArrayRef> =
-                Vec::with_capacity(source_schema.fields().len() + joined_schema.fields().len());
-
-            if source_index == 0 {
-                for column in 0..(source_schema.fields().len() - 1) {
-                    // Omit the retraction field, it goes last.
-                    match batch.column(column).data_type() {
-                        DataType::Int64 => {
-                            let mut array = Int64Builder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((_, state_other_rows)) = state_map.get(key) {
-                                    for (_other_row, &count) in state_other_rows {
-                                        for new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Int64(n) = &new_row[column] {
-                                                    array.append_value(*n)?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
-                        }
-                        DataType::Utf8 => {
-                            let mut array = StringBuilder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((_, state_other_rows)) = state_map.get(key) {
-                                    for (_other_row, &count) in state_other_rows {
-                                        for new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Utf8(text) = &new_row[column] {
-                                                    array.append_value(text.as_str())?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
-                        }
-                        _ => unimplemented!(),
+                let key_vec = key_columns.iter()
+                    .map(|column| get_scalar_value(column, row))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let retraction = retractions.value(row);
+                let row_vec = (0..batch.num_columns() - 1)
+                    .map(|column| get_scalar_value(batch.column(column), row))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // A null key part never equals anything, so such a row can't ever be joined.
+                if key_vec.contains(&ScalarValue::Null) {
+                    if source_index == 0 && left_outer {
+                        output.push((row_vec, None, retraction));
                     }
+                    continue;
                 }
 
-                for column in 0..(joined_schema.fields().len() - 1) {
-                    match joined_schema.field(column).data_type() {
-                        DataType::Int64 => {
-                            let mut array = Int64Builder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((_, state_other_rows)) = state_map.get(key) {
-                                    for (other_row, &count) in state_other_rows {
-                                        for _new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Int64(n) = &other_row[column] {
-                                                    array.append_value(*n)?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
-                        }
-                        DataType::Utf8 => {
-                            let mut array = StringBuilder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((_, state_other_rows)) = state_map.get(key) {
-                                    for (other_row, &count) in state_other_rows {
-                                        for _new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Utf8(text) = &other_row[column]
-                                                {
-                                                    array.append_value(text.as_str())?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
-                        }
-                        _ => unimplemented!(),
+                let (source_rows, joined_rows) = state_map.entry(key_vec).or_default();
+                if source_index == 0 {
+                    if joined_rows.is_empty() && left_outer {
+                        output.push((row_vec.clone(), None, retraction));
                     }
-                }
-
-                let retraction_column = batch.num_columns() - 1;
-
-                let mut array = BooleanBuilder::new(required_capacity);
-                for (key, cur_new_rows) in &new_rows {
-                    if let Some((_, state_other_rows)) = state_map.get(key) {
-                        for (_other_row, &count) in state_other_rows {
-                            for new_row in cur_new_rows {
-                                for _repetition in 0..count {
-                                    if let ScalarValue::Boolean(retraction) =
-                                        new_row[retraction_column]
-                                    {
-                                        array.append_value(retraction)?;
-                                    } else {
-                                        panic!("invalid type");
-                                    }
-                                }
-                            }
-                        }
+                    for (joined_row, &count) in joined_rows.iter() {
+                        push_repeated(&mut output, (row_vec.clone(), Some(joined_row.clone()), retraction), count);
                     }
-                }
-                output_columns.push(Arc::new(array.finish()) as ArrayRef)
-            } else {
-                for column in 0..(source_schema.fields().len() - 1) {
-                    match source_schema.field(column).data_type() {
-                        DataType::Int64 => {
-                            let mut array = Int64Builder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((state_other_rows, _)) = state_map.get(key) {
-                                    for (other_row, &count) in state_other_rows {
-                                        for _new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Int64(n) = &other_row[column] {
-                                                    array.append_value(*n)?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
-                        }
-                        DataType::Utf8 => {
-                            let mut array = StringBuilder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((state_other_rows, _)) = state_map.get(key) {
-                                    for (other_row, &count) in state_other_rows {
-                                        for _new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Utf8(text) = &other_row[column]
-                                                {
-                                                    array.append_value(text.as_str())?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
+                    update_side_state(source_rows, row_vec, retraction);
+                } else {
+                    // The first joined row replaces the null-padded rows, after the last one they're back.
+                    if joined_rows.is_empty() && left_outer && !retraction {
+                        for (source_row, &count) in source_rows.iter() {
+                            push_repeated(&mut output, (source_row.clone(), None, true), count);
                         }
-                        _ => unimplemented!(),
                     }
-                }
-
-                for column in 0..(joined_schema.fields().len() - 1) {
-                    // Omit the retraction field, it goes last.
-                    match batch.column(column).data_type() {
-                        DataType::Int64 => {
-                            let mut array = Int64Builder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((state_other_rows, _)) = state_map.get(key) {
-                                    for (_other_row, &count) in state_other_rows {
-                                        for new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Int64(n) = &new_row[column] {
-                                                    array.append_value(*n)?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
-                        }
-                        DataType::Utf8 => {
-                            let mut array = StringBuilder::new(required_capacity);
-                            for (key, cur_new_rows) in &new_rows {
-                                if let Some((state_other_rows, _)) = state_map.get(key) {
-                                    for (_other_row, &count) in state_other_rows {
-                                        for new_row in cur_new_rows {
-                                            for _repetition in 0..count {
-                                                if let ScalarValue::Utf8(text) = &new_row[column] {
-                                                    array.append_value(text.as_str())?;
-                                                } else {
-                                                    panic!("invalid type");
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            output_columns.push(Arc::new(array.finish()) as ArrayRef)
-                        }
-                        _ => unimplemented!(),
+                    for (source_row, &count) in source_rows.iter() {
+                        push_repeated(&mut output, (source_row.clone(), Some(row_vec.clone()), retraction), count);
                     }
-                }
-
-                let retraction_column = batch.num_columns() - 1;
-
-                let mut array = BooleanBuilder::new(required_capacity);
-                for (key, cur_new_rows) in &new_rows {
-                    if let Some((state_other_rows, _)) = state_map.get(key) {
-                        for (_other_row, &count) in state_other_rows {
-                            for new_row in cur_new_rows {
-                                for _repetition in 0..count {
-                                    if let ScalarValue::Boolean(retraction) =
-                                        new_row[retraction_column]
-                                    {
-                                        array.append_value(retraction)?;
-                                    } else {
-                                        panic!("invalid type");
-                                    }
-                                }
-                            }
+                    update_side_state(joined_rows, row_vec, retraction);
+                    if joined_rows.is_empty() && left_outer && retraction {
+                        for (source_row, &count) in source_rows.iter() {
+                            push_repeated(&mut output, (source_row.clone(), None, false), count);
                         }
                     }
                 }
-                output_columns.push(Arc::new(array.finish()) as ArrayRef)
             }
 
-            for (key, cur_new_rows) in new_rows {
-                for mut row in cur_new_rows {
-                    let (source_state, joined_state) = state_map
-                        .entry(key.clone())
-                        .or_insert((HashMap::default(), HashMap::default()));
-                    let retraction = if let ScalarValue::Boolean(retraction) = row[row.len() - 1] {
-                        retraction
-                    } else {
-                        panic!("invalid retraction type")
-                    };
-                    row.truncate(row.len() - 1);
-
-                    let my_rows = if source_index == 0 {
-                        source_state
-                    } else {
-                        joined_state
-                    };
-                    let my_entry = my_rows.entry(row).or_default();
-                    if !retraction {
-                        *my_entry += 1;
-                    } else {
-                        *my_entry -= 1;
-                    }
-                }
+            if output.is_empty() {
+                continue;
             }
 
-            let output_batch = RecordBatch::try_new(output_schema.clone(), output_columns)?;
-            if output_batch.num_rows() > 0 {
-                produce(&ProduceContext {}, output_batch)?;
-            }
+            // Source rows without a match are padded with nulls.
+            let joined_field_count = output_schema.fields().len() - 1 - source_field_count;
+            let records = output.into_iter()
+                .map(|(mut source_row, joined_row, retraction)| {
+                    source_row.extend(joined_row.unwrap_or_else(|| vec![ScalarValue::Null; joined_field_count]));
+                    (source_row, retraction)
+                })
+                .collect::<Vec<_>>();
+            produce(&ProduceContext {}, records_to_batch(&output_schema, &records)?)?;
         }
 
-        handle1.join().unwrap();
-        handle2.join().unwrap();
-
         Ok(())
     }
 }
+
+#[cfg(test)]
+fn test_join(source: crate::physical::testing::Values, joined: crate::physical::testing::Values) -> Result<Vec<Vec<ScalarValue>>, Error> {
+    use crate::physical::expression::FieldExpression;
+    use crate::physical::testing::collect;
+
+    let key = |name: &str| Arc::new(FieldExpression::new(Identifier::SimpleIdentifier(name.to_string()))) as Arc<dyn Expression>;
+    let join = StreamJoin::new(Arc::new(source), vec![key("a.id")], Arc::new(joined), vec![key("b.id")], JoinType::LeftOuter);
+    collect(&join)
+}
+
+#[test]
+fn test_left_outer_join() {
+    use crate::physical::testing::{Latch, row, Values};
+
+    let (int, text) = (ScalarValue::Int64, |text: &str| ScalarValue::Utf8(text.to_string()));
+    let latch = Latch::default();
+    let source = Values::new(vec![("a.id", DataType::Int64), ("a.name", DataType::Utf8)], vec![
+        vec![row(&[int(1), text("x")], false), row(&[ScalarValue::Null, text("n")], false)],
+        vec![row(&[int(2), text("z")], false)],
+    ]).opens(&latch);
+    let joined = Values::new(vec![("b.id", DataType::Int64), ("b.name", DataType::Utf8)], vec![
+        vec![row(&[int(1), text("y")], false), row(&[int(3), text("w")], false)],
+        vec![row(&[int(1), text("y")], true)],
+    ]).waits_for(&latch);
+
+    let null = ScalarValue::Null;
+    assert_eq!(test_join(source, joined).unwrap(), vec![
+        // Source rows without a match, also the one with a null key, are padded with nulls.
+        row(&[int(1), text("x"), null.clone(), null.clone()], false),
+        row(&[null.clone(), text("n"), null.clone(), null.clone()], false),
+        row(&[int(2), text("z"), null.clone(), null.clone()], false),
+        // The first match replaces the padded row.
+        row(&[int(1), text("x"), null.clone(), null.clone()], true),
+        row(&[int(1), text("x"), int(1), text("y")], false),
+        // Once the match is retracted, the padded row is back.
+        row(&[int(1), text("x"), int(1), text("y")], true),
+        row(&[int(1), text("x"), null.clone(), null.clone()], false),
+    ]);
+}
+
+#[test]
+fn test_join_float_keys() {
+    use crate::physical::testing::{net, row, Values};
+
+    let float = ScalarValue::Float64;
+    let source = Values::new(vec![("a.id", DataType::Float64)], vec![vec![row(&[float(1.5)], false), row(&[float(-0.0)], false), row(&[float(2.0)], false)]]);
+    let joined = Values::new(vec![("b.id", DataType::Float64)], vec![vec![row(&[float(1.5)], false), row(&[float(0.0)], false)]]);
+    let mut rows = net(test_join(source, joined).unwrap());
+    rows.sort_by(|a, b| format!("{:?}", a).cmp(&format!("{:?}", b)));
+    assert_eq!(rows, vec![
+        vec![float(-0.0), float(0.0)],
+        vec![float(1.5), float(1.5)],
+        vec![float(2.0), ScalarValue::Null],
+    ]);
+}
+
+#[test]
+fn test_join_error() {
+    use crate::physical::testing::{row, Values};
+
+    // A row which doesn't match the schema of its source makes it fail.
+    let source = Values::new(vec![("a.id", DataType::Int64)], vec![vec![row(&[ScalarValue::Int64(1)], false)]]);
+    let joined = Values::new(vec![("b.id", DataType::Int64)], vec![vec![row(&[ScalarValue::Utf8("x".to_string())], false)]]);
+    match test_join(source, joined) {
+        Err(Error::BadInput(message)) => assert!(message.contains("can't put"), "{}", message),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_join_key_types() {
+    use crate::physical::testing::{row, Values};
+
+    let source = Values::new(vec![("a.id", DataType::Int64)], vec![vec![row(&[ScalarValue::Int64(1)], false), row(&[ScalarValue::Int64(2)], false)]]);
+    let joined = Values::new(vec![("b.id", DataType::Float64)], vec![vec![row(&[ScalarValue::Float64(1.0)], false), row(&[ScalarValue::Float64(2.5)], false)]]);
+    let mut rows = test_join(source, joined).unwrap();
+    rows.retain(|row| row[1] != ScalarValue::Null);
+    assert_eq!(rows, vec![row(&[ScalarValue::Int64(1), ScalarValue::Float64(1.0)], false)]);
+
+    let source = Values::new(vec![("a.id", DataType::Int64)], vec![]);
+    let joined = Values::new(vec![("b.id", DataType::Boolean)], vec![]);
+    match test_join(source, joined) {
+        Err(Error::BadInput(message)) => assert_eq!(message, "can't join on keys of types Int64 and Boolean"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_cross_join() {
+    use crate::physical::testing::{collect, Latch, net, row, Values};
+
+    let int = ScalarValue::Int64;
+    let latch = Latch::default();
+    let source = Values::new(vec![("a.x", DataType::Int64)], vec![vec![row(&[int(1)], false), row(&[int(2)], false)]]).opens(&latch);
+    let joined = Values::new(vec![("b.y", DataType::Int64)], vec![
+        vec![row(&[int(10)], false), row(&[int(20)], false)],
+        vec![row(&[int(10)], true)],
+    ]).waits_for(&latch);
+    let join = StreamJoin::new(Arc::new(source), vec![], Arc::new(joined), vec![], JoinType::Inner);
+    let rows = collect(&join).unwrap();
+    // Each joined record is joined with all source records, in any order.
+    assert_eq!(rows.len(), 6);
+    let mut rows = net(rows);
+    rows.sort_by(|a, b| format!("{:?}", a).cmp(&format!("{:?}", b)));
+    assert_eq!(rows, vec![vec![int(1), int(20)], vec![int(2), int(20)]]);
+}
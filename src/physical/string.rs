@@ -0,0 +1,130 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::physical::physical::{Error, ScalarValue};
+
+// SQL positions are 1-based, and a substring may start before the first character.
+pub fn substr(text: &str, start: i64, length: Option<i64>) -> Result<String, Error> {
+    let end = match length {
+        Some(length) if length < 0 => return Err(Error::BadInput(format!("negative substring length: {}", length))),
+        Some(length) => start.saturating_add(length),
+        None => i64::MAX,
+    };
+    let start = start.max(1);
+    if end <= start {
+        return Ok(String::new());
+    }
+    Ok(text.chars().skip((start - 1) as usize).take((end - start) as usize).collect())
+}
+
+pub fn pad(text: &str, length: i64, fill: &str, left: bool) -> String {
+    let length = length.max(0) as usize;
+    let text_length = text.chars().count();
+    if text_length >= length || fill.is_empty() {
+        return text.chars().take(length).collect();
+    }
+    let padding: String = fill.chars().cycle().take(length - text_length).collect();
+    if left {
+        padding + text
+    } else {
+        text.to_string() + padding.as_str()
+    }
+}
+
+pub fn position(substring: &str, text: &str) -> i64 {
+    match text.find(substring) {
+        Some(index) => text[..index].chars().count() as i64 + 1,
+        None => 0,
+    }
+}
+
+pub fn split_part(text: &str, delimiter: &str, n: i64) -> Result<String, Error> {
+    if n <= 0 {
+        return Err(Error::BadInput(format!("field position must be greater than zero, is {}", n)));
+    }
+    if delimiter.is_empty() {
+        return Ok(if n == 1 { text.to_string() } else { String::new() });
+    }
+    Ok(text.split(delimiter).nth((n - 1) as usize).unwrap_or("").to_string())
+}
+
+pub fn trim(text: &str, characters: Option<&str>, leading: bool, trailing: bool) -> String {
+    let should_trim = |c: char| match characters {
+        None => c.is_whitespace(),
+        Some(characters) => characters.contains(c),
+    };
+    let mut trimmed = text;
+    if leading {
+        trimmed = trimmed.trim_start_matches(should_trim);
+    }
+    if trailing {
+        trimmed = trimmed.trim_end_matches(should_trim);
+    }
+    trimmed.to_string()
+}
+
+/// Caches compiled regular expressions. A constant pattern is compiled only once.
+/// The flags may contain 'i' for case-insensitive matching.
+pub struct RegexCache {
+    constant: Option<Regex>,
+    compiled: HashMap<String, Regex>,
+}
+
+impl RegexCache {
+    pub fn new(pattern: &Option<ScalarValue>, flags: Option<&Option<ScalarValue>>) -> Result<RegexCache, Error> {
+        let constant = match (pattern, flags) {
+            (Some(ScalarValue::Utf8(pattern)), None) => Some(compile_regex(pattern, "")?),
+            (Some(ScalarValue::Utf8(pattern)), Some(Some(ScalarValue::Utf8(flags)))) => Some(compile_regex(pattern, flags)?),
+            _ => None,
+        };
+        Ok(RegexCache {
+            constant,
+            compiled: HashMap::new(),
+        })
+    }
+
+    pub fn get(&mut self, pattern: &str, flags: &str) -> Result<&Regex, Error> {
+        if let Some(regex) = &self.constant {
+            return Ok(regex);
+        }
+        let key = format!("{}/{}", flags, pattern);
+        if !self.compiled.contains_key(&key) {
+            let regex = compile_regex(pattern, flags)?;
+            self.compiled.insert(key.clone(), regex);
+        }
+        Ok(&self.compiled[&key])
+    }
+}
+
+fn compile_regex(pattern: &str, flags: &str) -> Result<Regex, Error> {
+    let pattern = if flags.contains('i') { format!("(?i){}", pattern) } else { pattern.to_string() };
+    Regex::new(pattern.as_str()).map_err(|err| Error::BadInput(format!("invalid regular expression '{}': {}", pattern, err)))
+}
+
+#[test]
+fn test_string_helpers() {
+    assert_eq!(substr("hello", 2, Some(3)).unwrap(), "ell");
+    assert_eq!(substr("hello", 0, Some(2)).unwrap(), "h");
+    assert_eq!(substr("hello", 4, None).unwrap(), "lo");
+    assert_eq!(pad("ab", 5, "xy", true), "xyxab");
+    assert_eq!(pad("abcdef", 3, " ", false), "abc");
+    assert_eq!(position("ł", "zażółć"), 5);
+    assert_eq!(split_part("a,b,c", ",", 2).unwrap(), "b");
+    assert_eq!(split_part("a,b,c", ",", 4).unwrap(), "");
+    assert_eq!(trim("xxaxx", Some("x"), true, false), "axx");
+}
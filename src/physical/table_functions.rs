@@ -0,0 +1,167 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, BooleanArray, Int64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::csv::CSVSource;
+use crate::physical::json::JSONSource;
+use crate::physical::physical::*;
+
+/// The constant arguments of a table function, positional ones in order and named ones by name.
+pub struct TableFunctionArgs {
+    name: String,
+    positional: Vec<ScalarValue>,
+    named: HashMap<String, ScalarValue>,
+}
+
+impl TableFunctionArgs {
+    pub fn new(name: &str, positional: Vec<ScalarValue>, named: HashMap<String, ScalarValue>) -> TableFunctionArgs {
+        TableFunctionArgs {
+            name: name.to_string(),
+            positional,
+            named,
+        }
+    }
+
+    // Arguments can be given by position or by name.
+    fn get(&self, position: usize, name: &str) -> Option<&ScalarValue> {
+        self.named.get(name).or_else(|| self.positional.get(position))
+    }
+
+    fn check(&self, max_positional: usize, names: &[&str]) -> Result<(), Error> {
+        if self.positional.len() > max_positional {
+            return Err(Error::BadInput(format!("{} takes at most {} arguments, got {}", self.name, max_positional, self.positional.len())));
+        }
+        match self.named.keys().find(|name| !names.contains(&name.as_str())) {
+            Some(name) => Err(Error::BadInput(format!("unknown argument of {}: {}", self.name, name))),
+            None => Ok(()),
+        }
+    }
+
+    fn int(&self, position: usize, name: &str) -> Result<Option<i64>, Error> {
+        match self.get(position, name) {
+            None => Ok(None),
+            Some(ScalarValue::Int64(value)) => Ok(Some(*value)),
+            Some(value) => Err(Error::BadInput(format!("argument {} of {} has to be an integer, got {:?}", name, self.name, value))),
+        }
+    }
+
+    fn string(&self, position: usize, name: &str) -> Result<Option<String>, Error> {
+        match self.get(position, name) {
+            None => Ok(None),
+            Some(ScalarValue::Utf8(value)) => Ok(Some(value.clone())),
+            Some(value) => Err(Error::BadInput(format!("argument {} of {} has to be a string, got {:?}", name, self.name, value))),
+        }
+    }
+
+    fn required<T>(&self, value: Option<T>, name: &str) -> Result<T, Error> {
+        value.ok_or_else(|| Error::BadInput(format!("missing argument {} of {}", name, self.name)))
+    }
+}
+
+type TableFunctionConstructor = Arc<dyn Fn(&TableFunctionArgs) -> Result<Arc<dyn Node>, Error> + Send + Sync>;
+
+lazy_static! {
+    pub static ref TABLE_FUNCTIONS: HashMap<&'static str, TableFunctionConstructor> = {
+        let mut m: HashMap<&'static str, TableFunctionConstructor> = HashMap::new();
+        m.insert("range", Arc::new(|args: &TableFunctionArgs| {
+            args.check(3, &["start", "stop", "step"])?;
+            let start = args.required(args.int(0, "start")?, "start")?;
+            let stop = args.required(args.int(1, "stop")?, "stop")?;
+            let step = args.int(2, "step")?.unwrap_or(1);
+            if step == 0 {
+                return Err(Error::BadInput("the step of range can't be 0".to_string()));
+            }
+            Ok(Arc::new(Range::new(start, stop, step)) as Arc<dyn Node>)
+        }));
+        m.insert("read_csv", Arc::new(|args: &TableFunctionArgs| {
+            args.check(1, &["path", "delimiter"])?;
+            let path = args.required(args.string(0, "path")?, "path")?;
+            let mut source = CSVSource::new(path);
+            if let Some(delimiter) = args.string(1, "delimiter")? {
+                match delimiter.as_bytes() {
+                    [delimiter] => source = source.with_delimiter(*delimiter),
+                    _ => return Err(Error::BadInput(format!("the delimiter of read_csv has to be a single character, got {:?}", delimiter))),
+                }
+            }
+            Ok(Arc::new(source) as Arc<dyn Node>)
+        }));
+        m.insert("read_json", Arc::new(|args: &TableFunctionArgs| {
+            args.check(1, &["path"])?;
+            let path = args.required(args.string(0, "path")?, "path")?;
+            Ok(Arc::new(JSONSource::new(path)) as Arc<dyn Node>)
+        }));
+        m
+    };
+}
+
+/// The integers from start up to, but excluding, stop, in a single column named range.
+pub struct Range {
+    start: i64,
+    stop: i64,
+    step: i64,
+}
+
+impl Range {
+    pub fn new(start: i64, stop: i64, step: i64) -> Range {
+        Range { start, stop, step }
+    }
+
+    fn values(&self) -> impl Iterator<Item=i64> + '_ {
+        std::iter::successors(Some(self.start), move |value| value.checked_add(self.step))
+            .take_while(move |value| if self.step > 0 { *value < self.stop } else { *value > self.stop })
+    }
+}
+
+impl Node for Range {
+    fn schema(&self, _schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        Ok(Arc::new(Schema::new(vec![
+            Field::new("range", DataType::Int64, false),
+            Field::new(RETRACTIONS_FIELD, DataType::Boolean, false),
+        ])))
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let schema = self.schema(ctx.variable_context.clone())?;
+        let mut values = self.values().peekable();
+        while values.peek().is_some() {
+            let batch = values.by_ref().take(BATCH_SIZE).collect::<Vec<_>>();
+            let retractions = vec![false; batch.len()];
+            produce(&ProduceContext {}, RecordBatch::try_new(schema.clone(), vec![
+                Arc::new(Int64Array::from(batch)) as ArrayRef,
+                Arc::new(BooleanArray::from(retractions)) as ArrayRef,
+            ])?)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_range() {
+    let values = |start, stop, step| Range::new(start, stop, step).values().collect::<Vec<_>>();
+    assert_eq!(values(1, 5, 1), vec![1, 2, 3, 4]);
+    assert_eq!(values(0, 10, 4), vec![0, 4, 8]);
+    assert_eq!(values(5, 1, -2), vec![5, 3]);
+    assert_eq!(values(3, 3, 1), Vec::<i64>::new());
+}
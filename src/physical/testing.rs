@@ -0,0 +1,152 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Helpers for the tests of nodes and queries, which work with rows of scalars.
+// The last value of a row is always its retraction flag.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{get_scalar_value, scalars_to_array};
+use crate::physical::datetime::Zone;
+use crate::physical::physical::*;
+use crate::query::PreparedQuery;
+
+pub fn test_context() -> ExecutionContext {
+    ExecutionContext {
+        variable_context: Arc::new(VariableContext {
+            previous: None,
+            schema: Arc::new(Schema::new(vec![])),
+            variables: vec![],
+        }),
+        session: Arc::new(Session::new(Some(0), Zone::utc())),
+    }
+}
+
+pub fn row(values: &[ScalarValue], retraction: bool) -> Vec<ScalarValue> {
+    let mut row = values.to_vec();
+    row.push(ScalarValue::Boolean(retraction));
+    row
+}
+
+/// Makes a source wait until another one produced all of its batches,
+/// so that the order of the batches of sources which run concurrently is known.
+#[derive(Clone, Default)]
+pub struct Latch(Arc<(Mutex<bool>, Condvar)>);
+
+impl Latch {
+    fn open(&self) {
+        *(self.0).0.lock().unwrap() = true;
+        (self.0).1.notify_all();
+    }
+
+    fn wait(&self) {
+        let mut open = (self.0).0.lock().unwrap();
+        while !*open {
+            open = (self.0).1.wait(open).unwrap();
+        }
+    }
+}
+
+/// A source which produces the given batches of rows.
+pub struct Values {
+    schema: Arc<Schema>,
+    batches: Vec<Vec<Vec<ScalarValue>>>,
+    opens: Option<Latch>,
+    waits_for: Option<Latch>,
+}
+
+impl Values {
+    /// The fields don't include the retraction field.
+    pub fn new(fields: Vec<(&str, DataType)>, batches: Vec<Vec<Vec<ScalarValue>>>) -> Values {
+        let mut fields: Vec<Field> = fields.into_iter().map(|(name, data_type)| Field::new(name, data_type, true)).collect();
+        fields.push(Field::new(RETRACTIONS_FIELD, DataType::Boolean, false));
+        Values { schema: Arc::new(Schema::new(fields)), batches, opens: None, waits_for: None }
+    }
+
+    /// Opens the latch after producing all the batches.
+    pub fn opens(self, latch: &Latch) -> Values {
+        Values { opens: Some(latch.clone()), ..self }
+    }
+
+    /// Waits for the latch to be opened before producing anything.
+    pub fn waits_for(self, latch: &Latch) -> Values {
+        Values { waits_for: Some(latch.clone()), ..self }
+    }
+}
+
+impl Node for Values {
+    fn schema(&self, _schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        Ok(self.schema.clone())
+    }
+
+    fn run(&self, _ctx: &ExecutionContext, produce: ProduceFn, meta_send: MetaSendFn) -> Result<(), Error> {
+        if let Some(latch) = &self.waits_for {
+            latch.wait();
+        }
+        for rows in &self.batches {
+            let columns = self.schema.fields().iter()
+                .enumerate()
+                .map(|(i, field)| scalars_to_array(&rows.iter().map(|row| row[i].clone()).collect::<Vec<_>>(), field.data_type()))
+                .collect::<Result<Vec<_>, Error>>()?;
+            produce(&ProduceContext {}, RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        }
+        if let Some(latch) = &self.opens {
+            latch.open();
+        }
+        meta_send(&ProduceContext {}, MetadataMessage::EndOfStream)
+    }
+}
+
+fn append_rows(rows: &Mutex<Vec<Vec<ScalarValue>>>, batch: &RecordBatch) -> Result<(), Error> {
+    let mut rows = rows.lock().unwrap();
+    for i in 0..batch.num_rows() {
+        rows.push(batch.columns().iter().map(|column| get_scalar_value(column, i)).collect::<Result<Vec<_>, Error>>()?);
+    }
+    Ok(())
+}
+
+/// Runs the node and returns all the rows it produced, in order.
+pub fn collect(node: &dyn Node) -> Result<Vec<Vec<ScalarValue>>, Error> {
+    let rows = Mutex::new(vec![]);
+    node.run(&test_context(), &mut |_ctx, batch| append_rows(&rows, &batch), &mut noop_meta_send)?;
+    Ok(rows.into_inner().unwrap())
+}
+
+/// Runs the query and returns all the rows it produced, in order.
+pub fn query(sql: &str) -> Result<Vec<Vec<ScalarValue>>, Error> {
+    let query = PreparedQuery::new(sql).map_err(|err| Error::BadInput(err.to_string()))?;
+    let rows = Mutex::new(vec![]);
+    query.run(test_context().session, &HashMap::new(), &mut |_ctx, batch| append_rows(&rows, &batch))?;
+    Ok(rows.into_inner().unwrap())
+}
+
+/// Applies the retractions, leaving the rows which are left in the end without their retraction flag.
+pub fn net(rows: Vec<Vec<ScalarValue>>) -> Vec<Vec<ScalarValue>> {
+    let mut output: Vec<Vec<ScalarValue>> = vec![];
+    for mut row in rows {
+        let retraction = row.pop() == Some(ScalarValue::Boolean(true));
+        if !retraction {
+            output.push(row);
+        } else if let Some(position) = output.iter().position(|other| other == &row) {
+            output.remove(position);
+        } else {
+            panic!("retraction of a row which wasn't produced: {:?}", row);
+        }
+    }
+    output
+}
@@ -0,0 +1,254 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use arrow::array::BooleanArray;
+use arrow::compute::kernels::sort::SortOptions;
+use arrow::datatypes::Schema;
+
+use crate::physical::arrow::{get_scalar_value, records_to_batch};
+use crate::physical::expression::Expression;
+use crate::physical::physical::*;
+
+/// Continuously maintains the first `limit` records after `offset` in the order of the keys.
+/// Whenever a batch changes these records, the ones which left are retracted and the ones which came in are emitted.
+/// Only the first `2 * (offset + limit)` records are kept, the ones after the top are a buffer for retractions.
+/// Records which didn't fit are dropped, so if more records are retracted than the buffer holds,
+/// the top is incomplete until new records come in.
+pub struct TopN {
+    source: Arc<dyn Node>,
+    keys: Vec<Arc<dyn Expression>>,
+    options: Arc<Vec<SortOptions>>,
+    limit: usize,
+    offset: usize,
+}
+
+impl TopN {
+    pub fn new(source: Arc<dyn Node>, keys: Vec<Arc<dyn Expression>>, options: Vec<SortOptions>, limit: usize, offset: usize) -> TopN {
+        TopN {
+            source,
+            keys,
+            options: Arc::new(options),
+            limit,
+            offset,
+        }
+    }
+}
+
+// The values of the keys of a record, ordered by the sort options.
+#[derive(Clone)]
+struct SortKey {
+    values: Vec<ScalarValue>,
+    options: Arc<Vec<SortOptions>>,
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_keys(&self.values, &other.values, &self.options)
+    }
+}
+
+impl Node for TopN {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        self.source.schema(schema_context)
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let output_schema = self.schema(ctx.variable_context.clone())?;
+        let capacity = 2 * (self.offset + self.limit);
+
+        // Records with equal keys are ordered by their sequence number, so in arrival order.
+        let mut records: BTreeMap<(SortKey, u64), Vec<ScalarValue>> = BTreeMap::new();
+        let mut sequence: u64 = 0;
+        let mut visible: Vec<(u64, Vec<ScalarValue>)> = Vec::new();
+
+        self.source.run(
+            ctx,
+            &mut |produce_ctx, batch| {
+                let keys = self.keys.iter()
+                    .map(|key| key.evaluate(ctx, &batch))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let retractions = batch.column(batch.num_columns() - 1).as_any().downcast_ref::<BooleanArray>().unwrap();
+
+                for row in 0..batch.num_rows() {
+                    let key = SortKey {
+                        values: keys.iter()
+                            .map(|key| get_scalar_value(key, row))
+                            .collect::<Result<Vec<_>, _>>()?,
+                        options: self.options.clone(),
+                    };
+                    let record = (0..batch.num_columns() - 1)
+                        .map(|column| get_scalar_value(batch.column(column), row))
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    if !retractions.value(row) {
+                        records.insert((key, sequence), record);
+                        sequence += 1;
+                        if records.len() > capacity {
+                            let last = records.keys().next_back().cloned().unwrap();
+                            records.remove(&last);
+                        }
+                    } else {
+                        // The retracted record may have been dropped, then there's nothing to remove.
+                        let position = records.range((key.clone(), 0)..=(key, u64::MAX))
+                            .find(|(_, other)| **other == record)
+                            .map(|(position, _)| position.clone());
+                        if let Some(position) = position {
+                            records.remove(&position);
+                        }
+                    }
+                }
+
+                let current = records.iter()
+                    .skip(self.offset)
+                    .take(self.limit)
+                    .map(|((_, sequence), record)| (*sequence, record.clone()))
+                    .collect::<Vec<_>>();
+
+                // Only the difference between the previous and the current top is emitted.
+                let visible_sequences = visible.iter().map(|(sequence, _)| *sequence).collect::<HashSet<_>>();
+                let current_sequences = current.iter().map(|(sequence, _)| *sequence).collect::<HashSet<_>>();
+                let output = visible.iter()
+                    .filter(|(sequence, _)| !current_sequences.contains(sequence))
+                    .map(|(_, record)| (record, true))
+                    .chain(current.iter()
+                        .filter(|(sequence, _)| !visible_sequences.contains(sequence))
+                        .map(|(_, record)| (record, false)))
+                    .collect::<Vec<_>>();
+
+                if !output.is_empty() {
+                    produce(produce_ctx, records_to_batch(&output_schema, &output)?)?;
+                }
+
+                visible = current;
+                Ok(())
+            },
+            &mut noop_meta_send,
+        )?;
+        Ok(())
+    }
+}
+
+fn compare_keys(left: &[ScalarValue], right: &[ScalarValue], options: &[SortOptions]) -> Ordering {
+    left.iter()
+        .zip(right.iter())
+        .zip(options.iter())
+        .map(|((left, right), options)| {
+            match (left, right) {
+                (ScalarValue::Null, ScalarValue::Null) => Ordering::Equal,
+                (ScalarValue::Null, _) => if options.nulls_first { Ordering::Less } else { Ordering::Greater },
+                (_, ScalarValue::Null) => if options.nulls_first { Ordering::Greater } else { Ordering::Less },
+                _ if options.descending => compare_scalars(right, left),
+                _ => compare_scalars(left, right),
+            }
+        })
+        .find(|ordering| ordering != &Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+// Values of different types are never compared, as keys have a single type.
+pub fn compare_scalars(left: &ScalarValue, right: &ScalarValue) -> Ordering {
+    match (left, right) {
+        (ScalarValue::Boolean(left), ScalarValue::Boolean(right)) => left.cmp(right),
+        (ScalarValue::Float32(left), ScalarValue::Float32(right)) => compare_floats(*left as f64, *right as f64),
+        (ScalarValue::Float64(left), ScalarValue::Float64(right)) => compare_floats(*left, *right),
+        (ScalarValue::Int8(left), ScalarValue::Int8(right)) => left.cmp(right),
+        (ScalarValue::Int16(left), ScalarValue::Int16(right)) => left.cmp(right),
+        (ScalarValue::Int32(left), ScalarValue::Int32(right)) => left.cmp(right),
+        (ScalarValue::Int64(left), ScalarValue::Int64(right)) => left.cmp(right),
+        (ScalarValue::UInt8(left), ScalarValue::UInt8(right)) => left.cmp(right),
+        (ScalarValue::UInt16(left), ScalarValue::UInt16(right)) => left.cmp(right),
+        (ScalarValue::UInt32(left), ScalarValue::UInt32(right)) => left.cmp(right),
+        (ScalarValue::UInt64(left), ScalarValue::UInt64(right)) => left.cmp(right),
+        (ScalarValue::Utf8(left), ScalarValue::Utf8(right)) => left.cmp(right),
+        (ScalarValue::Date32(left), ScalarValue::Date32(right)) => left.cmp(right),
+        (ScalarValue::Timestamp(left), ScalarValue::Timestamp(right)) => left.cmp(right),
+        (ScalarValue::IntervalYearMonth(left), ScalarValue::IntervalYearMonth(right)) => left.cmp(right),
+        (ScalarValue::IntervalDayTime(left), ScalarValue::IntervalDayTime(right)) => left.cmp(right),
+        (ScalarValue::Struct(left), ScalarValue::Struct(right)) => {
+            let options = vec![SortOptions { descending: false, nulls_first: true }; left.len()];
+            compare_keys(left, right, &options)
+        }
+        _ => Ordering::Equal,
+    }
+}
+
+// NaN is greater than all numbers and equal to itself, so that keys are totally ordered.
+fn compare_floats(left: f64, right: f64) -> Ordering {
+    left.partial_cmp(&right).unwrap_or_else(|| left.is_nan().cmp(&right.is_nan()))
+}
+
+#[test]
+fn test_compare_keys() {
+    let options = vec![
+        SortOptions { descending: true, nulls_first: false },
+        SortOptions { descending: false, nulls_first: true },
+    ];
+    let key = |count: Option<i64>, name: &str| vec![
+        count.map(ScalarValue::Int64).unwrap_or(ScalarValue::Null),
+        ScalarValue::Utf8(name.to_string()),
+    ];
+    assert_eq!(compare_keys(&key(Some(3), "b"), &key(Some(2), "a"), &options), Ordering::Less);
+    assert_eq!(compare_keys(&key(Some(2), "a"), &key(Some(2), "b"), &options), Ordering::Less);
+    assert_eq!(compare_keys(&key(None, "a"), &key(Some(1), "b"), &options), Ordering::Greater);
+    assert_eq!(compare_keys(&key(Some(2), "a"), &key(Some(2), "a"), &options), Ordering::Equal);
+}
+
+#[test]
+fn test_top_n_retractions() {
+    use arrow::datatypes::DataType;
+
+    use crate::physical::expression::FieldExpression;
+    use crate::physical::testing::{collect, row, Values};
+
+    let float = ScalarValue::Float64;
+    let source = Values::new(vec![("a.x", DataType::Float64)], vec![
+        // Only the first four records are kept, so NaN is dropped.
+        vec![row(&[float(3.5)], false), row(&[float(1.5)], false), row(&[float(f64::NAN)], false), row(&[float(2.5)], false), row(&[float(0.5)], false)],
+        vec![row(&[float(0.5)], true), row(&[float(f64::NAN)], true), row(&[float(-0.0)], false)],
+        vec![row(&[float(1.5)], true), row(&[float(2.5)], true)],
+    ]);
+    let key = Arc::new(FieldExpression::new(Identifier::SimpleIdentifier("a.x".to_string()))) as Arc<dyn Expression>;
+    let top_n = TopN::new(Arc::new(source), vec![key], vec![SortOptions { descending: false, nulls_first: false }], 2, 0);
+    assert_eq!(collect(&top_n).unwrap(), vec![
+        row(&[float(0.5)], false),
+        row(&[float(1.5)], false),
+        row(&[float(0.5)], true),
+        row(&[float(-0.0)], false),
+        row(&[float(1.5)], true),
+        row(&[float(3.5)], false),
+    ]);
+}
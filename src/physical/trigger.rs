@@ -15,10 +15,11 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
-use arrow::array::{ArrayRef, Int64Builder, StringBuilder};
-use arrow::datatypes::DataType;
+use arrow::array::{ArrayRef, Date32Builder, Int64Builder, StringBuilder, TimestampNanosecondBuilder};
+use arrow::datatypes::{DataType, DateUnit, TimeUnit};
 
 use crate::physical::arrow::{create_key, GroupByScalar};
+use crate::physical::datetime::with_time_zone;
 
 pub trait TriggerPrototype: Send + Sync {
     fn create_trigger(&self, key_data_types: Vec<DataType>) -> Box<dyn Trigger>;
@@ -95,6 +96,7 @@ impl Trigger for CountingTrigger {
                     self.to_trigger.iter().for_each(|k| {
                         match &k[key_index] {
                             GroupByScalar::Utf8(text) => array.append_value(text.as_str()).unwrap(),
+                            GroupByScalar::Null => array.append_null().unwrap(),
                             _ => panic!("bug: key doesn't match schema"),
                             // TODO: Maybe use as_any -> downcast?
                         }
@@ -106,12 +108,36 @@ impl Trigger for CountingTrigger {
                     self.to_trigger.iter().for_each(|k| {
                         match k[key_index] {
                             GroupByScalar::Int64(n) => array.append_value(n).unwrap(),
+                            GroupByScalar::Null => array.append_null().unwrap(),
                             _ => panic!("bug: key doesn't match schema"),
                             // TODO: Maybe use as_any -> downcast?
                         }
                     });
                     output_columns.push(Arc::new(array.finish()) as ArrayRef);
                 }
+                DataType::Date32(DateUnit::Day) => {
+                    let mut array = Date32Builder::new(self.to_trigger.len());
+                    self.to_trigger.iter().for_each(|k| {
+                        match k[key_index] {
+                            GroupByScalar::Date32(days) => array.append_value(days).unwrap(),
+                            GroupByScalar::Null => array.append_null().unwrap(),
+                            _ => panic!("bug: key doesn't match schema"),
+                        }
+                    });
+                    output_columns.push(Arc::new(array.finish()) as ArrayRef);
+                }
+                DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                    let mut array = TimestampNanosecondBuilder::new(self.to_trigger.len());
+                    self.to_trigger.iter().for_each(|k| {
+                        match k[key_index] {
+                            GroupByScalar::Timestamp(nanos) => array.append_value(nanos).unwrap(),
+                            GroupByScalar::Null => array.append_null().unwrap(),
+                            _ => panic!("bug: key doesn't match schema"),
+                        }
+                    });
+                    let array = Arc::new(array.finish()) as ArrayRef;
+                    output_columns.push(with_time_zone(&array, &self.key_data_types[key_index]));
+                }
                 _ => unimplemented!(),
             }
         }
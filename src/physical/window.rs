@@ -0,0 +1,512 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, Int64Array};
+use arrow::compute::kernels::sort::SortOptions;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use crate::physical::arrow::{get_scalar_value, is_float, is_integer, is_numeric, scalars_to_array, take_array};
+use crate::physical::cast::cast_array;
+use crate::physical::expression::Expression;
+use crate::physical::physical::*;
+use crate::physical::sort::{net_retractions, sort_indices};
+use crate::physical::top_n::compare_scalars;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameUnits {
+    Rows,
+    Range,
+}
+
+// A bound without a number of rows is unbounded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameBound {
+    Preceding(Option<u64>),
+    CurrentRow,
+    Following(Option<u64>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WindowFrame {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+impl WindowFrame {
+    /// The frame used when none is given, everything up to the current row and its peers with an ORDER BY,
+    /// the whole partition without one.
+    pub fn default_for(ordered: bool) -> WindowFrame {
+        WindowFrame {
+            units: FrameUnits::Range,
+            start: FrameBound::Preceding(None),
+            end: if ordered { FrameBound::CurrentRow } else { FrameBound::Following(None) },
+        }
+    }
+
+    // The positions of the frame of the row at the given position, the end being exclusive.
+    // Peers are the positions of the first row equal to the current one and the one after the last such row.
+    fn bounds(&self, position: usize, len: usize, peers: (usize, usize)) -> (usize, usize) {
+        let start = match self.start {
+            FrameBound::Preceding(None) => 0,
+            FrameBound::Preceding(Some(n)) => position.saturating_sub(n as usize),
+            FrameBound::CurrentRow if self.units == FrameUnits::Range => peers.0,
+            FrameBound::CurrentRow => position,
+            FrameBound::Following(Some(n)) => position + n as usize,
+            FrameBound::Following(None) => len,
+        };
+        let end = match self.end {
+            FrameBound::Preceding(None) => 0,
+            FrameBound::Preceding(Some(n)) => (position + 1).saturating_sub(n as usize),
+            FrameBound::CurrentRow if self.units == FrameUnits::Range => peers.1,
+            FrameBound::CurrentRow => position + 1,
+            FrameBound::Following(Some(n)) => position + n as usize + 1,
+            FrameBound::Following(None) => len,
+        };
+        let start = start.min(len);
+        (start, end.min(len).max(start))
+    }
+}
+
+/// A single `function(args) OVER (PARTITION BY ... ORDER BY ... frame)`.
+pub struct WindowFunction {
+    name: String,
+    args: Vec<Arc<dyn Expression>>,
+    partition_by: Vec<Arc<dyn Expression>>,
+    order_by: Vec<Arc<dyn Expression>>,
+    options: Vec<SortOptions>,
+    frame: WindowFrame,
+}
+
+impl WindowFunction {
+    pub fn new(
+        name: &str,
+        args: Vec<Arc<dyn Expression>>,
+        partition_by: Vec<Arc<dyn Expression>>,
+        order_by: Vec<Arc<dyn Expression>>,
+        options: Vec<SortOptions>,
+        frame: WindowFrame,
+    ) -> Result<WindowFunction, Error> {
+        let offset_bound = |bound: FrameBound| matches!(bound, FrameBound::Preceding(Some(_)) | FrameBound::Following(Some(_)));
+        if frame.units == FrameUnits::Range && (offset_bound(frame.start) || offset_bound(frame.end)) {
+            return Err(Error::BadInput("RANGE window frames can only be bounded by UNBOUNDED or CURRENT ROW".to_string()));
+        }
+        Ok(WindowFunction {
+            name: name.to_lowercase(),
+            args,
+            partition_by,
+            order_by,
+            options,
+            frame,
+        })
+    }
+
+    fn field_meta(&self, schema_context: Arc<dyn SchemaContext>, record_schema: &Arc<Schema>) -> Result<Field, Error> {
+        let arg_types = self.args.iter()
+            .map(|arg| arg.field_meta(schema_context.clone(), record_schema).map(|field| field.data_type().clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let wrong_arguments = || Error::BadInput(format!("wrong arguments for window function {}: {:?}", self.name, arg_types));
+
+        let data_type = match (self.name.as_str(), arg_types.as_slice()) {
+            ("row_number", []) | ("rank", []) | ("dense_rank", []) => return Ok(Field::new("", DataType::Int64, false)),
+            ("count", []) | ("count", [_]) => return Ok(Field::new("", DataType::Int64, false)),
+            ("lag", [data_type, ..]) | ("lead", [data_type, ..]) if arg_types.len() <= 3 => {
+                if arg_types.len() > 1 && !is_integer(&arg_types[1]) {
+                    return Err(Error::BadInput(format!("the offset of {} has to be an integer, is {:?}", self.name, arg_types[1])));
+                }
+                match (data_type, arg_types.get(2)) {
+                    (DataType::Null, Some(default_type)) => default_type.clone(),
+                    (data_type, _) => data_type.clone(),
+                }
+            }
+            ("sum", [data_type]) if is_integer(data_type) || data_type == &DataType::Null => DataType::Int64,
+            ("sum", [data_type]) if is_float(data_type) => DataType::Float64,
+            ("avg", [data_type]) if is_numeric(data_type) || data_type == &DataType::Null => DataType::Float64,
+            ("min", [data_type]) | ("max", [data_type]) => data_type.clone(),
+            ("row_number", _) | ("rank", _) | ("dense_rank", _) | ("count", _) | ("lag", _) | ("lead", _)
+            | ("sum", _) | ("avg", _) | ("min", _) | ("max", _) => return Err(wrong_arguments()),
+            _ => return Err(Error::BadInput(format!("unknown window function: {}", self.name))),
+        };
+        Ok(Field::new("", data_type, true))
+    }
+
+    fn evaluate(&self, ctx: &ExecutionContext, record: &RecordBatch, data_type: &DataType) -> Result<ArrayRef, Error> {
+        let args = self.args.iter()
+            .map(|arg| arg.evaluate(ctx, record))
+            .collect::<Result<Vec<_>, _>>()?;
+        let order_keys = self.order_by.iter()
+            .map(|key| key.evaluate(ctx, record))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut values = vec![ScalarValue::Null; record.num_rows()];
+        for rows in self.partitions(ctx, record, &order_keys)? {
+            let peers = peer_groups(&order_keys, &rows)?;
+            self.evaluate_partition(&args, &rows, &peers, data_type, &mut values)?;
+        }
+        scalars_to_array(&values, data_type)
+    }
+
+    // The rows of each partition, sorted by the ORDER BY keys.
+    fn partitions(&self, ctx: &ExecutionContext, record: &RecordBatch, order_keys: &[ArrayRef]) -> Result<Vec<Vec<usize>>, Error> {
+        let partition_keys = self.partition_by.iter()
+            .map(|key| key.evaluate(ctx, record))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut partitions: Vec<Vec<usize>> = vec![];
+        let mut partition_indices: HashMap<Vec<ScalarValue>, usize> = HashMap::new();
+        for row in 0..record.num_rows() {
+            let key = partition_keys.iter()
+                .map(|key| get_scalar_value(key, row))
+                .collect::<Result<Vec<_>, _>>()?;
+            let index = *partition_indices.entry(key).or_insert_with(|| {
+                partitions.push(vec![]);
+                partitions.len() - 1
+            });
+            partitions[index].push(row);
+        }
+
+        if order_keys.is_empty() {
+            return Ok(partitions);
+        }
+        partitions.into_iter()
+            .map(|rows| {
+                let indices = rows.iter().map(|row| *row as u32).collect::<Vec<_>>().into();
+                let keys = order_keys.iter()
+                    .map(|key| take_array(key, &indices))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let order = sort_indices(&keys, &self.options, rows.len())?;
+                Ok((0..order.len()).map(|i| rows[order.value(i) as usize]).collect())
+            })
+            .collect()
+    }
+
+    fn evaluate_partition(&self, args: &[ArrayRef], rows: &[usize], peers: &[(usize, usize)], data_type: &DataType, values: &mut [ScalarValue]) -> Result<(), Error> {
+        match self.name.as_str() {
+            "row_number" => {
+                for (position, row) in rows.iter().enumerate() {
+                    values[*row] = ScalarValue::Int64(position as i64 + 1);
+                }
+            }
+            "rank" => {
+                for (position, row) in rows.iter().enumerate() {
+                    values[*row] = ScalarValue::Int64(peers[position].0 as i64 + 1);
+                }
+            }
+            "dense_rank" => {
+                let mut rank = 0;
+                for (position, row) in rows.iter().enumerate() {
+                    if peers[position].0 == position {
+                        rank += 1;
+                    }
+                    values[*row] = ScalarValue::Int64(rank);
+                }
+            }
+            "lag" | "lead" => {
+                let source = cast_array(&args[0], data_type)?;
+                let offsets = args.get(1).map(|offset| cast_array(offset, &DataType::Int64)).transpose()?;
+                let default = args.get(2).map(|default| cast_array(default, data_type)).transpose()?;
+                for (position, row) in rows.iter().enumerate() {
+                    let offset = match &offsets {
+                        Some(offsets) => match get_scalar_value(offsets, *row)? {
+                            ScalarValue::Int64(offset) => offset,
+                            _ => return Err(Error::BadInput(format!("the offset of {} can't be null", self.name))),
+                        },
+                        None => 1,
+                    };
+                    let target = if self.name == "lag" { position as i64 - offset } else { position as i64 + offset };
+                    values[*row] = if target >= 0 && (target as usize) < rows.len() {
+                        get_scalar_value(&source, rows[target as usize])?
+                    } else if let Some(default) = &default {
+                        get_scalar_value(default, *row)?
+                    } else {
+                        ScalarValue::Null
+                    };
+                }
+            }
+            "min" | "max" => {
+                // Both ends of the frames only move forward, so the candidates are kept in a queue in which each value
+                // is better than the ones after it, the first one being the result. A value entering the frame
+                // removes the ones which aren't better from the back of the queue, as they leave the frame before it.
+                let wanted = if self.name == "min" { Ordering::Less } else { Ordering::Greater };
+                let source = rows.iter()
+                    .map(|row| get_scalar_value(&args[0], *row))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut candidates: VecDeque<usize> = VecDeque::new();
+                let mut added = 0;
+                for (position, row) in rows.iter().enumerate() {
+                    let (start, end) = self.frame.bounds(position, rows.len(), peers[position]);
+                    for candidate in added..end {
+                        if source[candidate] == ScalarValue::Null {
+                            continue;
+                        }
+                        while let Some(last) = candidates.back() {
+                            if compare_scalars(&source[candidate], &source[*last]) == wanted.reverse() {
+                                break;
+                            }
+                            candidates.pop_back();
+                        }
+                        candidates.push_back(candidate);
+                    }
+                    added = end;
+                    while candidates.front().map(|first| *first < start).unwrap_or(false) {
+                        candidates.pop_front();
+                    }
+                    values[*row] = candidates.front()
+                        .map(|first| source[*first].clone())
+                        .unwrap_or(ScalarValue::Null);
+                }
+            }
+            _ => {
+                // count, sum and avg use prefix sums of the partition, so that each frame takes constant time.
+                let mut counts = vec![0i64; rows.len() + 1];
+                let mut int_sums = vec![0i64; rows.len() + 1];
+                let mut float_sums = vec![0f64; rows.len() + 1];
+                let ints = match (self.name.as_str(), args.first()) {
+                    ("sum", Some(arg)) if data_type == &DataType::Int64 => Some(cast_array(arg, &DataType::Int64)?),
+                    _ => None,
+                };
+                let floats = match (self.name.as_str(), args.first()) {
+                    ("count", _) | (_, None) => None,
+                    (_, Some(_)) if ints.is_some() => None,
+                    (_, Some(arg)) => Some(cast_array(arg, &DataType::Float64)?),
+                };
+                for (position, row) in rows.iter().enumerate() {
+                    let present = args.first().map(|arg| arg.is_valid(*row)).unwrap_or(true);
+                    counts[position + 1] = counts[position] + present as i64;
+                    int_sums[position + 1] = int_sums[position];
+                    float_sums[position + 1] = float_sums[position];
+                    if !present {
+                        continue;
+                    }
+                    if let Some(ints) = &ints {
+                        int_sums[position + 1] += ints.as_any().downcast_ref::<Int64Array>().unwrap().value(*row);
+                    }
+                    if let Some(floats) = &floats {
+                        float_sums[position + 1] += floats.as_any().downcast_ref::<Float64Array>().unwrap().value(*row);
+                    }
+                }
+
+                for (position, row) in rows.iter().enumerate() {
+                    let (start, end) = self.frame.bounds(position, rows.len(), peers[position]);
+                    let count = counts[end] - counts[start];
+                    values[*row] = match self.name.as_str() {
+                        "count" => ScalarValue::Int64(count),
+                        _ if count == 0 => ScalarValue::Null,
+                        "sum" if ints.is_some() => ScalarValue::Int64(int_sums[end] - int_sums[start]),
+                        "sum" => ScalarValue::Float64(float_sums[end] - float_sums[start]),
+                        _ => ScalarValue::Float64((float_sums[end] - float_sums[start]) / count as f64),
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// For each position of the sorted rows, the positions of its first peer and the one after its last peer.
+// Without ORDER BY keys all rows of the partition are peers.
+fn peer_groups(order_keys: &[ArrayRef], rows: &[usize]) -> Result<Vec<(usize, usize)>, Error> {
+    let keys = rows.iter()
+        .map(|row| order_keys.iter().map(|key| get_scalar_value(key, *row)).collect::<Result<Vec<_>, _>>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut peers = vec![(0, 0); rows.len()];
+    let mut start = 0;
+    for position in 1..=rows.len() {
+        if position == rows.len() || keys[position] != keys[start] {
+            for peer in peers.iter_mut().take(position).skip(start) {
+                *peer = (start, position);
+            }
+            start = position;
+        }
+    }
+    Ok(peers)
+}
+
+/// Computes window functions over all records of the source, adding a column for each of them.
+/// The records are buffered until the source is done, as the value for each of them may depend on all others,
+/// and emitted once, with their retractions applied.
+pub struct Window {
+    source: Arc<dyn Node>,
+    functions: Vec<WindowFunction>,
+    names: Vec<Identifier>,
+}
+
+impl Window {
+    pub fn new(source: Arc<dyn Node>, functions: Vec<WindowFunction>, names: Vec<Identifier>) -> Window {
+        Window {
+            source,
+            functions,
+            names,
+        }
+    }
+}
+
+impl Node for Window {
+    fn schema(&self, schema_context: Arc<dyn SchemaContext>) -> Result<Arc<Schema>, Error> {
+        let source_schema = self.source.schema(schema_context.clone())?;
+        let mut fields = source_schema.fields().clone();
+        let retraction_field = fields.pop().unwrap();
+        for (function, name) in self.functions.iter().zip(self.names.iter()) {
+            let field = function.field_meta(schema_context.clone(), &source_schema)?;
+            fields.push(Field::new(name.to_string().as_str(), field.data_type().clone(), field.is_nullable()));
+        }
+        fields.push(retraction_field);
+        Ok(Arc::new(Schema::new(fields)))
+    }
+
+    fn run(
+        &self,
+        ctx: &ExecutionContext,
+        produce: ProduceFn,
+        _meta_send: MetaSendFn,
+    ) -> Result<(), Error> {
+        let source_schema = self.source.schema(ctx.variable_context.clone())?;
+        let output_schema = self.schema(ctx.variable_context.clone())?;
+
+        let mut batches = vec![];
+        self.source.run(
+            ctx,
+            &mut |_produce_ctx, batch| {
+                batches.push(batch);
+                Ok(())
+            },
+            &mut noop_meta_send,
+        )?;
+        let batch = net_retractions(&source_schema, &batches)?;
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+
+        let mut columns = batch.columns().to_vec();
+        let retractions = columns.pop().unwrap();
+        for (function, field) in self.functions.iter().zip(output_schema.fields().iter().skip(columns.len())) {
+            columns.push(function.evaluate(ctx, &batch, field.data_type())?);
+        }
+        columns.push(retractions);
+
+        produce(&ProduceContext {}, RecordBatch::try_new(output_schema, columns)?)
+    }
+}
+
+#[test]
+fn test_frame_bounds() {
+    let rows = WindowFrame { units: FrameUnits::Rows, start: FrameBound::Preceding(Some(2)), end: FrameBound::CurrentRow };
+    assert_eq!(rows.bounds(0, 5, (0, 1)), (0, 1));
+    assert_eq!(rows.bounds(3, 5, (3, 4)), (1, 4));
+    let following = WindowFrame { units: FrameUnits::Rows, start: FrameBound::Following(Some(1)), end: FrameBound::Following(Some(3)) };
+    assert_eq!(following.bounds(3, 5, (3, 4)), (4, 5));
+    assert_eq!(following.bounds(4, 5, (4, 5)), (5, 5));
+    let peers = WindowFrame::default_for(true);
+    assert_eq!(peers.bounds(1, 5, (1, 3)), (0, 3));
+    assert_eq!(WindowFrame::default_for(false).bounds(1, 5, (0, 5)), (0, 5));
+}
+
+#[cfg(test)]
+fn test_window(fields: Vec<(&str, DataType)>, batches: Vec<Vec<Vec<ScalarValue>>>, functions: Vec<WindowFunction>) -> Vec<Vec<ScalarValue>> {
+    use crate::physical::testing::{collect, Values};
+
+    let names = (0..functions.len()).map(|i| Identifier::SimpleIdentifier(format!("w{}", i))).collect();
+    let window = Window::new(Arc::new(Values::new(fields, batches)), functions, names);
+    collect(&window).unwrap()
+}
+
+#[cfg(test)]
+fn test_field(name: &str) -> Arc<dyn Expression> {
+    Arc::new(crate::physical::expression::FieldExpression::new(Identifier::SimpleIdentifier(name.to_string())))
+}
+
+#[test]
+fn test_ranking() {
+    use crate::physical::testing::row;
+
+    let (float, int) = (ScalarValue::Float64, ScalarValue::Int64);
+    let ascending = vec![SortOptions { descending: false, nulls_first: false }];
+    let function = |name: &str| WindowFunction::new(name, vec![], vec![test_field("a.g")], vec![test_field("a.x")], ascending.clone(), WindowFrame::default_for(true)).unwrap();
+    // NaNs form a single partition, and so do 0.0 and -0.0.
+    let rows = test_window(vec![("a.g", DataType::Float64), ("a.x", DataType::Int64)], vec![
+        vec![row(&[float(1.5), int(3)], false), row(&[float(f64::NAN), int(1)], false), row(&[float(1.5), int(1)], false), row(&[float(-0.0), int(3)], false)],
+        vec![row(&[float(f64::NAN), int(2)], false), row(&[float(1.5), int(5)], false), row(&[float(1.5), int(5)], true), row(&[float(0.0), int(7)], false)],
+        vec![row(&[float(1.5), int(3)], false), row(&[float(1.5), int(4)], false)],
+    ], vec![function("rank"), function("dense_rank")]);
+    assert_eq!(rows, vec![
+        row(&[float(1.5), int(3), int(2), int(2)], false),
+        row(&[float(f64::NAN), int(1), int(1), int(1)], false),
+        row(&[float(1.5), int(1), int(1), int(1)], false),
+        row(&[float(-0.0), int(3), int(1), int(1)], false),
+        row(&[float(f64::NAN), int(2), int(2), int(2)], false),
+        row(&[float(0.0), int(7), int(2), int(2)], false),
+        row(&[float(1.5), int(3), int(2), int(2)], false),
+        row(&[float(1.5), int(4), int(4), int(3)], false),
+    ]);
+}
+
+#[test]
+fn test_lag_and_lead() {
+    use crate::physical::expression::Constant;
+    use crate::physical::testing::row;
+
+    let (text, int) = (|text: &str| ScalarValue::Utf8(text.to_string()), ScalarValue::Int64);
+    let constant = |value: i64| Arc::new(Constant::new(int(value))) as Arc<dyn Expression>;
+    let ascending = vec![SortOptions { descending: false, nulls_first: false }];
+    let function = |name: &str, args: Vec<Arc<dyn Expression>>| WindowFunction::new(name, args, vec![test_field("a.g")], vec![test_field("a.x")], ascending.clone(), WindowFrame::default_for(true)).unwrap();
+    let rows = test_window(vec![("a.g", DataType::Utf8), ("a.x", DataType::Int64)], vec![
+        vec![row(&[text("a"), int(1)], false), row(&[text("b"), int(10)], false), row(&[text("a"), int(3)], false)],
+        vec![row(&[text("a"), int(2)], false), row(&[text("b"), int(20)], false)],
+    ], vec![
+        function("lag", vec![test_field("a.x")]),
+        function("lead", vec![test_field("a.x"), constant(2), constant(-1)]),
+    ]);
+    let null = ScalarValue::Null;
+    assert_eq!(rows, vec![
+        row(&[text("a"), int(1), null.clone(), int(3)], false),
+        row(&[text("b"), int(10), null.clone(), int(-1)], false),
+        row(&[text("a"), int(3), int(2), int(-1)], false),
+        row(&[text("a"), int(2), int(1), int(-1)], false),
+        row(&[text("b"), int(20), int(10), int(-1)], false),
+    ]);
+}
+
+#[test]
+fn test_aggregate_frames() {
+    use crate::physical::testing::row;
+
+    let (float, int) = (ScalarValue::Float64, ScalarValue::Int64);
+    let ascending = vec![SortOptions { descending: false, nulls_first: false }];
+    let function = |name: &str, args: Vec<Arc<dyn Expression>>, frame: WindowFrame| WindowFunction::new(name, args, vec![], vec![test_field("a.t")], ascending.clone(), frame).unwrap();
+    let rows_frame = |start, end| WindowFrame { units: FrameUnits::Rows, start, end };
+    let around = rows_frame(FrameBound::Preceding(Some(1)), FrameBound::Following(Some(1)));
+    let rows = test_window(vec![("a.t", DataType::Int64), ("a.x", DataType::Int64)], vec![
+        vec![row(&[int(2), int(1)], false), row(&[int(1), int(3)], false), row(&[int(3), int(4)], false)],
+        vec![row(&[int(3), int(4)], true), row(&[int(3), int(2)], false), row(&[int(5), int(6)], false), row(&[int(4), int(5)], false)],
+    ], vec![
+        function("max", vec![test_field("a.x")], around),
+        function("min", vec![test_field("a.x")], around),
+        function("sum", vec![test_field("a.x")], rows_frame(FrameBound::Preceding(Some(1)), FrameBound::CurrentRow)),
+        function("avg", vec![test_field("a.x")], WindowFrame::default_for(true)),
+        function("count", vec![], rows_frame(FrameBound::Following(Some(1)), FrameBound::Following(None))),
+    ]);
+    // Ordered by t, x is 3, 1, 2, 5, 6.
+    assert_eq!(rows, vec![
+        row(&[int(2), int(1), int(3), int(1), int(4), float(2.0), int(3)], false),
+        row(&[int(1), int(3), int(3), int(1), int(3), float(3.0), int(4)], false),
+        row(&[int(3), int(2), int(5), int(1), int(3), float(2.0), int(2)], false),
+        row(&[int(5), int(6), int(6), int(5), int(11), float(3.4), int(0)], false),
+        row(&[int(4), int(5), int(6), int(2), int(7), float(2.75), int(1)], false),
+    ]);
+}
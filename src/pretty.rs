@@ -28,29 +28,31 @@
 
 //! Utilities for printing record batches
 
-use std::time::{Duration, UNIX_EPOCH};
-
 use arrow::array;
-use arrow::datatypes::{DataType, TimeUnit};
+use arrow::datatypes::{DataType, IntervalUnit, TimeUnit};
 use arrow::error::{ArrowError, Result};
 use arrow::record_batch::RecordBatch;
-use chrono::{DateTime, Utc};
 use prettytable::{Cell, Row, Table};
 use prettytable::format;
 
+use crate::physical::datetime;
+use crate::physical::encoding;
+use crate::physical::datetime::Zone;
+
 ///! Create a visual representation of record batches
-pub fn pretty_format_batches(results: &[RecordBatch]) -> Result<String> {
-    Ok(create_table(results)?.to_string())
+///! Timestamps without a time zone are shown in the given one.
+pub fn pretty_format_batches(results: &[RecordBatch], time_zone: &Zone) -> Result<String> {
+    Ok(create_table(results, time_zone)?.to_string())
 }
 
 ///! Prints a visual representation of record batches to stdout
-pub fn print_batches(results: &[RecordBatch]) -> Result<()> {
-    create_table(results)?.printstd();
+pub fn print_batches(results: &[RecordBatch], time_zone: &Zone) -> Result<()> {
+    create_table(results, time_zone)?.printstd();
     Ok(())
 }
 
 ///! Convert a series of record batches into a table
-fn create_table(results: &[RecordBatch]) -> Result<Table> {
+fn create_table(results: &[RecordBatch], time_zone: &Zone) -> Result<Table> {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
@@ -71,7 +73,7 @@ fn create_table(results: &[RecordBatch]) -> Result<Table> {
             let mut cells = Vec::new();
             for col in 0..batch.num_columns() {
                 let column = batch.column(col);
-                cells.push(Cell::new(&array_value_to_string(column.clone(), row)?));
+                cells.push(Cell::new(&array_value_to_string(column.clone(), row, time_zone)?));
             }
             table.add_row(Row::new(cells));
         }
@@ -83,7 +85,7 @@ fn create_table(results: &[RecordBatch]) -> Result<Table> {
 macro_rules! make_string {
     ($array_type:ty, $column: ident, $row: ident) => {{
         if $column.is_null($row) {
-            Ok("<null>".to_string())
+            Ok("NULL".to_string())
         } else {
             Ok($column
                 .as_any()
@@ -96,14 +98,17 @@ macro_rules! make_string {
 }
 
 /// Get the value at the given row in an array as a string
-fn array_value_to_string(column: array::ArrayRef, row: usize) -> Result<String> {
+fn array_value_to_string(column: array::ArrayRef, row: usize, time_zone: &Zone) -> Result<String> {
     match column.data_type() {
-        DataType::Utf8 => Ok(column
-            .as_any()
-            .downcast_ref::<array::StringArray>()
-            .unwrap()
-            .value(row)
-            .to_string()),
+        DataType::Utf8 => make_string!(array::StringArray, column, row),
+        DataType::Null => Ok("NULL".to_string()),
+        DataType::Binary => {
+            if column.is_null(row) {
+                return Ok("NULL".to_string());
+            }
+            let array = column.as_any().downcast_ref::<array::BinaryArray>().unwrap();
+            Ok(format!("\\x{}", encoding::hex(array.value(row))))
+        }
         DataType::Boolean => make_string!(array::BooleanArray, column, row),
         DataType::Int16 => make_string!(array::Int16Array, column, row),
         DataType::Int32 => make_string!(array::Int32Array, column, row),
@@ -126,19 +131,48 @@ fn array_value_to_string(column: array::ArrayRef, row: usize) -> Result<String>
         }
         DataType::Timestamp(unit, _) if *unit == TimeUnit::Nanosecond => {
             if column.is_null(row) {
-                Ok("<null>".to_string())
+                Ok("NULL".to_string())
             } else {
-                let nano_duration = UNIX_EPOCH + Duration::from_nanos(column
+                let nanos = column
                     .as_any()
                     .downcast_ref::<array::TimestampNanosecondArray>()
                     .unwrap()
-                    .value(row) as u64);
-                let datetime = DateTime::<Utc>::from(nano_duration);
-                Ok(datetime.to_rfc3339())
+                    .value(row);
+                let zone = datetime::time_zone(column.data_type(), time_zone)
+                    .map_err(|err| ArrowError::InvalidArgumentError(format!("{:?}", err)))?;
+                Ok(zone.to_datetime(nanos).to_rfc3339())
+            }
+        }
+        DataType::Date32(_) => {
+            if column.is_null(row) {
+                Ok("NULL".to_string())
+            } else {
+                Ok(column
+                    .as_any()
+                    .downcast_ref::<array::Date32Array>()
+                    .unwrap()
+                    .value_as_date(row)
+                    .map(|date| date.to_string())
+                    .unwrap_or_else(|| "NULL".to_string()))
             }
         }
-        DataType::Date32(_) => make_string!(array::Date32Array, column, row),
         DataType::Date64(_) => make_string!(array::Date64Array, column, row),
+        DataType::Interval(IntervalUnit::DayTime) => {
+            if column.is_null(row) {
+                Ok("NULL".to_string())
+            } else {
+                let interval = column.as_any().downcast_ref::<array::IntervalDayTimeArray>().unwrap().value(row);
+                Ok(datetime::format_interval_day_time(interval))
+            }
+        }
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            if column.is_null(row) {
+                Ok("NULL".to_string())
+            } else {
+                let months = column.as_any().downcast_ref::<array::IntervalYearMonthArray>().unwrap().value(row);
+                Ok(datetime::format_interval_year_month(months))
+            }
+        }
         DataType::Time32(unit) if *unit == TimeUnit::Second => {
             make_string!(array::Time32SecondArray, column, row)
         }
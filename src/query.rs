@@ -0,0 +1,129 @@
+// Copyright 2020 The OctoSQL Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::datatypes::{Field, Schema};
+
+use crate::logical::logical;
+use crate::logical::logical::{MaterializationContext, Node};
+use crate::logical::sql::{parameter_identifier, parameter_name, query_to_logical_plan, Scope};
+use crate::logical::validation::validate;
+use crate::parser::ParseError;
+use crate::parser::parser::parse_sql;
+use crate::physical::physical;
+use crate::physical::physical::{Error, ExecutionContext, noop_meta_send, ProduceFn, ScalarValue, Session, VariableContext};
+
+/// A query planned once, which can then be run any number of times with different values of its parameters.
+pub struct PreparedQuery {
+    plan: Box<Node>,
+    // The names of the parameters referenced by the query, 1 for $1 and name for :name.
+    parameters: Vec<String>,
+}
+
+impl PreparedQuery {
+    pub fn new(sql: &str) -> Result<PreparedQuery, ParseError> {
+        let query = parse_sql(sql)?;
+        let plan = query_to_logical_plan(&Scope::default(), query.as_ref());
+
+        let mut variables = vec![];
+        plan.collect_variables(&mut variables);
+        let mut parameters: Vec<String> = vec![];
+        for name in variables.iter().filter_map(parameter_name) {
+            if !parameters.iter().any(|parameter| parameter == name) {
+                parameters.push(name.to_string());
+            }
+        }
+
+        Ok(PreparedQuery { plan, parameters })
+    }
+
+    pub fn schema(&self, parameters: &HashMap<String, ScalarValue>) -> Result<Arc<Schema>, Error> {
+        let variable_context = self.variable_context(parameters)?;
+        self.physical(variable_context.clone())?.schema(variable_context)
+    }
+
+    pub fn run(&self, session: Arc<Session>, parameters: &HashMap<String, ScalarValue>, produce: ProduceFn) -> Result<(), Error> {
+        let variable_context = self.variable_context(parameters)?;
+        let ctx = ExecutionContext {
+            variable_context: variable_context.clone(),
+            session,
+        };
+        self.physical(variable_context)?.run(&ctx, produce, &mut noop_meta_send)
+    }
+
+    // Physical nodes keep state between runs, like materialized common table expressions, so each run gets its own.
+    // The plan is validated for each run too, as the types of the parameters may change.
+    fn physical(&self, variable_context: Arc<VariableContext>) -> Result<Arc<dyn physical::Node>, Error> {
+        let logical_error = |err| match err {
+            logical::Error::BadInput(message) => Error::BadInput(message),
+            err => Error::BadInput(format!("{:?}", err)),
+        };
+        validate(self.plan.as_ref(), variable_context).map_err(logical_error)?
+            .physical(&MaterializationContext::default())
+            .map_err(logical_error)
+    }
+
+    // The parameters are the variables of the root variable context, typed by their values.
+    fn variable_context(&self, parameters: &HashMap<String, ScalarValue>) -> Result<Arc<VariableContext>, Error> {
+        if let Some(name) = parameters.keys().find(|name| !self.parameters.contains(name)) {
+            return Err(Error::BadInput(format!("the query has no parameter {}", name)));
+        }
+        let mut fields = Vec::with_capacity(self.parameters.len());
+        let mut variables = Vec::with_capacity(self.parameters.len());
+        for name in &self.parameters {
+            let value = parameters.get(name)
+                .ok_or_else(|| Error::BadInput(format!("missing value of parameter {}", name)))?;
+            fields.push(Field::new(parameter_identifier(name).to_string().as_str(), value.data_type(), value == &ScalarValue::Null));
+            variables.push(value.clone());
+        }
+        Ok(Arc::new(VariableContext {
+            previous: None,
+            schema: Arc::new(Schema::new(fields)),
+            variables,
+        }))
+    }
+}
+
+/// Parses a parameter value given as text, like on the command line.
+/// Numbers, true, false and null are typed accordingly, everything else is a string.
+/// A value in single quotes is always a string.
+pub fn parse_parameter_value(text: &str) -> ScalarValue {
+    if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        return ScalarValue::Utf8(text[1..text.len() - 1].to_string());
+    }
+    if let Ok(value) = text.parse::<i64>() {
+        return ScalarValue::Int64(value);
+    }
+    if let Ok(value) = text.parse::<f64>() {
+        return ScalarValue::Float64(value);
+    }
+    match text.to_lowercase().as_str() {
+        "true" => ScalarValue::Boolean(true),
+        "false" => ScalarValue::Boolean(false),
+        "null" => ScalarValue::Null,
+        _ => ScalarValue::Utf8(text.to_string()),
+    }
+}
+
+#[test]
+fn test_parse_parameter_value() {
+    assert_eq!(parse_parameter_value("42"), ScalarValue::Int64(42));
+    assert_eq!(parse_parameter_value("-1.5"), ScalarValue::Float64(-1.5));
+    assert_eq!(parse_parameter_value("TRUE"), ScalarValue::Boolean(true));
+    assert_eq!(parse_parameter_value("null"), ScalarValue::Null);
+    assert_eq!(parse_parameter_value("'42'"), ScalarValue::Utf8("42".to_string()));
+    assert_eq!(parse_parameter_value("Tiger"), ScalarValue::Utf8("Tiger".to_string()));
+}